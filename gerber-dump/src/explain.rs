@@ -0,0 +1,309 @@
+//! Human-readable descriptions of commands
+
+use gerber::aperture::ApertureTemplate;
+use gerber::attribute::{ApertureAttributeName, FileAttributeName, ObjectAttributeName};
+use gerber::command::Command::{self, *};
+use gerber::data::{
+    CoordinateFormat, Coordinates, EscapedString, InterpolationMode, Mirroring, Polarity, Unit,
+};
+use gerber::span::Spanned;
+use gerber::GerberLayer;
+
+/// Print each command of `layer` next to a description of what it does
+pub fn explain(src: &str, layer: &GerberLayer) {
+    let mut state = State::default();
+    for Spanned { node, span } in layer.spanned() {
+        let text = src[span.bytes].split_whitespace().collect::<Vec<_>>();
+        println!(
+            "{:>6}  {:<24}  {}",
+            span.line,
+            text.join(" "),
+            state.describe(node)
+        );
+    }
+}
+
+/// The graphics state needed to describe commands
+#[derive(Default)]
+struct State {
+    unit: Option<Unit>,
+    format: Option<(CoordinateFormat, CoordinateFormat)>,
+    point: (i64, i64),
+    interpolation: Option<InterpolationMode>,
+    region: bool,
+    aperture: Option<String>,
+}
+
+impl State {
+    fn describe(&mut self, command: &Command) -> String {
+        match command {
+            Comment(_) => "comment".to_string(),
+            Mode(unit) => {
+                self.unit = Some(*unit);
+                match unit {
+                    Unit::Millimeters => "set the unit to millimeters".to_string(),
+                    Unit::Inches => "set the unit to inches".to_string(),
+                }
+            }
+            FormatSpecification(x, y) => {
+                self.format = Some((*x, *y));
+                format!(
+                    "coordinates have {} integer and {} decimal digits",
+                    x.integer, x.decimal
+                )
+            }
+            ApertureDefine(id, template) => {
+                format!("define aperture {id} as {}", self.template(template))
+            }
+            ApertureMacro(name, _) => format!("define aperture macro {name}"),
+            SetCurrentAperture(id) => {
+                self.aperture = Some(id.to_string());
+                format!("select aperture {id}")
+            }
+            Plot(coordinates, offset) => {
+                let start = self.point;
+                let from = self.position();
+                let to = self.move_to(coordinates);
+                let direction = match self.interpolation {
+                    Some(InterpolationMode::Clockwise) => Some("clockwise"),
+                    Some(InterpolationMode::CounterClockwise) => Some("counterclockwise"),
+                    _ => None,
+                };
+                let what = match (direction, self.region) {
+                    (Some(direction), true) => format!("add {direction} arc segment to contour"),
+                    (Some(direction), false) => format!("{direction} arc"),
+                    (None, true) => "add line segment to contour".to_string(),
+                    (None, false) => "draw line".to_string(),
+                };
+                let mut description = format!("{what} from {from} to {to}");
+                if let Some(offset) = offset.filter(|_| direction.is_some()) {
+                    // the offset is relative to the start point
+                    let center = self.point_mm(start.0 + offset.i, start.1 + offset.j);
+                    description += &format!(", center {center}");
+                }
+                if !self.region {
+                    description += &self.with_aperture();
+                }
+                description
+            }
+            Move(coordinates) => format!("move to {}", self.move_to(coordinates)),
+            Flash(coordinates) => {
+                let at = self.move_to(coordinates);
+                format!("flash at {at}{}", self.with_aperture())
+            }
+            SetLinear | SetCWCircular | SetCCWCircular => {
+                self.interpolation = command.interpolation_mode();
+                match self.interpolation {
+                    Some(InterpolationMode::Linear) => "interpolate lines",
+                    Some(InterpolationMode::Clockwise) => "interpolate clockwise arcs",
+                    _ => "interpolate counterclockwise arcs",
+                }
+                .to_string()
+            }
+            ArcInit => "enable multi quadrant arcs".to_string(),
+            SingleQuadrant => "limit arcs to a single quadrant (deprecated)".to_string(),
+            LoadPolarity(Polarity::Dark) => "following objects darken the image".to_string(),
+            LoadPolarity(Polarity::Clear) => "following objects clear the image".to_string(),
+            LoadMirroring(mirroring) => match mirroring {
+                Mirroring::None => "stop mirroring following objects",
+                Mirroring::X => "mirror following objects along the X axis",
+                Mirroring::Y => "mirror following objects along the Y axis",
+                Mirroring::XY => "mirror following objects along both axes",
+            }
+            .to_string(),
+            LoadRotation(rotation) => {
+                format!("rotate following objects {}° counterclockwise", rotation.0)
+            }
+            LoadScaling(scaling) => format!("scale following objects by {}", scaling.0),
+            StartRegion => {
+                self.region = true;
+                "start a region".to_string()
+            }
+            EndRegion => {
+                self.region = false;
+                "end the region".to_string()
+            }
+            ApertureBlock(Some(id)) => format!("open block aperture {id}"),
+            ApertureBlock(None) => "close the block aperture".to_string(),
+            StepAndRepeat(Some(repeat)) => format!(
+                "repeat the following objects {}×{} times, step {} by {}",
+                repeat.x,
+                repeat.y,
+                self.length(repeat.i),
+                self.length(repeat.j)
+            ),
+            StepAndRepeat(None) => "end the step and repeat".to_string(),
+            AttributeOnFile(name, values) => format!(
+                "file attribute {} = {}{}",
+                name.name(),
+                join(values),
+                meaning(file_attribute(name))
+            ),
+            AttributeOnAperture(name, values) => format!(
+                "aperture attribute {} = {}{}",
+                name.name(),
+                join(values),
+                meaning(aperture_attribute(name))
+            ),
+            AttributeOnObject(name, values) => format!(
+                "object attribute {} = {}{}",
+                name.name(),
+                join(values),
+                meaning(object_attribute(name))
+            ),
+            AttributeDelete(Some(name)) => format!("delete attribute {name}"),
+            AttributeDelete(None) => "delete all aperture and object attributes".to_string(),
+            EndOfFile => "end of file".to_string(),
+            _ => command.code().to_string(),
+        }
+    }
+
+    fn template(&self, template: &ApertureTemplate) -> String {
+        let hole = |hole: &Option<f64>| match hole {
+            Some(hole) => format!(" with a {} hole", self.length(*hole)),
+            None => String::new(),
+        };
+        match template {
+            ApertureTemplate::Circle { diameter, hole: h } => {
+                format!("circle, diameter {}{}", self.length(*diameter), hole(h))
+            }
+            ApertureTemplate::Rectangle { x, y, hole: h } => format!(
+                "rectangle, {} x {}{}",
+                self.length(*x),
+                self.length(*y),
+                hole(h)
+            ),
+            ApertureTemplate::Obround { x, y, hole: h } => format!(
+                "obround, {} x {}{}",
+                self.length(*x),
+                self.length(*y),
+                hole(h)
+            ),
+            ApertureTemplate::Polygon {
+                diameter,
+                vertices,
+                rotation,
+                hole: h,
+            } => format!(
+                "polygon with {vertices} vertices, outer diameter {}, rotated {}°{}",
+                self.length(*diameter),
+                rotation.unwrap_or(0.0),
+                hole(h)
+            ),
+            ApertureTemplate::Macro { name, parameters } if parameters.is_empty() => {
+                format!("macro {name}")
+            }
+            ApertureTemplate::Macro { name, parameters } => format!(
+                "macro {name} with parameters {}",
+                parameters
+                    .iter()
+                    .map(f64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// A size in the file unit, converted to mm
+    fn length(&self, value: f64) -> String {
+        match self.unit {
+            Some(unit) => format!("{} mm", round(unit.to_mm(value))),
+            None => format!("{value} (unit not set)"),
+        }
+    }
+
+    fn move_to(&mut self, coordinates: &Coordinates) -> String {
+        self.point = (
+            coordinates.x.unwrap_or(self.point.0),
+            coordinates.y.unwrap_or(self.point.1),
+        );
+        self.position()
+    }
+
+    fn position(&self) -> String {
+        self.point_mm(self.point.0, self.point.1)
+    }
+
+    fn point_mm(&self, x: i64, y: i64) -> String {
+        match (self.unit, self.format) {
+            (Some(unit), Some((x_format, y_format))) => format!(
+                "({}, {}) mm",
+                round(unit.to_mm(scale(x, x_format))),
+                round(unit.to_mm(scale(y, y_format)))
+            ),
+            _ => format!("({x}, {y})"),
+        }
+    }
+
+    fn with_aperture(&self) -> String {
+        match &self.aperture {
+            Some(aperture) => format!(" with {aperture}"),
+            None => " without an aperture".to_string(),
+        }
+    }
+}
+
+fn scale(value: i64, format: CoordinateFormat) -> f64 {
+    value as f64 / 10f64.powi(format.decimal as i32)
+}
+
+/// Round away floating point noise from unit conversion
+fn round(value: f64) -> f64 {
+    (value * 1e6).round() / 1e6
+}
+
+fn join(values: &[EscapedString]) -> String {
+    values
+        .iter()
+        .map(|v| v.unescape().into_owned())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn meaning(description: Option<&str>) -> String {
+    description.map_or(String::new(), |d| format!(" ({d})"))
+}
+
+fn file_attribute(name: &FileAttributeName) -> Option<&'static str> {
+    Some(match name {
+        FileAttributeName::Part => "the part of the product this file represents",
+        FileAttributeName::FileFunction => "the function of this layer in the product",
+        FileAttributeName::FilePolarity => "whether the image is positive or negative",
+        FileAttributeName::SameCoordinates => "files sharing this value are aligned",
+        FileAttributeName::CreationDate => "when the file was created",
+        FileAttributeName::GenerationSoftware => "the software which wrote the file",
+        FileAttributeName::ProjectId => "the project and revision the file belongs to",
+        FileAttributeName::MD5 => "checksum of the file content",
+        _ => return None,
+    })
+}
+
+fn aperture_attribute(name: &ApertureAttributeName) -> Option<&'static str> {
+    Some(match name {
+        ApertureAttributeName::AperFunction => "what objects created with the aperture are for",
+        ApertureAttributeName::DrillTolerance => "plus and minus tolerance of the drill",
+        ApertureAttributeName::FlashText => "text represented by the flashed aperture",
+        _ => return None,
+    })
+}
+
+fn object_attribute(name: &ObjectAttributeName) -> Option<&'static str> {
+    Some(match name {
+        ObjectAttributeName::N => "net name",
+        ObjectAttributeName::P => "component reference designator and pin",
+        ObjectAttributeName::C => "component reference designator",
+        ObjectAttributeName::CRot => "component rotation",
+        ObjectAttributeName::CMfr => "component manufacturer",
+        ObjectAttributeName::CMPN => "component manufacturer part number",
+        ObjectAttributeName::CVal => "component value",
+        ObjectAttributeName::CMnt => "component mount type",
+        ObjectAttributeName::CFtp => "component footprint",
+        ObjectAttributeName::CPgN => "component package name",
+        ObjectAttributeName::CPgD => "component package description",
+        ObjectAttributeName::CHgt => "component height",
+        ObjectAttributeName::CLbN => "component library name",
+        ObjectAttributeName::CLbD => "component library description",
+        ObjectAttributeName::CSup => "component supplier",
+        _ => return None,
+    })
+}
@@ -0,0 +1,201 @@
+//! Batch processing of files, directories and glob patterns
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Extensions of Gerber files picked up from directories
+const GERBER_EXTENSIONS: &[&str] = &[
+    "gbr", "ger", "gtl", "gbl", "gto", "gbo", "gts", "gbs", "gtp", "gbp", "gko", "gm1", "g1", "g2",
+    "g3", "g4",
+];
+
+/// Expand arguments into a sorted list of files
+///
+/// Arguments containing glob characters are matched as patterns, directories
+/// are searched recursively for Gerber files and anything else is taken as a
+/// file name.
+pub fn expand(args: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for arg in args {
+        if arg.contains(['*', '?', '[']) {
+            for path in glob::glob(arg)? {
+                let path = path?;
+                if path.is_dir() {
+                    walk(&path, &mut files)?;
+                } else {
+                    files.push(path);
+                }
+            }
+        } else if Path::new(arg).is_dir() {
+            walk(Path::new(arg), &mut files)?;
+        } else {
+            files.push(PathBuf::from(arg));
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else if is_gerber(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_gerber(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| GERBER_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Read the Gerber files of a board from a directory or a zip archive
+///
+/// Paths are relative to the directory or archive, in sorted order.
+pub fn read_board(path: &Path) -> anyhow::Result<Vec<(PathBuf, String)>> {
+    let mut board = Vec::new();
+    if path.is_dir() {
+        let mut files = Vec::new();
+        walk(path, &mut files)?;
+        files.sort();
+        for file in files {
+            let src = fs::read_to_string(&file)?;
+            board.push((file.strip_prefix(path)?.to_path_buf(), src));
+        }
+        return Ok(board);
+    }
+
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        if entry.is_file() && is_gerber(&name) {
+            let mut src = String::new();
+            entry.read_to_string(&mut src)?;
+            board.push((name, src));
+        }
+    }
+    board.sort();
+    Ok(board)
+}
+
+/// Apply `f` to each file on all available cores, keeping the input order
+pub fn process<T: Send>(files: &[PathBuf], f: impl Fn(&Path) -> T + Sync) -> Vec<T> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..files.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..workers.min(files.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(index) else { break };
+                let result = f(file);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.expect("every file is processed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::time::Duration;
+    use zip::write::SimpleFileOptions;
+
+    /// A directory with Gerber files, one in a subdirectory, and a text file
+    fn board() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        for (name, src) in [
+            ("top.gbr", "top"),
+            ("sub/bottom.GBL", "bottom"),
+            ("notes.txt", "notes"),
+        ] {
+            fs::write(dir.path().join(name), src).unwrap();
+        }
+        dir
+    }
+
+    fn arg(path: PathBuf) -> String {
+        path.display().to_string()
+    }
+
+    #[test]
+    fn test_expand() {
+        let dir = board();
+        let path = |name: &str| dir.path().join(name);
+
+        // directories are searched for Gerber files only
+        let files = expand(&[arg(path(""))]).unwrap();
+        assert_eq!(files, [path("sub/bottom.GBL"), path("top.gbr")]);
+
+        // files are taken as they are, sorted and without duplicates
+        let args = [path("top.gbr"), path("notes.txt"), path("top.gbr")].map(arg);
+        assert_eq!(expand(&args).unwrap(), [path("notes.txt"), path("top.gbr")]);
+
+        // patterns match files of any kind and directories to search
+        let files = expand(&[arg(path("*.txt")), arg(path("s*"))]).unwrap();
+        assert_eq!(files, [path("notes.txt"), path("sub/bottom.GBL")]);
+        assert!(expand(&[arg(path("*.gtl"))]).unwrap().is_empty());
+        assert!(expand(&["[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_read_board() {
+        let dir = board();
+        let expected = [
+            (PathBuf::from("sub/bottom.GBL"), "bottom".to_string()),
+            (PathBuf::from("top.gbr"), "top".to_string()),
+        ];
+        assert_eq!(read_board(dir.path()).unwrap(), expected);
+
+        let archive = dir.path().join("board.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, src) in [
+            ("top.gbr", "top"),
+            ("readme.txt", "readme"),
+            ("sub/bottom.GBL", "bottom"),
+        ] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(src.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        assert_eq!(read_board(&archive).unwrap(), expected);
+
+        assert!(read_board(&dir.path().join("missing.zip")).is_err());
+    }
+
+    #[test]
+    fn test_process() {
+        let files: Vec<_> = (0..32).map(|i| PathBuf::from(i.to_string())).collect();
+        // the first files take longest, so they finish last
+        let results = process(&files, |path| {
+            let i: u64 = path.to_str().unwrap().parse().unwrap();
+            thread::sleep(Duration::from_millis(32 - i));
+            i
+        });
+        assert_eq!(results, (0..32).collect::<Vec<_>>());
+        assert!(process(&[], |_| ()).is_empty());
+    }
+}
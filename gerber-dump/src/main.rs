@@ -1,19 +1,861 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use gerber::attribute::FileAttributeName;
+use gerber::command::Command::AttributeOnFile;
+use gerber::equivalence::Difference;
+use gerber::fabrication::ProjectFabrication;
+use gerber::lint::Lints;
+use gerber::project::GerberProject;
+use gerber::span::{LineColumn, LineIndex};
+use gerber::validate::Severity;
 use gerber::GerberLayer;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use table::{print_row, Format};
+
+mod explain;
+mod files;
+mod table;
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    dump: DumpArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check a file against the specification, for use in CI
+    ///
+    /// Exits with a non-zero status when errors are found.
+    Validate(ValidateArgs),
+
+    /// Check files against their .MD5 file attribute
+    ///
+    /// Exits with a non-zero status when a checksum does not match.
+    VerifyMd5(VerifyMd5Args),
+
+    /// Compare two revisions of a board layer by layer
+    ///
+    /// Layers are paired by their .FileFunction attribute, or by file name
+    /// when they have none. Exits with a non-zero status when a layer
+    /// changed.
+    CompareDir(CompareDirArgs),
+
+    /// Print what board quote forms ask for: size, layers, the smallest
+    /// track, space and drill, and the number of holes
+    QuoteInfo(QuoteInfoArgs),
+}
+
+#[derive(Args)]
+struct DumpArgs {
     /// Name of the file to dump
-    filename: String,
+    #[arg(required = true)]
+    filename: Option<String>,
+
+    /// Only dump commands with these codes, e.g. AD,AM,TF
+    #[arg(long, value_delimiter = ',')]
+    filter: Vec<String>,
+
+    /// Print the number of commands with each code instead of the commands
+    #[arg(long)]
+    count_only: bool,
+
+    /// Describe each command in plain words, with coordinates in mm
+    #[arg(long, conflicts_with_all = ["filter", "count_only"])]
+    explain: bool,
+
+    /// Format of the --count-only report
+    #[arg(long, value_enum, default_value_t, requires = "count_only")]
+    format: Format,
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    /// Files, directories or glob patterns to validate
+    ///
+    /// Directories are searched recursively for Gerber files. A summary
+    /// table is printed when more than one file is validated.
+    #[arg(required = true)]
+    paths: Vec<String>,
+
+    /// Also exit with a non-zero status when warnings are found
+    #[arg(long)]
+    deny_warnings: bool,
+
+    /// Config file of lint levels, with lines like
+    /// `unused_aperture = "allow"`
+    #[arg(long)]
+    lints: Option<PathBuf>,
+
+    /// Format of the report
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+#[derive(Args)]
+struct VerifyMd5Args {
+    /// Files, directories or glob patterns to verify
+    ///
+    /// Directories are searched recursively for Gerber files.
+    #[arg(required = true)]
+    paths: Vec<String>,
+
+    /// Also exit with a non-zero status when a file has no .MD5 attribute
+    #[arg(long)]
+    require: bool,
+
+    /// Format of the report
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+#[derive(Args)]
+struct CompareDirArgs {
+    /// Directory with the old revision of the board
+    old_dir: PathBuf,
+
+    /// Directory with the new revision of the board
+    new_dir: PathBuf,
+
+    /// Distance in mm within which points are the same
+    #[arg(long, default_value_t = 0.001)]
+    tolerance: f64,
+
+    /// Format of the report
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
 }
 
-fn main() -> anyhow::Result<()> {
+#[derive(Args)]
+struct QuoteInfoArgs {
+    /// Directory or zip archive with the Gerber files of the board
+    path: PathBuf,
+
+    /// How far polygons may be from true curves when measuring spaces, in
+    /// mm
+    #[arg(long, default_value_t = 0.001)]
+    tolerance: f64,
+
+    /// Format of the report
+    #[arg(long, value_enum, default_value_t)]
+    format: Format,
+}
+
+fn main() -> anyhow::Result<ExitCode> {
     let cli = Cli::parse();
-    let src = read_to_string(cli.filename)?;
+    match cli.command {
+        Some(Command::Validate(args)) => validate(args),
+        Some(Command::VerifyMd5(args)) => verify_md5(args),
+        Some(Command::CompareDir(args)) => compare_dir(args),
+        Some(Command::QuoteInfo(args)) => quote_info(args),
+        None => dump(cli.dump),
+    }
+}
+
+fn dump(args: DumpArgs) -> anyhow::Result<ExitCode> {
+    let src = read_to_string(args.filename.unwrap_or_default())?;
     let layer = GerberLayer::parse(&src)?;
 
-    println!("{:?}", layer);
+    if args.explain {
+        explain::explain(&src, &layer);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.filter.is_empty() && !args.count_only {
+        println!("{:?}", layer);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let commands = layer.commands().iter().filter(|command| {
+        args.filter.is_empty()
+            || args
+                .filter
+                .iter()
+                .any(|code| code.eq_ignore_ascii_case(command.code()))
+    });
+    if args.count_only {
+        let mut counts = BTreeMap::new();
+        for command in commands {
+            *counts.entry(command.code()).or_insert(0) += 1;
+        }
+        if args.format != Format::Text {
+            print_row(args.format, &["code", "count"]);
+        }
+        for (code, count) in counts {
+            print_row(args.format, &[code, &count.to_string()]);
+        }
+    } else {
+        for command in commands {
+            println!("{:?}", command);
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn validate(args: ValidateArgs) -> anyhow::Result<ExitCode> {
+    let lints = match &args.lints {
+        Some(path) => Lints::from_config(&read_to_string(path)?)?,
+        None => Lints::default(),
+    };
+    let files = files::expand(&args.paths)?;
+    let reports = files::process(&files, |path| validate_file(path, &lints));
+
+    if args.format != Format::Text {
+        print_row(
+            args.format,
+            &["file", "line", "column", "severity", "message"],
+        );
+    }
+    let mut failed = false;
+    for (file, report) in files.iter().zip(&reports) {
+        let name = file.display().to_string();
+        for finding in &report.findings {
+            let (line, column) = match finding.location {
+                Some(location) => (location.line.to_string(), location.column.to_string()),
+                None => (String::new(), String::new()),
+            };
+            if args.format == Format::Text {
+                let location = match finding.location {
+                    Some(location) => format!("{name}:{location}"),
+                    None => name.clone(),
+                };
+                println!("{location}: {}: {}", finding.severity, finding.message);
+            } else {
+                let row = [&name, &line, &column, finding.severity, &finding.message];
+                print_row(args.format, &row);
+            }
+        }
+        failed |= report.failed(args.deny_warnings);
+    }
+    if files.len() > 1 && args.format == Format::Text {
+        print_summary(&files, &reports, args.deny_warnings);
+    }
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// The outcome of validating one file
+struct Report {
+    findings: Vec<Finding>,
+
+    /// Number of commands, or None if the file could not be parsed
+    commands: Option<usize>,
+
+    errors: usize,
+    warnings: usize,
+}
+
+/// A problem found in a file
+struct Finding {
+    location: Option<LineColumn>,
+
+    severity: &'static str,
+    message: String,
+}
+
+impl Report {
+    fn failed(&self, deny_warnings: bool) -> bool {
+        self.commands.is_none() || self.errors > 0 || (deny_warnings && self.warnings > 0)
+    }
+}
+
+fn validate_file(path: &Path, lints: &Lints) -> Report {
+    let mut report = Report {
+        findings: Vec::new(),
+        commands: None,
+        errors: 0,
+        warnings: 0,
+    };
+    let error = |message: String| Finding {
+        location: None,
+        severity: "error",
+        message,
+    };
+    let src = match read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => {
+            report.findings.push(error(e.to_string()));
+            return report;
+        }
+    };
+    let layer = match GerberLayer::parse(&src) {
+        Ok(layer) => layer,
+        Err(e) => {
+            report.findings.push(error(e.to_string()));
+            return report;
+        }
+    };
+
+    report.commands = Some(layer.commands().len());
+    let index = LineIndex::new(&src);
+    for diagnostic in lints.apply(layer.validate()) {
+        let severity = match diagnostic.severity {
+            Severity::Warning => {
+                report.warnings += 1;
+                "warning"
+            }
+            Severity::Error => {
+                report.errors += 1;
+                "error"
+            }
+        };
+        let location = diagnostic
+            .command
+            .and_then(|index| layer.span(index))
+            .map(|span| index.line_column(span.bytes.start));
+        report.findings.push(Finding {
+            location,
+            severity,
+            message: format!("{} [{}]", diagnostic.kind, diagnostic.kind.lint().id),
+        });
+    }
+    report
+}
+
+fn print_summary(files: &[PathBuf], reports: &[Report], deny_warnings: bool) {
+    let names: Vec<_> = files.iter().map(|f| f.display().to_string()).collect();
+    let width = names.iter().map(String::len).max().unwrap_or(0).max(4);
+    println!();
+    println!(
+        "{:width$}  {:>8}  {:>6}  {:>8}  status",
+        "file", "commands", "errors", "warnings"
+    );
+    for (name, report) in names.iter().zip(reports) {
+        let status = if report.failed(deny_warnings) {
+            "FAIL"
+        } else {
+            "ok"
+        };
+        match report.commands {
+            Some(commands) => println!(
+                "{name:width$}  {commands:>8}  {:>6}  {:>8}  {status}",
+                report.errors, report.warnings
+            ),
+            None => println!(
+                "{name:width$}  {:>8}  {:>6}  {:>8}  {status}",
+                "-", "-", "-"
+            ),
+        }
+    }
+}
+
+fn verify_md5(args: VerifyMd5Args) -> anyhow::Result<ExitCode> {
+    let files = files::expand(&args.paths)?;
+    let checks = files::process(&files, verify_md5_file);
+
+    if args.format != Format::Text {
+        print_row(args.format, &["file", "status", "attribute", "content"]);
+    }
+    let mut failed = false;
+    for (file, check) in files.iter().zip(&checks) {
+        let name = file.display().to_string();
+        let (status, attribute, content) = match check {
+            Md5Check::Match => ("ok", "", ""),
+            Md5Check::Mismatch { attribute, content } => {
+                failed = true;
+                ("mismatch", attribute.as_str(), content.as_str())
+            }
+            Md5Check::Missing => {
+                failed |= args.require;
+                ("missing", "", "")
+            }
+            Md5Check::Error(e) => {
+                failed = true;
+                ("error", e.as_str(), "")
+            }
+        };
+        if args.format != Format::Text {
+            print_row(args.format, &[&name, status, attribute, content]);
+            continue;
+        }
+        match check {
+            Md5Check::Match => println!("{name}: ok"),
+            Md5Check::Mismatch { attribute, content } => {
+                println!("{name}: mismatch: attribute is {attribute}, content is {content}")
+            }
+            Md5Check::Missing => println!("{name}: no .MD5 attribute"),
+            Md5Check::Error(e) => println!("{name}: error: {e}"),
+        }
+    }
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// The outcome of checking one file against its .MD5 attribute
+#[derive(PartialEq, Debug)]
+enum Md5Check {
+    Match,
+    Mismatch { attribute: String, content: String },
+    Missing,
+    Error(String),
+}
+
+fn verify_md5_file(path: &Path) -> Md5Check {
+    match read_to_string(path) {
+        Ok(src) => check_md5(&src),
+        Err(e) => Md5Check::Error(e.to_string()),
+    }
+}
+
+/// Compare the .MD5 attribute with the checksum of the file content
+///
+/// Following §5.6.13 of the specification the checksum covers everything
+/// before the .MD5 attribute, with CR and LF characters removed.
+fn check_md5(src: &str) -> Md5Check {
+    let layer = match GerberLayer::parse(src) {
+        Ok(layer) => layer,
+        Err(e) => return Md5Check::Error(e.to_string()),
+    };
+
+    let attribute =
+        layer
+            .commands()
+            .iter()
+            .enumerate()
+            .find_map(|(index, command)| match command {
+                AttributeOnFile(FileAttributeName::MD5, values) => Some((index, values)),
+                _ => None,
+            });
+    let Some((index, values)) = attribute else {
+        return Md5Check::Missing;
+    };
+    let attribute = values
+        .first()
+        .map(|value| value.unescape().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let end = layer.span(index).map_or(0, |span| span.bytes.start);
+    let content: Vec<u8> = src[..end]
+        .bytes()
+        .filter(|&b| b != b'\r' && b != b'\n')
+        .collect();
+    let content = format!("{:x}", md5::compute(content));
+
+    if attribute == content {
+        Md5Check::Match
+    } else {
+        Md5Check::Mismatch { attribute, content }
+    }
+}
+
+fn compare_dir(args: CompareDirArgs) -> anyhow::Result<ExitCode> {
+    let old = load_board(&args.old_dir)?;
+    let new = load_board(&args.new_dir)?;
+    let changes = compare_boards(&old, &new, args.tolerance);
+
+    if args.format != Format::Text {
+        print_row(
+            args.format,
+            &["layer", "old", "new", "status", "area", "difference"],
+        );
+    }
+    let width = changes
+        .iter()
+        .map(|(key, _)| key.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    if args.format == Format::Text {
+        println!(
+            "{:width$}  {:9}  {:>12}  difference",
+            "layer", "status", "area (mm²)"
+        );
+    }
+    let (mut changed, mut failed, mut total) = (0, false, 0.0);
+    for (key, change) in &changes {
+        let (old, new) = (old.get(*key), new.get(*key));
+        let (status, area, detail) = match &change {
+            LayerChange::Unchanged => ("unchanged", None, String::new()),
+            LayerChange::Changed { difference, area } => {
+                ("changed", Some(*area), difference.to_string())
+            }
+            LayerChange::Added => ("added", None, String::new()),
+            LayerChange::Removed => ("removed", None, String::new()),
+            LayerChange::Error(e) => ("error", None, e.clone()),
+        };
+        match change {
+            LayerChange::Unchanged => {}
+            LayerChange::Error(_) => failed = true,
+            _ => changed += 1,
+        }
+        total += area.unwrap_or(0.0);
+        let area = area.map_or(String::new(), |area| format!("{area:.3}"));
+        if args.format == Format::Text {
+            let line = format!("{key:width$}  {status:9}  {area:>12}  {detail}");
+            println!("{}", line.trim_end());
+        } else {
+            let name = |layer: Option<&BoardLayer>| {
+                layer.map_or(String::new(), |layer| layer.path.display().to_string())
+            };
+            let row = [key.as_str(), &name(old), &name(new), status, &area, &detail];
+            print_row(args.format, &row);
+        }
+    }
+    if args.format == Format::Text {
+        println!();
+        println!(
+            "{changed} of {} layers changed, {total:.3} mm² in total",
+            changes.len()
+        );
+    }
+
+    Ok(if failed || changed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// A file of a board directory
+struct BoardLayer {
+    path: PathBuf,
+    layer: Result<GerberLayer<'static>, String>,
+}
+
+/// How a layer differs between two revisions of a board
+enum LayerChange {
+    Unchanged,
+
+    /// The first differing object and the area covered by only one of the
+    /// revisions
+    Changed {
+        difference: Difference,
+        area: f64,
+    },
+
+    Added,
+    Removed,
+    Error(String),
+}
+
+/// The Gerber files of `dir`, keyed by their .FileFunction or, when they
+/// have none or share it with an earlier file, their path within `dir`
+fn load_board(dir: &Path) -> anyhow::Result<BTreeMap<String, BoardLayer>> {
+    if !dir.is_dir() {
+        anyhow::bail!("{} is not a directory", dir.display());
+    }
+    let files = files::expand(&[dir.display().to_string()])?;
+    let layers = files::process(&files, |path| {
+        let src = read_to_string(path).map_err(|e| e.to_string())?;
+        let layer = GerberLayer::parse(&src).map_err(|e| e.to_string())?;
+        Ok(layer.into_owned())
+    });
+
+    let mut board = BTreeMap::new();
+    for (path, layer) in files.into_iter().zip(layers) {
+        let function = layer.as_ref().ok().and_then(|layer: &GerberLayer| {
+            let values = layer.image().file_attributes.remove(".FileFunction")?;
+            Some(values.join(","))
+        });
+        let name = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        let key = match function {
+            Some(function) if !board.contains_key(&function) => function,
+            _ => name,
+        };
+        board.insert(key, BoardLayer { path, layer });
+    }
+    Ok(board)
+}
+
+/// The change of each layer of either board, paired by key, in key order
+fn compare_boards<'b>(
+    old: &'b BTreeMap<String, BoardLayer>,
+    new: &'b BTreeMap<String, BoardLayer>,
+    tolerance: f64,
+) -> Vec<(&'b String, LayerChange)> {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .map(|key| {
+            let change = compare_layers(old.get(key), new.get(key), tolerance);
+            (key, change)
+        })
+        .collect()
+}
+
+fn compare_layers(
+    old: Option<&BoardLayer>,
+    new: Option<&BoardLayer>,
+    tolerance: f64,
+) -> LayerChange {
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        (Some(_), None) => return LayerChange::Removed,
+        _ => return LayerChange::Added,
+    };
+    let (old, new) = match (&old.layer, &new.layer) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => return LayerChange::Error(e.clone()),
+    };
+    match old.difference(new, tolerance) {
+        None => LayerChange::Unchanged,
+        Some(difference) => {
+            let area = old
+                .copper(tolerance)
+                .symmetric_difference(&new.copper(tolerance))
+                .area();
+            LayerChange::Changed { difference, area }
+        }
+    }
+}
+
+fn quote_info(args: QuoteInfoArgs) -> anyhow::Result<ExitCode> {
+    let board = files::read_board(&args.path)?;
+    let (fabrication, smallest_gap) = quote(&board, args.tolerance)?;
+
+    let total = &fabrication.total;
+    if args.format != Format::Text {
+        let mm = |value: Option<f64>| value.map_or(String::new(), |value| format!("{value:.3}"));
+        let (width, height) = fabrication.board_size.unzip();
+        print_row(args.format, &["quantity", "value"]);
+        print_row(args.format, &["board width", &mm(width)]);
+        print_row(args.format, &["board height", &mm(height)]);
+        let layers = fabrication.copper_layers.to_string();
+        print_row(args.format, &["copper layers", &layers]);
+        print_row(args.format, &["smallest track", &mm(total.smallest_trace)]);
+        print_row(args.format, &["smallest space", &mm(smallest_gap)]);
+        print_row(args.format, &["smallest drill", &mm(total.smallest_drill)]);
+        print_row(args.format, &["holes", &total.holes().to_string()]);
+        print_row(args.format, &["slots", &total.slots.to_string()]);
+        for drill in &total.drills {
+            let name = format!("drill {:.3}", drill.diameter);
+            print_row(args.format, &[&name, &drill.count.to_string()]);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mm = |value: Option<f64>| value.map_or("-".to_string(), |value| format!("{value:.3} mm"));
+    let size = match fabrication.board_size {
+        Some((width, height)) => format!("{width:.3} x {height:.3} mm"),
+        None => "- (no profile layer)".to_string(),
+    };
+    println!("board size      {size}");
+    println!("copper layers   {}", fabrication.copper_layers);
+    println!("smallest track  {}", mm(total.smallest_trace));
+    println!("smallest space  {}", mm(smallest_gap));
+    println!("smallest drill  {}", mm(total.smallest_drill));
+    println!("holes           {} ({} slots)", total.holes(), total.slots);
+    for drill in &total.drills {
+        println!("  {:>9.3} mm  {:>6}", drill.diameter, drill.count);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The fabrication statistics of the files of a board, and the smallest
+/// space between copper
+fn quote(
+    board: &[(PathBuf, String)],
+    tolerance: f64,
+) -> anyhow::Result<(ProjectFabrication, Option<f64>)> {
+    let mut project = GerberProject::new();
+    for (path, src) in board {
+        let layer =
+            GerberLayer::parse(src).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        project.add(path.display().to_string(), layer);
+    }
+    let fabrication = project.fabrication();
+
+    // spaces are measured on the same layers as tracks
+    let smallest_gap = project
+        .layers
+        .iter()
+        .filter(|layer| {
+            let image = layer.layer.image();
+            let function = image.file_attributes.get(".FileFunction");
+            function.is_none_or(|values| values.first().is_some_and(|kind| &**kind == "Copper"))
+        })
+        .filter_map(|layer| layer.layer.copper(tolerance).smallest_gap())
+        .min_by(f64::total_cmp);
+    Ok((fabrication, smallest_gap))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "%FSLAX26Y26*%\n%MOMM*%\n";
+
+    /// The checksum of `content` as the .MD5 attribute gives it
+    fn md5(content: &str) -> String {
+        format!("{:x}", md5::compute(content))
+    }
+
+    #[test]
+    fn test_report_failed() {
+        let report = |commands, errors, warnings| Report {
+            findings: Vec::new(),
+            commands,
+            errors,
+            warnings,
+        };
+        for deny_warnings in [false, true] {
+            assert!(!report(Some(10), 0, 0).failed(deny_warnings));
+            assert!(report(Some(10), 1, 0).failed(deny_warnings));
+            assert!(report(Some(10), 1, 1).failed(deny_warnings));
+            // a file which can't be parsed fails whatever it found
+            assert!(report(None, 0, 0).failed(deny_warnings));
+        }
+        assert!(!report(Some(10), 0, 2).failed(false));
+        assert!(report(Some(10), 0, 2).failed(true));
+    }
+
+    /// A layer with a file function, or none if empty, and a pad at `x`
+    fn layer(function: &str, x: u32) -> String {
+        let function = match function {
+            "" => String::new(),
+            function => format!("%TF.FileFunction,{function}*%\n"),
+        };
+        format!("{HEADER}{function}%ADD10C,1*%\nD10*\nX{x}000000Y0D03*\nM02*\n")
+    }
+
+    fn write_board(files: &[(&str, String)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, src) in files {
+            std::fs::write(dir.path().join(name), src).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_compare_boards() {
+        let old = write_board(&[
+            ("top.gbr", layer("Copper,L1,Top", 0)),
+            ("bottom.gbr", layer("Copper,L2,Bot", 0)),
+            ("outline.gbr", layer("", 0)),
+            ("drawing.gbr", layer("", 0)),
+        ]);
+        let new = write_board(&[
+            // paired by function although renamed
+            ("copper_top.gbr", layer("Copper,L1,Top", 0)),
+            ("bottom.gbr", layer("Copper,L2,Bot", 1)),
+            ("outline.gbr", layer("", 0)),
+            ("assembly.gbr", layer("", 0)),
+        ]);
+        let (old, new) = (
+            load_board(old.path()).unwrap(),
+            load_board(new.path()).unwrap(),
+        );
+        let changes = compare_boards(&old, &new, 0.001);
+        let statuses: Vec<_> = changes
+            .iter()
+            .map(|(key, change)| {
+                let status = match change {
+                    LayerChange::Unchanged => "unchanged",
+                    LayerChange::Changed { .. } => "changed",
+                    LayerChange::Added => "added",
+                    LayerChange::Removed => "removed",
+                    LayerChange::Error(_) => "error",
+                };
+                (key.as_str(), status)
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("Copper,L1,Top", "unchanged"),
+                ("Copper,L2,Bot", "changed"),
+                ("assembly.gbr", "added"),
+                ("drawing.gbr", "removed"),
+                ("outline.gbr", "unchanged"),
+            ]
+        );
+        // two disks of 1 mm which don't overlap
+        let LayerChange::Changed { area, .. } = changes[1].1 else {
+            unreachable!()
+        };
+        assert!((area - std::f64::consts::PI / 2.0).abs() < 0.01, "{area}");
+    }
+
+    #[test]
+    fn test_quote() {
+        let profile = "%TF.FileFunction,Profile,NP*%\n%ADD10C,0.1*%\nD10*\nG01*\n\
+                       X0Y0D02*\nX10000000Y0D01*\nX10000000Y5000000D01*\n\
+                       X0Y5000000D01*\nX0Y0D01*\n";
+        // tracks 0.2 and 0.15 wide, 1 mm apart
+        let top = "%TF.FileFunction,Copper,L1,Top*%\n%ADD10C,0.2*%\n%ADD11C,0.15*%\n\
+                   G01*\nD10*\nX1000000Y1000000D02*\nX9000000Y1000000D01*\n\
+                   D11*\nX1000000Y2000000D02*\nX9000000Y2000000D01*\n";
+        let bottom = "%TF.FileFunction,Copper,L2,Bot*%\n%ADD10C,1*%\nD10*\n\
+                      X2000000Y3000000D03*\nX4000000Y3000000D03*\n";
+        let drill = "%TF.FileFunction,Plated,1,2,PTH*%\n%ADD10C,0.3*%\n%ADD11C,0.8*%\n\
+                     D10*\nX2000000Y3000000D03*\nX4000000Y3000000D03*\n\
+                     D11*\nX8000000Y4000000D03*\n";
+        let dir = write_board(
+            &[
+                ("board.gko", profile),
+                ("board.gtl", top),
+                ("board.gbl", bottom),
+                ("board-PTH.gbr", drill),
+            ]
+            .map(|(name, body)| (name, format!("{HEADER}{body}M02*\n"))),
+        );
+        let board = files::read_board(dir.path()).unwrap();
+        let (fabrication, smallest_gap) = quote(&board, 0.001).unwrap();
+
+        // the profile includes the width of its line
+        let (width, height) = fabrication.board_size.unwrap();
+        assert!((width - 10.1).abs() < 1e-9 && (height - 5.1).abs() < 1e-9);
+        assert_eq!(fabrication.copper_layers, 2);
+        let total = &fabrication.total;
+        assert_eq!(total.smallest_trace, Some(0.15));
+        let gap = smallest_gap.unwrap();
+        assert!((gap - 0.825).abs() < 0.001, "{gap}");
+        assert_eq!(total.smallest_drill, Some(0.3));
+        let drills: Vec<_> = total.drills.iter().map(|d| (d.diameter, d.count)).collect();
+        assert_eq!(drills, [(0.3, 2), (0.8, 1)]);
+        assert_eq!((total.holes(), total.slots), (3, 0));
+
+        // a file which isn't Gerber is named in the error
+        let broken = [(PathBuf::from("broken.gbr"), "X*".to_string())];
+        let error = quote(&broken, 0.001).unwrap_err().to_string();
+        assert!(error.starts_with("broken.gbr: "), "{error}");
+    }
+
+    #[test]
+    fn test_check_md5() {
+        let checksum = md5("%FSLAX26Y26*%%MOMM*%");
+        let src = format!("{HEADER}%TF.MD5,{checksum}*%\nM02*\n");
+        assert_eq!(check_md5(&src), Md5Check::Match);
+
+        // line breaks don't count, whatever they are, nor does the case
+        let crlf = src.replace('\n', "\r\n");
+        assert_eq!(check_md5(&crlf), Md5Check::Match);
+        let upper = format!("{HEADER}%TF.MD5,{}*%\nM02*\n", checksum.to_uppercase());
+        assert_eq!(check_md5(&upper), Md5Check::Match);
+
+        // the content before the attribute changed
+        let changed = src.replace("MOMM", "MOIN");
+        assert_eq!(
+            check_md5(&changed),
+            Md5Check::Mismatch {
+                attribute: checksum,
+                content: md5("%FSLAX26Y26*%%MOIN*%"),
+            }
+        );
 
-    Ok(())
+        assert_eq!(check_md5(&format!("{HEADER}M02*\n")), Md5Check::Missing);
+        assert!(matches!(
+            check_md5("%FSLAX26Y26*%\nX*\n"),
+            Md5Check::Error(_)
+        ));
+    }
 }
@@ -0,0 +1,79 @@
+//! Delimited output of reports for spreadsheets
+
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+
+/// Output format of reports
+#[derive(Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Format {
+    /// Human readable
+    #[default]
+    Text,
+
+    /// Comma separated values with a header row
+    Csv,
+
+    /// Tab separated values with a header row
+    Tsv,
+}
+
+/// Print one row of delimited values to stdout, see [write_row]
+pub fn print_row(format: Format, fields: &[&str]) {
+    write_row(&mut io::stdout().lock(), format, fields).expect("failed printing to stdout");
+}
+
+/// Write one row of delimited values, ending with a line break
+///
+/// CSV fields are quoted when they contain a delimiter, quote or line
+/// break. TSV has no quoting, so tabs and line breaks become spaces.
+pub fn write_row(out: &mut impl Write, format: Format, fields: &[&str]) -> io::Result<()> {
+    let row: Vec<_> = match format {
+        Format::Text | Format::Tsv => fields
+            .iter()
+            .map(|field| field.replace(['\t', '\r', '\n'], " "))
+            .collect(),
+        Format::Csv => fields
+            .iter()
+            .map(|field| {
+                if field.contains([',', '"', '\r', '\n']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.to_string()
+                }
+            })
+            .collect(),
+    };
+    let delimiter = if format == Format::Csv { "," } else { "\t" };
+    writeln!(out, "{}", row.join(delimiter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(format: Format, fields: &[&str]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_row(&mut out, format, fields).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(row(Format::Csv, &["a", "", "b c"]), b"a,,b c\n");
+        assert_eq!(
+            row(Format::Csv, &["1,5", "say \"hi\"", "two\nlines", "cr\r"]),
+            b"\"1,5\",\"say \"\"hi\"\"\",\"two\nlines\",\"cr\r\"\n"
+        );
+        assert_eq!(row(Format::Csv, &["tab\there"]), b"tab\there\n");
+    }
+
+    #[test]
+    fn test_tsv() {
+        assert_eq!(row(Format::Tsv, &["a", "", "b,c"]), b"a\t\tb,c\n");
+        assert_eq!(
+            row(Format::Tsv, &["tab\there", "two\r\nlines", "\"quoted\""]),
+            b"tab here\ttwo  lines\t\"quoted\"\n"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Python bindings for the [gerber] crate
+//!
+//! Build and install into the current virtualenv with `maturin develop`
+//! from this directory:
+//!
+//! ```python
+//! import gerber
+//!
+//! layer = gerber.parse(open("top_copper.gbr").read())
+//! print(layer.unit, layer.file_attributes.get(".FileFunction"))
+//! for code in layer:
+//!     print(code)
+//! for object in layer.objects():
+//!     print(object.kind, object.aperture, object.attributes.get(".N"))
+//! open("top_copper.svg", "w").write(layer.to_svg(0.01, (184, 115, 51, 255)))
+//! ```
+
+use std::collections::BTreeMap;
+
+use gerber::data::{Polarity, Unit};
+use gerber::image::{AttributeMap, Shape};
+use gerber::GerberLayer;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+/// A parsed Gerber layer
+#[pyclass(name = "Layer", module = "gerber", frozen)]
+pub struct Layer {
+    inner: GerberLayer<'static>,
+}
+
+#[pymethods]
+impl Layer {
+    /// Parse the source of a Gerber file, raising ValueError on invalid input
+    #[new]
+    fn new(src: &str) -> PyResult<Self> {
+        GerberLayer::parse(src)
+            .map(|inner| Layer {
+                inner: inner.into_owned(),
+            })
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// The codes of the commands in file order, e.g. `AD` or `D01`
+    fn commands(&self) -> Vec<&'static str> {
+        self.inner
+            .commands()
+            .iter()
+            .map(|command| command.code())
+            .collect()
+    }
+
+    /// `"mm"` or `"in"` as set by `%MO`, or None before it
+    #[getter]
+    fn unit(&self) -> Option<&'static str> {
+        self.inner.image().unit.map(|unit| match unit {
+            Unit::Millimeters => "mm",
+            Unit::Inches => "in",
+        })
+    }
+
+    /// The file attributes set by `%TF`, as a dict of lists of values
+    #[getter]
+    fn file_attributes(&self) -> BTreeMap<String, Vec<String>> {
+        attributes(&self.inner.image().file_attributes)
+    }
+
+    /// The graphical objects of the layer, in the order they were created
+    fn objects(&self) -> Vec<Object> {
+        let image = self.inner.image();
+        image.objects.iter().map(Object::new).collect()
+    }
+
+    /// The copper of the layer as an SVG document
+    ///
+    /// Arcs are flattened to within `tolerance` millimeters, and `color` is
+    /// the red, green, blue and alpha of the fill.
+    #[pyo3(signature = (tolerance = 0.01, color = (0, 0, 0, 255)))]
+    fn to_svg(&self, tolerance: f64, color: (u8, u8, u8, u8)) -> String {
+        let (red, green, blue, alpha) = color;
+        self.inner
+            .copper(tolerance)
+            .to_svg([red, green, blue, alpha])
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.commands().len()
+    }
+
+    fn __iter__(&self) -> CommandIter {
+        CommandIter {
+            codes: self.commands().into_iter(),
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<gerber.Layer with {} commands>",
+            self.inner.commands().len()
+        )
+    }
+}
+
+/// Iterator over the command codes of a [Layer]
+#[pyclass(module = "gerber")]
+pub struct CommandIter {
+    codes: std::vec::IntoIter<&'static str>,
+}
+
+#[pymethods]
+impl CommandIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<&'static str> {
+        slf.codes.next()
+    }
+}
+
+/// A graphical object of a [Layer], with coordinates in millimeters
+#[pyclass(name = "Object", module = "gerber", frozen, get_all)]
+pub struct Object {
+    /// `"draw"`, `"arc"`, `"flash"` or `"region"`
+    kind: &'static str,
+
+    /// `"dark"` or `"clear"`
+    polarity: &'static str,
+
+    /// The D code of a draw, arc or flash, e.g. `"D10"`
+    aperture: Option<String>,
+
+    /// The start and end of a draw or arc, the flash point, or the start
+    /// of each contour of a region
+    points: Vec<(f64, f64)>,
+
+    /// `(min_x, min_y, max_x, max_y)`, or None when the shape of a macro
+    /// aperture is unknown
+    bounds: Option<(f64, f64, f64, f64)>,
+
+    /// The object and aperture attributes, the object's taking precedence
+    attributes: BTreeMap<String, Vec<String>>,
+}
+
+impl Object {
+    fn new(object: &gerber::image::Object) -> Self {
+        let (kind, aperture, points) = match &object.shape {
+            Shape::Draw {
+                start,
+                end,
+                aperture,
+            } => ("draw", Some(aperture), vec![*start, *end]),
+            Shape::Arc {
+                start,
+                end,
+                aperture,
+                ..
+            } => ("arc", Some(aperture), vec![*start, *end]),
+            Shape::Flash { at, aperture } => ("flash", Some(aperture), vec![*at]),
+            Shape::Region { contours } => (
+                "region",
+                None,
+                contours.iter().map(|contour| contour.start).collect(),
+            ),
+        };
+        let mut merged = attributes(&object.attributes.aperture);
+        merged.extend(attributes(&object.attributes.object));
+        Object {
+            kind,
+            polarity: match object.polarity {
+                Polarity::Dark => "dark",
+                Polarity::Clear => "clear",
+            },
+            aperture: aperture.map(ToString::to_string),
+            points: points.iter().map(|point| (point.x, point.y)).collect(),
+            bounds: object
+                .bounds
+                .map(|bounds| (bounds.min.x, bounds.min.y, bounds.max.x, bounds.max.y)),
+            attributes: merged,
+        }
+    }
+}
+
+#[pymethods]
+impl Object {
+    fn __repr__(&self) -> String {
+        match &self.aperture {
+            Some(aperture) => format!("<gerber.Object {} {} {aperture}>", self.polarity, self.kind),
+            None => format!("<gerber.Object {} {}>", self.polarity, self.kind),
+        }
+    }
+}
+
+fn attributes(map: &AttributeMap) -> BTreeMap<String, Vec<String>> {
+    map.iter()
+        .map(|(name, values)| {
+            let values = values.iter().map(ToString::to_string).collect();
+            (name.to_string(), values)
+        })
+        .collect()
+}
+
+/// Parse the source of a Gerber file
+#[pyfunction]
+fn parse(src: &str) -> PyResult<Layer> {
+    Layer::new(src)
+}
+
+#[pymodule]
+#[pyo3(name = "gerber")]
+fn gerber_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Layer>()?;
+    m.add_class::<Object>()?;
+    m.add_function(wrap_pyfunction!(parse, m)?)?;
+    Ok(())
+}
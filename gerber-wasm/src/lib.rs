@@ -0,0 +1,75 @@
+//! JavaScript bindings for the [gerber] crate
+//!
+//! Build with `wasm-pack build gerber-wasm` to produce a package which can
+//! be imported by browser-based viewers:
+//!
+//! ```js
+//! import { Layer } from "gerber-wasm";
+//!
+//! const layer = new Layer(source);
+//! for (const command of layer.commands()) {
+//!     console.log(command);
+//! }
+//! document.body.innerHTML = layer.toSVG(0.01, [184, 115, 51, 255]);
+//! ```
+
+use gerber::GerberLayer;
+use wasm_bindgen::prelude::*;
+
+/// A parsed Gerber layer
+#[wasm_bindgen]
+pub struct Layer {
+    inner: GerberLayer<'static>,
+}
+
+#[wasm_bindgen]
+impl Layer {
+    /// Parse the source of a Gerber file, throwing on invalid input
+    #[wasm_bindgen(constructor)]
+    pub fn new(src: &str) -> Result<Layer, JsError> {
+        Ok(Layer {
+            inner: GerberLayer::parse(src)?.into_owned(),
+        })
+    }
+
+    /// Number of commands in the layer, including the terminating `M02`
+    #[wasm_bindgen(js_name = commandCount)]
+    pub fn command_count(&self) -> usize {
+        self.inner.commands().len()
+    }
+
+    /// The commands as an array of plain JavaScript values
+    pub fn commands(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.inner.commands())?)
+    }
+
+    /// The layer serialized as a JSON string
+    #[wasm_bindgen(js_name = toJSON)]
+    pub fn to_json(&self) -> Result<String, JsError> {
+        Ok(serde_json::to_string(&self.inner)?)
+    }
+
+    /// The copper of the layer as an SVG document, see `Copper::to_svg`
+    ///
+    /// Arcs are flattened to within `tolerance` millimeters, and `color` is
+    /// the red, green, blue and alpha of the fill.
+    #[wasm_bindgen(js_name = toSVG)]
+    pub fn to_svg(&self, tolerance: f64, color: &[u8]) -> Result<String, JsError> {
+        let color = color
+            .try_into()
+            .map_err(|_| JsError::new("color must have 4 components"))?;
+        Ok(self.inner.copper(tolerance).to_svg(color))
+    }
+}
+
+/// Parse the source of a Gerber file directly into JSON
+#[wasm_bindgen(js_name = parseToJSON)]
+pub fn parse_to_json(src: &str) -> Result<String, JsError> {
+    Layer::new(src)?.to_json()
+}
+
+/// Parse the source of a Gerber file directly into an SVG document
+#[wasm_bindgen(js_name = renderToSVG)]
+pub fn render_to_svg(src: &str, tolerance: f64, color: &[u8]) -> Result<String, JsError> {
+    Layer::new(src)?.to_svg(tolerance, color)
+}
@@ -0,0 +1,716 @@
+//! Aperture templates
+//!
+//! The four standard apertures are also plain shapes, [Circle],
+//! [Rectangle], [Obround] and [Polygon], which know their area, extent and
+//! outline through [ApertureShape]. The aperture dictionary, the
+//! copper conversion and the design rule checks share
+//! them, so an obround is rounded the same way everywhere.
+
+use std::f64::consts::{PI, TAU};
+use std::hash::{Hash, Hasher};
+
+use crate::data::SharedStr;
+use crate::image::{Bounds, Point};
+use crate::GerberError;
+
+/// The template and parameters of an aperture defined by `%AD`
+///
+/// Sizes are in the unit set by `%MO`.
+#[derive(Clone, PartialEq, PartialOrd, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ApertureTemplate<'a> {
+    /// Standard circle, `C`
+    Circle { diameter: f64, hole: Option<f64> },
+
+    /// Standard rectangle, `R`
+    Rectangle { x: f64, y: f64, hole: Option<f64> },
+
+    /// Standard obround, `O`
+    Obround { x: f64, y: f64, hole: Option<f64> },
+
+    /// Standard regular polygon, `P`
+    Polygon {
+        diameter: f64,
+        vertices: f64,
+        rotation: Option<f64>,
+        hole: Option<f64>,
+    },
+
+    /// An aperture macro defined by `%AM`
+    Macro {
+        name: SharedStr<'a>,
+        parameters: Vec<f64>,
+    },
+}
+
+impl ApertureTemplate<'_> {
+    /// True when the template creates no image, e.g. a zero-width rectangle
+    /// or a polygon of fewer than 3 vertices
+    ///
+    /// Macros are never considered degenerate here as their primitives are
+    /// not known from the definition alone, see
+    /// [ApertureMacro::is_degenerate](crate::macros::ApertureMacro::is_degenerate).
+    pub fn is_degenerate(&self) -> bool {
+        match *self {
+            Self::Circle { diameter, .. } => diameter == 0.0,
+            Self::Rectangle { x, y, .. } | Self::Obround { x, y, .. } => x == 0.0 || y == 0.0,
+            Self::Polygon { diameter, .. } => diameter == 0.0 || self.standard().is_err(),
+            Self::Macro { .. } => false,
+        }
+    }
+
+    /// The standard aperture as a shape, or `None` for a macro
+    ///
+    /// Returns [GerberError::PolygonVertices] for a polygon whose number of
+    /// vertices is not an integer from 3 to 12.
+    pub fn standard(&self) -> Result<Option<StandardAperture>, GerberError> {
+        Ok(Some(match *self {
+            Self::Circle { diameter, hole } => Circle { diameter, hole }.into(),
+            Self::Rectangle { x, y, hole } => Rectangle { x, y, hole }.into(),
+            Self::Obround { x, y, hole } => Obround { x, y, hole }.into(),
+            Self::Polygon {
+                diameter,
+                vertices,
+                rotation,
+                hole,
+            } => {
+                if !(3.0..=12.0).contains(&vertices) || vertices.fract() != 0.0 {
+                    return Err(GerberError::PolygonVertices(vertices));
+                }
+                Polygon {
+                    diameter,
+                    vertices: vertices as u32,
+                    rotation: rotation.unwrap_or(0.0),
+                    hole,
+                }
+                .into()
+            }
+            Self::Macro { .. } => return Ok(None),
+        }))
+    }
+
+    /// The area of the aperture less its hole, or `None` for a macro or an
+    /// invalid polygon
+    pub fn area(&self) -> Option<f64> {
+        Some(self.standard().ok()??.area())
+    }
+
+    /// The smallest width of the aperture, or `None` for a macro or an
+    /// invalid polygon
+    ///
+    /// For a polygon this is the diameter of its inscribed circle.
+    pub fn min_dimension(&self) -> Option<f64> {
+        Some(self.standard().ok()??.min_dimension())
+    }
+
+    /// Convert into a template which does not borrow from the source
+    pub fn into_owned(self) -> ApertureTemplate<'static> {
+        match self {
+            Self::Circle { diameter, hole } => ApertureTemplate::Circle { diameter, hole },
+            Self::Rectangle { x, y, hole } => ApertureTemplate::Rectangle { x, y, hole },
+            Self::Obround { x, y, hole } => ApertureTemplate::Obround { x, y, hole },
+            Self::Polygon {
+                diameter,
+                vertices,
+                rotation,
+                hole,
+            } => ApertureTemplate::Polygon {
+                diameter,
+                vertices,
+                rotation,
+                hole,
+            },
+            Self::Macro { name, parameters } => ApertureTemplate::Macro {
+                name: name.into_owned(),
+                parameters,
+            },
+        }
+    }
+}
+
+/// Hashes the bit patterns of the parameters, so commands remain hashable
+impl Hash for ApertureTemplate<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        fn decimal<H: Hasher>(x: Option<f64>, state: &mut H) {
+            // 0.0 and -0.0 compare equal, so must hash equal
+            x.map(|x| if x == 0.0 { 0.0f64 } else { x }.to_bits())
+                .hash(state)
+        }
+
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Circle { diameter, hole } => {
+                decimal(Some(*diameter), state);
+                decimal(*hole, state);
+            }
+            Self::Rectangle { x, y, hole } | Self::Obround { x, y, hole } => {
+                decimal(Some(*x), state);
+                decimal(Some(*y), state);
+                decimal(*hole, state);
+            }
+            Self::Polygon {
+                diameter,
+                vertices,
+                rotation,
+                hole,
+            } => {
+                decimal(Some(*diameter), state);
+                decimal(Some(*vertices), state);
+                decimal(*rotation, state);
+                decimal(*hole, state);
+            }
+            Self::Macro { name, parameters } => {
+                name.hash(state);
+                parameters.iter().for_each(|&p| decimal(Some(p), state));
+            }
+        }
+    }
+}
+
+/// The geometry of a standard aperture, centered on the origin
+///
+/// Sizes are in the unit of the file, as are the outlines, which run
+/// counter-clockwise before any `%LM`, `%LR` or `%LS` transformation.
+pub trait ApertureShape {
+    /// The diameter of the round hole in the center, if any
+    fn hole(&self) -> Option<f64>;
+
+    /// The area of the shape less its hole
+    fn area(&self) -> f64;
+
+    /// The smallest box around the shape
+    fn bounds(&self) -> Bounds;
+
+    /// The smallest width of the shape
+    fn min_dimension(&self) -> f64;
+
+    /// A polygon within `tolerance` of the shape, curves placed by
+    /// `placement`
+    fn outline(&self, tolerance: f64, placement: Placement) -> Vec<Point>;
+
+    /// A polygon within `tolerance` of the hole, clockwise, or `None`
+    /// without a hole
+    ///
+    /// Whatever the placement, the hole is polygonized as the convex curve
+    /// of a circle, the same as a circle aperture.
+    fn hole_outline(&self, tolerance: f64, placement: Placement) -> Option<Vec<Point>> {
+        let radius = self.hole()? / 2.0;
+        let mut hole = points(circle(radius, tolerance, placement));
+        hole.reverse();
+        Some(hole)
+    }
+}
+
+/// Standard circle aperture, `C`
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Circle {
+    pub diameter: f64,
+    pub hole: Option<f64>,
+}
+
+/// Standard rectangle aperture, `R`, `x` wide and `y` high
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Rectangle {
+    pub x: f64,
+    pub y: f64,
+    pub hole: Option<f64>,
+}
+
+/// Standard obround aperture, `O`: a rectangle whose shorter sides are
+/// semicircles
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Obround {
+    pub x: f64,
+    pub y: f64,
+    pub hole: Option<f64>,
+}
+
+/// Standard regular polygon aperture, `P`
+///
+/// The first vertex is at `rotation` degrees counter-clockwise from the
+/// positive X axis, on the circle of `diameter`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Polygon {
+    pub diameter: f64,
+
+    /// From 3 to 12, as checked by [ApertureTemplate::standard]
+    #[cfg_attr(
+        feature = "arbitrary",
+        arbitrary(with = |u: &mut arbitrary::Unstructured| u.int_in_range(3..=12))
+    )]
+    pub vertices: u32,
+    pub rotation: f64,
+    pub hole: Option<f64>,
+}
+
+/// The area of a circle of `diameter`, zero for `None`
+fn disk_area(diameter: Option<f64>) -> f64 {
+    diameter.map_or(0.0, |diameter| PI * diameter * diameter / 4.0)
+}
+
+/// A box centered on the origin
+fn centered_bounds(x: f64, y: f64) -> Bounds {
+    Bounds {
+        min: Point {
+            x: -x / 2.0,
+            y: -y / 2.0,
+        },
+        max: Point {
+            x: x / 2.0,
+            y: y / 2.0,
+        },
+    }
+}
+
+impl ApertureShape for Circle {
+    fn hole(&self) -> Option<f64> {
+        self.hole
+    }
+
+    fn area(&self) -> f64 {
+        disk_area(Some(self.diameter)) - disk_area(self.hole)
+    }
+
+    fn bounds(&self) -> Bounds {
+        centered_bounds(self.diameter, self.diameter)
+    }
+
+    fn min_dimension(&self) -> f64 {
+        self.diameter
+    }
+
+    fn outline(&self, tolerance: f64, placement: Placement) -> Vec<Point> {
+        points(circle(self.diameter / 2.0, tolerance, placement))
+    }
+}
+
+impl ApertureShape for Rectangle {
+    fn hole(&self) -> Option<f64> {
+        self.hole
+    }
+
+    fn area(&self) -> f64 {
+        self.x * self.y - disk_area(self.hole)
+    }
+
+    fn bounds(&self) -> Bounds {
+        centered_bounds(self.x, self.y)
+    }
+
+    fn min_dimension(&self) -> f64 {
+        self.x.min(self.y)
+    }
+
+    fn outline(&self, _tolerance: f64, _placement: Placement) -> Vec<Point> {
+        let (x, y) = (self.x / 2.0, self.y / 2.0);
+        points(vec![[-x, -y], [x, -y], [x, y], [-x, y]])
+    }
+}
+
+impl ApertureShape for Obround {
+    fn hole(&self) -> Option<f64> {
+        self.hole
+    }
+
+    fn area(&self) -> f64 {
+        // a rectangle with the corners rounded off
+        let radius = self.x.min(self.y) / 2.0;
+        self.x * self.y - (4.0 - PI) * radius * radius - disk_area(self.hole)
+    }
+
+    fn bounds(&self) -> Bounds {
+        centered_bounds(self.x, self.y)
+    }
+
+    fn min_dimension(&self) -> f64 {
+        self.x.min(self.y)
+    }
+
+    fn outline(&self, tolerance: f64, placement: Placement) -> Vec<Point> {
+        let Obround { x, y, .. } = *self;
+        let radius = x.min(y) / 2.0;
+        // centers of the two rounded ends
+        let (dx, dy) = if x > y {
+            (x / 2.0 - radius, 0.0)
+        } else {
+            (0.0, y / 2.0 - radius)
+        };
+        let start = if x > y { -PI / 2.0 } else { 0.0 };
+        let end = |sign: f64, offset: f64| {
+            let center = [sign * dx, sign * dy];
+            curve(
+                center,
+                radius,
+                start + offset,
+                PI,
+                true,
+                tolerance,
+                placement,
+            )
+        };
+        points([end(1.0, 0.0), end(-1.0, PI)].concat())
+    }
+}
+
+impl Polygon {
+    /// The vertices, counter-clockwise from the first
+    pub fn vertices(&self) -> Vec<Point> {
+        let (radius, vertices) = (self.diameter / 2.0, self.vertices);
+        let rotation = self.rotation.to_radians();
+        (0..vertices)
+            .map(|i| {
+                let angle = rotation + TAU * i as f64 / vertices as f64;
+                Point {
+                    x: radius * angle.cos(),
+                    y: radius * angle.sin(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl ApertureShape for Polygon {
+    fn hole(&self) -> Option<f64> {
+        self.hole
+    }
+
+    fn area(&self) -> f64 {
+        let (radius, vertices) = (self.diameter / 2.0, self.vertices as f64);
+        vertices / 2.0 * radius * radius * (TAU / vertices).sin() - disk_area(self.hole)
+    }
+
+    fn bounds(&self) -> Bounds {
+        let vertices = self.vertices();
+        let Some(&first) = vertices.first() else {
+            return Bounds::default();
+        };
+        vertices.iter().fold(
+            Bounds {
+                min: first,
+                max: first,
+            },
+            |bounds, point| Bounds {
+                min: Point {
+                    x: bounds.min.x.min(point.x),
+                    y: bounds.min.y.min(point.y),
+                },
+                max: Point {
+                    x: bounds.max.x.max(point.x),
+                    y: bounds.max.y.max(point.y),
+                },
+            },
+        )
+    }
+
+    fn min_dimension(&self) -> f64 {
+        self.diameter * (PI / self.vertices as f64).cos()
+    }
+
+    fn outline(&self, _tolerance: f64, _placement: Placement) -> Vec<Point> {
+        self.vertices()
+    }
+}
+
+/// Any of the standard apertures
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum StandardAperture {
+    Circle(Circle),
+    Rectangle(Rectangle),
+    Obround(Obround),
+    Polygon(Polygon),
+}
+
+impl StandardAperture {
+    fn shape(&self) -> &dyn ApertureShape {
+        match self {
+            Self::Circle(shape) => shape,
+            Self::Rectangle(shape) => shape,
+            Self::Obround(shape) => shape,
+            Self::Polygon(shape) => shape,
+        }
+    }
+}
+
+impl ApertureShape for StandardAperture {
+    fn hole(&self) -> Option<f64> {
+        self.shape().hole()
+    }
+
+    fn area(&self) -> f64 {
+        self.shape().area()
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.shape().bounds()
+    }
+
+    fn min_dimension(&self) -> f64 {
+        self.shape().min_dimension()
+    }
+
+    fn outline(&self, tolerance: f64, placement: Placement) -> Vec<Point> {
+        self.shape().outline(tolerance, placement)
+    }
+}
+
+impl From<Circle> for StandardAperture {
+    fn from(shape: Circle) -> Self {
+        Self::Circle(shape)
+    }
+}
+
+impl From<Rectangle> for StandardAperture {
+    fn from(shape: Rectangle) -> Self {
+        Self::Rectangle(shape)
+    }
+}
+
+impl From<Obround> for StandardAperture {
+    fn from(shape: Obround) -> Self {
+        Self::Obround(shape)
+    }
+}
+
+impl From<Polygon> for StandardAperture {
+    fn from(shape: Polygon) -> Self {
+        Self::Polygon(shape)
+    }
+}
+
+/// The template of an `%AD` command for the shape, a polygon without
+/// rotation written without one
+impl From<StandardAperture> for ApertureTemplate<'_> {
+    fn from(shape: StandardAperture) -> Self {
+        match shape {
+            StandardAperture::Circle(Circle { diameter, hole }) => Self::Circle { diameter, hole },
+            StandardAperture::Rectangle(Rectangle { x, y, hole }) => Self::Rectangle { x, y, hole },
+            StandardAperture::Obround(Obround { x, y, hole }) => Self::Obround { x, y, hole },
+            StandardAperture::Polygon(Polygon {
+                diameter,
+                vertices,
+                rotation,
+                hole,
+            }) => Self::Polygon {
+                diameter,
+                vertices: vertices as f64,
+                rotation: (rotation != 0.0).then_some(rotation),
+                hole,
+            },
+        }
+    }
+}
+
+/// Where the polygon approximating a curved edge lies
+///
+/// Applies to circles, obround ends, the round caps and joins of circle
+/// apertures, and the sides of arcs drawn with them. Arcs in region
+/// contours always have their vertices on the curve.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Placement {
+    /// Polygons within the true shape, with the vertices of convex edges on
+    /// the curve
+    #[default]
+    Inscribed,
+
+    /// Polygons covering the true shape, as for clearance checks, with the
+    /// edges of convex curves touching them
+    Circumscribed,
+
+    /// Halfway between, so the error is split evenly and areas are closest
+    Balanced,
+}
+
+impl Placement {
+    /// The radius at which to place the vertices of a curve of `radius`
+    /// divided into steps of `step` radians
+    ///
+    /// The chords of a concave edge, such as the inside of an arc, fall
+    /// outside the shape rather than inside it, so the sides swap.
+    pub(crate) fn vertex_radius(self, radius: f64, step: f64, convex: bool) -> f64 {
+        let cos = (step / 2.0).cos();
+        match (self, convex) {
+            (Placement::Inscribed, true) | (Placement::Circumscribed, false) => radius,
+            (Placement::Inscribed, false) | (Placement::Circumscribed, true) => radius / cos,
+            (Placement::Balanced, _) => 2.0 * radius / (1.0 + cos),
+        }
+    }
+}
+
+/// The number of segments approximating `angle` radians of a circle
+/// within `tolerance`
+pub(crate) fn segments(radius: f64, angle: f64, tolerance: f64) -> usize {
+    if radius <= tolerance {
+        return 4;
+    }
+    let step = 2.0 * (1.0 - tolerance / radius).acos();
+    ((angle / step).ceil() as usize).clamp(4, 4096)
+}
+
+/// A polygon approximating a circle around the origin, starting at angle 0
+pub(crate) fn circle(radius: f64, tolerance: f64, placement: Placement) -> Vec<[f64; 2]> {
+    let segments = segments(radius, TAU, tolerance);
+    let step = TAU / segments as f64;
+    let radius = placement.vertex_radius(radius, step, true);
+    (0..segments)
+        .map(|i| {
+            let angle = step * i as f64;
+            [radius * angle.cos(), radius * angle.sin()]
+        })
+        .collect()
+}
+
+/// Points along `sweep` radians of a circle from `angle`, including
+/// both ends, placed on the [Placement] side of the curve
+///
+/// The ends stay on the curve so neighbouring edges meet it.
+pub(crate) fn curve(
+    [x, y]: [f64; 2],
+    radius: f64,
+    angle: f64,
+    sweep: f64,
+    convex: bool,
+    tolerance: f64,
+    placement: Placement,
+) -> Vec<[f64; 2]> {
+    let segments = segments(radius, sweep.abs(), tolerance);
+    let step = sweep / segments as f64;
+    let vertex = placement.vertex_radius(radius, step.abs(), convex);
+    (0..=segments)
+        .map(|i| {
+            let radius = if i == 0 || i == segments {
+                radius
+            } else {
+                vertex
+            };
+            let angle = angle + step * i as f64;
+            [x + radius * angle.cos(), y + radius * angle.sin()]
+        })
+        .collect()
+}
+
+fn points(path: Vec<[f64; 2]>) -> Vec<Point> {
+    path.into_iter().map(|[x, y]| Point { x, y }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    /// The area of a ring, negative when it runs clockwise
+    fn signed_area(ring: &[Point]) -> f64 {
+        ring.iter()
+            .zip(ring.iter().cycle().skip(1))
+            .map(|(p, q)| p.x * q.y - q.x * p.y)
+            .sum::<f64>()
+            / 2.0
+    }
+
+    #[test]
+    fn test_shapes() {
+        let obround = Obround {
+            x: 3.0,
+            y: 1.0,
+            hole: Some(0.5),
+        };
+        assert_close(obround.area(), 2.0 + PI / 4.0 - PI / 16.0);
+        assert_eq!(obround.bounds(), centered_bounds(3.0, 1.0));
+        assert_eq!(obround.min_dimension(), 1.0);
+
+        let outline = obround.outline(0.0001, Placement::Inscribed);
+        assert!(signed_area(&outline) > 0.0);
+        assert!((signed_area(&outline) - (2.0 + PI / 4.0)).abs() < 1e-3);
+        assert!(outline
+            .iter()
+            .all(|&point| obround.bounds().contains(point)));
+        let hole = obround.hole_outline(0.0001, Placement::Inscribed).unwrap();
+        assert!((signed_area(&hole) + PI / 16.0).abs() < 1e-3);
+
+        // a square standing on a corner
+        let diamond = Polygon {
+            diameter: 2.0,
+            vertices: 4,
+            rotation: 90.0,
+            hole: None,
+        };
+        assert_close(diamond.area(), 2.0);
+        assert_close(diamond.bounds().width(), 2.0);
+        assert_close(diamond.min_dimension(), 2f64.sqrt());
+        assert_close(diamond.outline(0.1, Placement::Inscribed)[0].y, 1.0);
+        assert!(diamond.hole_outline(0.1, Placement::Inscribed).is_none());
+
+        let circle = Circle {
+            diameter: 1.0,
+            hole: None,
+        };
+        let outline = |placement| signed_area(&circle.outline(0.01, placement));
+        assert!(outline(Placement::Inscribed) < PI / 4.0);
+        assert!(outline(Placement::Circumscribed) > PI / 4.0);
+    }
+
+    #[test]
+    fn test_standard() {
+        let template = ApertureTemplate::Polygon {
+            diameter: 1.0,
+            vertices: 6.0,
+            rotation: None,
+            hole: Some(0.2),
+        };
+        let shape = template.standard().unwrap().unwrap();
+        assert_eq!(
+            shape,
+            StandardAperture::Polygon(Polygon {
+                diameter: 1.0,
+                vertices: 6,
+                rotation: 0.0,
+                hole: Some(0.2),
+            })
+        );
+        assert_eq!(ApertureTemplate::from(shape), template);
+        assert_eq!(template.area(), Some(shape.area()));
+
+        let rectangle = ApertureTemplate::Rectangle {
+            x: 2.0,
+            y: 1.0,
+            hole: None,
+        };
+        assert_eq!(rectangle.area(), Some(2.0));
+        assert_eq!(rectangle.min_dimension(), Some(1.0));
+        let name = "THERMAL".into();
+        let parameters = Vec::new();
+        assert!(ApertureTemplate::Macro { name, parameters }
+            .standard()
+            .unwrap()
+            .is_none());
+
+        for vertices in [0.0, 2.0, 2.5, -3.0, 13.0, 4e9, f64::NAN] {
+            let polygon = ApertureTemplate::Polygon {
+                diameter: 1.0,
+                vertices,
+                rotation: None,
+                hole: None,
+            };
+            assert!(
+                matches!(polygon.standard(), Err(GerberError::PolygonVertices(_))),
+                "{vertices}"
+            );
+            assert_eq!(polygon.area(), None);
+        }
+    }
+}
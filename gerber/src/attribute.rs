@@ -2,9 +2,13 @@ use nom::bytes::complete::tag;
 use nom::combinator::value;
 use nom::{branch::alt, combinator::map};
 
-use crate::{system_name, user_name, IResult};
+use crate::data::SharedStr;
+use crate::grammar::{system_name, user_name};
+use crate::{GerberError, IResult};
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum FileAttributeName<'a> {
     Part,
     FileFunction,
@@ -14,12 +18,12 @@ pub enum FileAttributeName<'a> {
     GenerationSoftware,
     ProjectId,
     MD5,
-    UnknownStandardName(&'a str),
-    UserDefinedName(&'a str),
+    UnknownStandardName(SharedStr<'a>),
+    UserDefinedName(SharedStr<'a>),
 }
 
 impl<'a> FileAttributeName<'a> {
-    pub(crate) fn parse(input: &'a str) -> IResult<Self> {
+    pub(crate) fn parse(input: &'a str) -> IResult<'a, Self> {
         alt((
             value(Self::Part, tag(".Part")),
             value(Self::FileFunction, tag(".FileFunction")),
@@ -29,8 +33,321 @@ impl<'a> FileAttributeName<'a> {
             value(Self::GenerationSoftware, tag(".GenerationSoftware")),
             value(Self::ProjectId, tag(".ProjectId")),
             value(Self::MD5, tag(".MD5")),
-            map(system_name, |s| Self::UnknownStandardName(s)),
-            map(user_name, |s| Self::UserDefinedName(s)),
+            map(system_name, |s| Self::UnknownStandardName(s.into())),
+            map(user_name, |s| Self::UserDefinedName(s.into())),
         ))(input)
     }
+
+    /// The name as written in the file, e.g. `.FileFunction`
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Part => ".Part",
+            Self::FileFunction => ".FileFunction",
+            Self::FilePolarity => ".FilePolarity",
+            Self::SameCoordinates => ".SameCoordinates",
+            Self::CreationDate => ".CreationDate",
+            Self::GenerationSoftware => ".GenerationSoftware",
+            Self::ProjectId => ".ProjectId",
+            Self::MD5 => ".MD5",
+            Self::UnknownStandardName(s) | Self::UserDefinedName(s) => s,
+        }
+    }
+
+    /// Convert into a name which does not borrow from the source
+    pub fn into_owned(self) -> FileAttributeName<'static> {
+        match self {
+            Self::Part => FileAttributeName::Part,
+            Self::FileFunction => FileAttributeName::FileFunction,
+            Self::FilePolarity => FileAttributeName::FilePolarity,
+            Self::SameCoordinates => FileAttributeName::SameCoordinates,
+            Self::CreationDate => FileAttributeName::CreationDate,
+            Self::GenerationSoftware => FileAttributeName::GenerationSoftware,
+            Self::ProjectId => FileAttributeName::ProjectId,
+            Self::MD5 => FileAttributeName::MD5,
+            Self::UnknownStandardName(s) => FileAttributeName::UnknownStandardName(s.into_owned()),
+            Self::UserDefinedName(s) => FileAttributeName::UserDefinedName(s.into_owned()),
+        }
+    }
+}
+
+/// The value of `.ProjectId`, the project a file belongs to
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProjectId {
+    pub name: String,
+
+    /// An RFC 4122 GUID, e.g. `8b3a8e1c-2f70-4c2a-9c3d-5e9f0a1b2c3d`
+    pub guid: String,
+
+    pub revision: String,
+}
+
+impl ProjectId {
+    /// Split the unescaped fields of a `.ProjectId` value into its parts
+    ///
+    /// Returns [GerberError::Attribute] unless there are exactly three
+    /// fields, the name and revision aren't empty, and the GUID is in the
+    /// 8-4-4-4-12 hexadecimal form.
+    ///
+    /// ```
+    /// use gerber::attribute::ProjectId;
+    ///
+    /// let id = ProjectId::from_fields(&["Widget", "8b3a8e1c-2f70-4c2a-9c3d-5e9f0a1b2c3d", "B"]);
+    /// assert_eq!(id.unwrap().revision, "B");
+    /// assert!(ProjectId::from_fields(&["Widget", "1234", "B"]).is_err());
+    /// ```
+    pub fn from_fields<S: AsRef<str>>(fields: &[S]) -> Result<Self, GerberError> {
+        let fields: Vec<&str> = fields.iter().map(AsRef::as_ref).collect();
+        match fields[..] {
+            [name, guid, revision] if !name.is_empty() && is_guid(guid) && !revision.is_empty() => {
+                Ok(ProjectId {
+                    name: name.to_string(),
+                    guid: guid.to_string(),
+                    revision: revision.to_string(),
+                })
+            }
+            _ => Err(GerberError::Attribute(
+                ".ProjectId".to_string(),
+                fields.join(","),
+            )),
+        }
+    }
+}
+
+/// True for a GUID in the 8-4-4-4-12 hexadecimal form of RFC 4122
+pub(crate) fn is_guid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, length)| {
+            group.len() == length && group.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+}
+
+/// Names of the attributes attached to apertures by `%TA`
+#[derive(Clone, PartialEq, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ApertureAttributeName<'a> {
+    AperFunction,
+    DrillTolerance,
+    FlashText,
+    UnknownStandardName(SharedStr<'a>),
+    UserDefinedName(SharedStr<'a>),
+}
+
+impl<'a> ApertureAttributeName<'a> {
+    pub(crate) fn parse(input: &'a str) -> IResult<'a, Self> {
+        // match whole names so a standard name can't match the prefix of a longer one
+        alt((
+            map(system_name, |s| match s {
+                ".AperFunction" => Self::AperFunction,
+                ".DrillTolerance" => Self::DrillTolerance,
+                ".FlashText" => Self::FlashText,
+                _ => Self::UnknownStandardName(s.into()),
+            }),
+            map(user_name, |s| Self::UserDefinedName(s.into())),
+        ))(input)
+    }
+
+    /// The name as written in the file, e.g. `.AperFunction`
+    pub fn name(&self) -> &str {
+        match self {
+            Self::AperFunction => ".AperFunction",
+            Self::DrillTolerance => ".DrillTolerance",
+            Self::FlashText => ".FlashText",
+            Self::UnknownStandardName(s) | Self::UserDefinedName(s) => s,
+        }
+    }
+
+    /// Convert into a name which does not borrow from the source
+    pub fn into_owned(self) -> ApertureAttributeName<'static> {
+        match self {
+            Self::AperFunction => ApertureAttributeName::AperFunction,
+            Self::DrillTolerance => ApertureAttributeName::DrillTolerance,
+            Self::FlashText => ApertureAttributeName::FlashText,
+            Self::UnknownStandardName(s) => {
+                ApertureAttributeName::UnknownStandardName(s.into_owned())
+            }
+            Self::UserDefinedName(s) => ApertureAttributeName::UserDefinedName(s.into_owned()),
+        }
+    }
+}
+
+/// Names of the attributes attached to graphical objects by `%TO`
+///
+/// The `C*` names carry component information and were introduced with
+/// Gerber X3.
+#[derive(Clone, PartialEq, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ObjectAttributeName<'a> {
+    /// Net name
+    N,
+    /// Component pin
+    P,
+    /// Component reference designator
+    C,
+    /// Component rotation
+    CRot,
+    /// Component manufacturer
+    CMfr,
+    /// Component manufacturer part number
+    CMPN,
+    /// Component value
+    CVal,
+    /// Component mount type
+    CMnt,
+    /// Component footprint name
+    CFtp,
+    /// Component package name
+    CPgN,
+    /// Component package description
+    CPgD,
+    /// Component height
+    CHgt,
+    /// Component library name
+    CLbN,
+    /// Component library description
+    CLbD,
+    /// Component supplier
+    CSup,
+    UnknownStandardName(SharedStr<'a>),
+    UserDefinedName(SharedStr<'a>),
+}
+
+impl<'a> ObjectAttributeName<'a> {
+    pub(crate) fn parse(input: &'a str) -> IResult<'a, Self> {
+        // match whole names, otherwise ".C" would match the start of ".CRot"
+        alt((
+            map(system_name, |s| match s {
+                ".N" => Self::N,
+                ".P" => Self::P,
+                ".C" => Self::C,
+                ".CRot" => Self::CRot,
+                ".CMfr" => Self::CMfr,
+                ".CMPN" => Self::CMPN,
+                ".CVal" => Self::CVal,
+                ".CMnt" => Self::CMnt,
+                ".CFtp" => Self::CFtp,
+                ".CPgN" => Self::CPgN,
+                ".CPgD" => Self::CPgD,
+                ".CHgt" => Self::CHgt,
+                ".CLbN" => Self::CLbN,
+                ".CLbD" => Self::CLbD,
+                ".CSup" => Self::CSup,
+                _ => Self::UnknownStandardName(s.into()),
+            }),
+            map(user_name, |s| Self::UserDefinedName(s.into())),
+        ))(input)
+    }
+
+    /// True for the component attributes added by Gerber X3
+    pub fn is_component(&self) -> bool {
+        matches!(
+            self,
+            Self::C
+                | Self::CRot
+                | Self::CMfr
+                | Self::CMPN
+                | Self::CVal
+                | Self::CMnt
+                | Self::CFtp
+                | Self::CPgN
+                | Self::CPgD
+                | Self::CHgt
+                | Self::CLbN
+                | Self::CLbD
+                | Self::CSup
+        )
+    }
+
+    /// The name as written in the file, e.g. `.CRot`
+    pub fn name(&self) -> &str {
+        match self {
+            Self::N => ".N",
+            Self::P => ".P",
+            Self::C => ".C",
+            Self::CRot => ".CRot",
+            Self::CMfr => ".CMfr",
+            Self::CMPN => ".CMPN",
+            Self::CVal => ".CVal",
+            Self::CMnt => ".CMnt",
+            Self::CFtp => ".CFtp",
+            Self::CPgN => ".CPgN",
+            Self::CPgD => ".CPgD",
+            Self::CHgt => ".CHgt",
+            Self::CLbN => ".CLbN",
+            Self::CLbD => ".CLbD",
+            Self::CSup => ".CSup",
+            Self::UnknownStandardName(s) | Self::UserDefinedName(s) => s,
+        }
+    }
+
+    /// Convert into a name which does not borrow from the source
+    pub fn into_owned(self) -> ObjectAttributeName<'static> {
+        match self {
+            Self::N => ObjectAttributeName::N,
+            Self::P => ObjectAttributeName::P,
+            Self::C => ObjectAttributeName::C,
+            Self::CRot => ObjectAttributeName::CRot,
+            Self::CMfr => ObjectAttributeName::CMfr,
+            Self::CMPN => ObjectAttributeName::CMPN,
+            Self::CVal => ObjectAttributeName::CVal,
+            Self::CMnt => ObjectAttributeName::CMnt,
+            Self::CFtp => ObjectAttributeName::CFtp,
+            Self::CPgN => ObjectAttributeName::CPgN,
+            Self::CPgD => ObjectAttributeName::CPgD,
+            Self::CHgt => ObjectAttributeName::CHgt,
+            Self::CLbN => ObjectAttributeName::CLbN,
+            Self::CLbD => ObjectAttributeName::CLbD,
+            Self::CSup => ObjectAttributeName::CSup,
+            Self::UnknownStandardName(s) => {
+                ObjectAttributeName::UnknownStandardName(s.into_owned())
+            }
+            Self::UserDefinedName(s) => ObjectAttributeName::UserDefinedName(s.into_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_attribute_name() {
+        assert_eq!(
+            ObjectAttributeName::parse(".C"),
+            Ok(("", ObjectAttributeName::C))
+        );
+        assert_eq!(
+            ObjectAttributeName::parse(".CRot"),
+            Ok(("", ObjectAttributeName::CRot))
+        );
+        assert_eq!(
+            ObjectAttributeName::parse(".Cfoo"),
+            Ok(("", ObjectAttributeName::UnknownStandardName(".Cfoo".into())))
+        );
+        assert_eq!(
+            ObjectAttributeName::parse("Custom"),
+            Ok(("", ObjectAttributeName::UserDefinedName("Custom".into())))
+        );
+        assert_eq!(ObjectAttributeName::CRot.name(), ".CRot");
+        assert!(ObjectAttributeName::C.is_component());
+        assert!(!ObjectAttributeName::N.is_component());
+    }
+
+    #[test]
+    fn test_aperture_attribute_name() {
+        assert_eq!(
+            ApertureAttributeName::parse(".AperFunction"),
+            Ok(("", ApertureAttributeName::AperFunction))
+        );
+        assert_eq!(
+            ApertureAttributeName::parse(".AperFunctionX"),
+            Ok((
+                "",
+                ApertureAttributeName::UnknownStandardName(".AperFunctionX".into())
+            ))
+        );
+    }
 }
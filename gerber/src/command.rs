@@ -1,51 +1,130 @@
 //! Commands and aliases
 
-use crate::IResult;
+use crate::aperture::ApertureTemplate;
+use crate::attribute::{ApertureAttributeName, FileAttributeName, ObjectAttributeName};
+use crate::data::{
+    ApertureId, CoordinateFormat, Coordinates, EscapedString, InterpolationMode, Mirroring, Offset,
+    Polarity, Rotation, Scaling, SharedStr, StepRepeat, Unit,
+};
+use crate::macros::MacroStatement;
+use crate::{GerberError, IResult};
 use nom::{
+    branch::alt,
     bytes::complete::tag,
     combinator::{map, value},
     sequence::{delimited, pair},
 };
+use std::fmt;
+use std::str::FromStr;
 pub use Command::*;
 
+/// Comment
+pub const G04: &str = "G04";
+/// Mode
+pub const MO: &str = "MO";
+/// Format specification
+pub const FS: &str = "FS";
+/// Aperture define
+pub const AD: &str = "AD";
+/// Aperture macro
+pub const AM: &str = "AM";
+/// Set current aperture, followed by the aperture number
+pub const D: &str = "D";
+/// Plot operation
+pub const D01: &str = "D01";
+/// Move operation
+pub const D02: &str = "D02";
+/// Flash operation
+pub const D03: &str = "D03";
+/// Linear interpolation
+pub const G01: &str = "G01";
+/// Clockwise circular interpolation
+pub const G02: &str = "G02";
+/// Counterclockwise circular interpolation
+pub const G03: &str = "G03";
+/// Arc initialization
+pub const G75: &str = "G75";
+/// Single quadrant mode, deprecated
+pub const G74: &str = "G74";
+/// Load polarity
+pub const LP: &str = "LP";
+/// Load mirroring
+pub const LM: &str = "LM";
+/// Load rotation
+pub const LR: &str = "LR";
+/// Load scaling
+pub const LS: &str = "LS";
+/// Start region
+pub const G36: &str = "G36";
+/// End region
+pub const G37: &str = "G37";
+/// Aperture block
+pub const AB: &str = "AB";
+/// Step and repeat
+pub const SR: &str = "SR";
+/// File attribute
+pub const TF: &str = "TF";
+/// Aperture attribute
+pub const TA: &str = "TA";
+/// Object attribute
+pub const TO: &str = "TO";
+/// Attribute delete
+pub const TD: &str = "TD";
+/// End of file
+pub const M02: &str = "M02";
+
 /// Gerber Commands
 ///
 /// Each variant is the "long name" listed in §2.8 of the specification.
 /// Variants are also identified by [command code constants](crate::command#constants).
+///
+/// Every variant carries what its command says, so the commands alone are
+/// enough to rebuild the image. New commands may be added as the
+/// specification evolves, so matches must have a wildcard arm.
 #[derive(Clone, PartialEq, PartialOrd, Hash, Debug)]
-pub enum Command {
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum Command<'a> {
     /// [G04] A human readable comment, does not affect the image.
-    Comment, // TODO: add comment string
+    ///
+    /// The text is kept as written, including the space usually following
+    /// the code.
+    Comment(EscapedString<'a>),
 
     /// [MO] Sets the unit to mm or inch.
-    Mode,
+    Mode(Unit),
 
     /// [FS] Sets the coordinate format, e.g. the number of decimals.
-    FormatSpecification,
+    /// The X and Y formats are given separately.
+    FormatSpecification(CoordinateFormat, CoordinateFormat),
 
     /// [AD] Defines a template-based aperture, assigns a D code to it.
-    ApertureDefine,
+    ApertureDefine(ApertureId, ApertureTemplate<'a>),
 
     /// [AM] Defines a macro aperture template.
-    ApertureMacro,
+    ///
+    /// The name is followed by the content of the macro, one statement per
+    /// `*` terminated word: comments, variable definitions and primitives.
+    ApertureMacro(SharedStr<'a>, Vec<MacroStatement>),
 
     /// [D] (Dnn for nn≥10) Sets the current aperture to D code nn.
-    SetCurrentAperture,
+    SetCurrentAperture(ApertureId),
 
     /// [D01] Outside a region statement [D01] creates a draw or arc
     /// object with the current aperture. Inside it adds a draw/arc
     /// segment to the contour under construction. The current
     /// point is moved to draw/arc end point after the creation of
     /// the draw/arc.
-    Plot,
+    Plot(Coordinates, Option<Offset>),
 
     /// [D02] Moves the current point to the coordinate in the
     /// command. It does not create an object.
-    Move,
+    Move(Coordinates),
 
     /// [D03] Creates a flash object with the current aperture. The
     /// current point is moved to the flash point.
-    Flash,
+    Flash(Coordinates),
 
     /// [G01] Sets linear/circular mode to linear.
     SetLinear,
@@ -59,17 +138,21 @@ pub enum Command {
     /// [G75] Must be called before creating the first arc.
     ArcInit,
 
+    /// [G74] Deprecated. Sets single quadrant mode, in which arcs span at
+    /// most 90° and the I and J offsets are unsigned. [G75] switches back.
+    SingleQuadrant,
+
     /// [LP] Loads the polarity object transformation parameter.
-    LoadPolarity,
+    LoadPolarity(Polarity),
 
     /// [LM] Loads the mirror object transformation parameter.
-    LoadMirroring,
+    LoadMirroring(Mirroring),
 
     /// [LR] Loads the rotation object transformation parameter.
-    LoadRotation,
+    LoadRotation(Rotation),
 
     /// [LS] Loads the scale object transformation parameter.
-    LoadScaling,
+    LoadScaling(Scaling),
 
     /// [G36] Starts a region statement which creates a region by
     /// defining its contours.
@@ -80,32 +163,685 @@ pub enum Command {
 
     /// [AB] Opens a block aperture statement and assigns its aperture
     /// number or closes a block aperture statement.
-    ApertureBlock,
+    ///
+    /// `Some` opens a block defining the aperture, `None` closes it.
+    ApertureBlock(Option<ApertureId>),
 
     /// [SR] Open or closes a step and repeat statement.
-    StepAndRepeat,
+    ///
+    /// `Some` opens a statement repeating the following objects, `None`
+    /// closes it.
+    StepAndRepeat(Option<StepRepeat>),
 
     /// [TF] Set a file attribute.
-    AttributeOnFile,
+    AttributeOnFile(FileAttributeName<'a>, Vec<EscapedString<'a>>),
 
     /// [TA] Add an aperture attribute to the dictionary or modify it.
-    AttributeOnAperture,
+    AttributeOnAperture(ApertureAttributeName<'a>, Vec<EscapedString<'a>>),
 
     /// [TO] Add an object attribute to the dictionary or modify it.
-    AttributeOnObject,
+    AttributeOnObject(ObjectAttributeName<'a>, Vec<EscapedString<'a>>),
 
     /// [TD] Delete one or all attributes in the dictionary.
-    AttributeDelete,
+    AttributeDelete(Option<SharedStr<'a>>),
 
     /// [M02] End of file.
     EndOfFile,
 }
 
+impl Command<'_> {
+    /// The command code, one of the [constants](crate::command#constants)
+    pub fn code(&self) -> &'static str {
+        match self {
+            Comment(_) => G04,
+            Mode(_) => MO,
+            FormatSpecification(..) => FS,
+            ApertureDefine(..) => AD,
+            ApertureMacro(..) => AM,
+            SetCurrentAperture(_) => D,
+            Plot(..) => D01,
+            Move(_) => D02,
+            Flash(_) => D03,
+            SetLinear => G01,
+            SetCWCircular => G02,
+            SetCCWCircular => G03,
+            ArcInit => G75,
+            SingleQuadrant => G74,
+            LoadPolarity(_) => LP,
+            LoadMirroring(_) => LM,
+            LoadRotation(_) => LR,
+            LoadScaling(_) => LS,
+            StartRegion => G36,
+            EndRegion => G37,
+            ApertureBlock(_) => AB,
+            StepAndRepeat(_) => SR,
+            AttributeOnFile(..) => TF,
+            AttributeOnAperture(..) => TA,
+            AttributeOnObject(..) => TO,
+            AttributeDelete(_) => TD,
+            EndOfFile => M02,
+        }
+    }
+
+    /// The interpolation mode set by G01, G02 or G03
+    pub fn interpolation_mode(&self) -> Option<InterpolationMode> {
+        match self {
+            SetLinear => Some(InterpolationMode::Linear),
+            SetCWCircular => Some(InterpolationMode::Clockwise),
+            SetCCWCircular => Some(InterpolationMode::CounterClockwise),
+            _ => None,
+        }
+    }
+
+    /// Convert into a command which does not borrow from the source
+    pub fn into_owned(self) -> Command<'static> {
+        match self {
+            Comment(text) => Comment(text.into_owned()),
+            Mode(unit) => Mode(unit),
+            FormatSpecification(x, y) => FormatSpecification(x, y),
+            ApertureDefine(id, template) => ApertureDefine(id, template.into_owned()),
+            ApertureMacro(name, content) => ApertureMacro(name.into_owned(), content),
+            SetCurrentAperture(id) => SetCurrentAperture(id),
+            Plot(coordinates, offset) => Plot(coordinates, offset),
+            Move(coordinates) => Move(coordinates),
+            Flash(coordinates) => Flash(coordinates),
+            SetLinear => SetLinear,
+            SetCWCircular => SetCWCircular,
+            SetCCWCircular => SetCCWCircular,
+            ArcInit => ArcInit,
+            SingleQuadrant => SingleQuadrant,
+            LoadPolarity(polarity) => LoadPolarity(polarity),
+            LoadMirroring(mirroring) => LoadMirroring(mirroring),
+            LoadRotation(rotation) => LoadRotation(rotation),
+            LoadScaling(scaling) => LoadScaling(scaling),
+            StartRegion => StartRegion,
+            EndRegion => EndRegion,
+            ApertureBlock(id) => ApertureBlock(id),
+            StepAndRepeat(repeat) => StepAndRepeat(repeat),
+            AttributeOnFile(name, values) => AttributeOnFile(
+                name.into_owned(),
+                values.into_iter().map(EscapedString::into_owned).collect(),
+            ),
+            AttributeOnAperture(name, values) => AttributeOnAperture(
+                name.into_owned(),
+                values.into_iter().map(EscapedString::into_owned).collect(),
+            ),
+            AttributeOnObject(name, values) => AttributeOnObject(
+                name.into_owned(),
+                values.into_iter().map(EscapedString::into_owned).collect(),
+            ),
+            AttributeDelete(name) => AttributeDelete(name.map(SharedStr::into_owned)),
+            EndOfFile => EndOfFile,
+        }
+    }
+}
+
+/// A command classified by its syntax, as in §3.3 of the specification
+///
+/// Word commands are a single word terminated by `*`, e.g. `D10*`, while
+/// extended commands are enclosed in `%`, e.g. `%MOMM*%`. Converting from
+/// and to [Command] moves the payload without copying.
+///
+/// ```
+/// use gerber::command::{Command, Statement, WordCommand};
+/// use gerber::data::Unit;
+///
+/// let statement = Statement::from(Command::EndOfFile);
+/// assert_eq!(statement, Statement::Word(WordCommand::EndOfFile));
+/// assert_eq!(statement.delimiters(), ("", "*"));
+/// assert_eq!(Statement::from(Command::Mode(Unit::Inches)).delimiters(), ("%", "*%"));
+/// ```
+#[derive(Clone, PartialEq, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Statement<'a> {
+    /// A word command, terminated by `*`
+    Word(WordCommand<'a>),
+
+    /// An extended command, enclosed in `%`
+    Extended(ExtendedCommand<'a>),
+}
+
+/// Commands written as a single word, see [Command] for their meaning
+#[derive(Clone, PartialEq, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum WordCommand<'a> {
+    /// [G04]
+    Comment(EscapedString<'a>),
+    /// [D]
+    SetCurrentAperture(ApertureId),
+    /// [D01]
+    Plot(Coordinates, Option<Offset>),
+    /// [D02]
+    Move(Coordinates),
+    /// [D03]
+    Flash(Coordinates),
+    /// [G01]
+    SetLinear,
+    /// [G02]
+    SetCWCircular,
+    /// [G03]
+    SetCCWCircular,
+    /// [G75]
+    ArcInit,
+    /// [G74]
+    SingleQuadrant,
+    /// [G36]
+    StartRegion,
+    /// [G37]
+    EndRegion,
+    /// [M02]
+    EndOfFile,
+}
+
+/// Commands enclosed in `%`, see [Command] for their meaning
+#[derive(Clone, PartialEq, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub enum ExtendedCommand<'a> {
+    /// [MO]
+    Mode(Unit),
+    /// [FS]
+    FormatSpecification(CoordinateFormat, CoordinateFormat),
+    /// [AD]
+    ApertureDefine(ApertureId, ApertureTemplate<'a>),
+    /// [AM]
+    ApertureMacro(SharedStr<'a>, Vec<MacroStatement>),
+    /// [LP]
+    LoadPolarity(Polarity),
+    /// [LM]
+    LoadMirroring(Mirroring),
+    /// [LR]
+    LoadRotation(Rotation),
+    /// [LS]
+    LoadScaling(Scaling),
+    /// [AB]
+    ApertureBlock(Option<ApertureId>),
+    /// [SR]
+    StepAndRepeat(Option<StepRepeat>),
+    /// [TF]
+    AttributeOnFile(FileAttributeName<'a>, Vec<EscapedString<'a>>),
+    /// [TA]
+    AttributeOnAperture(ApertureAttributeName<'a>, Vec<EscapedString<'a>>),
+    /// [TO]
+    AttributeOnObject(ObjectAttributeName<'a>, Vec<EscapedString<'a>>),
+    /// [TD]
+    AttributeDelete(Option<SharedStr<'a>>),
+}
+
+impl Statement<'_> {
+    /// The text before and after the command code and its arguments
+    pub fn delimiters(&self) -> (&'static str, &'static str) {
+        match self {
+            Statement::Word(_) => ("", "*"),
+            Statement::Extended(_) => ("%", "*%"),
+        }
+    }
+}
+
+impl<'a> Command<'a> {
+    /// Parse exactly one command, e.g. `D10*` or `%MOMM*%`
+    ///
+    /// Line endings around the command are ignored, anything else is an
+    /// error. Unlike [GerberLayer::parse](crate::GerberLayer::parse) the
+    /// input needn't be a whole file, which suits snippets in tests,
+    /// REPLs and editors. [FromStr] gives a command which doesn't borrow
+    /// the input.
+    ///
+    /// ```
+    /// use gerber::command::Command;
+    /// use gerber::data::Unit;
+    ///
+    /// assert_eq!(Command::parse_one("%MOMM*%\n").unwrap(), Command::Mode(Unit::Millimeters));
+    /// assert_eq!("M02*".parse::<Command>().unwrap(), Command::EndOfFile);
+    /// assert!(Command::parse_one("D10*D11*").is_err());
+    /// ```
+    pub fn parse_one(input: &'a str) -> Result<Self, GerberError> {
+        let (rest, command) =
+            alt((crate::end_of_file, crate::command))(input.trim_matches(['\r', '\n']))
+                .map_err(|e| GerberError::ParseError(format!("{:?}", e)))?;
+        if !rest.is_empty() {
+            return Err(GerberError::ParseError(format!(
+                "content after command: {rest:?}"
+            )));
+        }
+        Ok(command)
+    }
+}
+
+impl FromStr for Command<'static> {
+    type Err = GerberError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Command::parse_one(s).map(Command::into_owned)
+    }
+}
+
+impl Command<'_> {
+    /// True for commands enclosed in `%`, false for word commands
+    pub fn is_extended(&self) -> bool {
+        matches!(
+            self,
+            Mode(_)
+                | FormatSpecification(..)
+                | ApertureDefine(..)
+                | ApertureMacro(..)
+                | LoadPolarity(_)
+                | LoadMirroring(_)
+                | LoadRotation(_)
+                | LoadScaling(_)
+                | ApertureBlock(_)
+                | StepAndRepeat(_)
+                | AttributeOnFile(..)
+                | AttributeOnAperture(..)
+                | AttributeOnObject(..)
+                | AttributeDelete(_)
+        )
+    }
+}
+
+/// The command as written in a file, without a line ending
+///
+/// Decimals are written in the shortest form which reads back as the same
+/// value, so parsing the text gives the same command. Macro content is
+/// written one word per line.
+///
+/// ```
+/// use gerber::command::Command;
+/// use gerber::data::{Coordinates, Polarity};
+///
+/// let flash = Command::Flash(Coordinates { x: Some(-2500), y: None });
+/// assert_eq!(flash.to_string(), "X-2500D03*");
+/// assert_eq!(Command::LoadPolarity(Polarity::Clear).to_string(), "%LPC*%");
+/// ```
+impl fmt::Display for Command<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (open, close) = if self.is_extended() {
+            ("%", "*%")
+        } else {
+            ("", "*")
+        };
+        f.write_str(open)?;
+        match self {
+            Comment(text) => write!(f, "{G04}{}", text.raw())?,
+            Mode(unit) => {
+                let unit = match unit {
+                    Unit::Millimeters => "MM",
+                    Unit::Inches => "IN",
+                };
+                write!(f, "{MO}{unit}")?
+            }
+            FormatSpecification(x, y) => write!(
+                f,
+                "{FS}LAX{}{}Y{}{}",
+                x.integer, x.decimal, y.integer, y.decimal
+            )?,
+            ApertureDefine(id, template) => {
+                write!(f, "{AD}{id}")?;
+                match template {
+                    ApertureTemplate::Circle { diameter, hole } => {
+                        write!(f, "C,{diameter}")?;
+                        write_hole(f, *hole)?
+                    }
+                    ApertureTemplate::Rectangle { x, y, hole } => {
+                        write!(f, "R,{x}X{y}")?;
+                        write_hole(f, *hole)?
+                    }
+                    ApertureTemplate::Obround { x, y, hole } => {
+                        write!(f, "O,{x}X{y}")?;
+                        write_hole(f, *hole)?
+                    }
+                    ApertureTemplate::Polygon {
+                        diameter,
+                        vertices,
+                        rotation,
+                        hole,
+                    } => {
+                        write!(f, "P,{diameter}X{vertices}")?;
+                        // a hole can only follow a rotation
+                        if rotation.is_some() || hole.is_some() {
+                            write!(f, "X{}", rotation.unwrap_or(0.0))?;
+                        }
+                        write_hole(f, *hole)?
+                    }
+                    ApertureTemplate::Macro { name, parameters } => {
+                        f.write_str(name)?;
+                        for (index, parameter) in parameters.iter().enumerate() {
+                            let separator = if index == 0 { ',' } else { 'X' };
+                            write!(f, "{separator}{parameter}")?;
+                        }
+                    }
+                }
+            }
+            ApertureMacro(name, content) => {
+                write!(f, "{AM}{name}")?;
+                for statement in content {
+                    write!(f, "*\n{statement}")?;
+                }
+            }
+            SetCurrentAperture(id) => write!(f, "{id}")?,
+            Plot(coordinates, offset) => {
+                write_coordinates(f, coordinates)?;
+                if let Some(Offset { i, j }) = offset {
+                    write!(f, "I{i}J{j}")?;
+                }
+                f.write_str(D01)?
+            }
+            Move(coordinates) => {
+                write_coordinates(f, coordinates)?;
+                f.write_str(D02)?
+            }
+            Flash(coordinates) => {
+                write_coordinates(f, coordinates)?;
+                f.write_str(D03)?
+            }
+            LoadPolarity(polarity) => {
+                let polarity = match polarity {
+                    Polarity::Dark => "D",
+                    Polarity::Clear => "C",
+                };
+                write!(f, "{LP}{polarity}")?
+            }
+            LoadMirroring(mirroring) => {
+                let mirroring = match mirroring {
+                    Mirroring::None => "N",
+                    Mirroring::X => "X",
+                    Mirroring::Y => "Y",
+                    Mirroring::XY => "XY",
+                };
+                write!(f, "{LM}{mirroring}")?
+            }
+            LoadRotation(Rotation(degrees)) => write!(f, "{LR}{degrees}")?,
+            LoadScaling(Scaling(factor)) => write!(f, "{LS}{factor}")?,
+            ApertureBlock(id) => {
+                f.write_str(AB)?;
+                if let Some(id) = id {
+                    write!(f, "{id}")?;
+                }
+            }
+            StepAndRepeat(repeat) => {
+                f.write_str(SR)?;
+                if let Some(StepRepeat { x, y, i, j }) = repeat {
+                    write!(f, "X{x}Y{y}I{i}J{j}")?;
+                }
+            }
+            AttributeOnFile(name, values) => write_attribute(f, TF, name.name(), values)?,
+            AttributeOnAperture(name, values) => write_attribute(f, TA, name.name(), values)?,
+            AttributeOnObject(name, values) => write_attribute(f, TO, name.name(), values)?,
+            AttributeDelete(name) => {
+                f.write_str(TD)?;
+                if let Some(name) = name {
+                    f.write_str(name)?;
+                }
+            }
+            SetLinear | SetCWCircular | SetCCWCircular | ArcInit | SingleQuadrant | StartRegion
+            | EndRegion | EndOfFile => f.write_str(self.code())?,
+        }
+        f.write_str(close)
+    }
+}
+
+fn write_hole(f: &mut fmt::Formatter<'_>, hole: Option<f64>) -> fmt::Result {
+    match hole {
+        Some(hole) => write!(f, "X{hole}"),
+        None => Ok(()),
+    }
+}
+
+fn write_coordinates(f: &mut fmt::Formatter<'_>, coordinates: &Coordinates) -> fmt::Result {
+    if let Some(x) = coordinates.x {
+        write!(f, "X{x}")?;
+    }
+    if let Some(y) = coordinates.y {
+        write!(f, "Y{y}")?;
+    }
+    Ok(())
+}
+
+fn write_attribute(
+    f: &mut fmt::Formatter<'_>,
+    code: &str,
+    name: &str,
+    values: &[EscapedString<'_>],
+) -> fmt::Result {
+    write!(f, "{code}{name}")?;
+    for value in values {
+        write!(f, ",{}", value.raw())?;
+    }
+    Ok(())
+}
+
+impl<'a> From<Command<'a>> for Statement<'a> {
+    fn from(command: Command<'a>) -> Self {
+        use ExtendedCommand as E;
+        use Statement::{Extended, Word};
+        use WordCommand as W;
+        match command {
+            Comment(text) => Word(W::Comment(text)),
+            Mode(unit) => Extended(E::Mode(unit)),
+            FormatSpecification(x, y) => Extended(E::FormatSpecification(x, y)),
+            ApertureDefine(id, template) => Extended(E::ApertureDefine(id, template)),
+            ApertureMacro(name, content) => Extended(E::ApertureMacro(name, content)),
+            SetCurrentAperture(id) => Word(W::SetCurrentAperture(id)),
+            Plot(coordinates, offset) => Word(W::Plot(coordinates, offset)),
+            Move(coordinates) => Word(W::Move(coordinates)),
+            Flash(coordinates) => Word(W::Flash(coordinates)),
+            SetLinear => Word(W::SetLinear),
+            SetCWCircular => Word(W::SetCWCircular),
+            SetCCWCircular => Word(W::SetCCWCircular),
+            ArcInit => Word(W::ArcInit),
+            SingleQuadrant => Word(W::SingleQuadrant),
+            LoadPolarity(polarity) => Extended(E::LoadPolarity(polarity)),
+            LoadMirroring(mirroring) => Extended(E::LoadMirroring(mirroring)),
+            LoadRotation(rotation) => Extended(E::LoadRotation(rotation)),
+            LoadScaling(scaling) => Extended(E::LoadScaling(scaling)),
+            StartRegion => Word(W::StartRegion),
+            EndRegion => Word(W::EndRegion),
+            ApertureBlock(id) => Extended(E::ApertureBlock(id)),
+            StepAndRepeat(repeat) => Extended(E::StepAndRepeat(repeat)),
+            AttributeOnFile(name, values) => Extended(E::AttributeOnFile(name, values)),
+            AttributeOnAperture(name, values) => Extended(E::AttributeOnAperture(name, values)),
+            AttributeOnObject(name, values) => Extended(E::AttributeOnObject(name, values)),
+            AttributeDelete(name) => Extended(E::AttributeDelete(name)),
+            EndOfFile => Word(W::EndOfFile),
+        }
+    }
+}
+
+impl<'a> From<Statement<'a>> for Command<'a> {
+    fn from(statement: Statement<'a>) -> Self {
+        use ExtendedCommand as E;
+        use WordCommand as W;
+        match statement {
+            Statement::Word(command) => match command {
+                W::Comment(text) => Comment(text),
+                W::SetCurrentAperture(id) => SetCurrentAperture(id),
+                W::Plot(coordinates, offset) => Plot(coordinates, offset),
+                W::Move(coordinates) => Move(coordinates),
+                W::Flash(coordinates) => Flash(coordinates),
+                W::SetLinear => SetLinear,
+                W::SetCWCircular => SetCWCircular,
+                W::SetCCWCircular => SetCCWCircular,
+                W::ArcInit => ArcInit,
+                W::SingleQuadrant => SingleQuadrant,
+                W::StartRegion => StartRegion,
+                W::EndRegion => EndRegion,
+                W::EndOfFile => EndOfFile,
+            },
+            Statement::Extended(command) => match command {
+                E::Mode(unit) => Mode(unit),
+                E::FormatSpecification(x, y) => FormatSpecification(x, y),
+                E::ApertureDefine(id, template) => ApertureDefine(id, template),
+                E::ApertureMacro(name, content) => ApertureMacro(name, content),
+                E::LoadPolarity(polarity) => LoadPolarity(polarity),
+                E::LoadMirroring(mirroring) => LoadMirroring(mirroring),
+                E::LoadRotation(rotation) => LoadRotation(rotation),
+                E::LoadScaling(scaling) => LoadScaling(scaling),
+                E::ApertureBlock(id) => ApertureBlock(id),
+                E::StepAndRepeat(repeat) => StepAndRepeat(repeat),
+                E::AttributeOnFile(name, values) => AttributeOnFile(name, values),
+                E::AttributeOnAperture(name, values) => AttributeOnAperture(name, values),
+                E::AttributeOnObject(name, values) => AttributeOnObject(name, values),
+                E::AttributeDelete(name) => AttributeDelete(name),
+            },
+        }
+    }
+}
+
+/// An operation: D01, D02 or D03 with its coordinates
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Operation {
+    /// [D01]
+    Plot(Coordinates, Option<Offset>),
+    /// [D02]
+    Move(Coordinates),
+    /// [D03]
+    Flash(Coordinates),
+}
+
+impl Operation {
+    /// The coordinates of the end point, or the flash point
+    pub fn coordinates(&self) -> Coordinates {
+        match self {
+            Operation::Plot(coordinates, _)
+            | Operation::Move(coordinates)
+            | Operation::Flash(coordinates) => *coordinates,
+        }
+    }
+}
+
+/// Queries of the commands of a layer, without matching every command
+///
+/// ```
+/// use gerber::command::{Commands, Operation};
+/// use gerber::GerberLayer;
+///
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\n%TF.Part,Single*%\n%ADD10C,0.1*%\nD10*\n\
+///            X0Y0D02*\nX1000000Y0D01*\nM02*\n";
+/// let layer = GerberLayer::parse(src).unwrap();
+/// let commands = layer.commands();
+/// assert_eq!(commands.aperture_defines().count(), 1);
+/// assert!(matches!(commands.operations().last(), Some(Operation::Plot(..))));
+/// let (name, values) = commands.file_attributes().next().unwrap();
+/// assert_eq!((name.name(), values[0].unescape()), (".Part", "Single".into()));
+/// ```
+pub trait Commands<'a> {
+    /// The aperture numbers and templates of `%AD` commands
+    fn aperture_defines<'c>(
+        &'c self,
+    ) -> impl Iterator<Item = (ApertureId, &'c ApertureTemplate<'a>)>
+    where
+        'a: 'c;
+
+    /// The D01, D02 and D03 operations
+    fn operations(&self) -> impl Iterator<Item = Operation>;
+
+    /// The names and values of `%TF` commands
+    fn file_attributes<'c>(
+        &'c self,
+    ) -> impl Iterator<Item = (&'c FileAttributeName<'a>, &'c [EscapedString<'a>])>
+    where
+        'a: 'c;
+
+    /// The names and values of `%TA` commands
+    fn aperture_attributes<'c>(
+        &'c self,
+    ) -> impl Iterator<Item = (&'c ApertureAttributeName<'a>, &'c [EscapedString<'a>])>
+    where
+        'a: 'c;
+
+    /// The names and values of `%TO` commands
+    fn object_attributes<'c>(
+        &'c self,
+    ) -> impl Iterator<Item = (&'c ObjectAttributeName<'a>, &'c [EscapedString<'a>])>
+    where
+        'a: 'c;
+
+    /// The text of G04 comments
+    fn comments<'c>(&'c self) -> impl Iterator<Item = &'c EscapedString<'a>>
+    where
+        'a: 'c;
+}
+
+impl<'a> Commands<'a> for [Command<'a>] {
+    fn aperture_defines<'c>(
+        &'c self,
+    ) -> impl Iterator<Item = (ApertureId, &'c ApertureTemplate<'a>)>
+    where
+        'a: 'c,
+    {
+        self.iter().filter_map(|command| match command {
+            ApertureDefine(id, template) => Some((*id, template)),
+            _ => None,
+        })
+    }
+
+    fn operations(&self) -> impl Iterator<Item = Operation> {
+        self.iter().filter_map(|command| match command {
+            Plot(coordinates, offset) => Some(Operation::Plot(*coordinates, *offset)),
+            Move(coordinates) => Some(Operation::Move(*coordinates)),
+            Flash(coordinates) => Some(Operation::Flash(*coordinates)),
+            _ => None,
+        })
+    }
+
+    fn file_attributes<'c>(
+        &'c self,
+    ) -> impl Iterator<Item = (&'c FileAttributeName<'a>, &'c [EscapedString<'a>])>
+    where
+        'a: 'c,
+    {
+        self.iter().filter_map(|command| match command {
+            AttributeOnFile(name, values) => Some((name, values.as_slice())),
+            _ => None,
+        })
+    }
+
+    fn aperture_attributes<'c>(
+        &'c self,
+    ) -> impl Iterator<Item = (&'c ApertureAttributeName<'a>, &'c [EscapedString<'a>])>
+    where
+        'a: 'c,
+    {
+        self.iter().filter_map(|command| match command {
+            AttributeOnAperture(name, values) => Some((name, values.as_slice())),
+            _ => None,
+        })
+    }
+
+    fn object_attributes<'c>(
+        &'c self,
+    ) -> impl Iterator<Item = (&'c ObjectAttributeName<'a>, &'c [EscapedString<'a>])>
+    where
+        'a: 'c,
+    {
+        self.iter().filter_map(|command| match command {
+            AttributeOnObject(name, values) => Some((name, values.as_slice())),
+            _ => None,
+        })
+    }
+
+    fn comments<'c>(&'c self) -> impl Iterator<Item = &'c EscapedString<'a>>
+    where
+        'a: 'c,
+    {
+        self.iter().filter_map(|command| match command {
+            Comment(text) => Some(text),
+            _ => None,
+        })
+    }
+}
+
 pub(crate) fn extended_command<'a, T>(
     code: &'static str,
     parser: impl FnMut(&'a str) -> IResult<'a, T>,
-    command: impl Fn(T) -> Command,
-) -> impl FnMut(&'a str) -> IResult<'a, Command> {
+    command: impl Fn(T) -> Command<'a>,
+) -> impl FnMut(&'a str) -> IResult<'a, Command<'a>> {
     map(
         delimited(pair(tag("%"), tag(code)), parser, tag("*%")),
         command,
@@ -115,14 +851,14 @@ pub(crate) fn extended_command<'a, T>(
 pub(crate) fn word_command<'a, T>(
     code: &'static str,
     parser: impl FnMut(&'a str) -> IResult<'a, T>,
-    command: impl Fn(T) -> Command,
-) -> impl FnMut(&'a str) -> IResult<'a, Command> {
+    command: impl Fn(T) -> Command<'a>,
+) -> impl FnMut(&'a str) -> IResult<'a, Command<'a>> {
     map(delimited(tag(code), parser, tag("*")), command)
 }
 
 pub(crate) fn simple_word_command<'a>(
     code: &'static str,
-    command: Command,
-) -> impl FnMut(&'a str) -> IResult<'a, Command> {
+    command: Command<'a>,
+) -> impl FnMut(&'a str) -> IResult<'a, Command<'a>> {
     value(command, pair(tag(code), tag("*")))
 }
@@ -0,0 +1,359 @@
+//! Components from X3 component layers
+//!
+//! A component layer (`.FileFunction,Component,L1,Top`) describes the
+//! placed components. Each component has a flash with aperture function
+//! `ComponentMain` at its reference point, flashes with `ComponentPin` on
+//! its pins and outlines drawn with `ComponentOutline`, all carrying the
+//! component's reference designator in `.C`.
+//! [components](GerberLayer::components) groups these per reference
+//! designator.
+
+use std::collections::BTreeMap;
+use std::f64::consts::TAU;
+
+use crate::data::InterpolationMode;
+use crate::image::{Object, Point, Shape};
+use crate::testpoint::Side;
+use crate::GerberLayer;
+
+/// The largest angle an arc segment of an outline spans, in radians
+const ARC_STEP: f64 = TAU / 64.0;
+
+/// A component placed on the board, in millimeters
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Component {
+    /// The reference point, from the `ComponentMain` flash, or else the
+    /// centroid of the courtyard
+    pub centroid: Option<Point>,
+
+    /// The rotation in degrees, `.CRot`
+    pub rotation: Option<f64>,
+
+    /// The courtyard outline, `ComponentOutline,Courtyard`
+    pub courtyard: Vec<Point>,
+
+    /// The body outline, `ComponentOutline,Body`
+    pub body: Vec<Point>,
+
+    /// The pin locations
+    pub pins: Vec<Point>,
+
+    /// The side of the board, from `.FileFunction`
+    pub side: Option<Side>,
+
+    /// Indices of the component's objects in [Image](crate::image::Image)
+    pub objects: Vec<usize>,
+}
+
+impl Component {
+    /// True if the courtyards of two components overlap
+    ///
+    /// Courtyards which only touch do not overlap. Components without a
+    /// courtyard overlap nothing.
+    pub fn overlaps(&self, other: &Component) -> bool {
+        let (a, b) = (&self.courtyard, &other.courtyard);
+        if a.len() < 3 || b.len() < 3 {
+            return false;
+        }
+        // the vertices of coincident courtyards lie on each other's
+        // boundary, but their centroids lie inside
+        let inside = |polygon: &[Point], other: &[Point]| {
+            contains(polygon, other[0])
+                || centroid(other).is_some_and(|point| contains(polygon, point))
+        };
+        edges(a).any(|(p, q)| edges(b).any(|(r, s)| segments_cross(p, q, r, s)))
+            || inside(a, b)
+            || inside(b, a)
+    }
+}
+
+impl GerberLayer<'_> {
+    /// Group the objects of a component layer by reference designator
+    ///
+    /// Outlines are chained from consecutive draws and arcs in the order
+    /// they appear in the file, arcs flattened to segments spanning at
+    /// most 1/64 of a turn. Objects without `.C` are ignored.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%TA.AperFunction,ComponentMain*%\n%ADD10C,0.3*%\n\
+    ///            %TA.AperFunction,ComponentOutline,Courtyard*%\n%ADD11C,0*%\n\
+    ///            %TO.C,R1*%\n%TO.CRot,90*%\nD10*\nX1000000Y1000000D03*\nD11*\nG01*\n\
+    ///            X0Y0D02*\nX2000000D01*\nY2000000D01*\nX0D01*\nY0D01*\nM02*\n";
+    /// let components = GerberLayer::parse(src).unwrap().components();
+    /// let r1 = &components["R1"];
+    /// assert_eq!(r1.rotation, Some(90.0));
+    /// assert_eq!(r1.courtyard.len(), 4);
+    /// assert_eq!((r1.centroid.unwrap().x, r1.centroid.unwrap().y), (1.0, 1.0));
+    /// ```
+    pub fn components(&self) -> BTreeMap<String, Component> {
+        let image = self.image();
+        let side = Side::of(&image);
+        let mut components = BTreeMap::<String, Component>::new();
+        for (index, object) in image.objects.iter().enumerate() {
+            let Some(reference) = object.attributes.component() else {
+                continue;
+            };
+            let component = components
+                .entry(reference.to_string())
+                .or_insert_with(|| Component {
+                    side,
+                    ..Default::default()
+                });
+            component.objects.push(index);
+            if component.rotation.is_none() {
+                component.rotation = object
+                    .attributes
+                    .get(".CRot")
+                    .and_then(|values| values.first()?.parse().ok());
+            }
+
+            let attributes = &object.attributes;
+            match (attributes.aperture_function(), &object.shape) {
+                (Some("ComponentMain"), Shape::Flash { at, .. }) => component.centroid = Some(*at),
+                (Some("ComponentPin"), Shape::Flash { at, .. }) => component.pins.push(*at),
+                (Some("ComponentOutline"), _) => {
+                    let outline = match attributes
+                        .get(".AperFunction")
+                        .unwrap()
+                        .get(1)
+                        .map(AsRef::as_ref)
+                    {
+                        Some("Courtyard") => &mut component.courtyard,
+                        Some("Body") => &mut component.body,
+                        _ => continue,
+                    };
+                    extend_outline(outline, object);
+                }
+                _ => (),
+            }
+        }
+
+        for component in components.values_mut() {
+            for outline in [&mut component.courtyard, &mut component.body] {
+                // the closing point repeats the first
+                if outline.len() > 1 && outline.first() == outline.last() {
+                    outline.pop();
+                }
+            }
+            if component.centroid.is_none() {
+                component.centroid = centroid(&component.courtyard);
+            }
+        }
+        components
+    }
+}
+
+/// Append a draw or arc to an outline
+fn extend_outline(outline: &mut Vec<Point>, object: &Object) {
+    let start = match object.shape {
+        Shape::Draw { start, .. } | Shape::Arc { start, .. } => start,
+        _ => return,
+    };
+    if outline.last() != Some(&start) {
+        outline.push(start);
+    }
+    match object.shape {
+        Shape::Draw { end, .. } => outline.push(end),
+        Shape::Arc {
+            start,
+            end,
+            center,
+            direction,
+            ..
+        } => {
+            let radius = (start.x - center.x).hypot(start.y - center.y);
+            let start_angle = (start.y - center.y).atan2(start.x - center.x);
+            let end_angle = (end.y - center.y).atan2(end.x - center.x);
+            let mut sweep = (end_angle - start_angle).rem_euclid(TAU);
+            if sweep == 0.0 {
+                sweep = TAU;
+            }
+            if direction == InterpolationMode::Clockwise {
+                sweep -= TAU;
+            }
+            let steps = (sweep.abs() / ARC_STEP).ceil() as usize;
+            outline.extend((1..steps).map(|step| {
+                let angle = start_angle + sweep * step as f64 / steps as f64;
+                Point {
+                    x: center.x + radius * angle.cos(),
+                    y: center.y + radius * angle.sin(),
+                }
+            }));
+            outline.push(end);
+        }
+        _ => (),
+    }
+}
+
+/// The area centroid of a polygon
+fn centroid(polygon: &[Point]) -> Option<Point> {
+    let (mut area, mut x, mut y) = (0.0, 0.0, 0.0);
+    for (p, q) in edges(polygon) {
+        let cross = p.x * q.y - q.x * p.y;
+        area += cross;
+        x += (p.x + q.x) * cross;
+        y += (p.y + q.y) * cross;
+    }
+    (area != 0.0).then(|| Point {
+        x: x / (3.0 * area),
+        y: y / (3.0 * area),
+    })
+}
+
+/// The edges of a closed polygon
+fn edges(polygon: &[Point]) -> impl Iterator<Item = (Point, Point)> + '_ {
+    polygon
+        .iter()
+        .zip(polygon.iter().cycle().skip(1))
+        .map(|(p, q)| (*p, *q))
+}
+
+/// True if the segments `p`-`q` and `r`-`s` cross at a point inside both
+fn segments_cross(p: Point, q: Point, r: Point, s: Point) -> bool {
+    let side = |a: Point, b: Point, c: Point| (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+    let (d1, d2) = (side(r, s, p), side(r, s, q));
+    let (d3, d4) = (side(p, q, r), side(p, q, s));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+/// True if `point` is strictly inside `polygon`, by the even-odd rule
+fn contains(polygon: &[Point], point: Point) -> bool {
+    let mut inside = false;
+    for (p, q) in edges(polygon) {
+        if (p.y > point.y) != (q.y > point.y) {
+            let x = p.x + (point.y - p.y) / (q.y - p.y) * (q.x - p.x);
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const HEADER: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %TF.FileFunction,Component,L1,Top*%
+        %TA.AperFunction,ComponentMain*%
+        %ADD10C,0.3*%
+        %TA.AperFunction,ComponentPin*%
+        %ADD11C,0*%
+        %TA.AperFunction,ComponentOutline,Courtyard*%
+        %ADD12C,0*%
+        %TA.AperFunction,ComponentOutline,Body*%
+        %ADD13C,0*%
+        %TD*%
+        G01*
+        G75*
+    "};
+
+    fn components(body: &str) -> BTreeMap<String, Component> {
+        let src = format!("{HEADER}{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().components()
+    }
+
+    fn point(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_components() {
+        let components = components(indoc! {"
+            %TO.C,U1*%
+            D10*
+            X5000000Y5000000D03*
+            %TO.P,U1,1*%
+            D11*
+            X4000000Y5000000D03*
+            %TO.P,U1,2*%
+            X6000000Y5000000D03*
+            %TD.P*%
+            D13*
+            X4500000Y4500000D02*
+            X5500000D01*
+            Y5500000D01*
+            X4500000D01*
+            Y4500000D01*
+            %TO.C,C1*%
+            D12*
+            X0Y0D02*
+            X2000000D01*
+            Y1000000D01*
+            X0D01*
+            Y0D01*
+            %TD*%
+            D10*
+            X9000000Y9000000D03*
+        "});
+        assert_eq!(components.keys().collect::<Vec<_>>(), ["C1", "U1"]);
+
+        let u1 = &components["U1"];
+        assert_eq!(u1.centroid, Some(point(5.0, 5.0)));
+        assert_eq!(u1.pins, [point(4.0, 5.0), point(6.0, 5.0)]);
+        assert_eq!(u1.body.len(), 4);
+        assert!(u1.courtyard.is_empty());
+        assert_eq!(u1.side, Some(Side::Top));
+        assert_eq!(u1.objects, [0, 1, 2, 3, 4, 5, 6]);
+
+        // without a main flash the courtyard centroid is used
+        let c1 = &components["C1"];
+        assert_eq!(
+            c1.courtyard,
+            [
+                point(0.0, 0.0),
+                point(2.0, 0.0),
+                point(2.0, 1.0),
+                point(0.0, 1.0)
+            ]
+        );
+        assert_eq!(c1.centroid, Some(point(1.0, 0.5)));
+    }
+
+    #[test]
+    fn test_arc_outline() {
+        let components = components(indoc! {"
+            %TO.C,D1*%
+            D12*
+            X1000000Y0D02*
+            G03*
+            X-1000000Y0I-1000000J0D01*
+            X1000000Y0I1000000J0D01*
+        "});
+        let courtyard = &components["D1"].courtyard;
+        assert_eq!(courtyard.len(), 64);
+        assert!(courtyard
+            .iter()
+            .all(|point| (point.x.hypot(point.y) - 1.0).abs() < 1e-9));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let square = |x: f64, y: f64, size: f64| Component {
+            courtyard: vec![
+                point(x, y),
+                point(x + size, y),
+                point(x + size, y + size),
+                point(x, y + size),
+            ],
+            ..Default::default()
+        };
+        let a = square(0.0, 0.0, 2.0);
+        assert!(a.overlaps(&square(1.0, 1.0, 2.0)));
+        // containment without crossing edges
+        assert!(a.overlaps(&square(0.5, 0.5, 1.0)));
+        assert!(square(0.5, 0.5, 1.0).overlaps(&a));
+        assert!(a.overlaps(&a));
+        // touching
+        assert!(!a.overlaps(&square(2.0, 0.0, 2.0)));
+        assert!(!a.overlaps(&square(3.0, 3.0, 1.0)));
+        assert!(!a.overlaps(&Component::default()));
+    }
+}
@@ -0,0 +1,333 @@
+//! Spec-conformance report
+//!
+//! Lists the deprecated and non-conformant constructs used by a file. The
+//! strict parser rejects most of these outright, so the report is built from
+//! a lightweight scan of the source text rather than from parsed commands,
+//! which also means it can explain why a legacy file fails to parse.
+
+use std::fmt;
+use std::ops::Range;
+
+/// Section of the specification which describes deprecated commands
+const DEPRECATED_COMMANDS: &str = "8.1";
+
+/// Section of the specification which describes deprecated command options
+const DEPRECATED_OPTIONS: &str = "8.2";
+
+/// Section of the specification which describes deprecated syntax variations
+const DEPRECATED_SYNTAX: &str = "8.3";
+
+/// A construct reported by [report]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Construct {
+    /// A command removed from the current specification, e.g. `%IPPOS*%`
+    DeprecatedCommand(String),
+
+    /// `%FS` with trailing zero omission or incremental coordinates
+    DeprecatedFormatOption,
+
+    /// A standard aperture with a rectangular hole
+    RectangularHole,
+
+    /// Macro primitive 2 (vector line), 22 (lower left line) or 6 (moiré)
+    DeprecatedPrimitive(u8),
+
+    /// A G code combined with an operation, e.g. `G01X100Y100D01*`
+    CombinedCodes,
+
+    /// Coordinates without an operation code, e.g. `X100Y100*`
+    CoordinatesWithoutOperation,
+
+    /// A function code without its leading zero, e.g. `G1*`
+    ShortCode,
+}
+
+/// A construct found in the source
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Finding {
+    /// Byte range of the offending command in the source
+    pub span: Range<usize>,
+
+    /// What was found
+    pub construct: Construct,
+
+    /// Section of the specification describing the construct
+    pub section: &'static str,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match &self.construct {
+            Construct::DeprecatedCommand(code) => format!("deprecated command {code}"),
+            Construct::DeprecatedFormatOption => {
+                "deprecated format specification option".to_string()
+            }
+            Construct::RectangularHole => "aperture with a rectangular hole".to_string(),
+            Construct::DeprecatedPrimitive(code) => {
+                format!("deprecated macro primitive {code}")
+            }
+            Construct::CombinedCodes => "G code combined with an operation".to_string(),
+            Construct::CoordinatesWithoutOperation => {
+                "coordinates without an operation code".to_string()
+            }
+            Construct::ShortCode => "function code without leading zero".to_string(),
+        };
+        write!(
+            f,
+            "{}..{}: {} (spec §{})",
+            self.span.start, self.span.end, description, self.section
+        )
+    }
+}
+
+/// The result of [report]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// True when the file uses only current constructs
+    pub fn is_conformant(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{finding}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Build a conformance report for the source of a Gerber file
+pub fn report(src: &str) -> Report {
+    let mut findings = Vec::new();
+    for statement in statements(src) {
+        let mut push = |construct, section| {
+            findings.push(Finding {
+                span: statement.span.clone(),
+                construct,
+                section,
+            })
+        };
+        if statement.extended {
+            check_extended(&statement.words, &mut push);
+        } else if let Some(word) = statement.words.first() {
+            check_word(word, &mut push);
+        }
+    }
+    Report { findings }
+}
+
+/// A word command, or an extended command with all its words
+pub(crate) struct Statement<'a> {
+    pub(crate) span: Range<usize>,
+    pub(crate) extended: bool,
+    pub(crate) words: Vec<&'a str>,
+}
+
+/// Split the source into statements without interpreting them
+pub(crate) fn statements(src: &str) -> Vec<Statement<'_>> {
+    let mut statements = Vec::new();
+    let mut pos = 0;
+    while pos < src.len() {
+        let rest = &src[pos..];
+        pos += rest.len() - rest.trim_start().len();
+        if pos >= src.len() {
+            break;
+        }
+        if src[pos..].starts_with('%') {
+            let (body, end) = match src[pos + 1..].find('%') {
+                Some(i) => (&src[pos + 1..pos + 1 + i], pos + i + 2),
+                None => (&src[pos + 1..], src.len()),
+            };
+            let words = body
+                .split('*')
+                .map(str::trim)
+                .filter(|w| !w.is_empty())
+                .collect();
+            statements.push(Statement {
+                span: pos..end,
+                extended: true,
+                words,
+            });
+            pos = end;
+        } else {
+            let end = src[pos..].find('*').map_or(src.len(), |i| pos + i + 1);
+            let word = src[pos..end].trim_end_matches('*').trim();
+            statements.push(Statement {
+                span: pos..end,
+                extended: false,
+                words: vec![word],
+            });
+            pos = end;
+        }
+    }
+    statements
+}
+
+fn check_extended(words: &[&str], push: &mut impl FnMut(Construct, &'static str)) {
+    let Some(first) = words.first() else {
+        return;
+    };
+    let code = first.get(..2).unwrap_or(first);
+    match code {
+        "AS" | "IN" | "IP" | "IR" | "LN" | "MI" | "OF" | "SF" => push(
+            Construct::DeprecatedCommand(code.to_string()),
+            DEPRECATED_COMMANDS,
+        ),
+        "FS" if !first.starts_with("FSLA") => {
+            push(Construct::DeprecatedFormatOption, DEPRECATED_OPTIONS)
+        }
+        "AM" => {
+            for primitive in &words[1..] {
+                let code = primitive.split(',').next().unwrap_or_default().trim();
+                match code {
+                    "2" | "22" | "6" => push(
+                        Construct::DeprecatedPrimitive(code.parse().unwrap()),
+                        DEPRECATED_OPTIONS,
+                    ),
+                    _ => (),
+                }
+            }
+        }
+        "AD" => {
+            // skip the D code to find the template name and its modifiers
+            let template = first[2..].trim_start_matches(|c: char| c == 'D' || c.is_ascii_digit());
+            if let Some((name, modifiers)) = template.split_once(',') {
+                let count = modifiers.split('X').count();
+                let limit = match name {
+                    "C" => 2,
+                    "R" | "O" => 3,
+                    "P" => 4,
+                    _ => usize::MAX,
+                };
+                if count > limit {
+                    push(Construct::RectangularHole, DEPRECATED_OPTIONS);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+fn check_word(word: &str, push: &mut impl FnMut(Construct, &'static str)) {
+    if let Some(rest) = word.strip_prefix('G') {
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let (number, tail) = rest.split_at(digits);
+        // comments may contain anything
+        if number == "04" || number == "4" {
+            if number == "4" {
+                push(Construct::ShortCode, DEPRECATED_SYNTAX);
+            }
+            return;
+        }
+        match number.parse::<u8>() {
+            Ok(54 | 55 | 70 | 71 | 74 | 90 | 91) => push(
+                Construct::DeprecatedCommand(format!("G{number}")),
+                DEPRECATED_COMMANDS,
+            ),
+            Ok(1..=3) if number.len() == 1 => push(Construct::ShortCode, DEPRECATED_SYNTAX),
+            _ => (),
+        }
+        if matches!(number, "01" | "02" | "03" | "1" | "2" | "3") && !tail.is_empty() {
+            push(Construct::CombinedCodes, DEPRECATED_SYNTAX);
+        }
+    } else if word == "M00" || word == "M01" {
+        push(
+            Construct::DeprecatedCommand(word.to_string()),
+            DEPRECATED_COMMANDS,
+        );
+    } else if word.starts_with(['X', 'Y', 'I', 'J']) && !word.contains('D') {
+        push(Construct::CoordinatesWithoutOperation, DEPRECATED_SYNTAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn constructs(src: &str) -> Vec<Construct> {
+        report(src)
+            .findings
+            .into_iter()
+            .map(|f| f.construct)
+            .collect()
+    }
+
+    #[test]
+    fn test_conformant() {
+        let src = indoc! {"
+            G04 Comment with G70 and X100*
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1X0.05*%
+            G01*
+            D10*
+            X0Y0D02*
+            X100Y100D01*
+            M02*
+        "};
+        assert!(report(src).is_conformant());
+    }
+
+    #[test]
+    fn test_deprecated_commands() {
+        let src = indoc! {"
+            %FSTAX24Y24*%
+            %IPPOS*%
+            G70*
+            G54D10*
+            G1*
+            G01X100Y100D01*
+            X200Y200*
+            M02*
+        "};
+        assert_eq!(
+            constructs(src),
+            vec![
+                Construct::DeprecatedFormatOption,
+                Construct::DeprecatedCommand("IP".into()),
+                Construct::DeprecatedCommand("G70".into()),
+                Construct::DeprecatedCommand("G54".into()),
+                Construct::ShortCode,
+                Construct::CombinedCodes,
+                Construct::CoordinatesWithoutOperation,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deprecated_options() {
+        let src = indoc! {"
+            %ADD10C,1X0.2X0.3*%
+            %AMLINE*
+            2,1,0.1,0,0,1,1,0*
+            21,1,1,1,0,0,0*%
+            M02*
+        "};
+        assert_eq!(
+            constructs(src),
+            vec![
+                Construct::RectangularHole,
+                Construct::DeprecatedPrimitive(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let src = "G04 ok*\n%IPPOS*%\nM02*\n";
+        let report = report(src);
+        assert_eq!(report.findings[0].span, 8..16);
+        assert_eq!(&src[report.findings[0].span.clone()], "%IPPOS*%");
+        assert_eq!(report.findings[0].section, "8.1");
+    }
+}
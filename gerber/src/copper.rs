@@ -0,0 +1,1737 @@
+//! The united shape of a layer
+//!
+//! Every object of the [image](crate::image) is converted to polygons, and
+//! the objects are combined in order: dark objects are added to the shape
+//! and clear objects are cut out of it. The result is the final image of
+//! the layer as polygons with holes, the starting point for area
+//! statistics, design rule checks, connectivity and 3D export.
+//!
+//! Curves are approximated by polygons within `tolerance` millimeters of
+//! the true curve, inscribed by default; [ConvertOptions] picks the side.
+//! Draws follow the specification: a circle aperture gives round caps and,
+//! along arcs, exact concentric sides, and a rectangle aperture sweeps the
+//! hull of its corners with no rounding.
+//!
+//! The boolean operations are done by
+//! [i_overlay](https://crates.io/crates/i_overlay) on integer coordinates,
+//! with every path snapped once to a fixed grid of [RESOLUTION]. Results are
+//! exact on that grid and stay on it through any number of operations, so
+//! copper pours with thousands of nearly coincident vertices can't make them
+//! drift or fail.
+
+use std::collections::HashMap;
+use std::f64::consts::{PI, TAU};
+
+use i_overlay::core::fill_rule::FillRule;
+use i_overlay::core::overlay::Overlay;
+use i_overlay::core::overlay_rule::OverlayRule;
+use i_overlay::i_float::int::point::IntPoint;
+use i_overlay::i_shape::int::shape::{IntContour, IntShape};
+
+use crate::aperture::{self, segments, ApertureShape, ApertureTemplate, StandardAperture};
+use crate::command::Command::*;
+use crate::data::{InterpolationMode, Mirroring, Polarity, Unit};
+use crate::image::{ArcGeometry, Contour, Object, Point, Segment, Shape};
+use crate::macros::{ApertureMacro, MacroPrimitive};
+use crate::{parallel, GerberError, GerberLayer};
+
+pub use crate::aperture::Placement;
+
+type Path = Vec<[f64; 2]>;
+
+/// The grid the boolean operations work on, in millimeters
+///
+/// Ten nanometers is finer than any Gerber coordinate format in practice,
+/// and leaves room for coordinates up to ten meters from the origin.
+pub const RESOLUTION: f64 = 1e-5;
+
+/// Grid steps per millimeter, dividing by which is exact for round numbers
+const STEPS: f64 = 1e5;
+
+/// An area bounded by an exterior and cut by holes, in millimeters
+///
+/// The exterior is counter-clockwise and holes are clockwise.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Polygon {
+    pub exterior: Vec<Point>,
+    pub holes: Vec<Vec<Point>>,
+}
+
+impl Polygon {
+    /// Run the exterior counter-clockwise and the holes clockwise
+    ///
+    /// The polygons of this module are always wound this way. This is for
+    /// polygons built or transformed by hand.
+    pub fn normalize_winding(&mut self) {
+        if ring_signed_area(&self.exterior) < 0.0 {
+            self.exterior.reverse();
+        }
+        for hole in &mut self.holes {
+            if ring_signed_area(hole) > 0.0 {
+                hole.reverse();
+            }
+        }
+    }
+
+    /// The exterior followed by the holes, as single precision points for
+    /// GPU buffers
+    ///
+    /// The points of polygons from this module are on the [RESOLUTION]
+    /// grid, and a grid value is never close enough to halfway between two
+    /// `f32`s for rounding through `f64` to go the wrong way, so these are
+    /// the grid values correctly rounded to `f32`.
+    pub fn to_f32(&self) -> Vec<Vec<[f32; 2]>> {
+        std::iter::once(&self.exterior)
+            .chain(&self.holes)
+            .map(|ring| {
+                ring.iter()
+                    .map(|point| [point.x as f32, point.y as f32])
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The area in square millimeters
+    pub fn area(&self) -> f64 {
+        ring_area(&self.exterior) - self.holes.iter().map(|hole| ring_area(hole)).sum::<f64>()
+    }
+
+    /// True if `point` is inside the exterior and outside the holes
+    pub fn contains(&self, point: Point) -> bool {
+        ring_contains(&self.exterior, point)
+            && !self.holes.iter().any(|hole| ring_contains(hole, point))
+    }
+}
+
+/// The united shape of a layer
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Copper {
+    pub polygons: Vec<Polygon>,
+
+    /// Indices of objects left out because their geometry is unknown, i.e.
+    /// flashes and draws of apertures whose macro is undefined or can't be
+    /// evaluated
+    pub skipped: Vec<usize>,
+}
+
+/// How objects are converted to polygons
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ConvertOptions {
+    /// How far the polygons may be from the true curves, in millimeters
+    pub tolerance: f64,
+
+    /// Which side of the true curves the polygons are on
+    pub placement: Placement,
+}
+
+impl Default for ConvertOptions {
+    /// Inscribed within a micrometer
+    fn default() -> Self {
+        ConvertOptions {
+            tolerance: 0.001,
+            placement: Placement::default(),
+        }
+    }
+}
+
+impl Copper {
+    /// The total area in square millimeters
+    pub fn area(&self) -> f64 {
+        self.polygons.iter().map(Polygon::area).sum()
+    }
+
+    /// The areas of `board` without copper
+    ///
+    /// The board is usually the [profile](GerberLayer::profile) of the
+    /// board outline layer.
+    pub fn clearance(&self, board: &[Polygon]) -> Copper {
+        let board = shapes(board);
+        let copper = shapes(&self.polygons);
+        Copper {
+            polygons: polygons(overlay(
+                &board,
+                &copper,
+                OverlayRule::Difference,
+                FillRule::NonZero,
+            )),
+            skipped: self.skipped.clone(),
+        }
+    }
+
+    /// The areas covered by one of `self` and `other` but not both, i.e.
+    /// what changed between two revisions of a layer
+    ///
+    /// Skipped objects belong to either layer, so none are listed.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let old = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,2X2*%\nD10*\nX0Y0D03*\nM02*\n";
+    /// let new = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,2X2*%\nD10*\nX1000000Y0D03*\nM02*\n";
+    /// let old = GerberLayer::parse(old).unwrap().copper(0.001);
+    /// let new = GerberLayer::parse(new).unwrap().copper(0.001);
+    /// assert!((old.symmetric_difference(&new).area() - 4.0).abs() < 1e-6);
+    /// ```
+    pub fn symmetric_difference(&self, other: &Copper) -> Copper {
+        Copper {
+            polygons: polygons(overlay(
+                &shapes(&self.polygons),
+                &shapes(&other.polygons),
+                OverlayRule::Xor,
+                FillRule::NonZero,
+            )),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// The narrowest gap between two separate polygons, the smallest
+    /// copper to copper spacing of the layer, or `None` with fewer than
+    /// two polygons
+    ///
+    /// Only distances between polygons count, so a notch in the outline of
+    /// a single polygon is no gap.
+    pub fn smallest_gap(&self) -> Option<f64> {
+        let mut boxes: Vec<_> = self
+            .polygons
+            .iter()
+            .filter_map(|polygon| Some((ring_bounds(&polygon.exterior)?, polygon)))
+            .collect();
+        boxes.sort_by(|(a, _), (b, _)| a.0.x.total_cmp(&b.0.x));
+
+        let mut smallest: Option<f64> = None;
+        for (index, (a, p)) in boxes.iter().enumerate() {
+            for (b, q) in &boxes[index + 1..] {
+                let limit = smallest.unwrap_or(f64::INFINITY);
+                // sorted by left edge, so the rest are further right
+                if b.0.x - a.1.x >= limit {
+                    break;
+                }
+                if bounds_distance(*a, *b) >= limit {
+                    continue;
+                }
+                let gap = polygon_distance(p, q, limit);
+                if gap < limit {
+                    smallest = Some(gap);
+                }
+            }
+        }
+        smallest
+    }
+
+    /// A Gerber file with a region for each polygon
+    ///
+    /// Holes are clear regions. Larger polygons are written first, so a
+    /// polygon within the hole of another isn't cleared by it.
+    pub fn to_gerber(&self) -> String {
+        let mut polygons: Vec<_> = self.polygons.iter().collect();
+        polygons.sort_by(|a, b| b.area().total_cmp(&a.area()));
+
+        let mut gerber = String::from("%FSLAX46Y46*%\n%MOMM*%\nG01*\n");
+        let mut polarity = Polarity::Dark;
+        let mut region = |gerber: &mut String, ring: &[Point], new: Polarity| {
+            if new != polarity {
+                polarity = new;
+                gerber.push_str(match new {
+                    Polarity::Dark => "%LPD*%\n",
+                    Polarity::Clear => "%LPC*%\n",
+                });
+            }
+            gerber.push_str("G36*\n");
+            for (index, point) in ring.iter().chain(ring.first()).enumerate() {
+                let operation = if index == 0 { "D02" } else { "D01" };
+                // six decimals of a millimeter are nanometers
+                let (x, y) = point.to_nm();
+                gerber.push_str(&format!("X{x}Y{y}{operation}*\n"));
+            }
+            gerber.push_str("G37*\n");
+        };
+        for polygon in polygons {
+            region(&mut gerber, &polygon.exterior, Polarity::Dark);
+            for hole in &polygon.holes {
+                region(&mut gerber, hole, Polarity::Clear);
+            }
+        }
+        gerber.push_str("M02*\n");
+        gerber
+    }
+
+    /// Write the polygons as an SVG image filled with an RGBA `color`
+    ///
+    /// Each polygon is one path whose holes are subpaths, filled with the
+    /// even-odd rule, so holes are transparent and show whatever is under
+    /// the image rather than a background color. The SVG y axis points
+    /// down, so y coordinates are negated.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,2X2X1*%\nD10*\nX0Y0D03*\nM02*\n";
+    /// let copper = GerberLayer::parse(src).unwrap().copper(0.1);
+    /// let svg = copper.to_svg([184, 115, 51, 255]);
+    /// assert!(svg.contains(r#"fill-rule="evenodd""#));
+    /// assert!(svg.contains(r#"viewBox="-1 -1 2 2""#));
+    /// ```
+    pub fn to_svg(&self, color: [u8; 4]) -> String {
+        let (min, max) = self.bounds().unwrap_or_default();
+        let (width, height) = (max.x - min.x, max.y - min.y);
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}mm\" height=\"{height}mm\" \
+             viewBox=\"{} {} {width} {height}\">\n{}</svg>\n",
+            min.x,
+            -max.y,
+            self.svg_paths(color)
+        )
+    }
+
+    /// The lower left and upper right corners of the polygons
+    pub(crate) fn bounds(&self) -> Option<(Point, Point)> {
+        let points = self.polygons.iter().flat_map(|polygon| &polygon.exterior);
+        points.fold(None, |bounds, point| {
+            let (min, max) = bounds.unwrap_or((*point, *point));
+            Some((
+                Point {
+                    x: min.x.min(point.x),
+                    y: min.y.min(point.y),
+                },
+                Point {
+                    x: max.x.max(point.x),
+                    y: max.y.max(point.y),
+                },
+            ))
+        })
+    }
+
+    /// An SVG path element for each polygon, see [to_svg](Copper::to_svg)
+    pub(crate) fn svg_paths(&self, color: [u8; 4]) -> String {
+        let [red, green, blue, alpha] = color;
+        let opacity = (alpha as f64 / 255.0 * 1000.0).round() / 1000.0;
+        let mut svg = String::new();
+        for polygon in &self.polygons {
+            let mut data = String::new();
+            for ring in std::iter::once(&polygon.exterior).chain(&polygon.holes) {
+                for (index, point) in ring.iter().enumerate() {
+                    let command = if index == 0 { 'M' } else { 'L' };
+                    data.push_str(&format!("{command}{} {} ", point.x, -point.y));
+                }
+                data.push('Z');
+            }
+            svg.push_str(&format!(
+                "<path fill=\"#{red:02x}{green:02x}{blue:02x}\" fill-opacity=\"{opacity}\" \
+                 fill-rule=\"evenodd\" d=\"{data}\"/>\n"
+            ));
+        }
+        svg
+    }
+}
+
+impl GerberLayer<'_> {
+    /// Unite the objects of the layer into polygons
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,2X2*%\nD10*\n\
+    ///            X0Y0D03*\nX1000000Y0D03*\nM02*\n";
+    /// let copper = GerberLayer::parse(src).unwrap().copper(0.001);
+    /// assert_eq!(copper.polygons.len(), 1);
+    /// assert!((copper.area() - 6.0).abs() < 1e-6);
+    /// ```
+    pub fn copper(&self, tolerance: f64) -> Copper {
+        self.copper_with_options(&ConvertOptions {
+            tolerance,
+            ..Default::default()
+        })
+    }
+
+    /// The united shape of the layer, with control over how curves are
+    /// approximated
+    ///
+    /// ```
+    /// use gerber::copper::{ConvertOptions, Placement};
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,2*%\nD10*\nX0Y0D03*\nM02*\n";
+    /// let layer = GerberLayer::parse(src).unwrap();
+    /// let area = |placement| {
+    ///     let options = ConvertOptions { tolerance: 0.01, placement };
+    ///     layer.copper_with_options(&options).area()
+    /// };
+    /// let circle = std::f64::consts::PI;
+    /// assert!(area(Placement::Inscribed) < circle);
+    /// assert!(area(Placement::Circumscribed) > circle);
+    /// ```
+    pub fn copper_with_options(&self, options: &ConvertOptions) -> Copper {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let paths = self.convert(image.objects.iter().collect(), unit, options);
+
+        // united in order, as clear objects only clear what comes before
+        let mut union = Union::default();
+        let mut skipped = Vec::new();
+        for (index, (object, paths)) in image.objects.iter().zip(paths).enumerate() {
+            match paths {
+                Some(paths) => {
+                    for paths in paths {
+                        union.add(object.polarity, paths);
+                    }
+                }
+                None => skipped.push(index),
+            }
+        }
+        Copper {
+            polygons: union.finish(),
+            skipped,
+        }
+    }
+
+    /// The union of `objects` of the layer's image, whatever their
+    /// polarity, leaving out those whose geometry is unknown
+    pub(crate) fn unite(
+        &self,
+        objects: Vec<&Object>,
+        unit: Unit,
+        options: &ConvertOptions,
+    ) -> Vec<Polygon> {
+        let mut union = Union::default();
+        for paths in self.convert(objects, unit, options).into_iter().flatten() {
+            for paths in paths {
+                union.add(Polarity::Dark, paths);
+            }
+        }
+        union.finish()
+    }
+
+    /// The paths of each of `objects`, in order
+    fn convert(
+        &self,
+        objects: Vec<&Object>,
+        unit: Unit,
+        options: &ConvertOptions,
+    ) -> Vec<Option<Vec<Paths>>> {
+        let macros = self.aperture_macros();
+        parallel::map(objects, |object| {
+            let template = match object.source.aperture.map(|index| &self.commands[index]) {
+                Some(ApertureDefine(_, template)) => Some(template),
+                _ => None,
+            };
+            let converter = Converter {
+                object,
+                template,
+                macros: &macros,
+                unit,
+                tolerance: options.tolerance,
+                placement: options.placement,
+            };
+            converter.paths()
+        })
+    }
+}
+
+impl GerberLayer<'_> {
+    /// The board drawn by a profile layer (`.FileFunction,Profile`)
+    ///
+    /// The center lines of draws and arcs are joined end to end, where ends
+    /// within `tolerance` of each other meet, and regions are taken as
+    /// they are. Closed paths inside others, such as cutouts, are holes.
+    /// Paths which don't close are ignored.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\nD10*\nG01*\nX0Y0D02*\n\
+    ///            X10000000D01*\nY10000000D01*\nX0D01*\nY0D01*\nM02*\n";
+    /// let profile = GerberLayer::parse(src).unwrap().profile(0.001);
+    /// assert_eq!(profile[0].area(), 100.0);
+    /// ```
+    pub fn profile(&self, tolerance: f64) -> Vec<Polygon> {
+        let mut paths = Vec::new();
+        let mut lines = Vec::new();
+        for object in self.image().objects {
+            match object.shape {
+                Shape::Draw { start, end, .. } => {
+                    lines.push(vec![[start.x, start.y], [end.x, end.y]]);
+                }
+                Shape::Arc {
+                    start,
+                    end,
+                    center,
+                    direction,
+                    ..
+                } => {
+                    let points = arc_points(start, end, center, direction, tolerance);
+                    let line = std::iter::once(start)
+                        .chain(points)
+                        .map(|point| [point.x, point.y])
+                        .collect();
+                    lines.push(line);
+                }
+                Shape::Region { contours } => paths.extend(
+                    contours
+                        .iter()
+                        .map(|contour| contour_path(contour, tolerance)),
+                ),
+                Shape::Flash { .. } => (),
+            }
+        }
+        paths.extend(join(lines, tolerance));
+        let shape = vec![paths.iter().map(|path| contour(path)).collect()];
+        polygons(overlay(
+            &shape,
+            &[],
+            OverlayRule::Subject,
+            FillRule::EvenOdd,
+        ))
+    }
+}
+
+impl StandardAperture {
+    /// The aperture and its hole as a polygon within `tolerance` of the
+    /// true shape, curves inscribed
+    ///
+    /// Unlike the polygons of a [Copper], this is in the unit of the file
+    /// and relative to the flash point, before any transformation.
+    ///
+    /// ```
+    /// use gerber::aperture::{Circle, StandardAperture};
+    ///
+    /// let pad = StandardAperture::Circle(Circle { diameter: 1.0, hole: Some(0.4) });
+    /// let polygon = pad.polygon(0.0001);
+    /// assert_eq!(polygon.holes.len(), 1);
+    /// assert!((polygon.area() - 0.21 * std::f64::consts::PI).abs() < 1e-3);
+    /// ```
+    pub fn polygon(&self, tolerance: f64) -> Polygon {
+        let placement = Placement::Inscribed;
+        let exterior = self.outline(tolerance, placement);
+        let holes = self.hole_outline(tolerance, placement);
+        let mut polygon = Polygon {
+            exterior,
+            holes: holes.into_iter().collect(),
+        };
+        polygon.normalize_winding();
+        polygon
+    }
+}
+
+impl ApertureMacro {
+    /// The polygons of an aperture with the `parameters` of its `%AD`
+    /// command, within `tolerance` of the true shape
+    ///
+    /// The primitives are combined in order, so a clear exposure erases
+    /// the primitives before it but not those after. As for
+    /// [StandardAperture::polygon], the polygons are in the unit of the
+    /// file. A macro may give several, such as the four parts of a
+    /// thermal.
+    pub fn polygons(
+        &self,
+        parameters: &[f64],
+        tolerance: f64,
+    ) -> Result<Vec<Polygon>, GerberError> {
+        let mut union = Union::default();
+        for primitive in self.evaluate(parameters)? {
+            let (sin, cos) = primitive.rotation().to_radians().sin_cos();
+            let rotate = |path: Path| -> Path {
+                let path = path
+                    .into_iter()
+                    .map(|[x, y]| [x * cos - y * sin, x * sin + y * cos]);
+                counter_clockwise(path.collect())
+            };
+            for (outline, hole) in primitive_paths(&primitive, tolerance) {
+                let paths = Paths {
+                    outlines: vec![rotate(outline)],
+                    holes: hole.into_iter().map(rotate).collect(),
+                };
+                union.add(primitive.exposure(), paths);
+            }
+        }
+        Ok(union.finish())
+    }
+}
+
+impl ApertureTemplate<'_> {
+    /// The polygons of the aperture within `tolerance` of the true shape,
+    /// in the unit of the file, looking up a macro by name in `macros`
+    ///
+    /// Returns [GerberError::Macro] for an undefined or invalid macro, and
+    /// [GerberError::PolygonVertices] for an invalid polygon.
+    ///
+    /// ```
+    /// use gerber::aperture::ApertureTemplate;
+    /// use gerber::macros::ApertureMacro;
+    ///
+    /// let thermal = ApertureMacro::parse("Thermal", &["7,0,0,1,0.6,0.2,0"]).unwrap();
+    /// let template = ApertureTemplate::Macro {
+    ///     name: "Thermal".into(),
+    ///     parameters: Vec::new(),
+    /// };
+    /// assert_eq!(template.polygons(&[thermal], 0.001).unwrap().len(), 4);
+    /// ```
+    pub fn polygons(
+        &self,
+        macros: &[ApertureMacro],
+        tolerance: f64,
+    ) -> Result<Vec<Polygon>, GerberError> {
+        match (self.standard()?, self) {
+            (Some(shape), _) => Ok(vec![shape.polygon(tolerance)]),
+            (None, ApertureTemplate::Macro { name, parameters }) => {
+                ApertureMacro::find(macros, name)?.polygons(parameters, tolerance)
+            }
+            (None, _) => unreachable!("only macros have no standard shape"),
+        }
+    }
+}
+
+/// Outlines of a macro primitive before its rotation, each with a hole
+fn primitive_paths(primitive: &MacroPrimitive, tolerance: f64) -> Vec<(Path, Option<Path>)> {
+    let offset = |path: Path, center: Point| -> Path {
+        let path = path.into_iter().map(|[x, y]| [x + center.x, y + center.y]);
+        path.collect()
+    };
+    let rectangle = |min: [f64; 2], max: [f64; 2]| {
+        vec![
+            [min[0], min[1]],
+            [max[0], min[1]],
+            [max[0], max[1]],
+            [min[0], max[1]],
+        ]
+    };
+    match *primitive {
+        MacroPrimitive::Circle {
+            diameter, center, ..
+        } => {
+            let circle = aperture::circle(diameter / 2.0, tolerance, Placement::Inscribed);
+            vec![(offset(circle, center), None)]
+        }
+        MacroPrimitive::VectorLine {
+            width, start, end, ..
+        } => {
+            let (dx, dy) = (end.x - start.x, end.y - start.y);
+            let length = dx.hypot(dy);
+            if length == 0.0 {
+                return Vec::new();
+            }
+            // half the width across the line
+            let (nx, ny) = (-dy / length * width / 2.0, dx / length * width / 2.0);
+            let path = vec![
+                [start.x + nx, start.y + ny],
+                [start.x - nx, start.y - ny],
+                [end.x - nx, end.y - ny],
+                [end.x + nx, end.y + ny],
+            ];
+            vec![(path, None)]
+        }
+        MacroPrimitive::CenterLine {
+            width,
+            height,
+            center,
+            ..
+        } => {
+            let (x, y) = (width / 2.0, height / 2.0);
+            vec![(offset(rectangle([-x, -y], [x, y]), center), None)]
+        }
+        MacroPrimitive::Outline { ref points, .. } => {
+            vec![(
+                points.iter().map(|point| [point.x, point.y]).collect(),
+                None,
+            )]
+        }
+        MacroPrimitive::Polygon {
+            vertices,
+            center,
+            diameter,
+            ..
+        } => {
+            let shape = aperture::Polygon {
+                diameter,
+                vertices,
+                rotation: 0.0,
+                hole: None,
+            };
+            let path = shape.vertices().iter().map(|p| [p.x, p.y]).collect();
+            vec![(offset(path, center), None)]
+        }
+        MacroPrimitive::Thermal {
+            center,
+            outer,
+            inner,
+            gap,
+            ..
+        } => {
+            let circle = |diameter: f64| {
+                let circle = aperture::circle(diameter / 2.0, tolerance, Placement::Inscribed);
+                contour(&offset(circle, center))
+            };
+            let mut inner = circle(inner);
+            inner.reverse();
+            let ring = [vec![circle(outer), inner]];
+            let (r, g) = (outer / 2.0, gap / 2.0);
+            let cross = [
+                vec![contour(&offset(rectangle([-r, -g], [r, g]), center))],
+                vec![contour(&offset(rectangle([-g, -r], [g, r]), center))],
+            ];
+            let parts = overlay(&ring, &cross, OverlayRule::Difference, FillRule::NonZero);
+            polygons(parts)
+                .into_iter()
+                .map(|polygon| {
+                    let path = |ring: &Vec<Point>| ring.iter().map(|p| [p.x, p.y]).collect();
+                    (path(&polygon.exterior), polygon.holes.first().map(path))
+                })
+                .collect()
+        }
+    }
+}
+
+/// Join lines end to end into closed paths
+fn join(lines: Vec<Path>, tolerance: f64) -> Vec<Path> {
+    let key = |[x, y]: [f64; 2]| {
+        (
+            (x / tolerance).round() as i64,
+            (y / tolerance).round() as i64,
+        )
+    };
+    let mut ends: HashMap<_, Vec<usize>> = HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        ends.entry(key(line[0])).or_default().push(index);
+        ends.entry(key(line[line.len() - 1]))
+            .or_default()
+            .push(index);
+    }
+
+    let mut used = vec![false; lines.len()];
+    let mut closed = Vec::new();
+    for first in 0..lines.len() {
+        if used[first] {
+            continue;
+        }
+        used[first] = true;
+        let mut path = lines[first].clone();
+        let start = key(path[0]);
+        loop {
+            let end = key(path[path.len() - 1]);
+            if end == start && path.len() > 2 {
+                closed.push(path);
+                break;
+            }
+            let next = ends
+                .get(&end)
+                .and_then(|candidates| candidates.iter().find(|&&index| !used[index]));
+            let Some(&next) = next else {
+                break;
+            };
+            used[next] = true;
+            let line = &lines[next];
+            if key(line[0]) == end {
+                path.extend(&line[1..]);
+            } else {
+                path.extend(line.iter().rev().skip(1));
+            }
+        }
+    }
+    closed
+}
+
+/// The parts of polygons inside the box from `min` to `max`
+pub(crate) fn clip(subject: &[Polygon], min: Point, max: Point) -> Vec<Polygon> {
+    let rectangle = vec![
+        [min.x, min.y],
+        [max.x, min.y],
+        [max.x, max.y],
+        [min.x, max.y],
+    ];
+    let clip = [vec![contour(&rectangle)]];
+    polygons(overlay(
+        &shapes(subject),
+        &clip,
+        OverlayRule::Intersect,
+        FillRule::NonZero,
+    ))
+}
+
+/// A boolean operation on the grid
+fn overlay(
+    subject: &[IntShape],
+    clip: &[IntShape],
+    rule: OverlayRule,
+    fill: FillRule,
+) -> Vec<IntShape> {
+    Overlay::with_shapes(subject, clip).overlay(rule, fill)
+}
+
+/// Snap a path to the grid
+///
+/// Coordinates beyond the range of the grid are clamped to its edge.
+fn contour(path: &[[f64; 2]]) -> IntContour {
+    let snap = |value: f64| (value * STEPS).round() as i32;
+    path.iter()
+        .map(|&[x, y]| IntPoint::new(snap(x), snap(y)))
+        .collect()
+}
+
+/// Convert shapes from the boolean operations into polygons
+fn polygons(shapes: Vec<IntShape>) -> Vec<Polygon> {
+    let point = |point: &IntPoint| Point {
+        x: point.x as f64 / STEPS,
+        y: point.y as f64 / STEPS,
+    };
+    shapes
+        .into_iter()
+        .filter_map(|shape| {
+            let mut contours = shape.into_iter();
+            let exterior = contours.next()?.iter().map(point).collect();
+            let holes = contours
+                .map(|hole| hole.iter().map(point).collect())
+                .collect();
+            let mut polygon = Polygon { exterior, holes };
+            polygon.normalize_winding();
+            Some(polygon)
+        })
+        .collect()
+}
+
+/// Convert polygons into shapes for the boolean operations
+fn shapes(polygons: &[Polygon]) -> Vec<IntShape> {
+    let path = |ring: &Vec<Point>| {
+        let path: Path = ring.iter().map(|point| [point.x, point.y]).collect();
+        contour(&path)
+    };
+    polygons
+        .iter()
+        .map(|polygon| {
+            std::iter::once(&polygon.exterior)
+                .chain(&polygon.holes)
+                .map(path)
+                .collect()
+        })
+        .collect()
+}
+
+/// The paths of one object, or of one polygon of a flash of a macro
+struct Paths {
+    /// Counter-clockwise paths whose union is the object
+    outlines: Vec<Path>,
+
+    /// Holes cut out of the outlines, for flashes of apertures with them
+    holes: Vec<Path>,
+}
+
+/// Combines objects in order, batching runs of the same polarity so each
+/// run needs a single boolean operation with the result so far
+#[derive(Default)]
+struct Union {
+    result: Vec<IntShape>,
+    polarity: Polarity,
+
+    /// Outlines of the current run, all counter-clockwise so the non-zero
+    /// fill rule unites them
+    outlines: Vec<IntContour>,
+
+    /// Objects of the current run with holes, which have to be united one
+    /// at a time so the hole doesn't cut the other objects
+    holed: Vec<Vec<IntShape>>,
+}
+
+impl Union {
+    fn add(&mut self, polarity: Polarity, paths: Paths) {
+        if polarity != self.polarity {
+            self.flush();
+            self.polarity = polarity;
+        }
+        if paths.holes.is_empty() {
+            self.outlines
+                .extend(paths.outlines.iter().map(|path| contour(path)));
+        } else {
+            let outlines = [paths.outlines.iter().map(|path| contour(path)).collect()];
+            let holes = [paths.holes.iter().map(|path| contour(path)).collect()];
+            let shape = overlay(
+                &outlines,
+                &holes,
+                OverlayRule::Difference,
+                FillRule::NonZero,
+            );
+            self.holed.push(shape);
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.outlines.is_empty() && self.holed.is_empty() {
+            return;
+        }
+        let outlines = [std::mem::take(&mut self.outlines)];
+        let mut run = overlay(&outlines, &[], OverlayRule::Subject, FillRule::NonZero);
+        for shape in self.holed.drain(..) {
+            run = overlay(&run, &shape, OverlayRule::Union, FillRule::NonZero);
+        }
+        let rule = match self.polarity {
+            Polarity::Dark => OverlayRule::Union,
+            Polarity::Clear => OverlayRule::Difference,
+        };
+        self.result = overlay(&self.result, &run, rule, FillRule::NonZero);
+    }
+
+    fn finish(mut self) -> Vec<Polygon> {
+        self.flush();
+        polygons(self.result)
+    }
+}
+
+/// The outline of a flash's aperture relative to the flash point, in
+/// millimeters, or `None` if the aperture's macro is undefined or can't be
+/// evaluated
+///
+/// The outline of a macro aperture is the convex hull of its polygons.
+pub(crate) fn flash_outline(
+    object: &Object,
+    template: &ApertureTemplate,
+    macros: &[ApertureMacro],
+    unit: Unit,
+    tolerance: f64,
+) -> Option<Vec<Point>> {
+    let converter = Converter {
+        object,
+        template: Some(template),
+        macros,
+        unit,
+        tolerance,
+        placement: Placement::default(),
+    };
+    let outline = converter.aperture_hull()?;
+    Some(outline.into_iter().map(|[x, y]| Point { x, y }).collect())
+}
+
+/// The paths of an object in millimeters, to be filled with the non-zero
+/// rule: counter-clockwise outlines, and clockwise holes for a flash of an
+/// aperture with them. `None` if the aperture's macro is undefined or can't
+/// be evaluated.
+pub(crate) fn object_paths(
+    object: &Object,
+    template: Option<&ApertureTemplate>,
+    macros: &[ApertureMacro],
+    unit: Unit,
+    tolerance: f64,
+) -> Option<Vec<Vec<Point>>> {
+    let converter = Converter {
+        object,
+        template,
+        macros,
+        unit,
+        tolerance,
+        placement: Placement::default(),
+    };
+    let points = |path: Path| path.into_iter().map(|[x, y]| Point { x, y }).collect();
+    let mut result = Vec::new();
+    for paths in converter.paths()? {
+        let holes = paths.holes.into_iter().map(|mut hole| {
+            hole.reverse();
+            hole
+        });
+        result.extend(paths.outlines.into_iter().chain(holes).map(points));
+    }
+    Some(result)
+}
+
+/// The polygons of a single object in millimeters, ignoring its polarity,
+/// or `None` if the aperture's macro is undefined or can't be evaluated
+pub(crate) fn object_polygons(
+    object: &Object,
+    template: Option<&ApertureTemplate>,
+    macros: &[ApertureMacro],
+    unit: Unit,
+    tolerance: f64,
+) -> Option<Vec<Polygon>> {
+    let converter = Converter {
+        object,
+        template,
+        macros,
+        unit,
+        tolerance,
+        placement: Placement::default(),
+    };
+    let mut union = Union::default();
+    for paths in converter.paths()? {
+        union.add(Polarity::Dark, paths);
+    }
+    Some(union.finish())
+}
+
+/// Converts an object to paths
+struct Converter<'a> {
+    object: &'a Object,
+    template: Option<&'a ApertureTemplate<'a>>,
+
+    /// The macros of the layer, for flashes and draws of macro apertures
+    macros: &'a [ApertureMacro],
+    unit: Unit,
+    tolerance: f64,
+    placement: Placement,
+}
+
+impl Converter<'_> {
+    /// The paths of the object, one for each polygon of a flash of a macro
+    fn paths(&self) -> Option<Vec<Paths>> {
+        let paths = match &self.object.shape {
+            Shape::Flash { at, .. } => {
+                let place = |path: &Path| path.iter().map(|&[x, y]| [at.x + x, at.y + y]).collect();
+                let parts = self.aperture()?.into_iter();
+                return Some(
+                    parts
+                        .map(|(outline, holes)| Paths {
+                            outlines: vec![place(&outline)],
+                            holes: holes.iter().map(place).collect(),
+                        })
+                        .collect(),
+                );
+            }
+            Shape::Draw { start, end, .. } => Paths {
+                outlines: self.stroke(*start, *end)?.into_iter().collect(),
+                holes: Vec::new(),
+            },
+            Shape::Arc {
+                start,
+                end,
+                center,
+                direction,
+                ..
+            } => {
+                if let Some(outline) = self.round_arc(*start, *end, *center, *direction) {
+                    return Some(vec![Paths {
+                        outlines: vec![outline],
+                        holes: Vec::new(),
+                    }]);
+                }
+                let points = arc_points(*start, *end, *center, *direction, self.tolerance);
+                let mut outlines = Vec::new();
+                let mut from = *start;
+                for to in points {
+                    outlines.extend(self.stroke(from, to)?);
+                    from = to;
+                }
+                Paths {
+                    outlines,
+                    holes: Vec::new(),
+                }
+            }
+            Shape::Region { contours } => Paths {
+                outlines: contours
+                    .iter()
+                    .map(|contour| counter_clockwise(contour_path(contour, self.tolerance)))
+                    .collect(),
+                holes: Vec::new(),
+            },
+        };
+        Some(vec![paths])
+    }
+
+    /// The outlines of the aperture, each with its holes, relative to the
+    /// flash point, with the object's transformations applied
+    ///
+    /// A standard aperture has one outline, a macro one for each of its
+    /// polygons, whose curves are always inscribed.
+    fn aperture(&self) -> Option<Vec<(Path, Vec<Path>)>> {
+        let template = self.template?;
+        // polygonized in the unit of the layer, within the same tolerance
+        let scale = self.unit.to_mm(1.0) * self.object.scaling.0;
+        let tolerance = self.tolerance / scale;
+        let path = |points: &[Point]| -> Path {
+            let path = points
+                .iter()
+                .map(|point| [point.x * scale, point.y * scale]);
+            counter_clockwise(self.transform(path.collect()))
+        };
+        match template.standard().ok()? {
+            Some(shape) => {
+                let outline = path(&shape.outline(tolerance, self.placement));
+                let hole = shape.hole_outline(tolerance, self.placement);
+                Some(vec![(
+                    outline,
+                    hole.iter().map(|hole| path(hole)).collect(),
+                )])
+            }
+            None => {
+                let polygons = template.polygons(self.macros, tolerance).ok()?;
+                let parts = polygons.iter().map(|polygon| {
+                    let holes = polygon.holes.iter().map(|hole| path(hole)).collect();
+                    (path(&polygon.exterior), holes)
+                });
+                Some(parts.collect())
+            }
+        }
+    }
+
+    /// The convex hull of the outlines of the aperture, empty for a macro
+    /// without dark area
+    fn aperture_hull(&self) -> Option<Path> {
+        let points = self
+            .aperture()?
+            .into_iter()
+            .flat_map(|(outline, _)| outline);
+        Some(convex_hull(points.collect()))
+    }
+
+    /// Mirror, then rotate, an aperture outline about its origin
+    fn transform(&self, path: Path) -> Path {
+        let (mirror_x, mirror_y) = match self.object.mirroring {
+            Mirroring::None => (1.0, 1.0),
+            Mirroring::X => (-1.0, 1.0),
+            Mirroring::Y => (1.0, -1.0),
+            Mirroring::XY => (-1.0, -1.0),
+        };
+        let (sin, cos) = self.object.rotation.0.to_radians().sin_cos();
+        path.into_iter()
+            .map(|[x, y]| {
+                let (x, y) = (x * mirror_x, y * mirror_y);
+                [x * cos - y * sin, x * sin + y * cos]
+            })
+            .collect()
+    }
+
+    /// Points along `sweep` radians of a circle from `angle`, including
+    /// both ends, placed on the [Placement] side of the curve
+    fn curve(&self, center: [f64; 2], radius: f64, angle: f64, sweep: f64, convex: bool) -> Path {
+        let (tolerance, placement) = (self.tolerance, self.placement);
+        aperture::curve(center, radius, angle, sweep, convex, tolerance, placement)
+    }
+
+    /// The area swept by a circle aperture along an arc: a ring sector
+    /// between the arc offset inward and outward, with round caps
+    ///
+    /// `None` for other apertures, which are stroked piecewise, and for full
+    /// circles or arcs tighter than the aperture, which have no single
+    /// outline.
+    fn round_arc(
+        &self,
+        start: Point,
+        end: Point,
+        center: Point,
+        direction: InterpolationMode,
+    ) -> Option<Path> {
+        let ApertureTemplate::Circle { diameter, .. } = *self.template? else {
+            return None;
+        };
+        let half = self.unit.to_mm(diameter) * self.object.scaling.0 / 2.0;
+        let arc = ArcGeometry::new(start, end, center, direction);
+        if arc.sweep.abs() >= TAU || arc.radius <= half || half <= 0.0 {
+            return None;
+        }
+        let (from, sweep) = (arc.start_angle, arc.sweep);
+        let to = from + sweep;
+        let turn = PI.copysign(sweep);
+        let center = [center.x, center.y];
+        let mut path = self.curve(center, arc.radius + half, from, sweep, true);
+        path.extend(self.curve([end.x, end.y], half, to, turn, true));
+        path.extend(self.curve(center, arc.radius - half, to, -sweep, false));
+        path.extend(self.curve([start.x, start.y], half, from + turn, turn, true));
+        Some(counter_clockwise(path))
+    }
+
+    /// The area swept by the aperture from `start` to `end`, which for the
+    /// convex standard apertures is the hull of the aperture at both ends,
+    /// or `None` for a macro without dark area
+    ///
+    /// For a rectangle this is the sharp cornered sweep the specification
+    /// prescribes, not a rectangle around the line. A macro aperture is
+    /// taken as its convex hull.
+    fn stroke(&self, start: Point, end: Point) -> Option<Option<Path>> {
+        let outline = self.aperture_hull()?;
+        if outline.is_empty() {
+            return Some(None);
+        }
+        let points = outline
+            .iter()
+            .map(|&[x, y]| [start.x + x, start.y + y])
+            .chain(outline.iter().map(|&[x, y]| [end.x + x, end.y + y]))
+            .collect();
+        Some(Some(convex_hull(points)))
+    }
+}
+
+/// The path along a region contour
+fn contour_path(contour: &Contour, tolerance: f64) -> Path {
+    let mut path = vec![[contour.start.x, contour.start.y]];
+    let mut from = contour.start;
+    for segment in &contour.segments {
+        match *segment {
+            Segment::Line { end } => {
+                path.push([end.x, end.y]);
+                from = end;
+            }
+            Segment::Arc {
+                end,
+                center,
+                direction,
+            } => {
+                let points = arc_points(from, end, center, direction, tolerance);
+                path.extend(points.iter().map(|point| [point.x, point.y]));
+                from = end;
+            }
+        }
+    }
+    path
+}
+
+/// Points along an arc, excluding the start and including the end
+///
+/// An arc ending at its start is a full circle.
+fn arc_points(
+    start: Point,
+    end: Point,
+    center: Point,
+    direction: InterpolationMode,
+    tolerance: f64,
+) -> Vec<Point> {
+    let arc = ArcGeometry::new(start, end, center, direction);
+    let segments = segments(arc.radius, arc.sweep.abs(), tolerance);
+    let mut points: Vec<_> = (1..segments)
+        .map(|i| arc.point_at(arc.start_angle + arc.sweep * i as f64 / segments as f64))
+        .collect();
+    points.push(end);
+    points
+}
+
+/// Twice the signed area, positive for counter-clockwise paths
+fn signed_area(path: &[[f64; 2]]) -> f64 {
+    path.iter()
+        .zip(path.iter().cycle().skip(1))
+        .map(|([x0, y0], [x1, y1])| x0 * y1 - x1 * y0)
+        .sum()
+}
+
+/// Twice the area of a ring, positive when it runs counter-clockwise
+fn ring_signed_area(ring: &[Point]) -> f64 {
+    let path: Path = ring.iter().map(|point| [point.x, point.y]).collect();
+    signed_area(&path)
+}
+
+fn ring_area(ring: &[Point]) -> f64 {
+    ring_signed_area(ring).abs() / 2.0
+}
+
+/// True if `point` is inside a ring, by the even-odd rule
+fn ring_contains(ring: &[Point], point: Point) -> bool {
+    let mut inside = false;
+    for (p, q) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+        if (p.y > point.y) != (q.y > point.y) {
+            let x = p.x + (point.y - p.y) / (q.y - p.y) * (q.x - p.x);
+            if point.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// The lower left and upper right corners of a ring
+fn ring_bounds(ring: &[Point]) -> Option<(Point, Point)> {
+    let first = *ring.first()?;
+    Some(ring.iter().fold((first, first), |(min, max), point| {
+        (
+            Point {
+                x: min.x.min(point.x),
+                y: min.y.min(point.y),
+            },
+            Point {
+                x: max.x.max(point.x),
+                y: max.y.max(point.y),
+            },
+        )
+    }))
+}
+
+/// The distance between two boxes, zero when they overlap
+fn bounds_distance(a: (Point, Point), b: (Point, Point)) -> f64 {
+    let dx = (b.0.x - a.1.x).max(a.0.x - b.1.x).max(0.0);
+    let dy = (b.0.y - a.1.y).max(a.0.y - b.1.y).max(0.0);
+    dx.hypot(dy)
+}
+
+/// The smallest distance between the rings of two polygons, or `limit`
+/// if none is smaller
+///
+/// The polygons of a [Copper] don't overlap, so their edges don't cross
+/// and the closest points are always at a vertex of one of them.
+fn polygon_distance(p: &Polygon, q: &Polygon, limit: f64) -> f64 {
+    let edges = |polygon: &'_ Polygon| {
+        let rings = std::iter::once(&polygon.exterior).chain(&polygon.holes);
+        rings
+            .flat_map(|ring| ring.iter().zip(ring.iter().cycle().skip(1)))
+            .map(|(a, b)| (*a, *b))
+            .collect::<Vec<_>>()
+    };
+    let (p, q) = (edges(p), edges(q));
+    let mut smallest = limit;
+    for &(a, b) in &p {
+        let bounds = ring_bounds(&[a, b]).expect("an edge has two points");
+        for &(c, d) in &q {
+            let other = ring_bounds(&[c, d]).expect("an edge has two points");
+            if bounds_distance(bounds, other) >= smallest {
+                continue;
+            }
+            smallest = smallest
+                .min(segment_distance(a, c, d))
+                .min(segment_distance(b, c, d))
+                .min(segment_distance(c, a, b))
+                .min(segment_distance(d, a, b));
+        }
+    }
+    smallest
+}
+
+/// The distance from `point` to the segment from `a` to `b`
+fn segment_distance(point: Point, a: Point, b: Point) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((point.x - a.x) * dx + (point.y - a.y) * dy) / length).clamp(0.0, 1.0)
+    };
+    (point.x - a.x - t * dx).hypot(point.y - a.y - t * dy)
+}
+
+fn counter_clockwise(mut path: Path) -> Path {
+    if signed_area(&path) < 0.0 {
+        path.reverse();
+    }
+    path
+}
+
+/// The counter-clockwise convex hull, by Andrew's monotone chain
+fn convex_hull(mut points: Path) -> Path {
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: [f64; 2], a: [f64; 2], b: [f64; 2]| {
+        (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+    };
+    let mut hull: Path = Vec::with_capacity(points.len() + 1);
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // the last point of each half is the first of the other
+        hull.pop();
+    }
+    hull
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{MacroTemplate, RoundedRectangle};
+    use indoc::indoc;
+
+    const HEADER: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10C,1*%
+        %ADD11R,2X2*%
+        %ADD12R,2X2X1*%
+        %ADD13O,1X2*%
+        %ADD14P,2X4*%
+    "};
+
+    fn copper(body: &str) -> Copper {
+        let src = format!("{HEADER}{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().copper(0.00001)
+    }
+
+    fn assert_area(copper: &Copper, expected: f64) {
+        let area = copper.area();
+        assert!((area - expected).abs() < 1e-3, "{area} != {expected}");
+    }
+
+    #[test]
+    fn test_flashes() {
+        assert_area(&copper("D10*\nX0Y0D03*\n"), PI / 4.0);
+        assert_area(&copper("D11*\nX0Y0D03*\n"), 4.0);
+        assert_area(&copper("D12*\nX0Y0D03*\n"), 4.0 - PI / 4.0);
+        assert_area(&copper("D13*\nX0Y0D03*\n"), 1.0 + PI / 4.0);
+        assert_area(&copper("D14*\nX0Y0D03*\n"), 2.0);
+    }
+
+    #[test]
+    fn test_union() {
+        let copper = copper(indoc! {"
+            D11*
+            X0Y0D03*
+            X1000000Y0D03*
+            X10000000Y0D03*
+        "});
+        assert_eq!(copper.polygons.len(), 2);
+        assert_area(&copper, 6.0 + 4.0);
+        assert!(copper.polygons.iter().all(|p| p.holes.is_empty()));
+    }
+
+    #[test]
+    fn test_hole_does_not_cut_other_objects() {
+        // the hole of D12 is covered by the flash of D11 before it
+        let copper = copper("D11*\nX0Y0D03*\nD12*\nX0Y0D03*\n");
+        assert_area(&copper, 4.0);
+
+        // but it does show when nothing covers it
+        let copper = self::copper("D12*\nX0Y0D03*\nX5000000Y0D03*\n");
+        assert_eq!(copper.polygons[0].holes.len(), 1);
+    }
+
+    #[test]
+    fn test_clear_polarity() {
+        let copper = copper(indoc! {"
+            D11*
+            X0Y0D03*
+            %LPC*%
+            D10*
+            X0Y0D03*
+            %LPD*%
+            D10*
+            X5000000Y0D03*
+        "});
+        assert_area(&copper, 4.0 - PI / 4.0 + PI / 4.0);
+        assert_eq!(copper.polygons.len(), 2);
+    }
+
+    #[test]
+    fn test_strokes() {
+        // a capsule 4mm long with 1mm round ends
+        assert_area(
+            &copper("D10*\nG01*\nX0Y0D02*\nX4000000D01*\n"),
+            4.0 + PI / 4.0,
+        );
+        // a rectangle swept diagonally
+        assert_area(
+            &copper("D11*\nG01*\nX0Y0D02*\nX2000000Y2000000D01*\n"),
+            4.0 + 2.0 * 2.0 * 2.0,
+        );
+        // a half ring of radius 5 drawn with a 1mm circle, where the
+        // approximated center line is slightly shorter than the arc
+        let area = copper("D10*\nG75*\nG03*\nX5000000Y0D02*\nX-5000000Y0I-5000000J0D01*\n").area();
+        assert!((area - (PI * 5.0 + PI / 4.0)).abs() < 1e-2, "{area}");
+    }
+
+    #[test]
+    fn test_placement() {
+        let src =
+            format!("{HEADER}D10*\nG75*\nG03*\nX5000000Y0D02*\nX-5000000Y0I-5000000J0D01*\nM02*\n");
+        let layer = GerberLayer::parse(&src).unwrap();
+        let area = |placement| {
+            let options = ConvertOptions {
+                tolerance: 0.01,
+                placement,
+            };
+            layer.copper_with_options(&options).area()
+        };
+        // a half ring with round caps
+        let exact = PI * 5.0 + PI / 4.0;
+        let inscribed = area(Placement::Inscribed);
+        let circumscribed = area(Placement::Circumscribed);
+        let balanced = area(Placement::Balanced);
+        assert!(inscribed < exact && exact < circumscribed);
+        assert!((balanced - exact).abs() < (inscribed - exact).abs());
+        assert!((balanced - exact).abs() < (circumscribed - exact).abs());
+    }
+
+    #[test]
+    fn test_grid() {
+        // a pour whose bottom edge zigzags by a nanometer at every micrometer,
+        // half covered by a pad
+        let mut body = String::from("G01*\nG36*\nX0Y0D02*\n");
+        for i in 1..=2000 {
+            body.push_str(&format!("X{}Y{}D01*\n", i * 1000, i % 2));
+        }
+        body.push_str("Y1000000D01*\nX0D01*\nY0D01*\nG37*\nD11*\nX1000000Y1500000D03*\n");
+        let pour = copper(&body);
+        assert_area(&pour, 5.0);
+        for point in pour.polygons.iter().flat_map(|p| &p.exterior) {
+            assert_eq!((point.x * STEPS).round() / STEPS, point.x);
+            assert_eq!((point.y * STEPS).round() / STEPS, point.y);
+        }
+
+        // the grid doesn't depend on the extent of the layer
+        let far = copper(&format!("{body}X900000000Y900000000D03*\n"));
+        assert_eq!(far.polygons.len(), 2);
+        assert!(far.polygons.contains(&pour.polygons[0]));
+    }
+
+    #[test]
+    fn test_to_f32() {
+        let copper = copper("D11*\nX1000000Y-1000000D03*\n");
+        assert_eq!(
+            copper.polygons[0].to_f32(),
+            [[[0.0, 0.0], [0.0, -2.0], [2.0, -2.0], [2.0, 0.0]]]
+        );
+
+        // every grid value rounds to the nearest f32, ties to even
+        for n in (i32::MIN..=i32::MAX).step_by(4099).chain(-70000..70000) {
+            let rounded = (n as f64 / STEPS) as f32;
+            // both products are exact in f64
+            let error = (rounded as f64 * STEPS - n as f64).abs();
+            let ulp = (rounded.abs().next_up() - rounded.abs()) as f64;
+            let half = ulp * STEPS / 2.0;
+            assert!(error <= half, "{n}");
+            if error == half {
+                assert_eq!(rounded.to_bits() % 2, 0, "{n}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_winding() {
+        let ring = |points: &[(f64, f64)]| -> Vec<Point> {
+            points.iter().map(|&(x, y)| Point { x, y }).collect()
+        };
+        let square = [(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)];
+        let inner = [(1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (1.0, 2.0)];
+        let mut polygon = Polygon {
+            exterior: ring(&square).into_iter().rev().collect(),
+            holes: vec![ring(&inner)],
+        };
+        polygon.normalize_winding();
+        assert_eq!(polygon.exterior, ring(&square));
+        assert!(ring_signed_area(&polygon.exterior) > 0.0);
+        assert!(ring_signed_area(&polygon.holes[0]) < 0.0);
+        assert_eq!(polygon.area(), 15.0);
+
+        // the polygons of a layer come out wound this way
+        let copper = copper("D12*\nX0Y0D03*\n");
+        let polygon = &copper.polygons[0];
+        assert!(ring_signed_area(&polygon.exterior) > 0.0);
+        assert!(ring_signed_area(&polygon.holes[0]) < 0.0);
+    }
+
+    #[test]
+    fn test_region() {
+        let copper = copper(indoc! {"
+            G01*
+            G36*
+            X0Y0D02*
+            X2000000Y0D01*
+            X2000000Y2000000D01*
+            X0Y2000000D01*
+            X0Y0D01*
+            X3000000Y0D02*
+            X3000000Y1000000D01*
+            X4000000Y1000000D01*
+            X3000000Y0D01*
+            G37*
+        "});
+        assert_area(&copper, 4.0 + 0.5);
+    }
+
+    #[test]
+    fn test_transformations() {
+        // rotating a rectangle keeps its area but changes its extent
+        let copper = copper("%LR45*%\n%ADD15R,2X1*%\nD15*\nX0Y0D03*\n");
+        assert_area(&copper, 2.0);
+        let max_y = copper.polygons[0]
+            .exterior
+            .iter()
+            .map(|p| p.y)
+            .fold(f64::MIN, f64::max);
+        assert!((max_y - 1.5 / 2f64.sqrt()).abs() < 1e-6);
+
+        assert_area(&self::copper("%LS2*%\nD11*\nX0Y0D03*\n"), 16.0);
+    }
+
+    #[test]
+    fn test_macro_skipped() {
+        // THERMAL isn't defined
+        let copper = copper("%ADD15THERMAL,1*%\nD15*\nX0Y0D03*\nD10*\nX5000000Y0D03*\n");
+        assert_eq!(copper.skipped, [0]);
+        assert_area(&copper, PI / 4.0);
+    }
+
+    #[test]
+    fn test_macro_apertures() {
+        // a box, a ring with a dot in its hole, and a box drawn along X
+        let copper = copper(indoc! {"
+            %AMBOX*21,1,$1,$1,0,0,0*%
+            %AMTARGET*1,1,3,0,0*1,0,2,0,0*1,1,1,0,0*%
+            %ADD15BOX,1*%
+            %ADD16TARGET*%
+            D15*
+            X0Y0D03*
+            D16*
+            X10000000Y0D03*
+            D15*
+            G01*
+            X0Y10000000D02*
+            X2000000Y10000000D01*
+        "});
+        assert!(copper.skipped.is_empty());
+        let ring = PI / 4.0 * (9.0 - 4.0);
+        let dot = PI / 4.0;
+        assert_area(&copper, 1.0 + ring + dot + 3.0);
+        assert_eq!(copper.polygons.len(), 4);
+
+        // %LR rotates the whole macro about the flash point
+        let rotated =
+            self::copper("%AMBAR*21,1,2,1,1,0,0*%\n%ADD15BAR*%\n%LR90*%\nD15*\nX0Y0D03*\n");
+        let bounds = rotated.bounds().unwrap();
+        assert!((bounds.0.y - 0.0).abs() < 1e-6 && (bounds.1.y - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_profile() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            G01*
+            X0Y0D02*
+            X10000000Y0D01*
+            X10000000Y10000000D01*
+            X0Y10000000D02*
+            X10000000Y10000000D01*
+            X0Y10000000D02*
+            X0Y0D01*
+            G75*
+            G02*
+            X6000000Y5000000D02*
+            X6000000Y5000000I-1000000J0D01*
+            G01*
+            X20000000Y0D02*
+            X21000000Y0D01*
+            M02*
+        "};
+        // a square with a reversed side and a round cutout, and a line
+        // which doesn't close
+        let profile = GerberLayer::parse(src).unwrap().profile(0.0001);
+        assert_eq!(profile.len(), 1);
+        assert_eq!(profile[0].holes.len(), 1);
+        assert!((profile[0].area() - (100.0 - PI)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_clearance() {
+        let board = [Polygon {
+            exterior: vec![
+                Point { x: -5.0, y: -5.0 },
+                Point { x: 5.0, y: -5.0 },
+                Point { x: 5.0, y: 5.0 },
+                Point { x: -5.0, y: 5.0 },
+            ],
+            holes: Vec::new(),
+        }];
+        let copper = copper("D11*\nX0Y0D03*\nX5000000Y5000000D03*\n");
+        let clearance = copper.clearance(&board);
+        assert_area(&clearance, 100.0 - 4.0 - 1.0);
+        assert_eq!(clearance.polygons[0].holes.len(), 1);
+        assert!(clearance.polygons[0].contains(Point { x: 3.0, y: 0.0 }));
+        assert!(!clearance.polygons[0].contains(Point { x: 0.0, y: 0.0 }));
+    }
+
+    #[test]
+    fn test_smallest_gap() {
+        assert_eq!(copper("D11*\nX0Y0D03*\n").smallest_gap(), None);
+        // squares 1 apart, and a third further away diagonally
+        let gap = copper("D11*\nX0Y0D03*\nX3000000Y0D03*\nX6000000Y3500000D03*\n");
+        assert_eq!(gap.smallest_gap(), Some(1.0));
+        let gap = copper("D11*\nX0Y0D03*\nX0Y2500000D03*\nX4000000Y0D03*\n");
+        assert_eq!(gap.smallest_gap(), Some(0.5));
+    }
+
+    #[test]
+    fn test_to_gerber() {
+        let copper = copper(indoc! {"
+            D12*
+            X0Y0D03*
+            D11*
+            X20000000Y0D03*
+            %LPC*%
+            X21000000Y0D03*
+        "});
+        let gerber = copper.to_gerber();
+        let written = GerberLayer::parse(&gerber).unwrap().copper(0.0001);
+        assert_area(&written, copper.area());
+        assert_eq!(written.polygons.len(), 2);
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let copper = copper("D11*\nX0Y0D03*\n%LPC*%\nD10*\nX0Y0D03*\n");
+        let svg = copper.to_svg([255, 0, 0, 128]);
+        let paths: Vec<_> = svg
+            .lines()
+            .filter(|line| line.starts_with("<path"))
+            .collect();
+        // the clear flash is a subpath of the same path, not a second
+        // path painted in a background color
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].matches('M').count(), 2);
+        assert!(paths[0].contains(r##"fill="#ff0000" fill-opacity="0.502""##));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn test_aperture_polygons() {
+        let template = ApertureTemplate::Rectangle {
+            x: 2.0,
+            y: 1.0,
+            hole: Some(0.5),
+        };
+        let polygons = template.polygons(&[], 0.0001).unwrap();
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].holes.len(), 1);
+        assert!((polygons[0].area() - template.area().unwrap()).abs() < 1e-3);
+
+        let pad = RoundedRectangle {
+            width: 2.0,
+            height: 1.0,
+            radius: 0.25,
+        };
+        let macros = [
+            ApertureMacro::parse(pad.name(), &pad.content()).unwrap(),
+            // a ring whose hole a later bar crosses, turned by 90°
+            ApertureMacro::parse("Ring", &["1,1,2,0,0", "1,0,1,0,0", "21,1,2,0.2,0,0,90"]).unwrap(),
+            ApertureMacro::parse("Thermal", &["7,0,0,2,1,0.2,45"]).unwrap(),
+        ];
+        let polygons = |name: &str, parameters: Vec<f64>| {
+            let name = name.into();
+            let template = ApertureTemplate::Macro { name, parameters };
+            template.polygons(&macros, 0.0001)
+        };
+        let rounded = polygons(pad.name(), pad.parameters()).unwrap();
+        assert_eq!(rounded.len(), 1);
+        assert!((rounded[0].area() - (2.0 - (4.0 - PI) / 16.0)).abs() < 1e-3);
+
+        let ring = polygons("Ring", Vec::new()).unwrap();
+        assert_eq!(ring.len(), 1);
+        assert_eq!(ring[0].holes.len(), 2);
+        assert!(!ring[0].contains(Point { x: 0.3, y: 0.0 }));
+        assert!(ring[0].contains(Point { x: 0.0, y: 0.3 }));
+
+        let thermal = polygons("Thermal", Vec::new()).unwrap();
+        assert_eq!(thermal.len(), 4);
+        let area: f64 = thermal.iter().map(Polygon::area).sum();
+        let gaps = 4.0 * 0.2 * 0.5;
+        assert!((area - (PI * 0.75 - gaps)).abs() < 1e-2, "{area}");
+        // the gaps are on the diagonals
+        assert!(thermal
+            .iter()
+            .any(|part| part.contains(Point { x: 0.75, y: 0.0 })));
+
+        let error = polygons("Missing", Vec::new()).unwrap_err().to_string();
+        assert_eq!(error, "aperture macro Missing: not defined");
+    }
+
+    #[test]
+    fn test_convex_hull() {
+        let hull = convex_hull(vec![
+            [0.0, 0.0],
+            [1.0, 0.0],
+            [0.5, 0.5],
+            [1.0, 1.0],
+            [0.0, 1.0],
+        ]);
+        assert_eq!(hull, [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+    }
+}
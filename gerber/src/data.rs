@@ -1,128 +1,461 @@
 //! Data types
 
 use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use crate::IResult;
-use nom::{
-    branch::alt,
-    character::complete::{anychar, char, digit0, digit1, none_of, one_of},
-    combinator::{map, not, opt, peek, recognize, verify},
-    multi::{many0, many_m_n},
-    sequence::{pair, preceded, terminated},
-};
+use crate::grammar::aperture_identifier;
+use crate::GerberError;
+use nom::combinator::all_consuming;
 
-/// Parse a string into an i32
-pub(crate) fn into_i32(x: &str) -> i32 {
-    // TODO: optimize this knowing the caller always passes ASCII digits
-    x.parse().unwrap()
+/// Aperture Identifier
+///
+/// The aperture number following the `D`, e.g. `D10`. Numbers below 10 are
+/// reserved for operations, so only 10 and up identify an aperture.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ApertureId(u32);
+
+impl ApertureId {
+    /// The lowest aperture number which is not reserved
+    pub const MIN: u32 = 10;
+
+    /// The highest aperture number the parser reads, as it reads integers
+    /// as i32
+    pub const MAX: u32 = i32::MAX as u32;
+
+    /// Create an identifier for aperture number `value`, from
+    /// [MIN](Self::MIN) to [MAX](Self::MAX)
+    ///
+    /// ```
+    /// use gerber::data::ApertureId;
+    ///
+    /// assert_eq!(ApertureId::new(10).unwrap().to_string(), "D10");
+    /// assert!(ApertureId::new(3).is_err());
+    /// assert!(ApertureId::new(3_000_000_000).is_err());
+    /// ```
+    pub fn new(value: u32) -> Result<Self, GerberError> {
+        if (Self::MIN..=Self::MAX).contains(&value) {
+            Ok(Self(value))
+        } else {
+            Err(GerberError::ApertureId(format!("D{value}")))
+        }
+    }
+
+    /// The aperture number
+    pub fn value(&self) -> u32 {
+        self.0
+    }
 }
 
-/// Parse an non-negative integer to an i32
-pub(crate) fn unsigned_integer(input: &str) -> IResult<i32> {
-    map(digit1, into_i32)(input)
+impl fmt::Display for ApertureId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "D{}", self.0)
+    }
 }
 
-/// Parse a positive integer to an i32
-pub(crate) fn positive_integer(input: &str) -> IResult<i32> {
-    map(preceded(many0(char('0')), digit1), into_i32)(input)
+/// Parse an identifier as written in a file, e.g. `D10`
+impl FromStr for ApertureId {
+    type Err = GerberError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        all_consuming(aperture_identifier)(s)
+            .map(|(_, id)| id)
+            .map_err(|_| GerberError::ApertureId(s.to_string()))
+    }
 }
 
-/// Parse an integer to an i32
-pub(crate) fn integer(input: &str) -> IResult<i32> {
-    map(recognize(pair(opt(one_of("+-")), digit1)), into_i32)(input)
+/// Only generates identifiers the parser accepts (D10 and up)
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for ApertureId {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(ApertureId(u.int_in_range(Self::MIN..=Self::MAX)?))
+    }
 }
 
-/// Parse a string into an f64
-pub(crate) fn into_f64(x: &str) -> f64 {
-    // TODO: optimize this knowing the caller always passes ASCII digits
-    x.parse().unwrap()
+/// Convert an aperture number the parser has already checked into an ApertureId
+pub(crate) fn into_aperture_id(x: i32) -> ApertureId {
+    ApertureId(x as u32)
 }
 
-/// Parse a positive decimal to an f64
-pub(crate) fn unsigned_decimal(input: &str) -> IResult<f64> {
-    map(
-        alt((
-            recognize(pair(digit1, opt(pair(char('.'), digit0)))),
-            recognize(pair(char('.'), digit1)),
-        )),
-        into_f64,
-    )(input)
+/// The unit of coordinates and sizes, set by `%MO`
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Unit {
+    /// Millimeters, written as `MM`
+    Millimeters,
+
+    /// Inches, written as `IN`
+    Inches,
 }
 
-/// Parse a decimal to an f64
-pub(crate) fn decimal(input: &str) -> IResult<f64> {
-    map(pair(opt(one_of("+-")), unsigned_decimal), |(sign, val)| {
-        if sign == Some('-') {
-            -val
-        } else {
-            val
+impl Unit {
+    /// Convert a value in this unit to millimeters
+    pub fn to_mm(&self, value: f64) -> f64 {
+        match self {
+            Self::Millimeters => value,
+            Self::Inches => value * 25.4,
         }
-    })(input)
+    }
+
+    /// The length of one unit in [nanometers](NM_PER_MM)
+    pub fn nanometers(&self) -> i64 {
+        match self {
+            Self::Millimeters => NM_PER_MM,
+            Self::Inches => 25_400_000,
+        }
+    }
+
+    /// Convert a coordinate written in `format` to nanometers, rounding
+    /// half away from zero
+    ///
+    /// ```
+    /// use gerber::data::{CoordinateFormat, Unit};
+    ///
+    /// let format = CoordinateFormat { integer: 2, decimal: 6 };
+    /// assert_eq!(Unit::Millimeters.coordinate_to_nm(1_500_000, format), 1_500_000);
+    /// // 0.3 in is 7.62 mm exactly, which 0.3 * 25.4 is not
+    /// assert_eq!(Unit::Inches.coordinate_to_nm(300_000, format), 7_620_000);
+    /// assert_eq!(Unit::Inches.coordinate_to_nm(-3, format), -76);
+    /// ```
+    pub fn coordinate_to_nm(&self, value: i64, format: CoordinateFormat) -> i64 {
+        // no coordinate reaches a nanometer with this many decimals
+        let Some(divisor) = 10i128.checked_pow(format.decimal as u32) else {
+            return 0;
+        };
+        let nm = value as i128 * self.nanometers() as i128;
+        let rounded = (nm.abs() + divisor / 2) / divisor * nm.signum();
+        rounded.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
 }
 
-/// Aperture Identifier
+/// Nanometers per millimeter
+///
+/// The nanometer is the canonical unit of coordinates: while evaluating,
+/// every coordinate is rounded to a whole number of nanometers whatever
+/// the unit and format of its layer. The same position written in
+/// millimeters in one layer and inches in another is then the same
+/// [Point](crate::image::Point), so cross-layer operations such as
+/// aligning drills with copper compare coordinates exactly.
+pub const NM_PER_MM: i64 = 1_000_000;
+
+/// Whether objects darken or clear the image, set by `%LP`
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Polarity {
+    /// Objects are added to the image
+    #[default]
+    Dark,
+
+    /// Objects erase the image below them
+    Clear,
+}
+
+/// Mirroring of objects, set by `%LM`
+///
+/// Mirroring is applied before rotation and scaling.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Mirroring {
+    /// No mirroring
+    #[default]
+    None,
+
+    /// Mirror along the X axis, negating X coordinates
+    X,
+
+    /// Mirror along the Y axis, negating Y coordinates
+    Y,
+
+    /// Mirror along both axes
+    XY,
+}
+
+/// Rotation of objects in degrees counterclockwise, set by `%LR`
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Rotation(pub f64);
+
+/// Scale factor of objects, set by `%LS`
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Scaling(pub f64);
+
+impl Default for Scaling {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+impl Hash for Rotation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_decimal(self.0, state)
+    }
+}
+
+impl Hash for Scaling {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_decimal(self.0, state)
+    }
+}
+
+fn hash_decimal<H: Hasher>(x: f64, state: &mut H) {
+    // 0.0 and -0.0 compare equal, so must hash equal
+    if x == 0.0 { 0.0f64 } else { x }.to_bits().hash(state)
+}
+
+/// How D01 connects the current point to the end point, set by G01, G02
+/// and G03
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-pub struct ApertureId(i32);
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum InterpolationMode {
+    /// Straight lines
+    Linear,
 
-/// Convert an i32 into an ApertureId
-pub(crate) fn into_aperture_id(x: i32) -> ApertureId {
-    ApertureId(x)
+    /// Clockwise circular arcs
+    Clockwise,
+
+    /// Counterclockwise circular arcs
+    CounterClockwise,
 }
 
-/// Parse an aperture identifier
-pub(crate) fn aperture_identifier(input: &str) -> IResult<ApertureId> {
-    verify(
-        map(preceded(char('D'), positive_integer), into_aperture_id),
-        |id| id.0 >= 10,
-    )(input)
+impl InterpolationMode {
+    /// True for either direction of circular interpolation
+    pub fn is_circular(&self) -> bool {
+        *self != Self::Linear
+    }
+
+    /// The opposite direction, for following an arc backwards
+    pub fn reversed(&self) -> Self {
+        match self {
+            Self::Linear => Self::Linear,
+            Self::Clockwise => Self::CounterClockwise,
+            Self::CounterClockwise => Self::Clockwise,
+        }
+    }
 }
 
-/// Parse the first character in a name fragment (excludes '.')
-pub(crate) fn name_fragment_first(input: &str) -> IResult<char> {
-    verify(anychar, |&c| c.is_alphabetic() || c == '_' || c == '$')(input)
+/// Number of integer and decimal digits in a coordinate, set by `%FS`
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct CoordinateFormat {
+    pub integer: u8,
+    pub decimal: u8,
 }
 
-/// Parse non-first character in a name fragment (includes '.')
-pub(crate) fn name_fragment_rest(input: &str) -> IResult<char> {
-    verify(anychar, |&c| {
-        c.is_alphanumeric() || c == '.' || c == '_' || c == '$'
-    })(input)
+impl CoordinateFormat {
+    /// The largest magnitude a coordinate in this format can represent
+    ///
+    /// Formats with more than 18 digits saturate to [i64::MAX], the
+    /// largest coordinate that can be parsed at all.
+    pub fn max_coordinate(&self) -> i64 {
+        let digits = u32::from(self.integer) + u32::from(self.decimal);
+        10i64
+            .checked_pow(digits)
+            .map_or(i64::MAX, |power| power - 1)
+    }
 }
 
-/// Create a parser which parses a user defined name no longer than the provided `max` length
-pub(crate) fn user_name_shorter_than(max: usize) -> impl Fn(&str) -> IResult<&str> {
-    move |input| {
-        if max == 0 {
-            Ok((input, ""))
-        } else {
-            recognize(pair(
-                // first user-defined name can't be a '.'
-                name_fragment_first,
-                terminated(
-                    // remaining characters may include '.', but name can't be longer than max
-                    many_m_n(0, max - 1, name_fragment_rest),
-                    // ensure parsing stopped because of mismatch, not length
-                    peek(not(name_fragment_rest)),
-                ),
-            ))(input)
+/// The X and Y coordinates of an operation, in units of the coordinate format
+///
+/// An omitted coordinate keeps the value of the current point. Coordinates
+/// are stored inline in the command, so operations allocate nothing and a
+/// flash takes one [Command](crate::command::Command) slot.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Coordinates {
+    pub x: Option<i64>,
+    pub y: Option<i64>,
+}
+
+/// The I and J offset of an arc center from its start point
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Offset {
+    pub i: i64,
+    pub j: i64,
+}
+
+/// The repeats of a step and repeat statement, set by `%SR`
+///
+/// The block is repeated `x` times along X and `y` times along Y, with a
+/// step of `i` and `j` in the unit of the file.
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StepRepeat {
+    pub x: u32,
+    pub y: u32,
+    pub i: f64,
+    pub j: f64,
+}
+
+impl Hash for StepRepeat {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.x.hash(state);
+        self.y.hash(state);
+        hash_decimal(self.i, state);
+        hash_decimal(self.j, state);
+    }
+}
+
+/// A string borrowed from the source, or owned and shared
+///
+/// Like `Cow<str>`, except that the owned form is an `Arc<str>`: cloning an
+/// owned layer, e.g. to hand it to a worker thread, copies pointers rather
+/// than every name, comment and attribute value.
+///
+/// ```
+/// use gerber::data::SharedStr;
+///
+/// let owned = SharedStr::from("GND").into_owned();
+/// let clone = owned.clone();
+/// assert_eq!(clone, "GND");
+/// assert!(std::ptr::eq(owned.as_ptr(), clone.as_ptr()));
+/// ```
+#[derive(Clone)]
+pub enum SharedStr<'a> {
+    Borrowed(&'a str),
+    Shared(Arc<str>),
+}
+
+impl SharedStr<'_> {
+    /// Convert into a string which does not borrow from the source
+    ///
+    /// Shared strings are kept, borrowed ones are copied once.
+    pub fn into_owned(self) -> SharedStr<'static> {
+        match self {
+            Self::Borrowed(s) => SharedStr::Shared(s.into()),
+            Self::Shared(s) => SharedStr::Shared(s),
         }
     }
 }
 
-/// Parse a user defined name
-pub(crate) fn user_name(input: &str) -> IResult<&str> {
-    user_name_shorter_than(127)(input)
+impl Default for SharedStr<'_> {
+    fn default() -> Self {
+        Self::Borrowed("")
+    }
+}
+
+impl Deref for SharedStr<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Self::Borrowed(s) => s,
+            Self::Shared(s) => s,
+        }
+    }
+}
+
+impl AsRef<str> for SharedStr<'_> {
+    fn as_ref(&self) -> &str {
+        self
+    }
 }
 
-/// Parse a system defined name
-pub(crate) fn system_name(input: &str) -> IResult<&str> {
-    // a system name just starts with a '.', but still can't be longer than 127 characters overall
-    recognize(pair(char('.'), user_name_shorter_than(126)))(input)
+impl<'a> From<&'a str> for SharedStr<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Borrowed(value)
+    }
+}
+
+impl From<String> for SharedStr<'_> {
+    fn from(value: String) -> Self {
+        Self::Shared(value.into())
+    }
+}
+
+impl From<Arc<str>> for SharedStr<'_> {
+    fn from(value: Arc<str>) -> Self {
+        Self::Shared(value)
+    }
 }
 
-/// Parse a system or user defined name
-pub(crate) fn name(input: &str) -> IResult<&str> {
-    alt((system_name, user_name))(input)
+impl<'a> From<Cow<'a, str>> for SharedStr<'a> {
+    fn from(value: Cow<'a, str>) -> Self {
+        match value {
+            Cow::Borrowed(s) => Self::Borrowed(s),
+            Cow::Owned(s) => s.into(),
+        }
+    }
+}
+
+impl PartialEq for SharedStr<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for SharedStr<'_> {}
+
+impl PartialEq<str> for SharedStr<'_> {
+    fn eq(&self, other: &str) -> bool {
+        &**self == other
+    }
+}
+
+impl PartialEq<&str> for SharedStr<'_> {
+    fn eq(&self, other: &&str) -> bool {
+        &**self == *other
+    }
+}
+
+impl PartialOrd for SharedStr<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SharedStr<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl Hash for SharedStr<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl fmt::Debug for SharedStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl fmt::Display for SharedStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for SharedStr<'a> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        <&'a str>::arbitrary(u).map(Self::Borrowed)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for SharedStr<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
 }
 
 /// Strings in the Gerber specification may contain unicode escapes,
@@ -130,204 +463,196 @@ pub(crate) fn name(input: &str) -> IResult<&str> {
 /// would be inefficient, so EscapedString tracks if expansion is required
 /// and delays that expansion until the `expand` function is called.
 #[derive(Clone, PartialEq, PartialOrd, Debug, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum EscapedString<'a> {
     /// A string which does not contain escape sequences
-    Unescaped(Cow<'a, str>),
+    Unescaped(SharedStr<'a>),
 
     /// A string containing escape sequences
-    Escaped(Cow<'a, str>),
+    Escaped(SharedStr<'a>),
 }
 
 impl<'a> EscapedString<'a> {
     /// Create an EscapedString which does not contain escape sequences
-    pub fn new_unescaped(value: impl Into<Cow<'a, str>>) -> Self {
+    pub fn new_unescaped(value: impl Into<SharedStr<'a>>) -> Self {
         Self::Unescaped(value.into())
     }
 
     /// Create an EscapedString which contains escape sequences
-    pub fn new_escaped(value: impl Into<Cow<'a, str>>) -> Self {
+    pub fn new_escaped(value: impl Into<SharedStr<'a>>) -> Self {
         Self::Escaped(value.into())
     }
 
-    /// Convert escape sequence, if present, and return the unescaped string
-    pub fn unescape(&self) -> Cow<str> {
+    /// Convert into a string which does not borrow from the source
+    pub fn into_owned(self) -> EscapedString<'static> {
         match self {
-            Self::Unescaped(s) => s.clone(),
-            Self::Escaped(s) => {
-                // TODO: expand unicode escape sequences
-                s.clone()
+            Self::Unescaped(s) => EscapedString::Unescaped(s.into_owned()),
+            Self::Escaped(s) => EscapedString::Escaped(s.into_owned()),
+        }
+    }
+
+    /// Create a field holding `value`, escaping the characters which may
+    /// not appear in a field
+    ///
+    /// Borrows `value` when nothing needs escaping.
+    pub fn escape_field(value: &'a str) -> Self {
+        Self::escape(value, "%*,\\")
+    }
+
+    /// Create a string holding `value`, escaping the characters which may
+    /// not appear in a string
+    ///
+    /// Borrows `value` when nothing needs escaping.
+    pub fn escape_string(value: &'a str) -> Self {
+        Self::escape(value, "%*\\")
+    }
+
+    /// Escape `reserved` and control characters as `\\uXXXX` sequences
+    fn escape(value: &'a str, reserved: &str) -> Self {
+        let needs_escape = |c: char| reserved.contains(c) || c.is_ascii_control();
+        if !value.contains(needs_escape) {
+            return Self::new_unescaped(value);
+        }
+        let mut escaped = String::with_capacity(value.len() + 8);
+        for c in value.chars() {
+            if needs_escape(c) {
+                escaped.push_str(&format!("\\u{:04X}", c as u32));
+            } else {
+                escaped.push(c);
             }
         }
+        Self::new_escaped(escaped)
     }
-}
 
-/// Parse a field
-pub(crate) fn field(input: &str) -> IResult<EscapedString<'_>> {
-    // TODO: use new_escaped if it contains escape sequences
-    map(
-        recognize(many0(none_of("%*,"))),
-        EscapedString::new_unescaped,
-    )(input)
-}
+    /// The string as it is written in a file, including escape sequences
+    pub fn raw(&self) -> &str {
+        match self {
+            Self::Unescaped(s) | Self::Escaped(s) => s,
+        }
+    }
 
-/// Parse a string
-pub(crate) fn string(input: &str) -> IResult<EscapedString<'_>> {
-    // TODO: use new_escaped if it contains escape sequences
-    map(
-        recognize(many0(none_of("%*"))),
-        EscapedString::new_unescaped,
-    )(input)
+    /// Convert escape sequence, if present, and return the unescaped string
+    ///
+    /// `\\uXXXX` and `\\UXXXXXXXX` sequences are expanded. Backslashes which
+    /// do not start a valid sequence are kept as they are.
+    pub fn unescape(&self) -> Cow<'_, str> {
+        match self {
+            Self::Unescaped(s) => Cow::Borrowed(s),
+            Self::Escaped(s) => {
+                let mut unescaped = String::with_capacity(s.len());
+                let mut rest = &s[..];
+                while let Some(index) = rest.find('\\') {
+                    unescaped.push_str(&rest[..index]);
+                    rest = &rest[index..];
+                    let digits = match rest.as_bytes().get(1) {
+                        Some(b'u') => 4,
+                        Some(b'U') => 8,
+                        _ => 0,
+                    };
+                    let c = rest
+                        .get(2..2 + digits)
+                        .filter(|hex| digits > 0 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .and_then(char::from_u32);
+                    match c {
+                        Some(c) => {
+                            unescaped.push(c);
+                            rest = &rest[2 + digits..];
+                        }
+                        None => {
+                            unescaped.push('\\');
+                            rest = &rest[1..];
+                        }
+                    }
+                }
+                unescaped.push_str(rest);
+                Cow::Owned(unescaped)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::grammar::field;
 
+    #[cfg(feature = "arbitrary")]
     #[test]
-    fn test_integers() {
-        // Unsigned Integers
-        assert_eq!(unsigned_integer("0"), Ok(("", 0)));
-        assert_eq!(unsigned_integer("123"), Ok(("", 123)));
-        assert!(unsigned_integer("+123").is_err());
-        assert!(unsigned_integer("-123").is_err());
-
-        // Positive Integers
-        assert!(positive_integer("0").is_err());
-        assert_eq!(positive_integer("123"), Ok(("", 123)));
-        // NOTE: grammar doesn't permit '+' on positive_integer (may want to relax this)
-        assert!(positive_integer("+123").is_err());
-        assert!(positive_integer("-123").is_err());
-
-        // Integers
-        assert_eq!(integer("0"), Ok(("", 0)));
-        assert_eq!(integer("123"), Ok(("", 123)));
-        assert_eq!(integer("+123"), Ok(("", 123)));
-        assert_eq!(integer("-123"), Ok(("", -123)));
-    }
+    fn test_arbitrary_aperture_id() {
+        use arbitrary::{Arbitrary, Unstructured};
 
-    #[test]
-    fn test_decimals() {
-        // Unsigned Decimals
-        assert_eq!(unsigned_decimal("0"), Ok(("", 0.)));
-        assert_eq!(unsigned_decimal("0."), Ok(("", 0.)));
-        assert_eq!(unsigned_decimal(".0"), Ok(("", 0.)));
-        assert_eq!(unsigned_decimal("0.0"), Ok(("", 0.)));
-        assert_eq!(unsigned_decimal("12.34"), Ok(("", 12.34)));
-        assert!(unsigned_decimal(".").is_err());
-
-        // Decimals
-        assert_eq!(decimal("0"), Ok(("", 0.)));
-        assert_eq!(decimal("0."), Ok(("", 0.)));
-        assert_eq!(decimal(".0"), Ok(("", 0.)));
-        assert_eq!(decimal("0.0"), Ok(("", 0.)));
-        assert_eq!(decimal("1"), Ok(("", 1.)));
-        assert_eq!(decimal("1."), Ok(("", 1.)));
-        assert_eq!(decimal(".1"), Ok(("", 0.1)));
-        assert_eq!(decimal("1.0"), Ok(("", 1.)));
-        assert_eq!(decimal("-1"), Ok(("", -1.)));
-        assert_eq!(decimal("-1."), Ok(("", -1.)));
-        assert_eq!(decimal("-.1"), Ok(("", -0.1)));
-        assert_eq!(decimal("-1.0"), Ok(("", -1.)));
-        assert!(decimal(".").is_err());
+        let raw = [0u8; 64];
+        let mut u = Unstructured::new(&raw);
+        let id = ApertureId::arbitrary(&mut u).unwrap();
+        assert!(id.0 >= 10);
     }
 
     #[test]
     fn test_aperture_id() {
-        assert_eq!(aperture_identifier("D0123"), Ok(("", ApertureId(123))));
+        let id = ApertureId::new(123).unwrap();
+        assert_eq!(id.value(), 123);
+        assert_eq!(id.to_string(), "D123");
+        assert_eq!("D123".parse::<ApertureId>().unwrap(), id);
+        assert_eq!("D0123".parse::<ApertureId>().unwrap(), id);
+
+        assert!(ApertureId::new(9).is_err());
+        let max = ApertureId::new(ApertureId::MAX).unwrap();
+        assert_eq!(max.to_string().parse::<ApertureId>().unwrap(), max);
+        assert!(ApertureId::new(ApertureId::MAX + 1).is_err());
+        assert!("D9".parse::<ApertureId>().is_err());
+        assert!("D10*".parse::<ApertureId>().is_err());
+        assert!("10".parse::<ApertureId>().is_err());
     }
 
     #[test]
-    fn test_name() {
-        // User-defined Name
-        assert_eq!(user_name("foo!"), Ok(("!", "foo")));
-        assert_eq!(user_name("_"), Ok(("", "_")));
-        assert_eq!(user_name("$"), Ok(("", "$")));
-        assert_eq!(user_name("a"), Ok(("", "a")));
-        assert_eq!(user_name("A"), Ok(("", "A")));
-        assert_eq!(user_name("__$Some.01__Name"), Ok(("", "__$Some.01__Name")));
-
-        let valid_long = "x".repeat(127);
-        assert_eq!(
-            user_name(valid_long.as_str()),
-            Ok(("", valid_long.as_str()))
-        );
-
-        let invalid_long = "x".repeat(128);
-        assert!(user_name(invalid_long.as_str()).is_err());
-
-        assert!(user_name(".Nope").is_err());
-        assert!(user_name("1Nope").is_err());
-
-        // System-defined Name
-        assert_eq!(system_name(".foo!"), Ok(("!", ".foo")));
-        assert_eq!(system_name("._"), Ok(("", "._")));
-        assert_eq!(system_name(".$"), Ok(("", ".$")));
-        assert_eq!(system_name(".a"), Ok(("", ".a")));
-        assert_eq!(system_name(".A"), Ok(("", ".A")));
-        assert_eq!(
-            system_name(".__$Some.01__Name"),
-            Ok(("", ".__$Some.01__Name"))
-        );
-
-        let valid_long = format!(".{}", "x".repeat(126));
-        assert_eq!(
-            system_name(valid_long.as_str()),
-            Ok(("", valid_long.as_str()))
-        );
-
-        let invalid_long = format!(".{}", "x".repeat(127));
-        assert!(system_name(invalid_long.as_str()).is_err());
-
-        assert!(system_name("Nope").is_err());
-        assert!(system_name(".1Nope").is_err());
+    fn test_max_coordinate() {
+        let format = |integer, decimal| CoordinateFormat { integer, decimal };
+        assert_eq!(format(2, 6).max_coordinate(), 99_999_999);
+        assert_eq!(format(9, 9).max_coordinate(), 999_999_999_999_999_999);
+        assert_eq!(format(9, 10).max_coordinate(), i64::MAX);
+        assert_eq!(format(u8::MAX, u8::MAX).max_coordinate(), i64::MAX);
     }
 
     #[test]
-    fn test_field() {
-        let valid_field = "Can be anything 😀; except for a comma!\nEven a newline is ok.";
+    fn test_escape() {
         assert_eq!(
-            field(valid_field),
-            Ok(("", EscapedString::new_unescaped(valid_field)))
+            EscapedString::escape_field("plain text"),
+            EscapedString::new_unescaped("plain text")
         );
-
-        let with_comma = "before,after";
         assert_eq!(
-            field(with_comma),
-            Ok((",after", EscapedString::new_unescaped("before")))
+            EscapedString::escape_field("50%, 3*2"),
+            EscapedString::new_escaped("50\\u0025\\u002C 3\\u002A2")
         );
-
-        let with_percent = "before%after";
-        assert_eq!(
-            field(with_percent),
-            Ok(("%after", EscapedString::new_unescaped("before")))
-        );
-
-        let with_star = "before*after";
         assert_eq!(
-            field(with_star),
-            Ok(("*after", EscapedString::new_unescaped("before")))
+            EscapedString::escape_string("a,b\\c"),
+            EscapedString::new_escaped("a,b\\u005Cc")
         );
+        assert_eq!(EscapedString::escape_field("Ωhm").raw(), "Ωhm");
+
+        // everything round-trips through the parser
+        for text in [
+            "",
+            "plain",
+            "50%, 3*2",
+            "C:\\dir\\u0041",
+            "line\nbreak",
+            "Ωhm",
+        ] {
+            let escaped = EscapedString::escape_field(text);
+            let raw = format!("{}*", escaped.raw());
+            let (_, parsed) = field(&raw).unwrap();
+            assert_eq!(parsed.unescape(), text);
+        }
     }
 
     #[test]
-    fn test_string() {
-        assert_eq!(string(""), Ok(("", EscapedString::new_unescaped(""))));
-        assert_eq!(string(" "), Ok(("", EscapedString::new_unescaped(" "))));
-        assert_eq!(
-            string("a\nb"),
-            Ok(("", EscapedString::new_unescaped("a\nb")))
-        );
-
-        let with_percent = "before%after";
-        assert_eq!(
-            string(with_percent),
-            Ok(("%after", EscapedString::new_unescaped("before")))
-        );
-
-        let with_star = "before*after";
-        assert_eq!(
-            string(with_star),
-            Ok(("*after", EscapedString::new_unescaped("before")))
-        );
+    fn test_unescape() {
+        let unescape = |s| EscapedString::new_escaped(s).unescape().into_owned();
+        assert_eq!(unescape("\\u0041\\u00e9"), "Aé");
+        assert_eq!(unescape("\\U0001F600!"), "😀!");
+        assert_eq!(unescape("a\\b\\u12"), "a\\b\\u12");
+        assert_eq!(unescape("\\uD800"), "\\uD800");
     }
 }
@@ -0,0 +1,403 @@
+//! Adding standard file attributes to a layer
+//!
+//! Fabricators rely on the X2 file attributes (§5.6 of the specification)
+//! to identify layers, so a layer which is written out should carry them.
+//! [decorate] checks each value against the format the specification
+//! gives for it and adds it to the source as a `%TF` command at the top of
+//! the file, after any leading comments. An attribute the file already has
+//! is replaced where it stands. Like [modernize](crate::modernize) this
+//! works on the source text, and everything else is copied verbatim.
+
+use std::ops::Range;
+
+use crate::attribute::ProjectId;
+use crate::conformance::statements;
+use crate::data::EscapedString;
+use crate::GerberError;
+
+/// The standard file attributes to add, each left out when `None`
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Metadata {
+    /// `.CreationDate`, an ISO 8601 date and time with an optional time
+    /// zone, e.g. `2024-05-01T12:30:00+02:00`
+    pub creation_date: Option<String>,
+
+    pub generation_software: Option<GenerationSoftware>,
+
+    pub project_id: Option<ProjectId>,
+
+    /// `.FileFunction`, its fields without the name, e.g.
+    /// `["Copper", "L1", "Top"]`
+    pub file_function: Option<Vec<String>>,
+
+    pub file_polarity: Option<FilePolarity>,
+}
+
+/// `.GenerationSoftware`, the software which wrote the file
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GenerationSoftware {
+    pub vendor: String,
+    pub application: String,
+    pub version: Option<String>,
+}
+
+/// `.FilePolarity`, whether the image is the material or its absence
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum FilePolarity {
+    Positive,
+    Negative,
+}
+
+/// Add the attributes in `metadata` to the source of a Gerber file
+///
+/// Returns [GerberError::Attribute] for the first value which doesn't
+/// conform to the specification.
+///
+/// ```
+/// use gerber::decorate::{decorate, FilePolarity, Metadata};
+///
+/// let src = "G04 board*\n%FSLAX26Y26*%\n%MOMM*%\nM02*\n";
+/// let metadata = Metadata {
+///     file_function: Some(vec!["Copper".into(), "L1".into(), "Top".into()]),
+///     file_polarity: Some(FilePolarity::Positive),
+///     ..Default::default()
+/// };
+/// assert_eq!(
+///     decorate(src, &metadata).unwrap(),
+///     "G04 board*\n%TF.FileFunction,Copper,L1,Top*%\n%TF.FilePolarity,Positive*%\n\
+///      %FSLAX26Y26*%\n%MOMM*%\nM02*\n"
+/// );
+/// ```
+pub fn decorate(src: &str, metadata: &Metadata) -> Result<String, GerberError> {
+    let attributes = attributes(metadata)?;
+    let newline = if src.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let statements = statements(src);
+    // insert after the leading comments
+    let insert_at = statements
+        .iter()
+        .find(|statement| statement.extended || !statement.words[0].starts_with("G04"))
+        .map_or(src.len(), |statement| statement.span.start);
+
+    // existing attributes are replaced, and repeats of them dropped
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    let mut inserted = String::new();
+    for (name, command) in &attributes {
+        let mut existing = statements.iter().filter(|statement| {
+            statement.extended
+                && statement.words.first().is_some_and(|word| {
+                    word.strip_prefix("TF")
+                        .and_then(|rest| rest.strip_prefix(name.as_str()))
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(','))
+                })
+        });
+        match existing.next() {
+            Some(statement) => edits.push((statement.span.clone(), command.clone())),
+            None => {
+                inserted.push_str(command);
+                inserted.push_str(newline);
+            }
+        }
+        for statement in existing {
+            let rest = &src[statement.span.end..];
+            let end = statement.span.end + rest.len() - rest.trim_start_matches(['\r', '\n']).len();
+            edits.push((statement.span.start..end, String::new()));
+        }
+    }
+    if !inserted.is_empty() {
+        edits.push((insert_at..insert_at, inserted));
+    }
+    edits.sort_by_key(|(span, _)| (span.start, span.end));
+
+    let mut decorated = String::with_capacity(src.len() + 256);
+    let mut copied = 0;
+    for (span, replacement) in edits {
+        decorated.push_str(&src[copied..span.start]);
+        decorated.push_str(&replacement);
+        copied = span.end;
+    }
+    decorated.push_str(&src[copied..]);
+    Ok(decorated)
+}
+
+/// The name and `%TF` command of each attribute, after checking its value
+fn attributes(metadata: &Metadata) -> Result<Vec<(String, String)>, GerberError> {
+    let mut attributes = Vec::new();
+    let mut add = |name: &str, fields: &[&str]| {
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|field| EscapedString::escape_field(field).raw().to_string())
+            .collect();
+        let command = format!("%TF{name},{}*%", fields.join(","));
+        attributes.push((name.to_string(), command));
+    };
+    let invalid =
+        |name: &str, value: &str| Err(GerberError::Attribute(name.to_string(), value.to_string()));
+
+    if let Some(function) = &metadata.file_function {
+        let fields: Vec<&str> = function.iter().map(String::as_str).collect();
+        if !is_file_function(&fields) {
+            return invalid(".FileFunction", &fields.join(","));
+        }
+        add(".FileFunction", &fields);
+    }
+    if let Some(polarity) = metadata.file_polarity {
+        let polarity = match polarity {
+            FilePolarity::Positive => "Positive",
+            FilePolarity::Negative => "Negative",
+        };
+        add(".FilePolarity", &[polarity]);
+    }
+    if let Some(date) = &metadata.creation_date {
+        if !is_date_time(date) {
+            return invalid(".CreationDate", date);
+        }
+        add(".CreationDate", &[date]);
+    }
+    if let Some(software) = &metadata.generation_software {
+        let mut fields = vec![software.vendor.as_str(), software.application.as_str()];
+        fields.extend(software.version.as_deref());
+        if fields.iter().any(|field| field.is_empty()) {
+            return invalid(".GenerationSoftware", &fields.join(","));
+        }
+        add(".GenerationSoftware", &fields);
+    }
+    if let Some(project) = &metadata.project_id {
+        let fields = [
+            project.name.as_str(),
+            project.guid.as_str(),
+            project.revision.as_str(),
+        ];
+        ProjectId::from_fields(&fields)?;
+        add(".ProjectId", &fields);
+    }
+    Ok(attributes)
+}
+
+/// True for the fields of a `.FileFunction` value (§5.6.3)
+fn is_file_function(fields: &[&str]) -> bool {
+    let side = |field: &str| matches!(field, "Top" | "Bot");
+    let number = |field: &str| !field.is_empty() && field.bytes().all(|b| b.is_ascii_digit());
+    let layer = |field: &str| field.strip_prefix('L').is_some_and(number);
+    let optional_index = |rest: &[&str]| match rest {
+        [] => true,
+        [index] => number(index),
+        _ => false,
+    };
+    match fields {
+        ["Copper", position, location, rest @ ..] => {
+            layer(position)
+                && matches!(*location, "Top" | "Inr" | "Bot")
+                && match rest {
+                    [] => true,
+                    [kind] => matches!(*kind, "Plane" | "Signal" | "Mixed" | "Hatched"),
+                    _ => false,
+                }
+        }
+        [plating @ ("Plated" | "NonPlated"), from, to, kind, rest @ ..] => {
+            let kinds: &[&str] = if *plating == "Plated" {
+                &["PTH", "Blind", "Buried"]
+            } else {
+                &["NPTH", "Blind", "Buried"]
+            };
+            number(from)
+                && number(to)
+                && kinds.contains(kind)
+                && match rest {
+                    [] => true,
+                    [label] => matches!(*label, "Drill" | "Rout" | "Mixed"),
+                    _ => false,
+                }
+        }
+        ["Profile", "P" | "NP"] => true,
+        ["Soldermask" | "Legend" | "Carbonmask" | "Goldmask" | "Heatsinkmask" | "Peelablemask"
+        | "Silvermask" | "Tinmask", location, rest @ ..] => side(location) && optional_index(rest),
+        ["Component", position, location] => layer(position) && side(location),
+        ["Paste" | "Glue" | "Depthrout" | "Pads" | "AssemblyDrawing", location] => side(location),
+        ["Vcut"]
+        | ["Viafill"]
+        | ["Drillmap"]
+        | ["FabricationDrawing"]
+        | ["Vcutmap"]
+        | ["ArrayDrawing"] => true,
+        ["Vcut", location] => side(location),
+        ["Other" | "OtherDrawing", description] => !description.is_empty(),
+        _ => false,
+    }
+}
+
+/// True for an ISO 8601 date and time, `YYYY-MM-DDThh:mm:ss` followed by
+/// optional fractional seconds and an optional `Z` or `±hh:mm` time zone
+fn is_date_time(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let digits = |range: Range<usize>| -> Option<u32> {
+        let text = value.get(range)?;
+        text.bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| text.parse().ok())?
+    };
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':')];
+    if bytes.len() < 19 || separators.iter().any(|&(i, c)| bytes[i] != c) {
+        return false;
+    }
+    let (Some(year), Some(month), Some(day), Some(hour), Some(minute), Some(second)) = (
+        digits(0..4),
+        digits(5..7),
+        digits(8..10),
+        digits(11..13),
+        digits(14..16),
+        digits(17..19),
+    ) else {
+        return false;
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    if !(1..=days).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return false;
+    }
+
+    let mut rest = &value[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let length = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if length == 0 {
+            return false;
+        }
+        rest = &fraction[length..];
+    }
+    match rest.as_bytes() {
+        [] | [b'Z'] => true,
+        [b'+' | b'-', h1, h2, b':', m1, m2] => {
+            [h1, h2, m1, m2].iter().all(|b| b.is_ascii_digit())
+                && rest[1..3].parse::<u32>().is_ok_and(|hours| hours <= 14)
+                && rest[4..6].parse::<u32>().is_ok_and(|minutes| minutes <= 59)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            creation_date: Some("2024-02-29T08:15:00.5+05:30".into()),
+            generation_software: Some(GenerationSoftware {
+                vendor: "Acme, Inc.".into(),
+                application: "Board".into(),
+                version: None,
+            }),
+            project_id: Some(ProjectId {
+                name: "Widget".into(),
+                guid: "8B3A8E1C-2F70-4c2a-9c3d-5e9f0a1b2c3d".into(),
+                revision: "rev2".into(),
+            }),
+            file_function: Some(vec!["Soldermask".into(), "Bot".into()]),
+            file_polarity: Some(FilePolarity::Negative),
+        }
+    }
+
+    #[test]
+    fn test_decorate() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %TF.FileFunction,Other,unknown*%
+            %MOMM*%
+            %TF.FileFunction,Legend,Top*%
+            M02*
+        "};
+        let decorated = decorate(src, &metadata()).unwrap();
+        assert_eq!(
+            decorated,
+            indoc! {r"
+                %TF.FilePolarity,Negative*%
+                %TF.CreationDate,2024-02-29T08:15:00.5+05:30*%
+                %TF.GenerationSoftware,Acme\u002C Inc.,Board*%
+                %TF.ProjectId,Widget,8B3A8E1C-2F70-4c2a-9c3d-5e9f0a1b2c3d,rev2*%
+                %FSLAX26Y26*%
+                %TF.FileFunction,Soldermask,Bot*%
+                %MOMM*%
+                M02*
+            "}
+        );
+        let layer = GerberLayer::parse(&decorated).unwrap();
+        let image = layer.image();
+        assert_eq!(
+            image.file_attributes[".GenerationSoftware"],
+            ["Acme, Inc.", "Board"].map(std::sync::Arc::<str>::from)
+        );
+        assert!(layer.validate().is_empty());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let src = "%FSLAX26Y26*%\n%MOMM*%\nM02*\n";
+        let invalid = [
+            Metadata {
+                creation_date: Some("2023-02-29T00:00:00".into()),
+                ..Default::default()
+            },
+            Metadata {
+                project_id: Some(ProjectId {
+                    name: "Widget".into(),
+                    guid: "not-a-guid".into(),
+                    revision: "1".into(),
+                }),
+                ..Default::default()
+            },
+            Metadata {
+                file_function: Some(vec!["Copper".into(), "1".into(), "Top".into()]),
+                ..Default::default()
+            },
+        ];
+        for metadata in invalid {
+            let error = decorate(src, &metadata).unwrap_err();
+            assert!(matches!(error, GerberError::Attribute(..)), "{error}");
+        }
+        assert_eq!(decorate(src, &Metadata::default()).unwrap(), src);
+    }
+
+    #[test]
+    fn test_file_function() {
+        let valid: [&[&str]; 8] = [
+            &["Copper", "L2", "Inr", "Plane"],
+            &["Plated", "1", "4", "PTH", "Drill"],
+            &["NonPlated", "1", "4", "NPTH"],
+            &["Profile", "NP"],
+            &["Legend", "Top", "2"],
+            &["Component", "L1", "Bot"],
+            &["Vcut"],
+            &["Other", "Stiffener"],
+        ];
+        assert!(valid.iter().all(|fields| is_file_function(fields)));
+        let invalid: [&[&str]; 5] = [
+            &[],
+            &["Copper", "L1", "Side"],
+            &["NonPlated", "1", "2", "PTH"],
+            &["Paste"],
+            &["Other"],
+        ];
+        assert!(!invalid.iter().any(|fields| is_file_function(fields)));
+    }
+
+    #[test]
+    fn test_date_time() {
+        assert!(is_date_time("2015-02-23T15:59:51+01:00"));
+        assert!(is_date_time("2024-12-31T23:59:59Z"));
+        assert!(!is_date_time("2015-02-23 15:59:51"));
+        assert!(!is_date_time("2015-13-01T00:00:00"));
+        assert!(!is_date_time("2015-02-23T15:59:51+1:00"));
+        assert!(!is_date_time("2015-02-23T15:59:51."));
+    }
+}
@@ -0,0 +1,209 @@
+//! Parsing a file with what it declares about itself
+//!
+//! [GerberDoc::parse] is the entry point for tools which want more than
+//! the commands: the coordinate format and unit the file declares, where
+//! each command is in the source and what is wrong with it. The parser
+//! itself is an implementation detail, and errors are [GerberError]s.
+
+use crate::command::Command::{self, *};
+use crate::data::{CoordinateFormat, Unit};
+use crate::lint::Lints;
+use crate::modernize::convert_legacy_format;
+use crate::span::{self, Span};
+use crate::validate::{Diagnostic, DiagnosticKind};
+use crate::{GerberError, GerberLayer};
+
+/// A parsed file and its metadata
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GerberDoc<'a> {
+    /// The commands and their spans, for further analysis
+    pub layer: GerberLayer<'a>,
+
+    /// The X and Y coordinate formats of the first `%FS` command
+    pub format: Option<(CoordinateFormat, CoordinateFormat)>,
+
+    /// The unit of the first `%MO` command
+    pub unit: Option<Unit>,
+
+    /// Violations of the specification, if [ParseOptions::validate] is
+    /// set, and a warning if a legacy format was converted
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// What [GerberDoc::parse] does besides parsing
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ParseOptions {
+    /// Check the commands against the semantic rules of the
+    /// specification, see [validate](crate::validate), on by default
+    pub validate: bool,
+
+    /// Accept a `%FS` with trailing zero omission, incremental coordinates
+    /// or fewer than six decimals, as found in old files, by converting
+    /// the coordinates, see [convert_legacy_format]; off by default
+    ///
+    /// Spans refer to the original source. The conversion is reported as
+    /// a [LegacyFormatSpecification](DiagnosticKind::LegacyFormatSpecification)
+    /// warning, as such files are better exported again.
+    pub legacy_format: bool,
+
+    /// Levels of the lints reported in the diagnostics
+    pub lints: Lints,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            validate: true,
+            legacy_format: false,
+            lints: Lints::default(),
+        }
+    }
+}
+
+impl<'a> GerberDoc<'a> {
+    /// Parse a whole file
+    ///
+    /// ```
+    /// use gerber::data::Unit;
+    /// use gerber::{GerberDoc, ParseOptions};
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\nM02*\n";
+    /// let doc = GerberDoc::parse(src, &ParseOptions::default()).unwrap();
+    /// assert_eq!(doc.commands().len(), 4);
+    /// assert_eq!(doc.format.unwrap().0.decimal, 6);
+    /// assert_eq!(doc.unit, Some(Unit::Millimeters));
+    /// assert_eq!(doc.spans()[1].line, 2);
+    /// // D10 is never used
+    /// assert_eq!(doc.diagnostics.len(), 1);
+    /// ```
+    pub fn parse(src: &'a str, options: &ParseOptions) -> Result<Self, GerberError> {
+        let converted = options
+            .legacy_format
+            .then(|| convert_legacy_format(src))
+            .filter(|converted| !converted.rewrites.is_empty());
+        let layer = match &converted {
+            Some(converted) => {
+                let mut layer = GerberLayer::parse(&converted.source)?.into_owned();
+                let ranges: Vec<_> = layer
+                    .spans
+                    .iter()
+                    .map(|span| converted.original_range(span.bytes.clone()))
+                    .collect();
+                layer.spans = span::spans(src, ranges);
+                layer
+            }
+            None => GerberLayer::parse(src)?,
+        };
+        let format = layer.commands.iter().find_map(|command| match command {
+            FormatSpecification(x, y) => Some((*x, *y)),
+            _ => None,
+        });
+        let unit = layer.commands.iter().find_map(|command| match command {
+            Mode(unit) => Some(*unit),
+            _ => None,
+        });
+        let mut diagnostics = if options.validate {
+            layer.validate()
+        } else {
+            Vec::new()
+        };
+        if converted.is_some() {
+            let command = layer
+                .commands
+                .iter()
+                .position(|command| matches!(command, FormatSpecification(..)));
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::LegacyFormatSpecification,
+                command,
+            ));
+        }
+        Ok(Self {
+            layer,
+            format,
+            unit,
+            diagnostics: options.lints.apply(diagnostics),
+        })
+    }
+
+    /// The commands, in file order
+    pub fn commands(&self) -> &[Command<'a>] {
+        self.layer.commands()
+    }
+
+    /// The location in the source of each command
+    pub fn spans(&self) -> &[Span] {
+        &self.layer.spans
+    }
+
+    /// Convert into a document which does not borrow from the source
+    pub fn into_owned(self) -> GerberDoc<'static> {
+        GerberDoc {
+            layer: self.layer.into_owned(),
+            format: self.format,
+            unit: self.unit,
+            diagnostics: self.diagnostics,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{Point, Shape};
+    use crate::lint::LintLevel;
+    use indoc::indoc;
+
+    #[test]
+    fn test_legacy_format() {
+        let src = indoc! {"
+            %FSTAX24Y24*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X15Y-025D03*
+            M02*
+        "};
+        assert!(GerberDoc::parse(src, &ParseOptions::default()).is_err());
+
+        let options = ParseOptions {
+            legacy_format: true,
+            ..Default::default()
+        };
+        let doc = GerberDoc::parse(src, &options).unwrap();
+        assert_eq!(doc.format.unwrap().0.decimal, 6);
+        let Shape::Flash { at, .. } = doc.layer.image().objects[0].shape else {
+            panic!("expected a flash");
+        };
+        assert_eq!(at, Point { x: 15.0, y: -2.5 });
+        assert_eq!(&src[doc.spans()[4].bytes.clone()], "X15Y-025D03*");
+        assert_eq!(doc.spans()[4].line, 5);
+        assert_eq!(
+            doc.diagnostics
+                .iter()
+                .map(|diagnostic| (&diagnostic.kind, diagnostic.command))
+                .collect::<Vec<_>>(),
+            [(&DiagnosticKind::LegacyFormatSpecification, Some(0))]
+        );
+
+        // current files are parsed as they are
+        let current = "%FSLAX26Y26*%\n%MOMM*%\nM02*\n";
+        let doc = GerberDoc::parse(current, &options).unwrap();
+        assert!(doc
+            .diagnostics
+            .iter()
+            .all(|diagnostic| { diagnostic.kind != DiagnosticKind::LegacyFormatSpecification }));
+
+        // the warning is a lint like the others
+        let mut lints = Lints::default();
+        lints
+            .set("legacy_format_specification", LintLevel::Allow)
+            .unwrap();
+        let options = ParseOptions { lints, ..options };
+        assert!(GerberDoc::parse(src, &options)
+            .unwrap()
+            .diagnostics
+            .is_empty());
+    }
+}
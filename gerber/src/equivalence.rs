@@ -0,0 +1,465 @@
+//! Comparison of layers by the image they describe
+//!
+//! Two layers are equivalent when they create the same objects in the same
+//! order, whatever unit, coordinate format and aperture numbers they are
+//! written with. This verifies that a rewrite of a file, e.g.
+//! [snapping](GerberLayer::snap_to_grid) coordinates or renumbering
+//! apertures, didn't change the artwork.
+//!
+//! Objects are compared one to one, so a rewrite which merges, splits or
+//! reorders objects is reported as a difference even where the image it
+//! creates is unchanged. Attributes are not compared, as they don't change
+//! the image.
+
+use std::fmt;
+
+use crate::aperture::ApertureTemplate;
+use crate::data::Unit;
+use crate::image::{Contour, Image, Object, Point, Segment, Shape};
+use crate::GerberLayer;
+
+/// The first object in which two layers differ
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Difference {
+    /// Index of the object in both images, or the number of objects in the
+    /// shorter image for [Count](DifferenceKind::Count)
+    pub object: usize,
+
+    pub kind: DifferenceKind,
+}
+
+/// How an object differs
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DifferenceKind {
+    /// The images have different numbers of objects
+    Count { expected: usize, found: usize },
+
+    /// The objects are different kinds of shape, e.g. a flash and a draw
+    Shape,
+
+    /// Points of the objects are further apart than the tolerance
+    Geometry,
+
+    /// The objects use different aperture templates or sizes
+    Aperture,
+
+    /// One object darkens and the other clears
+    Polarity,
+
+    /// The objects are mirrored, rotated or scaled differently
+    Transformation,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            DifferenceKind::Count { expected, found } => {
+                write!(f, "{found} objects where {expected} were expected")
+            }
+            DifferenceKind::Shape => write!(f, "object {} has a different shape", self.object),
+            DifferenceKind::Geometry => write!(f, "object {} is in a different place", self.object),
+            DifferenceKind::Aperture => {
+                write!(f, "object {} uses a different aperture", self.object)
+            }
+            DifferenceKind::Polarity => {
+                write!(f, "object {} has a different polarity", self.object)
+            }
+            DifferenceKind::Transformation => {
+                write!(f, "object {} is transformed differently", self.object)
+            }
+        }
+    }
+}
+
+impl GerberLayer<'_> {
+    /// True when `other` describes the same image, with points at most
+    /// `tolerance` millimeters apart
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let mm = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.254*%\nD10*\nX25400000Y0D03*\nM02*\n";
+    /// let inches = "%FSLAX36Y36*%\n%MOIN*%\n%ADD22C,0.01*%\nD22*\nX1000000Y0D03*\nM02*\n";
+    /// let mm = GerberLayer::parse(mm).unwrap();
+    /// assert!(mm.equivalent_to(&GerberLayer::parse(inches).unwrap(), 1e-6));
+    /// ```
+    pub fn equivalent_to(&self, other: &GerberLayer<'_>, tolerance: f64) -> bool {
+        self.difference(other, tolerance).is_none()
+    }
+
+    /// The first object which differs from `other`, or `None` if the layers
+    /// are equivalent
+    pub fn difference(&self, other: &GerberLayer<'_>, tolerance: f64) -> Option<Difference> {
+        self.image().difference(&other.image(), tolerance)
+    }
+}
+
+impl Image {
+    /// The first object which differs from `other`, or `None` if the images
+    /// are equivalent
+    ///
+    /// Aperture templates are compared in millimeters, except for macro
+    /// parameters whose unit isn't known, which must be equal.
+    pub fn difference(&self, other: &Image, tolerance: f64) -> Option<Difference> {
+        let compare = Comparison {
+            expected: self,
+            found: other,
+            tolerance,
+        };
+        let objects = self.objects.iter().zip(&other.objects);
+        for (index, (expected, found)) in objects.enumerate() {
+            if let Some(kind) = compare.objects(expected, found) {
+                return Some(Difference {
+                    object: index,
+                    kind,
+                });
+            }
+        }
+        let (expected, found) = (self.objects.len(), other.objects.len());
+        (expected != found).then_some(Difference {
+            object: expected.min(found),
+            kind: DifferenceKind::Count { expected, found },
+        })
+    }
+}
+
+struct Comparison<'i> {
+    expected: &'i Image,
+    found: &'i Image,
+    tolerance: f64,
+}
+
+impl Comparison<'_> {
+    fn objects(&self, expected: &Object, found: &Object) -> Option<DifferenceKind> {
+        if expected.polarity != found.polarity {
+            return Some(DifferenceKind::Polarity);
+        }
+        if (expected.mirroring, expected.rotation, expected.scaling)
+            != (found.mirroring, found.rotation, found.scaling)
+        {
+            return Some(DifferenceKind::Transformation);
+        }
+        let same = match (&expected.shape, &found.shape) {
+            (
+                Shape::Draw {
+                    start: a0, end: a1, ..
+                },
+                Shape::Draw {
+                    start: b0, end: b1, ..
+                },
+            ) => {
+                // a stroke is the same whichever end it starts from
+                (self.point(a0, b0) && self.point(a1, b1))
+                    || (self.point(a0, b1) && self.point(a1, b0))
+            }
+            (
+                Shape::Arc {
+                    start: a0,
+                    end: a1,
+                    center: ac,
+                    direction: ad,
+                    ..
+                },
+                Shape::Arc {
+                    start: b0,
+                    end: b1,
+                    center: bc,
+                    direction: bd,
+                    ..
+                },
+            ) => {
+                self.point(ac, bc)
+                    && ((ad == bd && self.point(a0, b0) && self.point(a1, b1))
+                        || (*ad == bd.reversed() && self.point(a0, b1) && self.point(a1, b0)))
+            }
+            (Shape::Flash { at: a, .. }, Shape::Flash { at: b, .. }) => self.point(a, b),
+            (Shape::Region { contours: a }, Shape::Region { contours: b }) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| self.contours(a, b))
+            }
+            _ => return Some(DifferenceKind::Shape),
+        };
+        if !same {
+            return Some(DifferenceKind::Geometry);
+        }
+
+        let template = |image: &Image, object: &Object| {
+            let unit = image.unit.unwrap_or(Unit::Millimeters);
+            let template = image.apertures.get(&object.source.aperture?)?;
+            Some(to_mm(template, unit))
+        };
+        let same = match (
+            template(self.expected, expected),
+            template(self.found, found),
+        ) {
+            (Some(a), Some(b)) => self.templates(&a, &b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        (!same).then_some(DifferenceKind::Aperture)
+    }
+
+    fn point(&self, a: &Point, b: &Point) -> bool {
+        (a.x - b.x).hypot(a.y - b.y) <= self.tolerance
+    }
+
+    fn value(&self, a: f64, b: f64) -> bool {
+        (a - b).abs() <= self.tolerance
+    }
+
+    fn hole(&self, a: Option<f64>, b: Option<f64>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => self.value(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+
+    fn contours(&self, a: &Contour, b: &Contour) -> bool {
+        self.point(&a.start, &b.start)
+            && a.segments.len() == b.segments.len()
+            && a.segments.iter().zip(&b.segments).all(|pair| match pair {
+                (Segment::Line { end: a }, Segment::Line { end: b }) => self.point(a, b),
+                (
+                    Segment::Arc {
+                        end: a,
+                        center: ac,
+                        direction: ad,
+                    },
+                    Segment::Arc {
+                        end: b,
+                        center: bc,
+                        direction: bd,
+                    },
+                ) => ad == bd && self.point(a, b) && self.point(ac, bc),
+                _ => false,
+            })
+    }
+
+    fn templates(&self, a: &ApertureTemplate, b: &ApertureTemplate) -> bool {
+        use ApertureTemplate::*;
+        match (a, b) {
+            (
+                Circle {
+                    diameter: a,
+                    hole: ah,
+                },
+                Circle {
+                    diameter: b,
+                    hole: bh,
+                },
+            ) => self.value(*a, *b) && self.hole(*ah, *bh),
+            (
+                Rectangle {
+                    x: ax,
+                    y: ay,
+                    hole: ah,
+                },
+                Rectangle {
+                    x: bx,
+                    y: by,
+                    hole: bh,
+                },
+            )
+            | (
+                Obround {
+                    x: ax,
+                    y: ay,
+                    hole: ah,
+                },
+                Obround {
+                    x: bx,
+                    y: by,
+                    hole: bh,
+                },
+            ) => self.value(*ax, *bx) && self.value(*ay, *by) && self.hole(*ah, *bh),
+            (
+                Polygon {
+                    diameter: a,
+                    vertices: av,
+                    rotation: ar,
+                    hole: ah,
+                },
+                Polygon {
+                    diameter: b,
+                    vertices: bv,
+                    rotation: br,
+                    hole: bh,
+                },
+            ) => {
+                self.value(*a, *b)
+                    && av == bv
+                    && ar.unwrap_or(0.0) == br.unwrap_or(0.0)
+                    && self.hole(*ah, *bh)
+            }
+            (Macro { .. }, Macro { .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// The template with its sizes in millimeters
+fn to_mm(template: &ApertureTemplate, unit: Unit) -> ApertureTemplate<'static> {
+    let mm = |value| unit.to_mm(value);
+    let hole = |hole: Option<f64>| hole.map(mm);
+    match template.clone().into_owned() {
+        ApertureTemplate::Circle { diameter, hole: h } => ApertureTemplate::Circle {
+            diameter: mm(diameter),
+            hole: hole(h),
+        },
+        ApertureTemplate::Rectangle { x, y, hole: h } => ApertureTemplate::Rectangle {
+            x: mm(x),
+            y: mm(y),
+            hole: hole(h),
+        },
+        ApertureTemplate::Obround { x, y, hole: h } => ApertureTemplate::Obround {
+            x: mm(x),
+            y: mm(y),
+            hole: hole(h),
+        },
+        ApertureTemplate::Polygon {
+            diameter,
+            vertices,
+            rotation,
+            hole: h,
+        } => ApertureTemplate::Polygon {
+            diameter: mm(diameter),
+            vertices,
+            rotation,
+            hole: hole(h),
+        },
+        template @ ApertureTemplate::Macro { .. } => template,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const MM: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10C,0.254*%
+        %ADD11R,2.54X1.27*%
+        D10*
+        G01*
+        X0Y0D02*
+        X25400000Y0D01*
+        G75*
+        G03*
+        X0Y25400000I-25400000J0D01*
+        D11*
+        X12700000Y12700000D03*
+        G36*
+        X0Y0D02*
+        G01*
+        X2540000Y0D01*
+        X0Y2540000D01*
+        X0Y0D01*
+        G37*
+        %LPC*%
+        X12700000Y12700000D03*
+        M02*
+    "};
+
+    /// The same image in inches, with other aperture numbers, the draw
+    /// reversed and the arc drawn the other way round
+    const INCHES: &str = indoc! {"
+        %FSLAX36Y36*%
+        %MOIN*%
+        %ADD20R,0.1X0.05*%
+        %ADD21C,0.01*%
+        D21*
+        G01*
+        X1000000Y0D02*
+        X0Y0D01*
+        G75*
+        X0Y1000000D02*
+        G02*
+        X1000000Y0I0J-1000000D01*
+        D20*
+        X500000Y500000D03*
+        G36*
+        X0Y0D02*
+        G01*
+        X100000Y0D01*
+        X0Y100000D01*
+        X0Y0D01*
+        G37*
+        %LPC*%
+        X500000Y500000D03*
+        M02*
+    "};
+
+    fn difference(a: &str, b: &str, tolerance: f64) -> Option<Difference> {
+        let a = GerberLayer::parse(a).unwrap();
+        a.difference(&GerberLayer::parse(b).unwrap(), tolerance)
+    }
+
+    #[test]
+    fn test_equivalent() {
+        assert_eq!(difference(MM, MM, 0.0), None);
+        assert_eq!(difference(MM, INCHES, 1e-6), None);
+        assert_eq!(difference(INCHES, MM, 1e-6), None);
+    }
+
+    #[test]
+    fn test_snapped() {
+        let src = MM.replace("X25400000Y0D01", "X25400004Y0D01");
+        let expected = Difference {
+            object: 0,
+            kind: DifferenceKind::Geometry,
+        };
+        assert_eq!(difference(MM, &src, 1e-9), Some(expected.clone()));
+        assert_eq!(expected.to_string(), "object 0 is in a different place");
+
+        let mut snapped = GerberLayer::parse(&src).unwrap();
+        snapped.snap_to_grid(0.001);
+        let layer = GerberLayer::parse(MM).unwrap();
+        assert!(layer.equivalent_to(&snapped, 1e-9));
+        assert!(layer.equivalent_to(&GerberLayer::parse(&src).unwrap(), 1e-5));
+    }
+
+    #[test]
+    fn test_differences() {
+        let kind = |src: String| difference(MM, &src, 1e-6).map(|difference| difference.kind);
+        assert_eq!(
+            kind(MM.replace("%ADD10C,0.254*%", "%ADD10C,0.25*%")),
+            Some(DifferenceKind::Aperture)
+        );
+        assert_eq!(
+            kind(MM.replace("%ADD11R,2.54X1.27*%", "%ADD11O,2.54X1.27*%")),
+            Some(DifferenceKind::Aperture)
+        );
+        assert_eq!(
+            kind(MM.replace("%LPC*%", "%LPD*%")),
+            Some(DifferenceKind::Polarity)
+        );
+        assert_eq!(
+            kind(MM.replace("%LPC*%", "%LPC*%\n%LR90*%")),
+            Some(DifferenceKind::Transformation)
+        );
+        assert_eq!(
+            kind(MM.replace("G03*", "G02*")),
+            Some(DifferenceKind::Geometry)
+        );
+        assert_eq!(
+            kind(MM.replace("X12700000Y12700000D03*\nG36", "X12700000Y12700000D02*\nG36")),
+            Some(DifferenceKind::Shape)
+        );
+
+        let fewer = MM.replace("%LPC*%\nX12700000Y12700000D03*\n", "");
+        let difference = difference(MM, &fewer, 1e-6).unwrap();
+        assert_eq!(
+            difference,
+            Difference {
+                object: 4,
+                kind: DifferenceKind::Count {
+                    expected: 5,
+                    found: 4
+                }
+            }
+        );
+        assert_eq!(difference.to_string(), "4 objects where 5 were expected");
+    }
+}
@@ -0,0 +1,364 @@
+//! Fabrication statistics for quoting
+//!
+//! Fab quote forms ask for the same few numbers: the size of the board,
+//! the number of copper layers, the smallest trace, and the number and
+//! sizes of the holes. [Fabrication] collects them from a layer, and
+//! [ProjectFabrication] from the layers of a board.
+//!
+//! The kind of a layer comes from `.FileFunction`. Drill and rout layers
+//! (`Plated` or `NonPlated`) give holes, and copper layers give pads and
+//! traces. A layer without the attribute is taken as copper, and other
+//! layers, such as the solder mask or profile, give nothing.
+
+use std::collections::BTreeMap;
+
+use crate::aperture::ApertureTemplate;
+use crate::data::{Unit, NM_PER_MM};
+use crate::image::Image;
+use crate::object::View;
+use crate::project::{board_size, copper_layers, file_function, GerberProject};
+use crate::GerberLayer;
+
+/// Fabrication statistics of one or more layers, in millimeters
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Fabrication {
+    /// The number of drilled holes of each diameter, smallest first
+    pub drills: Vec<DrillSize>,
+
+    /// The number of routed or oblong holes, see
+    /// [slots](GerberLayer::slots)
+    pub slots: usize,
+
+    pub smallest_drill: Option<f64>,
+
+    /// Flashes, and regions whose `.AperFunction` is a pad, such as
+    /// `SMDPad`. Flashes with a function other than a pad, e.g. text, are
+    /// not counted.
+    pub pads: usize,
+
+    /// The total length of the draws and arcs which are conductors, i.e.
+    /// without `.AperFunction` or with `Conductor`
+    pub trace_length: f64,
+
+    /// The width of the narrowest trace drawn with a round aperture
+    pub smallest_trace: Option<f64>,
+}
+
+/// The number of holes of a diameter
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DrillSize {
+    pub diameter: f64,
+    pub count: usize,
+}
+
+/// Fabrication statistics of a board
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProjectFabrication {
+    /// The width and height of the board, see
+    /// [board_size](GerberProject::board_size)
+    pub board_size: Option<(f64, f64)>,
+
+    /// The number of distinct copper layers in `.FileFunction`
+    pub copper_layers: usize,
+
+    /// The statistics of all layers together
+    pub total: Fabrication,
+
+    /// The statistics of each layer, in the order of the project
+    pub layers: Vec<Fabrication>,
+}
+
+impl Fabrication {
+    /// Add the statistics of another layer
+    pub fn merge(&mut self, other: &Fabrication) {
+        let mut drills = drill_counts(&self.drills);
+        for (diameter, count) in drill_counts(&other.drills) {
+            *drills.entry(diameter).or_default() += count;
+        }
+        self.drills = drill_sizes(drills);
+        self.slots += other.slots;
+        self.smallest_drill = min(self.smallest_drill, other.smallest_drill);
+        self.pads += other.pads;
+        self.trace_length += other.trace_length;
+        self.smallest_trace = min(self.smallest_trace, other.smallest_trace);
+    }
+
+    /// The total number of holes, drilled and routed
+    pub fn holes(&self) -> usize {
+        self.drills.iter().map(|drill| drill.count).sum::<usize>() + self.slots
+    }
+}
+
+impl GerberLayer<'_> {
+    /// Collect the fabrication statistics of the layer
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%TF.FileFunction,Plated,1,2,PTH*%\n\
+    ///            %ADD10C,0.3*%\n%ADD11C,0.8*%\nD10*\nX0Y0D03*\nX1000000Y0D03*\n\
+    ///            D11*\nX5000000Y0D03*\nM02*\n";
+    /// let fabrication = GerberLayer::parse(src).unwrap().fabrication();
+    /// assert_eq!(fabrication.drills[0].diameter, 0.3);
+    /// assert_eq!(fabrication.drills[0].count, 2);
+    /// assert_eq!(fabrication.holes(), 3);
+    /// assert_eq!(fabrication.smallest_drill, Some(0.3));
+    /// ```
+    pub fn fabrication(&self) -> Fabrication {
+        let image = self.image();
+        let function = file_function(&image);
+        match function.as_ref().and_then(|function| function.first()) {
+            Some(&"Plated" | &"NonPlated") => drill_fabrication(&image, self.slots().len()),
+            Some(&"Copper") | None => copper_fabrication(&image),
+            Some(_) => Fabrication::default(),
+        }
+    }
+}
+
+impl GerberProject<'_> {
+    /// Collect the fabrication statistics of the board
+    ///
+    /// ```
+    /// use gerber::project::GerberProject;
+    /// use gerber::GerberLayer;
+    ///
+    /// let copper = "%FSLAX26Y26*%\n%MOMM*%\n%TF.FileFunction,Copper,L1,Top*%\n\
+    ///               %ADD10C,0.15*%\nD10*\nX0Y0D02*\nX3000000Y4000000D01*\nM02*\n";
+    /// let mut project = GerberProject::new();
+    /// project.add("board-F_Cu.gbr", GerberLayer::parse(copper).unwrap());
+    /// let fabrication = project.fabrication();
+    /// assert_eq!(fabrication.copper_layers, 1);
+    /// assert_eq!(fabrication.total.trace_length, 5.0);
+    /// assert_eq!(fabrication.total.smallest_trace, Some(0.15));
+    /// ```
+    pub fn fabrication(&self) -> ProjectFabrication {
+        let images = self.images();
+        let layers: Vec<_> = self
+            .layers
+            .iter()
+            .map(|layer| layer.layer.fabrication())
+            .collect();
+        let mut total = Fabrication::default();
+        for layer in &layers {
+            total.merge(layer);
+        }
+        ProjectFabrication {
+            board_size: board_size(&images),
+            copper_layers: copper_layers(&images),
+            total,
+            layers,
+        }
+    }
+}
+
+fn drill_fabrication(image: &Image, slots: usize) -> Fabrication {
+    let unit = image.unit.unwrap_or(Unit::Millimeters);
+    let mut drills = BTreeMap::new();
+    for view in image.views() {
+        let scaling = view.object().scaling.0;
+        let View::Flash(flash) = view else {
+            continue;
+        };
+        if let Some(&ApertureTemplate::Circle { diameter, .. }) = flash.template() {
+            let diameter = unit.to_mm(diameter) * scaling;
+            *drills.entry(nanometers(diameter)).or_default() += 1;
+        }
+    }
+    let drills = drill_sizes(drills);
+    Fabrication {
+        smallest_drill: drills.first().map(|drill| drill.diameter),
+        drills,
+        slots,
+        ..Default::default()
+    }
+}
+
+fn copper_fabrication(image: &Image) -> Fabrication {
+    let mut fabrication = Fabrication::default();
+    for view in image.views() {
+        let function = view.attributes().aperture_function();
+        let is_pad = function.is_some_and(|function| function.ends_with("Pad"));
+        let is_conductor = matches!(function, None | Some("Conductor"));
+        let (length, width) = match view {
+            View::Flash(_) => {
+                fabrication.pads += usize::from(function.is_none() || is_pad);
+                continue;
+            }
+            View::Region(_) => {
+                fabrication.pads += usize::from(is_pad);
+                continue;
+            }
+            View::Draw(draw) => (draw.length(), draw.width()),
+            View::Arc(arc) => {
+                let geometry = arc.geometry();
+                (geometry.radius * geometry.sweep.abs(), arc.width())
+            }
+        };
+        if is_conductor {
+            fabrication.trace_length += length;
+            fabrication.smallest_trace = min(fabrication.smallest_trace, width);
+        }
+    }
+    fabrication
+}
+
+/// A diameter rounded to the nanometer, so sizes written with different
+/// units or decimals are counted together
+fn nanometers(mm: f64) -> i64 {
+    (mm * NM_PER_MM as f64).round() as i64
+}
+
+fn drill_counts(drills: &[DrillSize]) -> BTreeMap<i64, usize> {
+    drills
+        .iter()
+        .map(|drill| (nanometers(drill.diameter), drill.count))
+        .collect()
+}
+
+fn drill_sizes(drills: BTreeMap<i64, usize>) -> Vec<DrillSize> {
+    drills
+        .into_iter()
+        .map(|(diameter, count)| DrillSize {
+            diameter: diameter as f64 / NM_PER_MM as f64,
+            count,
+        })
+        .collect()
+}
+
+fn min(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn layer(src: &str) -> GerberLayer<'static> {
+        GerberLayer::parse(src).unwrap().into_owned()
+    }
+
+    #[test]
+    fn test_fabrication() {
+        let copper = layer(indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %TF.FileFunction,Copper,L1,Top*%
+            %ADD10C,1*%
+            %TA.AperFunction,Conductor*%
+            %ADD11C,0.2*%
+            %TA.AperFunction,NonConductor*%
+            %ADD12C,0.05*%
+            %TD*%
+            D10*
+            X0Y0D03*
+            X5000000Y0D03*
+            D11*
+            G01*
+            X0Y0D02*
+            X5000000Y0D01*
+            G75*
+            G03*
+            X5000000Y0I1000000J0D01*
+            D12*
+            G01*
+            X0Y1000000D02*
+            X9000000Y1000000D01*
+            %TA.AperFunction,SMDPad,CuDef*%
+            G36*
+            X0Y2000000D02*
+            X1000000Y2000000D01*
+            X1000000Y3000000D01*
+            X0Y2000000D01*
+            G37*
+            G36*
+            X0Y4000000D02*
+            X1000000Y4000000D01*
+            X1000000Y5000000D01*
+            X0Y4000000D01*
+            G37*
+            M02*
+        "});
+        let plated = layer(indoc! {"
+            %FSLAX26Y26*%
+            %MOIN*%
+            %TF.FileFunction,Plated,1,2,PTH*%
+            %ADD10C,0.0118*%
+            %ADD11C,0.04*%
+            %ADD12O,0.04X0.08*%
+            D10*
+            X0Y0D03*
+            X1000000Y0D03*
+            D11*
+            X2000000Y0D03*
+            D12*
+            X3000000Y0D03*
+            M02*
+        "});
+        let non_plated = layer(indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %TF.FileFunction,NonPlated,1,2,NPTH*%
+            %ADD10C,0.2997*%
+            %ADD11C,3.2*%
+            D10*
+            X0Y0D03*
+            D11*
+            X1000000Y0D03*
+            M02*
+        "});
+        let profile = layer(indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %TF.FileFunction,Profile,NP*%
+            %ADD10C,0*%
+            D10*
+            G01*
+            X0Y0D02*
+            X40000000D01*
+            Y25000000D01*
+            X0D01*
+            Y0D01*
+            M02*
+        "});
+
+        let layers = copper.fabrication();
+        assert_eq!(layers.pads, 4);
+        // the NonConductor draw is neither length nor width
+        let circle = 2.0 * std::f64::consts::PI;
+        assert!((layers.trace_length - (5.0 + circle)).abs() < 1e-9);
+        assert_eq!(layers.smallest_trace, Some(0.2));
+        assert!(layers.drills.is_empty());
+
+        let mut project = GerberProject::new();
+        project.add("board-F_Cu.gbr", copper);
+        project.add("board-PTH.gbr", plated);
+        project.add("board-NPTH.gbr", non_plated);
+        project.add("board-Edge_Cuts.gbr", profile);
+        let fabrication = project.fabrication();
+        assert_eq!(fabrication.board_size, Some((40.0, 25.0)));
+        assert_eq!(fabrication.copper_layers, 1);
+        assert_eq!(fabrication.layers.len(), 4);
+        assert_eq!(fabrication.layers[1].slots, 1);
+        assert_eq!(fabrication.layers[1].smallest_drill, Some(0.29972));
+        assert_eq!(fabrication.layers[3], Fabrication::default());
+
+        let total = &fabrication.total;
+        let drills: Vec<_> = total
+            .drills
+            .iter()
+            .map(|drill| (drill.diameter, drill.count))
+            .collect();
+        assert_eq!(drills, [(0.2997, 1), (0.29972, 2), (1.016, 1), (3.2, 1)]);
+        assert_eq!(total.smallest_drill, Some(0.2997));
+        assert_eq!(total.holes(), 6);
+        assert_eq!(total.pads, 4);
+        assert_eq!(total.smallest_trace, Some(0.2));
+    }
+}
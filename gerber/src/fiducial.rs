@@ -0,0 +1,390 @@
+//! Fiducial extraction
+//!
+//! Pick-and-place machines locate the board, and fine-pitch parts on it,
+//! by optical fiducials: small bare copper discs with a clear area around
+//! them. Gerber X2 marks them with `.AperFunction,FiducialPad,Global` (or
+//! `Local` or `Panel`). Older files carry no attributes, so fiducials are
+//! then recognised by their shape instead.
+
+use crate::aperture::ApertureTemplate;
+use crate::command::Command::*;
+use crate::data::{Polarity, Unit};
+use crate::image::{Image, Object, Point, Segment, Shape};
+use crate::macros::{ApertureMacro, MacroPrimitive};
+use crate::redundant::distance_to_segment;
+use crate::testpoint::Side;
+use crate::GerberLayer;
+
+/// The smallest and largest diameter of a fiducial found by shape, in
+/// millimeters
+const DIAMETERS: (f64, f64) = (0.5, 3.0);
+
+/// The clear area around a fiducial found by shape, as a multiple of its
+/// diameter measured from the center
+const CLEARANCE: f64 = 1.5;
+
+/// A fiducial mark
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Fiducial {
+    /// Center of the fiducial in millimeters
+    pub at: Point,
+
+    /// What the fiducial locates, if given by its attributes
+    pub scope: Option<Scope>,
+
+    /// The reference designator of a local fiducial's component, `.C`
+    pub component: Option<String>,
+
+    /// The side of the board, from `.FileFunction`
+    pub side: Option<Side>,
+
+    pub detection: Detection,
+
+    /// Index of the object in [Image::objects]
+    pub object: usize,
+}
+
+/// What a fiducial locates
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Scope {
+    /// The board
+    Global,
+
+    /// A single component
+    Local,
+
+    /// The panel the board is part of
+    Panel,
+}
+
+/// How a fiducial was found
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Detection {
+    /// From its `.AperFunction` attribute
+    Attribute,
+
+    /// From its shape and surroundings
+    Shape,
+}
+
+impl GerberLayer<'_> {
+    /// Find the fiducials of a copper layer
+    ///
+    /// Flashes whose aperture function is `FiducialPad` (or `Fiducial`) are
+    /// fiducials. If there are none, a dark flash of a round aperture
+    /// without a hole, 0.5 to 3 mm in diameter, belonging to no net and
+    /// with nothing within 1.5 diameters of its center is taken to be one.
+    /// Flashes whose aperture has any other function are never fiducials.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    /// use gerber::fiducial::{Detection, Scope};
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%TA.AperFunction,FiducialPad,Global*%\n\
+    ///            %ADD10C,1*%\nD10*\nX5000000Y5000000D03*\nM02*\n";
+    /// let fiducials = GerberLayer::parse(src).unwrap().fiducials();
+    /// assert_eq!(fiducials[0].scope, Some(Scope::Global));
+    /// assert_eq!(fiducials[0].detection, Detection::Attribute);
+    /// ```
+    pub fn fiducials(&self) -> Vec<Fiducial> {
+        let image = self.image();
+        let side = Side::of(&image);
+        let fiducial = |index: usize, at: Point, scope, detection| {
+            let object: &Object = &image.objects[index];
+            Fiducial {
+                at,
+                scope,
+                component: object.attributes.component().map(str::to_string),
+                side,
+                detection,
+                object: index,
+            }
+        };
+
+        let marked: Vec<_> = image
+            .objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, object)| {
+                let Shape::Flash { at, .. } = object.shape else {
+                    return None;
+                };
+                let function = object.attributes.get(".AperFunction")?;
+                if !matches!(
+                    function.first().map(AsRef::as_ref),
+                    Some("FiducialPad" | "Fiducial")
+                ) {
+                    return None;
+                }
+                let scope = match function.get(1).map(AsRef::as_ref) {
+                    Some("Global") => Some(Scope::Global),
+                    Some("Local") => Some(Scope::Local),
+                    Some("Panel") => Some(Scope::Panel),
+                    _ => None,
+                };
+                Some(fiducial(index, at, scope, Detection::Attribute))
+            })
+            .collect();
+        if !marked.is_empty() {
+            return marked;
+        }
+
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let macros = self.aperture_macros();
+        image
+            .objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, object)| {
+                let Shape::Flash { at, .. } = object.shape else {
+                    return None;
+                };
+                let diameter = self.round_diameter(object, unit)?;
+                let attributes = &object.attributes;
+                if object.polarity != Polarity::Dark
+                    || !(DIAMETERS.0..=DIAMETERS.1).contains(&diameter)
+                    || attributes.aperture_function().is_some()
+                    || attributes
+                        .net()
+                        .is_some_and(|net| !net.is_empty() && net != "N/C")
+                    || !self.is_isolated(&image, &macros, index, at, CLEARANCE * diameter, unit)
+                {
+                    return None;
+                }
+                Some(fiducial(index, at, None, Detection::Shape))
+            })
+            .collect()
+    }
+
+    /// The diameter in millimeters of a flash of a round aperture without a
+    /// hole
+    fn round_diameter(&self, object: &Object, unit: Unit) -> Option<f64> {
+        match &self.commands[object.source.aperture?] {
+            ApertureDefine(
+                _,
+                ApertureTemplate::Circle {
+                    diameter,
+                    hole: None,
+                },
+            ) => Some(unit.to_mm(diameter * object.scaling.0)),
+            _ => None,
+        }
+    }
+
+    /// True if no dark object other than `index` comes within `radius`
+    /// millimeters of `center`
+    ///
+    /// Strokes and flashes are approximated by their aperture's
+    /// circumscribed circle along their path and arcs by their full circle,
+    /// which errs on the side of finding something close. The circle of a
+    /// macro aperture is around its primitives, looked up in `macros`.
+    /// Regions are measured to their contours, as a fiducial in a pour sits
+    /// in a hole cut into it.
+    fn is_isolated(
+        &self,
+        image: &Image,
+        macros: &[ApertureMacro],
+        index: usize,
+        center: Point,
+        radius: f64,
+        unit: Unit,
+    ) -> bool {
+        image.objects.iter().enumerate().all(|(other, object)| {
+            if other == index || object.polarity != Polarity::Dark {
+                return true;
+            }
+            let extent = object
+                .source
+                .aperture
+                .and_then(|index| match &self.commands[index] {
+                    ApertureDefine(_, template) => outer_radius(template, macros),
+                    _ => None,
+                })
+                .map_or(0.0, |extent| unit.to_mm(extent * object.scaling.0));
+            let distance = match &object.shape {
+                Shape::Flash { at, .. } => distance(center, *at),
+                Shape::Draw { start, end, .. } => distance_to_segment(center, *start, *end),
+                Shape::Arc {
+                    start, center: c, ..
+                } => (distance(center, *c) - distance(*start, *c)).abs(),
+                Shape::Region { contours } => contours
+                    .iter()
+                    .flat_map(|contour| {
+                        let mut from = contour.start;
+                        contour.segments.iter().map(move |segment| {
+                            let (Segment::Line { end } | Segment::Arc { end, .. }) = *segment;
+                            let start = std::mem::replace(&mut from, end);
+                            distance_to_segment(center, start, end)
+                        })
+                    })
+                    .fold(f64::INFINITY, f64::min),
+            };
+            distance - extent >= radius
+        })
+    }
+}
+
+/// The radius of the smallest circle around the aperture, or one a little
+/// larger for a macro, or `None` if the macro is undefined or can't be
+/// evaluated
+fn outer_radius(template: &ApertureTemplate, macros: &[ApertureMacro]) -> Option<f64> {
+    match template {
+        ApertureTemplate::Circle { diameter, .. } | ApertureTemplate::Polygon { diameter, .. } => {
+            Some(diameter / 2.0)
+        }
+        ApertureTemplate::Rectangle { x, y, .. } | ApertureTemplate::Obround { x, y, .. } => {
+            Some(x.hypot(*y) / 2.0)
+        }
+        ApertureTemplate::Macro { name, parameters } => {
+            let primitives = ApertureMacro::find(macros, name)
+                .and_then(|body| body.evaluate(parameters))
+                .ok()?;
+            let dark = primitives
+                .iter()
+                .filter(|primitive| primitive.exposure() == Polarity::Dark);
+            Some(dark.map(MacroPrimitive::radius).fold(0.0, f64::max))
+        }
+    }
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn fiducials(body: &str) -> Vec<Fiducial> {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().fiducials()
+    }
+
+    #[test]
+    fn test_attributes() {
+        let fiducials = fiducials(indoc! {"
+            %TF.FileFunction,Copper,L4,Bot*%
+            %TA.AperFunction,FiducialPad,Global*%
+            %ADD10C,1*%
+            %TA.AperFunction,FiducialPad,Local*%
+            %ADD11C,0.5*%
+            %TA.AperFunction,SMDPad,CuDef*%
+            %ADD12C,1*%
+            D10*
+            X0Y0D03*
+            %TO.C,U1*%
+            D11*
+            X10000000Y10000000D03*
+            %TD*%
+            D12*
+            X50000000Y50000000D03*
+        "});
+        let summary: Vec<_> = fiducials
+            .iter()
+            .map(|fiducial| {
+                (
+                    fiducial.object,
+                    fiducial.scope,
+                    fiducial.component.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, Some(Scope::Global), None),
+                (1, Some(Scope::Local), Some("U1"))
+            ]
+        );
+        assert!(fiducials
+            .iter()
+            .all(|fiducial| fiducial.side == Some(Side::Bottom)
+                && fiducial.detection == Detection::Attribute));
+    }
+
+    #[test]
+    fn test_shape() {
+        let fiducials = fiducials(indoc! {"
+            %ADD10C,1*%
+            %ADD11C,5*%
+            %ADD12C,0.2*%
+            %ADD13R,1X1*%
+            D10*
+            X0Y0D03*
+            X10000000Y0D03*
+            X10000000Y1000000D03*
+            D11*
+            X20000000Y0D03*
+            D13*
+            X30000000Y0D03*
+            D10*
+            X40000000Y0D03*
+            D12*
+            X40000000Y1500000D02*
+            X41000000Y1500000D01*
+        "});
+        let found: Vec<_> = fiducials.iter().map(|fiducial| fiducial.at).collect();
+        // the second and third pads are too close together, the fourth is
+        // too large, the fifth is square and the last is next to a track
+        assert_eq!(found, [Point { x: 0.0, y: 0.0 }]);
+        assert_eq!(fiducials[0].detection, Detection::Shape);
+        assert_eq!(fiducials[0].scope, None);
+    }
+
+    #[test]
+    fn test_macro_neighbour() {
+        // a pad of a macro whose rectangle is off its flash point, so the
+        // flash point alone is clear of the fiducial
+        let fiducials = fiducials(indoc! {"
+            %AMPAD*21,1,2,0.5,-1,0,0*%
+            %ADD10C,1*%
+            %ADD11PAD*%
+            D10*
+            X0Y0D03*
+            D11*
+            X2500000Y0D03*
+        "});
+        assert!(fiducials.is_empty());
+    }
+
+    #[test]
+    fn test_pour_clearance() {
+        // a fiducial in the middle of a pour with a clear ring around it
+        let fiducials = fiducials(indoc! {"
+            %ADD10C,1*%
+            G01*
+            G36*
+            X-10000000Y-10000000D02*
+            X10000000D01*
+            Y10000000D01*
+            X-10000000D01*
+            Y-10000000D01*
+            G37*
+            %LPC*%
+            G36*
+            X-2000000Y-2000000D02*
+            X2000000D01*
+            Y2000000D01*
+            X-2000000D01*
+            Y-2000000D01*
+            G37*
+            %LPD*%
+            D10*
+            X0Y0D03*
+        "});
+        assert_eq!(fiducials.len(), 1);
+        assert_eq!(fiducials[0].object, 2);
+    }
+
+    #[test]
+    fn test_net() {
+        let fiducials = fiducials("%ADD10C,1*%\n%TO.N,GND*%\nD10*\nX0Y0D03*\n");
+        assert!(fiducials.is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+//! GDSII export
+//!
+//! Some lithography tools and semiconductor-adjacent workflows read
+//! [GDSII](https://en.wikipedia.org/wiki/GDSII) stream files rather than
+//! Gerber. A [Gds] library holds a single cell with polygons on numbered
+//! layers, e.g. the [copper](crate::copper) of each Gerber layer on one
+//! GDSII layer and its [flash outlines](GerberLayer::flash_outlines) on
+//! another.
+//!
+//! GDSII boundaries can't have holes, so holes are joined to the exterior
+//! by a zero-width cut, and boundaries with more vertices than a record
+//! holds are split into pieces. Coordinates are written in nanometers with
+//! micrometer user units. Timestamps are left at zero so the output only
+//! depends on the geometry.
+
+use crate::command::Command::*;
+use crate::copper::{clip, object_polygons, Polygon};
+use crate::data::{Polarity, Unit, NM_PER_MM};
+use crate::image::{Point, Shape};
+use crate::GerberLayer;
+
+/// The most points in a boundary, including the repeated first point,
+/// which fill the 65535 bytes of an XY record
+const MAX_POINTS: usize = 8191;
+
+/// A GDSII library with a single cell
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Gds {
+    /// The name of the library
+    pub library: String,
+
+    /// The name of the cell
+    pub cell: String,
+
+    pub layers: Vec<GdsLayer>,
+}
+
+/// Polygons on a GDSII layer, in millimeters
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct GdsLayer {
+    pub layer: i16,
+    pub datatype: i16,
+    pub polygons: Vec<Polygon>,
+}
+
+impl Gds {
+    /// Write the library as a GDSII stream
+    ///
+    /// ```
+    /// use gerber::gds::{Gds, GdsLayer};
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,2X2*%\nD10*\nX0Y0D03*\nM02*\n";
+    /// let copper = GerberLayer::parse(src).unwrap().copper(0.01);
+    /// let gds = Gds {
+    ///     library: "BOARD".into(),
+    ///     cell: "TOP".into(),
+    ///     layers: vec![GdsLayer {
+    ///         layer: 1,
+    ///         datatype: 0,
+    ///         polygons: copper.polygons,
+    ///     }],
+    /// };
+    /// let stream = gds.to_bytes();
+    /// // the HEADER record of version 600
+    /// assert_eq!(stream[..6], [0, 6, 0, 2, 2, 88]);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut stream = Vec::new();
+        let zero_dates = [0i16; 12];
+        record(&mut stream, 0x0002, &600i16.to_be_bytes());
+        record(&mut stream, 0x0102, &int16s(&zero_dates));
+        record(&mut stream, 0x0206, &string(&self.library));
+        // user units per database unit, and the database unit in meters
+        let units: Vec<u8> = [real8(1e-3), real8(1e-9)].concat();
+        record(&mut stream, 0x0305, &units);
+        record(&mut stream, 0x0502, &int16s(&zero_dates));
+        record(&mut stream, 0x0606, &string(&self.cell));
+        for layer in &self.layers {
+            for polygon in &layer.polygons {
+                for boundary in boundaries(polygon) {
+                    record(&mut stream, 0x0800, &[]);
+                    record(&mut stream, 0x0D02, &layer.layer.to_be_bytes());
+                    record(&mut stream, 0x0E02, &layer.datatype.to_be_bytes());
+                    let xy: Vec<u8> = boundary
+                        .iter()
+                        .chain(boundary.first())
+                        .flat_map(|&(x, y)| [x.to_be_bytes(), y.to_be_bytes()])
+                        .flatten()
+                        .collect();
+                    record(&mut stream, 0x1003, &xy);
+                    record(&mut stream, 0x1100, &[]);
+                }
+            }
+        }
+        record(&mut stream, 0x0700, &[]);
+        record(&mut stream, 0x0400, &[]);
+        stream
+    }
+}
+
+impl GerberLayer<'_> {
+    /// The outlines of the dark flashes, such as pads, approximated within
+    /// `tolerance` millimeters
+    ///
+    /// Holes of apertures are holes of the polygons. Flashes of apertures
+    /// whose macro is undefined or can't be evaluated are left out.
+    pub fn flash_outlines(&self, tolerance: f64) -> Vec<Polygon> {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let macros = self.aperture_macros();
+        image
+            .objects
+            .iter()
+            .filter(|object| {
+                matches!(object.shape, Shape::Flash { .. }) && object.polarity == Polarity::Dark
+            })
+            .filter_map(|object| {
+                let template = match object.source.aperture.map(|index| &self.commands[index]) {
+                    Some(ApertureDefine(_, template)) => Some(template),
+                    _ => None,
+                };
+                object_polygons(object, template, &macros, unit, tolerance)
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+/// The boundaries of a polygon in nanometers, without repeating the first
+/// point
+fn boundaries(polygon: &Polygon) -> Vec<Vec<(i32, i32)>> {
+    let ring = keyhole(polygon);
+    if ring.len() < MAX_POINTS {
+        let nm = |value: f64| (value * NM_PER_MM as f64).round() as i32;
+        return vec![ring
+            .iter()
+            .map(|point| (nm(point.x), nm(point.y)))
+            .collect()];
+    }
+
+    // split the polygon in two across its longer side
+    let (mut min, mut max) = (polygon.exterior[0], polygon.exterior[0]);
+    for point in &polygon.exterior {
+        min = Point {
+            x: min.x.min(point.x),
+            y: min.y.min(point.y),
+        };
+        max = Point {
+            x: max.x.max(point.x),
+            y: max.y.max(point.y),
+        };
+    }
+    let (low, high) = if max.x - min.x >= max.y - min.y {
+        let middle = (min.x + max.x) / 2.0;
+        (
+            (min, Point { x: middle, ..max }),
+            (Point { x: middle, ..min }, max),
+        )
+    } else {
+        let middle = (min.y + max.y) / 2.0;
+        (
+            (min, Point { y: middle, ..max }),
+            (Point { y: middle, ..min }, max),
+        )
+    };
+    let polygon = std::slice::from_ref(polygon);
+    clip(polygon, low.0, low.1)
+        .iter()
+        .chain(&clip(polygon, high.0, high.1))
+        .flat_map(boundaries)
+        .collect()
+}
+
+/// A single ring with each hole joined to the exterior by a cut
+///
+/// Holes are joined in order of their rightmost point, from the right, each
+/// along a ray to the right to the nearest edge of the ring so far. The
+/// exterior is counter-clockwise and holes clockwise, so the ring goes
+/// around the holes the other way.
+fn keyhole(polygon: &Polygon) -> Vec<Point> {
+    let mut ring = polygon.exterior.clone();
+    let mut holes: Vec<(usize, &Vec<Point>)> = polygon
+        .holes
+        .iter()
+        .filter(|hole| !hole.is_empty())
+        .map(|hole| {
+            let rightmost = (0..hole.len())
+                .max_by(|&a, &b| hole[a].x.total_cmp(&hole[b].x))
+                .unwrap_or(0);
+            (rightmost, hole)
+        })
+        .collect();
+    holes.sort_by(|(a, hole_a), (b, hole_b)| hole_b[*b].x.total_cmp(&hole_a[*a].x));
+
+    for (rightmost, hole) in holes {
+        let from = hole[rightmost];
+        // the nearest edge crossed by the ray to the right
+        let crossing = (0..ring.len())
+            .filter_map(|i| {
+                let (a, b) = (ring[i], ring[(i + 1) % ring.len()]);
+                if (a.y <= from.y) == (b.y <= from.y) {
+                    return None;
+                }
+                let x = a.x + (from.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                (x >= from.x).then_some((i, x))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((edge, x)) = crossing else {
+            continue;
+        };
+        let to = Point { x, y: from.y };
+        let around = (0..=hole.len()).map(|i| hole[(rightmost + i) % hole.len()]);
+        let cut: Vec<Point> = std::iter::once(to)
+            .chain(around)
+            .chain(std::iter::once(to))
+            .collect();
+        ring.splice(edge + 1..edge + 1, cut);
+    }
+    ring
+}
+
+fn record(stream: &mut Vec<u8>, kind: u16, data: &[u8]) {
+    let length = 4 + data.len();
+    stream.extend((length as u16).to_be_bytes());
+    stream.extend(kind.to_be_bytes());
+    stream.extend(data);
+}
+
+fn int16s(values: &[i16]) -> Vec<u8> {
+    values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+/// An ASCII string, padded to an even length with a null
+fn string(value: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = value
+        .chars()
+        .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+        .collect();
+    if bytes.len() % 2 == 1 {
+        bytes.push(0);
+    }
+    bytes
+}
+
+/// An eight-byte real: a sign bit, a seven-bit exponent of 16 in excess
+/// 64, and a 56-bit fraction
+fn real8(value: f64) -> [u8; 8] {
+    if value == 0.0 {
+        return [0; 8];
+    }
+    let sign = if value < 0.0 { 0x80 } else { 0 };
+    let mut fraction = value.abs();
+    let mut exponent = 64i32;
+    while fraction >= 1.0 {
+        fraction /= 16.0;
+        exponent += 1;
+    }
+    while fraction < 1.0 / 16.0 {
+        fraction *= 16.0;
+        exponent -= 1;
+    }
+    let mantissa = (fraction * (1u64 << 56) as f64) as u64;
+    let mut bytes = mantissa.to_be_bytes();
+    bytes[0] = sign | exponent as u8;
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(min: f64, max: f64, clockwise: bool) -> Vec<Point> {
+        let mut ring: Vec<_> = [(min, min), (max, min), (max, max), (min, max)]
+            .map(|(x, y)| Point { x, y })
+            .to_vec();
+        if clockwise {
+            ring.reverse();
+        }
+        ring
+    }
+
+    #[test]
+    fn test_real8() {
+        assert_eq!(real8(1.0), [0x41, 0x10, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(real8(-0.5), [0xc0, 0x80, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            real8(1e-3),
+            [0x3e, 0x41, 0x89, 0x37, 0x4b, 0xc6, 0xa7, 0xf0]
+        );
+        assert_eq!(real8(0.0), [0; 8]);
+    }
+
+    #[test]
+    fn test_keyhole() {
+        let polygon = Polygon {
+            exterior: square(0.0, 10.0, false),
+            holes: vec![square(2.0, 4.0, true), square(6.0, 8.0, true)],
+        };
+        let ring = keyhole(&polygon);
+        assert_eq!(ring.len(), 4 + 2 * 7);
+        // the area is unchanged, as the cuts have no width
+        let area = Polygon {
+            exterior: ring,
+            holes: Vec::new(),
+        }
+        .area();
+        assert!((area - polygon.area()).abs() < 1e-9, "{area}");
+    }
+
+    #[test]
+    fn test_split() {
+        // a polygon with too many vertices for one boundary
+        let exterior: Vec<Point> = (0..10000)
+            .map(|i| {
+                let angle = std::f64::consts::TAU * i as f64 / 10000.0;
+                Point {
+                    x: angle.cos(),
+                    y: angle.sin(),
+                }
+            })
+            .collect();
+        let polygon = Polygon {
+            exterior,
+            holes: Vec::new(),
+        };
+        let boundaries = boundaries(&polygon);
+        assert_eq!(boundaries.len(), 2);
+        assert!(boundaries
+            .iter()
+            .all(|boundary| boundary.len() < MAX_POINTS));
+    }
+
+    #[test]
+    fn test_stream() {
+        let gds = Gds {
+            library: "LIB".into(),
+            cell: "TOP".into(),
+            layers: vec![GdsLayer {
+                layer: 5,
+                datatype: 2,
+                polygons: vec![Polygon {
+                    exterior: square(0.0, 1.0, false),
+                    holes: Vec::new(),
+                }],
+            }],
+        };
+        let stream = gds.to_bytes();
+        let mut records = Vec::new();
+        let mut rest = &stream[..];
+        while !rest.is_empty() {
+            let length = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+            records.push((
+                u16::from_be_bytes([rest[2], rest[3]]),
+                rest[4..length].to_vec(),
+            ));
+            rest = &rest[length..];
+        }
+        let kinds: Vec<_> = records.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                0x0002, 0x0102, 0x0206, 0x0305, 0x0502, 0x0606, 0x0800, 0x0D02, 0x0E02, 0x1003,
+                0x1100, 0x0700, 0x0400
+            ]
+        );
+        assert_eq!(records[2].1, b"LIB\0");
+        assert_eq!(records[7].1, [0, 5]);
+        // five points of two 32-bit coordinates, closed
+        let xy = &records[9].1;
+        assert_eq!(xy.len(), 5 * 8);
+        assert_eq!(xy[8..16], [0, 0x0f, 0x42, 0x40, 0, 0, 0, 0]);
+        assert_eq!(xy[..8], xy[32..]);
+    }
+
+    #[test]
+    fn test_flash_outlines() {
+        let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,1*%\n%ADD11R,1X1*%\nD10*\nX0Y0D03*\n\
+                   G01*\nX5000000D01*\nD11*\n%LPC*%\nX0Y0D03*\nM02*\n";
+        let outlines = GerberLayer::parse(src).unwrap().flash_outlines(0.01);
+        assert_eq!(outlines.len(), 1);
+        // both polygons of a macro pad
+        let src = "%FSLAX26Y26*%\n%MOMM*%\n%AMPAIR*1,1,1,-1,0*1,1,1,1,0*%\n%ADD10PAIR*%\n\
+                   D10*\nX0Y0D03*\nM02*\n";
+        let outlines = GerberLayer::parse(src).unwrap().flash_outlines(0.01);
+        assert_eq!(outlines.len(), 2);
+    }
+}
@@ -0,0 +1,178 @@
+//! GeoJSON export
+//!
+//! Writes the objects of a layer as a [GeoJSON](https://geojson.org)
+//! feature collection, one feature per object, so copper can be inspected
+//! in GIS tools and queried spatially. Coordinates are plain millimeters
+//! in the layer's own frame rather than longitude and latitude, so tools
+//! should be told the data is in a local, projected coordinate system.
+//!
+//! Each feature is a `MultiPolygon` of the object's area, with properties:
+//!
+//! - `object`: the index in [Image::objects](crate::image::Image::objects)
+//! - `polarity`: `"dark"` or `"clear"`
+//! - `function`, `net` and `component`: `.AperFunction`, `.N` and `.C`,
+//!   when set
+//! - `attributes`: every aperture and object attribute, by name, as arrays
+//!   of values
+//!
+//! Rings follow the right-hand rule of RFC 7946: exteriors run
+//! counter-clockwise and holes clockwise.
+//!
+//! Clear objects are exported like dark ones, so the features show how the
+//! layer was drawn rather than the final [copper](crate::copper).
+
+use std::fmt::Write;
+
+use crate::command::Command::*;
+use crate::copper::{object_polygons, Polygon};
+use crate::data::{Polarity, Unit};
+use crate::image::{Object, Point};
+use crate::{json_string, GerberLayer};
+
+impl GerberLayer<'_> {
+    /// Write the objects of the layer as a GeoJSON feature collection
+    ///
+    /// Curves are approximated within `tolerance` millimeters. Objects of
+    /// apertures whose macro is undefined or can't be evaluated are left
+    /// out.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%TO.N,GND*%\n%ADD10R,2X2*%\nD10*\n\
+    ///            X0Y0D03*\nM02*\n";
+    /// let geojson = GerberLayer::parse(src).unwrap().to_geojson(0.01);
+    /// assert!(geojson.contains(r#""type":"MultiPolygon""#));
+    /// assert!(geojson.contains(r#""net":"GND""#));
+    /// ```
+    pub fn to_geojson(&self, tolerance: f64) -> String {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let macros = self.aperture_macros();
+        let mut features = Vec::new();
+        for (index, object) in image.objects.iter().enumerate() {
+            let template = match object.source.aperture.map(|index| &self.commands[index]) {
+                Some(ApertureDefine(_, template)) => Some(template),
+                _ => None,
+            };
+            let Some(polygons) = object_polygons(object, template, &macros, unit, tolerance) else {
+                continue;
+            };
+            features.push(feature(index, object, &polygons));
+        }
+        format!(
+            "{{\"type\":\"FeatureCollection\",\"features\":[{}]}}\n",
+            features.join(",")
+        )
+    }
+}
+
+fn feature(index: usize, object: &Object, polygons: &[Polygon]) -> String {
+    let mut json = String::from("{\"type\":\"Feature\",\"geometry\":{\"type\":\"MultiPolygon\",");
+    json.push_str("\"coordinates\":[");
+    for (i, polygon) in polygons.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push('[');
+        for (j, ring) in std::iter::once(&polygon.exterior)
+            .chain(&polygon.holes)
+            .enumerate()
+        {
+            if j > 0 {
+                json.push(',');
+            }
+            push_ring(&mut json, ring);
+        }
+        json.push(']');
+    }
+    json.push_str("]},\"properties\":{");
+
+    let polarity = match object.polarity {
+        Polarity::Dark => "dark",
+        Polarity::Clear => "clear",
+    };
+    write!(json, "\"object\":{index},\"polarity\":\"{polarity}\"").unwrap();
+    let attributes = &object.attributes;
+    for (name, value) in [
+        ("function", attributes.aperture_function()),
+        ("net", attributes.net()),
+        ("component", attributes.component()),
+    ] {
+        if let Some(value) = value {
+            write!(json, ",\"{name}\":{}", json_string(value)).unwrap();
+        }
+    }
+
+    // object attributes override aperture attributes of the same name
+    let mut all = (*attributes.aperture).clone();
+    all.extend(
+        attributes
+            .object
+            .iter()
+            .map(|(name, values)| (name.clone(), values.clone())),
+    );
+    json.push_str(",\"attributes\":{");
+    for (i, (name, values)) in all.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let values: Vec<_> = values.iter().map(|value| json_string(value)).collect();
+        write!(json, "{}:[{}]", json_string(name), values.join(",")).unwrap();
+    }
+    json.push_str("}}}");
+    json
+}
+
+/// A closed ring, repeating its first point as GeoJSON requires
+fn push_ring(json: &mut String, ring: &[Point]) {
+    json.push('[');
+    for (i, point) in ring.iter().chain(ring.first()).enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(json, "[{},{}]", point.x, point.y).unwrap();
+    }
+    json.push(']');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_features() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %TA.AperFunction,SMDPad,CuDef*%
+            %ADD10R,2X2X1*%
+            %TD*%
+            %TO.C,R1*%
+            %TO.N,Net-(R1-Pad1)*%
+            D10*
+            X0Y0D03*
+            %TD*%
+            %LPC*%
+            X5000000Y0D03*
+            M02*
+        "};
+        let geojson = GerberLayer::parse(src).unwrap().to_geojson(0.01);
+        assert!(geojson.starts_with(r#"{"type":"FeatureCollection","features":[{"#));
+        assert_eq!(geojson.matches(r#""type":"Feature""#).count(), 2);
+        // a counter-clockwise exterior and clockwise hole, both closed
+        assert!(geojson
+            .contains(r#""coordinates":[[[[-1,1],[-1,-1],[1,-1],[1,1],[-1,1]],[[-0.5,0],[-0.46"#));
+        assert!(geojson.contains(r#"[-0.5,0]]]]},"properties""#));
+        assert!(geojson.contains(
+            r#""properties":{"object":0,"polarity":"dark","function":"SMDPad","net":"Net-(R1-Pad1)","component":"R1","#
+        ));
+        assert!(geojson.contains(
+            r#""attributes":{".AperFunction":["SMDPad","CuDef"],".C":["R1"],".N":["Net-(R1-Pad1)"]}"#
+        ));
+        assert!(geojson.contains(
+            r#""properties":{"object":1,"polarity":"clear","function":"SMDPad","attributes""#
+        ));
+    }
+}
@@ -0,0 +1,1643 @@
+//! Evaluation of commands into graphical objects
+//!
+//! The image is the stream of draws, arcs, flashes and regions created by
+//! the operations of a layer (§2.3 of the specification), in the order
+//! they are created. Coordinates are converted to millimeters, on the
+//! common [nanometer grid](crate::data::NM_PER_MM) of all layers.
+//!
+//! Every object records which commands created it, so tools can go from an
+//! object in a viewer back to the text in the file.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::f64::consts::{FRAC_PI_2, TAU};
+use std::ops::{ControlFlow, Range};
+use std::sync::Arc;
+
+use crate::aperture::{ApertureTemplate, StandardAperture};
+use crate::command::Command::{self, *};
+use crate::data::{
+    ApertureId, CoordinateFormat, Coordinates, EscapedString, InterpolationMode, Mirroring, Offset,
+    Polarity, Rotation, Scaling, Unit, NM_PER_MM,
+};
+use crate::validate::ARC_TOLERANCE;
+
+/// A point in millimeters
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    /// The point at coordinates in nanometers
+    pub fn from_nm(x: i64, y: i64) -> Point {
+        let mm = |nm: i64| nm as f64 / NM_PER_MM as f64;
+        Point { x: mm(x), y: mm(y) }
+    }
+
+    /// The coordinates of the nearest point on the nanometer grid
+    ///
+    /// Points of an image are on the grid, so this recovers the exact
+    /// coordinates they were evaluated from.
+    ///
+    /// ```
+    /// use gerber::image::Point;
+    ///
+    /// let point = Point::from_nm(2_540_000, -1);
+    /// assert_eq!(point.to_nm(), (2_540_000, -1));
+    /// ```
+    pub fn to_nm(&self) -> (i64, i64) {
+        let nm = |mm: f64| (mm * NM_PER_MM as f64).round() as i64;
+        (nm(self.x), nm(self.y))
+    }
+}
+
+/// The geometry of a graphical object
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Shape {
+    /// A straight line stroked with an aperture
+    Draw {
+        start: Point,
+        end: Point,
+        aperture: ApertureId,
+    },
+
+    /// A circular arc stroked with an aperture
+    Arc {
+        start: Point,
+        end: Point,
+        center: Point,
+        direction: InterpolationMode,
+        aperture: ApertureId,
+    },
+
+    /// An aperture image placed at a point
+    Flash { at: Point, aperture: ApertureId },
+
+    /// An area bounded by one or more contours
+    Region { contours: Vec<Contour> },
+}
+
+impl Shape {
+    /// The geometry of an arc, or `None` for other shapes
+    pub fn arc_geometry(&self) -> Option<ArcGeometry> {
+        match *self {
+            Shape::Arc {
+                start,
+                end,
+                center,
+                direction,
+                ..
+            } => Some(ArcGeometry::new(start, end, center, direction)),
+            _ => None,
+        }
+    }
+}
+
+/// The center, radius and angles of a circular arc
+///
+/// Angles are in radians, counterclockwise from the X axis. The sweep is
+/// positive for counterclockwise arcs and negative for clockwise ones, and
+/// an arc ending at its start is a full circle.
+///
+/// ```
+/// use gerber::data::InterpolationMode;
+/// use gerber::image::{ArcGeometry, Point};
+///
+/// let arc = ArcGeometry::new(
+///     Point { x: 1.0, y: 0.0 },
+///     Point { x: 0.0, y: 1.0 },
+///     Point { x: 0.0, y: 0.0 },
+///     InterpolationMode::Clockwise,
+/// );
+/// assert_eq!(arc.radius, 1.0);
+/// assert_eq!(arc.sweep.to_degrees().round(), -270.0);
+/// assert!(arc.is_consistent(1e-9));
+/// ```
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ArcGeometry {
+    pub center: Point,
+
+    /// The distance from the center to the start point
+    pub radius: f64,
+
+    pub start_angle: f64,
+    pub end_angle: f64,
+    pub sweep: f64,
+
+    /// How much farther the end point is from the center than the start
+    /// point, zero for a well-formed arc
+    pub radius_deviation: f64,
+}
+
+impl ArcGeometry {
+    /// The geometry of the arc from `start` to `end` around `center`
+    pub fn new(start: Point, end: Point, center: Point, direction: InterpolationMode) -> Self {
+        let radius = (start.x - center.x).hypot(start.y - center.y);
+        let end_radius = (end.x - center.x).hypot(end.y - center.y);
+        let start_angle = (start.y - center.y).atan2(start.x - center.x);
+        let end_angle = (end.y - center.y).atan2(end.x - center.x);
+        let mut sweep = (end_angle - start_angle).rem_euclid(TAU);
+        if sweep == 0.0 {
+            sweep = TAU;
+        }
+        if direction == InterpolationMode::Clockwise {
+            sweep -= TAU;
+            if sweep == 0.0 {
+                sweep = -TAU;
+            }
+        }
+        Self {
+            center,
+            radius,
+            start_angle,
+            end_angle,
+            sweep,
+            radius_deviation: end_radius - radius,
+        }
+    }
+
+    pub fn direction(&self) -> InterpolationMode {
+        if self.sweep < 0.0 {
+            InterpolationMode::Clockwise
+        } else {
+            InterpolationMode::CounterClockwise
+        }
+    }
+
+    /// True if the start and end points are the same distance from the
+    /// center, within `tolerance`
+    pub fn is_consistent(&self, tolerance: f64) -> bool {
+        self.radius_deviation.abs() <= tolerance
+    }
+
+    /// True if the arc passes through `angle`, including its ends
+    pub fn contains_angle(&self, angle: f64) -> bool {
+        let swept = if self.sweep < 0.0 {
+            self.start_angle - angle
+        } else {
+            angle - self.start_angle
+        };
+        swept.rem_euclid(TAU) <= self.sweep.abs()
+    }
+
+    /// The point on the circle at `angle`
+    pub fn point_at(&self, angle: f64) -> Point {
+        let (sin, cos) = angle.sin_cos();
+        Point {
+            x: self.center.x + self.radius * cos,
+            y: self.center.y + self.radius * sin,
+        }
+    }
+}
+
+/// A closed sequence of segments bounding part of a region
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Contour {
+    pub start: Point,
+    pub segments: Vec<Segment>,
+}
+
+impl Contour {
+    /// The area enclosed, in square millimeters, positive when the contour
+    /// runs counter-clockwise
+    ///
+    /// Arcs count exactly, not by their chords.
+    pub fn signed_area(&self) -> f64 {
+        let mut area = 0.0;
+        let mut from = self.start;
+        for segment in &self.segments {
+            let end = match *segment {
+                Segment::Line { end } => end,
+                Segment::Arc {
+                    end,
+                    center,
+                    direction,
+                } => {
+                    let arc = ArcGeometry::new(from, end, center, direction);
+                    area += arc.radius.powi(2) * (arc.sweep - arc.sweep.sin());
+                    end
+                }
+            };
+            area += from.x * end.y - end.x * from.y;
+            from = end;
+        }
+        area += from.x * self.start.y - self.start.x * from.y;
+        area / 2.0
+    }
+
+    /// The same contour followed in the opposite direction
+    pub fn reversed(&self) -> Contour {
+        let mut points = vec![self.start];
+        points.extend(self.segments.iter().map(|segment| match *segment {
+            Segment::Line { end } | Segment::Arc { end, .. } => end,
+        }));
+        let segments = self
+            .segments
+            .iter()
+            .zip(&points)
+            .rev()
+            .map(|(segment, &end)| match *segment {
+                Segment::Line { .. } => Segment::Line { end },
+                Segment::Arc {
+                    center, direction, ..
+                } => Segment::Arc {
+                    end,
+                    center,
+                    direction: direction.reversed(),
+                },
+            })
+            .collect();
+        Contour {
+            start: points[points.len() - 1],
+            segments,
+        }
+    }
+}
+
+/// A piece of a contour, continuing from the end of the previous one
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Segment {
+    Line {
+        end: Point,
+    },
+    Arc {
+        end: Point,
+        center: Point,
+        direction: InterpolationMode,
+    },
+}
+
+/// The commands which created an object, as indices into the command list
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Provenance {
+    /// The D01 or D03 operation, or for regions everything from G36 to G37
+    pub commands: Range<usize>,
+
+    /// The `%AD` command defining the aperture of a draw, arc or flash
+    pub aperture: Option<usize>,
+}
+
+/// An axis-aligned bounding box in millimeters
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Bounds {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Bounds {
+    pub fn width(&self) -> f64 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max.y - self.min.y
+    }
+
+    /// True if `point` is inside or on the edge
+    pub fn contains(&self, point: Point) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x) && (self.min.y..=self.max.y).contains(&point.y)
+    }
+
+    /// True if the boxes overlap or touch
+    pub fn intersects(&self, other: &Bounds) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+
+    /// The smallest box containing both
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min: Point {
+                x: self.min.x.min(other.min.x),
+                y: self.min.y.min(other.min.y),
+            },
+            max: Point {
+                x: self.max.x.max(other.max.x),
+                y: self.max.y.max(other.max.y),
+            },
+        }
+    }
+
+    fn of_point(point: Point) -> Bounds {
+        Bounds {
+            min: point,
+            max: point,
+        }
+    }
+
+    /// The box grown by another box around the origin
+    fn grow(&self, by: &Bounds) -> Bounds {
+        Bounds {
+            min: Point {
+                x: self.min.x + by.min.x,
+                y: self.min.y + by.min.y,
+            },
+            max: Point {
+                x: self.max.x + by.max.x,
+                y: self.max.y + by.max.y,
+            },
+        }
+    }
+}
+
+/// Attribute values by name, e.g. `.AperFunction` to `["SMDPad", "CuDef"]`
+///
+/// Names and values are interned while evaluating, so the thousands of
+/// `.N,GND` attributes of a board share one `GND`.
+///
+/// Names iterate in byte order rather than the order they were set.
+pub type AttributeMap = BTreeMap<Arc<str>, Vec<Arc<str>>>;
+
+/// The attributes attached to an object (§5)
+///
+/// Objects created in the same attribute state share their maps.
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Attributes {
+    /// The attributes of the aperture when it was defined, or for a region
+    /// the aperture attributes in effect when it was created
+    pub aperture: Arc<AttributeMap>,
+
+    /// The object attributes in effect when the object was created
+    pub object: Arc<AttributeMap>,
+}
+
+impl Attributes {
+    /// The values of an object or aperture attribute
+    pub fn get(&self, name: &str) -> Option<&[Arc<str>]> {
+        self.object
+            .get(name)
+            .or_else(|| self.aperture.get(name))
+            .map(Vec::as_slice)
+    }
+
+    /// The function of the aperture, the first value of `.AperFunction`
+    pub fn aperture_function(&self) -> Option<&str> {
+        self.first(".AperFunction")
+    }
+
+    /// The net name, `.N`
+    pub fn net(&self) -> Option<&str> {
+        self.first(".N")
+    }
+
+    /// The component reference designator, `.C`
+    pub fn component(&self) -> Option<&str> {
+        self.first(".C")
+    }
+
+    fn first(&self, name: &str) -> Option<&str> {
+        self.get(name)?.first().map(AsRef::as_ref)
+    }
+}
+
+/// A graphical object with the graphics state it was created in
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Object {
+    pub shape: Shape,
+    pub polarity: Polarity,
+
+    /// Transformations of the aperture, which do not affect coordinates
+    pub mirroring: Mirroring,
+    pub rotation: Rotation,
+    pub scaling: Scaling,
+
+    pub attributes: Attributes,
+    pub source: Provenance,
+
+    /// The extent of the object including its aperture and the aperture's
+    /// transformations, or `None` when the shape of a macro aperture is
+    /// unknown
+    pub bounds: Option<Bounds>,
+}
+
+/// The graphical objects of a layer
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Image {
+    pub objects: Vec<Object>,
+
+    /// The unit of the layer, which aperture templates are given in
+    pub unit: Option<Unit>,
+
+    /// The file attributes, set by `%TF`
+    pub file_attributes: AttributeMap,
+
+    /// The aperture templates, by the index of the `%AD` command defining
+    /// them as in [Provenance::aperture]
+    pub apertures: BTreeMap<usize, ApertureTemplate<'static>>,
+}
+
+impl Image {
+    /// Reverse the region contours which run clockwise, returning how many
+    /// were reversed
+    ///
+    /// Every contour of a region is filled whichever way it runs, so this
+    /// doesn't change the image. It helps clipping libraries which take
+    /// clockwise rings for holes.
+    pub fn normalize_winding(&mut self) -> usize {
+        self.objects.iter_mut().map(normalize_winding).sum()
+    }
+
+    /// The indices of the objects whose bounds intersect `bounds`, for
+    /// culling and hit testing
+    ///
+    /// Objects of macro apertures have no bounds and are always included.
+    pub fn objects_in(&self, bounds: Bounds) -> impl Iterator<Item = usize> + '_ {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(move |(_, object)| {
+                object
+                    .bounds
+                    .is_none_or(|object| object.intersects(&bounds))
+            })
+            .map(|(index, _)| index)
+    }
+}
+
+/// Evaluate commands into an image
+///
+/// Evaluation is best-effort: operations before `%FS` assume six decimals
+/// and millimeters, plots before a G01/G02/G03 are linear, and operations
+/// with an undefined aperture are skipped. Use [validate](crate::validate)
+/// to report these problems.
+pub fn evaluate(commands: &[Command]) -> Image {
+    evaluate_with(commands, |_| ControlFlow::Continue(())).unwrap()
+}
+
+/// Evaluate commands into an image, with choices for constructs whose
+/// meaning varies between tools
+pub fn evaluate_with_options(commands: &[Command], options: &EvaluateOptions) -> Image {
+    run(commands, options, |_| ControlFlow::Continue(()))
+        .unwrap()
+        .0
+}
+
+/// Evaluate commands one object at a time, in paint order
+///
+/// Each object is yielded as soon as the command creating it is applied,
+/// so a renderer can composite while evaluating and stop early. Objects
+/// come in file order, which is the order the specification paints them
+/// in: a clear object erases only the objects before it. Each carries its
+/// polarity, the aperture transformations and the attributes in effect
+/// when it was created, so compositing the stream in order with the
+/// [polarity](Object::polarity) gives the same image as the reference
+/// viewers.
+///
+/// ```
+/// use gerber::data::Polarity;
+/// use gerber::image::{objects, EvaluateOptions};
+/// use gerber::GerberLayer;
+///
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,1*%\nD10*\nX0Y0D03*\n\
+///            %LPC*%\n%ADD11C,0.5*%\nD11*\nX0Y0D03*\nM02*\n";
+/// let layer = GerberLayer::parse(src).unwrap();
+/// let mut stream = objects(layer.commands(), &EvaluateOptions::default());
+/// let dark = stream.next().unwrap();
+/// assert_eq!(dark.polarity, Polarity::Dark);
+/// let clear = stream.next().unwrap();
+/// assert_eq!(clear.polarity, Polarity::Clear);
+/// // D11 is defined by the time its flash comes out
+/// assert!(stream.template(&clear).is_some());
+/// assert!(stream.next().is_none());
+/// ```
+pub fn objects<'c>(commands: &'c [Command<'c>], options: &EvaluateOptions) -> Objects<'c> {
+    Objects {
+        commands: commands.iter().enumerate(),
+        state: State {
+            options: options.clone(),
+            ..Default::default()
+        },
+    }
+}
+
+/// The objects of commands in paint order, see [objects]
+pub struct Objects<'c> {
+    commands: std::iter::Enumerate<std::slice::Iter<'c, Command<'c>>>,
+    state: State,
+}
+
+impl Objects<'_> {
+    /// The unit set so far, which aperture templates are given in
+    pub fn unit(&self) -> Option<Unit> {
+        self.state.unit
+    }
+
+    /// The file attributes set so far
+    pub fn file_attributes(&self) -> &AttributeMap {
+        &self.state.file_attributes
+    }
+
+    /// The aperture template of an object yielded by the stream, `None`
+    /// for regions
+    pub fn template(&self, object: &Object) -> Option<&ApertureTemplate<'static>> {
+        self.state.templates.get(&object.source.aperture?)
+    }
+}
+
+impl Iterator for Objects<'_> {
+    type Item = Object;
+
+    fn next(&mut self) -> Option<Object> {
+        for (index, command) in self.commands.by_ref() {
+            if let Some(mut object) = self.state.apply(index, command) {
+                if self.state.options.normalize_winding {
+                    normalize_winding(&mut object);
+                }
+                return Some(object);
+            }
+        }
+        None
+    }
+}
+
+/// Reverse the clockwise contours of a region, returning how many
+fn normalize_winding(object: &mut Object) -> usize {
+    let Shape::Region { contours } = &mut object.shape else {
+        return 0;
+    };
+    let mut reversed = 0;
+    for contour in contours.iter_mut().filter(|c| c.signed_area() < 0.0) {
+        *contour = contour.reversed();
+        reversed += 1;
+    }
+    reversed
+}
+
+/// How commands are evaluated
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EvaluateOptions {
+    pub zero_length_draws: ZeroLengthDraws,
+
+    /// Run every region contour counter-clockwise, as
+    /// [Image::normalize_winding] does
+    pub normalize_winding: bool,
+}
+
+/// What a linear D01 ending at its start point creates
+///
+/// Some tools write these to mean a dot. [validate](crate::validate) warns
+/// about them whichever is chosen.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ZeroLengthDraws {
+    /// A draw from the point to itself, as the specification says
+    #[default]
+    Keep,
+
+    /// A flash of the current aperture at the point
+    Flash,
+
+    /// Nothing
+    Drop,
+}
+
+/// Evaluate, calling `after` with the index of each command once it is
+/// applied, and stopping with `None` if it breaks
+pub(crate) fn evaluate_with(
+    commands: &[Command],
+    after: impl FnMut(usize) -> ControlFlow<()>,
+) -> Option<Image> {
+    run(commands, &EvaluateOptions::default(), after).map(|(image, _)| image)
+}
+
+/// Evaluate, also returning the indices of the single quadrant arcs whose
+/// center is ambiguous: more than one candidate gives an arc of at most 90°
+/// on a circle
+pub(crate) fn evaluate_checked(commands: &[Command]) -> (Image, Vec<usize>) {
+    run(commands, &EvaluateOptions::default(), |_| {
+        ControlFlow::Continue(())
+    })
+    .unwrap()
+}
+
+fn run(
+    commands: &[Command],
+    options: &EvaluateOptions,
+    mut after: impl FnMut(usize) -> ControlFlow<()>,
+) -> Option<(Image, Vec<usize>)> {
+    let mut state = State {
+        options: options.clone(),
+        ..Default::default()
+    };
+    let mut objects = Vec::with_capacity(object_capacity(commands));
+    for (index, command) in commands.iter().enumerate() {
+        if let Some(object) = state.apply(index, command) {
+            objects.push(object);
+        }
+        if after(index).is_break() {
+            return None;
+        }
+    }
+    let mut image = Image {
+        objects,
+        unit: state.unit,
+        file_attributes: state.file_attributes,
+        apertures: state.templates,
+    };
+    if options.normalize_winding {
+        image.normalize_winding();
+    }
+    Some((image, state.ambiguous_arcs))
+}
+
+/// An upper bound on the number of objects created by `commands`: the
+/// operations outside regions, and one per region
+fn object_capacity(commands: &[Command]) -> usize {
+    let mut region = false;
+    commands
+        .iter()
+        .filter(|command| match command {
+            StartRegion => {
+                region = true;
+                false
+            }
+            EndRegion => {
+                region = false;
+                true
+            }
+            Plot(..) | Flash(..) => !region,
+            _ => false,
+        })
+        .count()
+}
+
+/// The graphics state (§2.3.2) while evaluating
+#[derive(Default)]
+struct State {
+    options: EvaluateOptions,
+    unit: Option<Unit>,
+    format: Option<(CoordinateFormat, CoordinateFormat)>,
+    point: (i64, i64),
+    interpolation: Option<InterpolationMode>,
+
+    /// Set by G74 and cleared by G75
+    single_quadrant: bool,
+    ambiguous_arcs: Vec<usize>,
+
+    apertures: HashMap<ApertureId, Aperture>,
+    aperture: Option<ApertureId>,
+    templates: BTreeMap<usize, ApertureTemplate<'static>>,
+
+    /// The attribute dictionary
+    file_attributes: AttributeMap,
+    aperture_attributes: Arc<AttributeMap>,
+    object_attributes: Arc<AttributeMap>,
+
+    polarity: Polarity,
+    mirroring: Mirroring,
+    rotation: Rotation,
+    scaling: Scaling,
+
+    region: Option<RegionBuilder>,
+
+    /// Attribute names and values seen so far
+    strings: HashSet<Arc<str>>,
+}
+
+/// An aperture as defined by `%AD`
+struct Aperture {
+    /// Index of the defining command
+    index: usize,
+    attributes: Arc<AttributeMap>,
+
+    /// Disks, in the unit of the layer, whose convex hull is the aperture,
+    /// or `None` for a macro or an invalid polygon
+    disks: Option<Vec<(Point, f64)>>,
+}
+
+impl Aperture {
+    fn disks(template: &ApertureTemplate) -> Option<Vec<(Point, f64)>> {
+        let origin = Point::default();
+        let disks = match *template {
+            ApertureTemplate::Circle { diameter, .. } => vec![(origin, diameter / 2.0)],
+            ApertureTemplate::Rectangle { x, y, .. } => {
+                [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+                    .map(|(sx, sy)| {
+                        let corner = Point {
+                            x: sx * x / 2.0,
+                            y: sy * y / 2.0,
+                        };
+                        (corner, 0.0)
+                    })
+                    .to_vec()
+            }
+            ApertureTemplate::Obround { x, y, .. } => {
+                let radius = x.min(y) / 2.0;
+                let (dx, dy) = (x / 2.0 - radius, y / 2.0 - radius);
+                vec![
+                    (Point { x: -dx, y: -dy }, radius),
+                    (Point { x: dx, y: dy }, radius),
+                ]
+            }
+            ApertureTemplate::Polygon { .. } => {
+                // only a valid number of vertices makes a polygon
+                let Ok(Some(StandardAperture::Polygon(polygon))) = template.standard() else {
+                    return None;
+                };
+                let vertices = polygon.vertices().into_iter();
+                vertices.map(|vertex| (vertex, 0.0)).collect()
+            }
+            ApertureTemplate::Macro { .. } => return None,
+        };
+        Some(disks)
+    }
+}
+
+/// A region under construction between G36 and G37
+struct RegionBuilder {
+    start: usize,
+    contours: Vec<Contour>,
+}
+
+impl State {
+    fn apply(&mut self, index: usize, command: &Command) -> Option<Object> {
+        match command {
+            Mode(unit) => self.unit = Some(*unit),
+            FormatSpecification(x, y) => self.format = Some((*x, *y)),
+            ApertureDefine(id, template) => {
+                let aperture = Aperture {
+                    index,
+                    attributes: self.aperture_attributes.clone(),
+                    disks: Aperture::disks(template),
+                };
+                self.apertures.insert(*id, aperture);
+                self.templates.insert(index, template.clone().into_owned());
+            }
+            AttributeOnFile(name, values) => {
+                let (name, values) = self.attribute(name.name(), values);
+                self.file_attributes.insert(name, values);
+            }
+            AttributeOnAperture(name, values) => {
+                let (name, values) = self.attribute(name.name(), values);
+                Arc::make_mut(&mut self.aperture_attributes).insert(name, values);
+            }
+            AttributeOnObject(name, values) => {
+                let (name, values) = self.attribute(name.name(), values);
+                Arc::make_mut(&mut self.object_attributes).insert(name, values);
+            }
+            AttributeDelete(Some(name)) => {
+                for attributes in [&mut self.aperture_attributes, &mut self.object_attributes] {
+                    if attributes.contains_key(name.as_ref()) {
+                        Arc::make_mut(attributes).remove(name.as_ref());
+                    }
+                }
+            }
+            AttributeDelete(None) => {
+                self.aperture_attributes = Default::default();
+                self.object_attributes = Default::default();
+            }
+            SetCurrentAperture(id) => self.aperture = Some(*id),
+            SetLinear | SetCWCircular | SetCCWCircular => {
+                self.interpolation = command.interpolation_mode()
+            }
+            SingleQuadrant => self.single_quadrant = true,
+            ArcInit => self.single_quadrant = false,
+            LoadPolarity(polarity) => self.polarity = *polarity,
+            LoadMirroring(mirroring) => self.mirroring = *mirroring,
+            LoadRotation(rotation) => self.rotation = *rotation,
+            LoadScaling(scaling) => self.scaling = *scaling,
+            Move(coordinates) => {
+                self.move_to(coordinates);
+                let start = self.current_point();
+                if let Some(region) = &mut self.region {
+                    region.contours.push(Contour {
+                        start,
+                        segments: Vec::new(),
+                    });
+                }
+            }
+            Plot(coordinates, offset) => {
+                let start = self.current_point();
+                let (x, y) = self.point;
+                self.move_to(coordinates);
+                let end = self.current_point();
+                let direction = self.interpolation.unwrap_or(InterpolationMode::Linear);
+                let center = offset.map(|offset| {
+                    if self.single_quadrant && direction.is_circular() {
+                        self.single_quadrant_center(index, (x, y), offset, start, end, direction)
+                    } else {
+                        self.to_point(x + offset.i, y + offset.j)
+                    }
+                });
+
+                if let Some(region) = &mut self.region {
+                    let segment = match (direction, center) {
+                        (InterpolationMode::Linear, _) | (_, None) => Segment::Line { end },
+                        (direction, Some(center)) => Segment::Arc {
+                            end,
+                            center,
+                            direction,
+                        },
+                    };
+                    match region.contours.last_mut() {
+                        Some(contour) => contour.segments.push(segment),
+                        // a contour without a leading D02 starts at the current point
+                        None => region.contours.push(Contour {
+                            start,
+                            segments: vec![segment],
+                        }),
+                    }
+                    return None;
+                }
+
+                let aperture = self.aperture?;
+                let shape = match (direction, center) {
+                    (InterpolationMode::Linear, _) | (_, None) if (x, y) == self.point => {
+                        match self.options.zero_length_draws {
+                            ZeroLengthDraws::Keep => Shape::Draw {
+                                start,
+                                end,
+                                aperture,
+                            },
+                            ZeroLengthDraws::Flash => Shape::Flash { at: end, aperture },
+                            ZeroLengthDraws::Drop => return None,
+                        }
+                    }
+                    (InterpolationMode::Linear, _) | (_, None) => Shape::Draw {
+                        start,
+                        end,
+                        aperture,
+                    },
+                    (direction, Some(center)) => Shape::Arc {
+                        start,
+                        end,
+                        center,
+                        direction,
+                        aperture,
+                    },
+                };
+                return self.object(shape, index..index + 1);
+            }
+            Flash(coordinates) => {
+                self.move_to(coordinates);
+                let shape = Shape::Flash {
+                    at: self.current_point(),
+                    aperture: self.aperture?,
+                };
+                return self.object(shape, index..index + 1);
+            }
+            StartRegion => {
+                self.region = Some(RegionBuilder {
+                    start: index,
+                    contours: Vec::new(),
+                })
+            }
+            EndRegion => {
+                let mut region = self.region.take()?;
+                region.contours.retain(|c| !c.segments.is_empty());
+                let shape = Shape::Region {
+                    contours: region.contours,
+                };
+                return self.object(shape, region.start..index + 1);
+            }
+            _ => (),
+        }
+        None
+    }
+
+    /// Create an object in the current graphics state
+    fn object(&self, shape: Shape, commands: Range<usize>) -> Option<Object> {
+        let (aperture, aperture_attributes, bounds) = match shape {
+            Shape::Region { .. } => (None, self.aperture_attributes.clone(), shape_bounds(&shape)),
+            Shape::Draw { aperture, .. }
+            | Shape::Arc { aperture, .. }
+            | Shape::Flash { aperture, .. } => {
+                let aperture = self.apertures.get(&aperture)?;
+                let bounds = self
+                    .aperture_bounds(aperture)
+                    .zip(shape_bounds(&shape))
+                    .map(|(aperture, path)| path.grow(&aperture));
+                (Some(aperture.index), aperture.attributes.clone(), bounds)
+            }
+        };
+        Some(Object {
+            shape,
+            polarity: self.polarity,
+            mirroring: self.mirroring,
+            rotation: self.rotation,
+            scaling: self.scaling,
+            attributes: Attributes {
+                aperture: aperture_attributes,
+                object: self.object_attributes.clone(),
+            },
+            source: Provenance { commands, aperture },
+            bounds,
+        })
+    }
+
+    /// The bounds of an aperture around its origin, mirrored, rotated and
+    /// scaled in the current graphics state
+    fn aperture_bounds(&self, aperture: &Aperture) -> Option<Bounds> {
+        let unit = self.unit.unwrap_or(Unit::Millimeters);
+        let (mirror_x, mirror_y) = match self.mirroring {
+            Mirroring::None => (1.0, 1.0),
+            Mirroring::X => (-1.0, 1.0),
+            Mirroring::Y => (1.0, -1.0),
+            Mirroring::XY => (-1.0, -1.0),
+        };
+        let (sin, cos) = self.rotation.0.to_radians().sin_cos();
+        let scale = unit.to_mm(self.scaling.0);
+        aperture
+            .disks
+            .as_ref()?
+            .iter()
+            .map(|&(center, radius)| {
+                let (x, y) = (center.x * mirror_x, center.y * mirror_y);
+                let (x, y) = ((x * cos - y * sin) * scale, (x * sin + y * cos) * scale);
+                let radius = radius * scale;
+                Bounds {
+                    min: Point {
+                        x: x - radius,
+                        y: y - radius,
+                    },
+                    max: Point {
+                        x: x + radius,
+                        y: y + radius,
+                    },
+                }
+            })
+            .reduce(|a, b| a.union(&b))
+    }
+
+    fn move_to(&mut self, coordinates: &Coordinates) {
+        self.point = (
+            coordinates.x.unwrap_or(self.point.0),
+            coordinates.y.unwrap_or(self.point.1),
+        );
+    }
+
+    /// The center of a single quadrant arc, whose offset is unsigned
+    ///
+    /// Of the up to four candidate centers, the specification takes the one
+    /// giving an arc of at most 90° in the direction of interpolation. If
+    /// none fits exactly the closest is taken, and if several fit within
+    /// [ARC_TOLERANCE] the arc is recorded as ambiguous.
+    fn single_quadrant_center(
+        &mut self,
+        index: usize,
+        (x, y): (i64, i64),
+        offset: Offset,
+        start: Point,
+        end: Point,
+        direction: InterpolationMode,
+    ) -> Point {
+        let signs = |value: i64| match value.abs() {
+            0 => vec![0],
+            value => vec![value, -value],
+        };
+        let mut candidates: Vec<ArcGeometry> = signs(offset.i)
+            .into_iter()
+            .flat_map(|i| signs(offset.j).into_iter().map(move |j| (i, j)))
+            .map(|(i, j)| ArcGeometry::new(start, end, self.to_point(x + i, y + j), direction))
+            .collect();
+        // allow for rounding of exact quarter circles
+        let quarter = |arc: &ArcGeometry| arc.sweep.abs() <= FRAC_PI_2 + 1e-9;
+        candidates.sort_by(|a, b| {
+            (!quarter(a), a.radius_deviation.abs())
+                .partial_cmp(&(!quarter(b), b.radius_deviation.abs()))
+                .unwrap_or(Ordering::Equal)
+        });
+        let fitting = candidates
+            .iter()
+            .filter(|arc| quarter(arc) && arc.is_consistent(ARC_TOLERANCE))
+            .count();
+        if fitting > 1 {
+            self.ambiguous_arcs.push(index);
+        }
+        candidates[0].center
+    }
+
+    /// The interned name and unescaped values of an attribute
+    fn attribute(&mut self, name: &str, values: &[EscapedString]) -> (Arc<str>, Vec<Arc<str>>) {
+        let name = self.intern(name);
+        let values = values
+            .iter()
+            .map(|value| self.intern(&value.unescape()))
+            .collect();
+        (name, values)
+    }
+
+    fn intern(&mut self, string: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(string) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(string);
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    fn current_point(&self) -> Point {
+        self.to_point(self.point.0, self.point.1)
+    }
+
+    fn to_point(&self, x: i64, y: i64) -> Point {
+        let default = CoordinateFormat {
+            integer: 6,
+            decimal: 6,
+        };
+        let (x_format, y_format) = self.format.unwrap_or((default, default));
+        let unit = self.unit.unwrap_or(Unit::Millimeters);
+        Point::from_nm(
+            unit.coordinate_to_nm(x, x_format),
+            unit.coordinate_to_nm(y, y_format),
+        )
+    }
+}
+
+/// The bounds of the path of a shape, without its aperture
+pub(crate) fn shape_bounds(shape: &Shape) -> Option<Bounds> {
+    match shape {
+        Shape::Draw { start, end, .. } => {
+            Some(Bounds::of_point(*start).union(&Bounds::of_point(*end)))
+        }
+        Shape::Arc {
+            start,
+            end,
+            center,
+            direction,
+            ..
+        } => Some(arc_bounds(*start, *end, *center, *direction)),
+        Shape::Flash { at, .. } => Some(Bounds::of_point(*at)),
+        Shape::Region { contours } => contours
+            .iter()
+            .map(|contour| {
+                let mut from = contour.start;
+                let mut bounds = Bounds::of_point(from);
+                for segment in &contour.segments {
+                    let (segment, end) = match *segment {
+                        Segment::Line { end } => (Bounds::of_point(end), end),
+                        Segment::Arc {
+                            end,
+                            center,
+                            direction,
+                        } => (arc_bounds(from, end, center, direction), end),
+                    };
+                    bounds = bounds.union(&segment);
+                    from = end;
+                }
+                bounds
+            })
+            .reduce(|a, b| a.union(&b)),
+    }
+}
+
+/// The bounds of an arc: its ends, and where it crosses the axes through
+/// its center. An arc ending at its start is a full circle.
+fn arc_bounds(start: Point, end: Point, center: Point, direction: InterpolationMode) -> Bounds {
+    let arc = ArcGeometry::new(start, end, center, direction);
+    let mut bounds = Bounds::of_point(start).union(&Bounds::of_point(end));
+    for quadrant in 0..4 {
+        let angle = FRAC_PI_2 * quadrant as f64;
+        if arc.contains_angle(angle) {
+            bounds = bounds.union(&Bounds::of_point(arc.point_at(angle)));
+        }
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    const HEADER: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10C,0.1*%
+        %ADD11R,1X2*%
+        G01*
+        G75*
+    "};
+
+    fn layer(body: &str) -> GerberLayer<'static> {
+        let src = format!("{HEADER}{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().into_owned()
+    }
+
+    fn point(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_draw_and_flash() {
+        let layer = layer(indoc! {"
+            D10*
+            X1000000Y0D02*
+            X2000000Y500000D01*
+            D11*
+            Y0D03*
+        "});
+        let image = layer.image();
+        let d10 = ApertureId::new(10).unwrap();
+        let d11 = ApertureId::new(11).unwrap();
+        assert_eq!(image.unit, Some(Unit::Millimeters));
+        assert_eq!(image.objects.len(), 2);
+
+        assert_eq!(
+            image.objects[0].shape,
+            Shape::Draw {
+                start: point(1.0, 0.0),
+                end: point(2.0, 0.5),
+                aperture: d10,
+            }
+        );
+        assert_eq!(
+            image.objects[0].source,
+            Provenance {
+                commands: 8..9,
+                aperture: Some(2),
+            }
+        );
+
+        assert_eq!(
+            image.objects[1].shape,
+            Shape::Flash {
+                at: point(2.0, 0.0),
+                aperture: d11,
+            }
+        );
+        let source: Vec<_> = layer
+            .source_spans(&image.objects[1])
+            .map(|span| span.line)
+            .collect();
+        assert_eq!(source, [11, 4]);
+    }
+
+    #[test]
+    fn test_arc() {
+        let image = layer(indoc! {"
+            D10*
+            X1000000Y0D02*
+            G03*
+            X0Y1000000I-1000000J0D01*
+        "})
+        .image();
+        assert_eq!(
+            image.objects[0].shape,
+            Shape::Arc {
+                start: point(1.0, 0.0),
+                end: point(0.0, 1.0),
+                center: point(0.0, 0.0),
+                direction: InterpolationMode::CounterClockwise,
+                aperture: ApertureId::new(10).unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_nanometer_grid() {
+        let points = |src: &str| -> Vec<Point> {
+            let image = GerberLayer::parse(src).unwrap().image();
+            image
+                .objects
+                .iter()
+                .map(|object| match object.shape {
+                    Shape::Flash { at, .. } => at,
+                    _ => panic!("expected a flash"),
+                })
+                .collect()
+        };
+        let mm = points(indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X7620000Y-33020000D03*
+            X76D03*
+            M02*
+        "});
+        let inches = points(indoc! {"
+            %FSLAX36Y36*%
+            %MOIN*%
+            %ADD10C,0.004*%
+            D10*
+            X300000Y-1300000D03*
+            X3D03*
+            M02*
+        "});
+        // exactly equal, unlike 0.3 * 25.4 and 7.62
+        assert_eq!(inches, mm);
+        assert_eq!(mm[0], point(7.62, -33.02));
+        assert_eq!(inches[1].to_nm(), (76, -33_020_000));
+    }
+
+    #[test]
+    fn test_zero_length_draws() {
+        let layer = layer(indoc! {"
+            D10*
+            X1000000Y0D02*
+            X1000000Y0D01*
+            G36*
+            X0Y0D02*
+            X0Y0D01*
+            X1000000Y0D01*
+            X0Y0D01*
+            G37*
+        "});
+        let shapes = |zero_length_draws| {
+            let options = EvaluateOptions {
+                zero_length_draws,
+                ..Default::default()
+            };
+            let image = layer.image_with_options(&options);
+            image
+                .objects
+                .into_iter()
+                .map(|object| object.shape)
+                .collect::<Vec<_>>()
+        };
+        let d10 = ApertureId::new(10).unwrap();
+        let at = point(1.0, 0.0);
+
+        let kept = shapes(ZeroLengthDraws::Keep);
+        assert_eq!(kept.len(), 2);
+        assert_eq!(
+            kept[0],
+            Shape::Draw {
+                start: at,
+                end: at,
+                aperture: d10
+            }
+        );
+        // contour segments are not draws, so are always kept
+        assert_eq!(kept[1], shapes(ZeroLengthDraws::Drop)[0]);
+
+        let flashed = shapes(ZeroLengthDraws::Flash);
+        assert_eq!(flashed[0], Shape::Flash { at, aperture: d10 });
+        assert_eq!(shapes(ZeroLengthDraws::Drop).len(), 1);
+    }
+
+    #[test]
+    fn test_single_quadrant_arc() {
+        let image = layer(indoc! {"
+            D10*
+            G74*
+            G02*
+            X1000000Y0D02*
+            X0Y1000000I0J1000000D01*
+            G03*
+            X1000000Y0D02*
+            X0Y1000000I1000000J0D01*
+            G75*
+            X1000000Y0D02*
+            X0Y1000000I1000000J0D01*
+        "})
+        .image();
+        let centers: Vec<_> = image
+            .objects
+            .iter()
+            .map(|object| object.shape.arc_geometry().unwrap().center)
+            .collect();
+        // the signs of the offset are chosen by the direction, and ignored
+        // again after G75
+        assert_eq!(centers, [point(1.0, 1.0), point(0.0, 0.0), point(2.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_region() {
+        let image = layer(indoc! {"
+            %LPC*%
+            G36*
+            X0Y0D02*
+            X1000000D01*
+            Y1000000D01*
+            X0Y0D01*
+            X5000000Y5000000D02*
+            G37*
+        "})
+        .image();
+        assert_eq!(image.objects.len(), 1);
+        let object = &image.objects[0];
+        assert_eq!(object.polarity, Polarity::Clear);
+        assert_eq!(
+            object.source,
+            Provenance {
+                commands: 7..14,
+                aperture: None,
+            }
+        );
+        let Shape::Region { contours } = &object.shape else {
+            panic!("expected a region");
+        };
+        // the trailing D02 starts an empty contour, which is dropped
+        assert_eq!(contours.len(), 1);
+        assert_eq!(contours[0].start, point(0.0, 0.0));
+        assert_eq!(
+            contours[0].segments,
+            [
+                Segment::Line {
+                    end: point(1.0, 0.0)
+                },
+                Segment::Line {
+                    end: point(1.0, 1.0)
+                },
+                Segment::Line {
+                    end: point(0.0, 0.0)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_winding() {
+        // a clockwise half disc: down the diameter, then around through -x
+        let body = indoc! {"
+            G36*
+            X0Y1000000D02*
+            Y-1000000D01*
+            G02*
+            Y1000000I0J1000000D01*
+            G01*
+            G37*
+        "};
+        let contours = |image: &Image| match &image.objects[0].shape {
+            Shape::Region { contours } => contours.clone(),
+            _ => panic!("expected a region"),
+        };
+        let mut image = layer(body).image();
+        let contour = &contours(&image)[0];
+        assert!((contour.signed_area() + FRAC_PI_2).abs() < 1e-9);
+
+        let reversed = contour.reversed();
+        assert_eq!(reversed.start, point(0.0, 1.0));
+        assert_eq!(
+            reversed.segments,
+            [
+                Segment::Arc {
+                    end: point(0.0, -1.0),
+                    center: point(0.0, 0.0),
+                    direction: InterpolationMode::CounterClockwise,
+                },
+                Segment::Line {
+                    end: point(0.0, 1.0)
+                },
+            ]
+        );
+        assert!((reversed.signed_area() - FRAC_PI_2).abs() < 1e-9);
+        assert_eq!(&reversed.reversed(), contour);
+
+        assert_eq!(image.normalize_winding(), 1);
+        assert_eq!(contours(&image)[0], reversed);
+        assert_eq!(image.normalize_winding(), 0);
+
+        let options = EvaluateOptions {
+            normalize_winding: true,
+            ..Default::default()
+        };
+        assert_eq!(layer(body).image_with_options(&options), image);
+    }
+
+    #[test]
+    fn test_objects() {
+        let layer = layer(indoc! {"
+            %TO.N,GND*%
+            D11*
+            X0Y0D03*
+            %LPC*%
+            %LR90*%
+            X0Y0D03*
+            %LPD*%
+            %LR0*%
+            G36*
+            X0Y1000000D02*
+            Y-1000000D01*
+            G02*
+            Y1000000I0J1000000D01*
+            G01*
+            G37*
+            D10*
+            X0Y0D02*
+            X1000000D01*
+        "});
+        for normalize_winding in [false, true] {
+            let options = EvaluateOptions {
+                normalize_winding,
+                ..Default::default()
+            };
+            let objects: Vec<_> = layer.objects(&options).collect();
+            assert_eq!(objects, layer.image_with_options(&options).objects);
+            assert!(objects
+                .windows(2)
+                .all(|pair| pair[0].source.commands.end <= pair[1].source.commands.start));
+        }
+
+        let mut stream = layer.objects(&EvaluateOptions::default());
+        let clear = stream.nth(1).unwrap();
+        assert_eq!(clear.polarity, Polarity::Clear);
+        assert_eq!(clear.rotation, Rotation(90.0));
+        assert_eq!(clear.attributes.net(), Some("GND"));
+        assert_eq!(stream.unit(), Some(Unit::Millimeters));
+        assert!(matches!(
+            stream.template(&clear),
+            Some(ApertureTemplate::Rectangle { .. })
+        ));
+    }
+
+    #[test]
+    fn test_object_capacity() {
+        let layer = layer(indoc! {"
+            D10*
+            X0Y0D02*
+            X1000000D01*
+            G36*
+            X0Y0D02*
+            X1000000D01*
+            Y1000000D01*
+            X0Y0D01*
+            G37*
+            X0Y0D03*
+        "});
+        assert_eq!(object_capacity(layer.commands()), 3);
+        assert_eq!(layer.image().objects.capacity(), 3);
+    }
+
+    #[test]
+    fn test_undefined_aperture() {
+        let image = layer("D12*\nX0Y0D03*\n").image();
+        assert!(image.objects.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_polygon() {
+        // a file can't define it, but commands built by hand can
+        let src = format!("{HEADER}%ADD12P,1X4000000000*%\nM02*\n");
+        assert!(GerberLayer::parse(&src).is_err());
+        let mut commands = layer("D12*\nX0Y0D03*\n").commands().to_vec();
+        commands.insert(
+            3,
+            ApertureDefine(
+                ApertureId::new(12).unwrap(),
+                ApertureTemplate::Polygon {
+                    diameter: 1.0,
+                    vertices: 4e9,
+                    rotation: None,
+                    hole: None,
+                },
+            ),
+        );
+        let image = evaluate(&commands);
+        assert_eq!(image.objects.len(), 1);
+        assert_eq!(image.objects[0].bounds, None);
+        assert_eq!(objects(&commands, &EvaluateOptions::default()).count(), 1);
+    }
+
+    #[test]
+    fn test_attributes() {
+        let image = layer(indoc! {"
+            %TF.FileFunction,Copper,L1,Top*%
+            %TA.AperFunction,SMDPad,CuDef*%
+            %ADD12C,1*%
+            %TD.AperFunction*%
+            %TO.N,GND*%
+            %TO.C,R1*%
+            D12*
+            X0Y0D03*
+            %TD.C*%
+            D10*
+            X1000000Y0D01*
+            %TD*%
+            G36*
+            X0Y0D02*
+            X1000000Y0D01*
+            Y1000000D01*
+            G37*
+        "})
+        .image();
+        assert_eq!(
+            image.file_attributes[".FileFunction"],
+            ["Copper", "L1", "Top"].map(Arc::<str>::from)
+        );
+
+        let pad = &image.objects[0].attributes;
+        assert_eq!(pad.aperture_function(), Some("SMDPad"));
+        assert_eq!(pad.net(), Some("GND"));
+        assert_eq!(pad.component(), Some("R1"));
+
+        let track = &image.objects[1].attributes;
+        assert_eq!(track.aperture_function(), None);
+        assert_eq!(track.net(), Some("GND"));
+        assert_eq!(track.component(), None);
+
+        assert_eq!(image.objects[2].attributes, Attributes::default());
+    }
+
+    #[test]
+    fn test_interned_attributes() {
+        let image = layer(indoc! {"
+            %TO.N,GND*%
+            D10*
+            X0Y0D03*
+            %TO.N,VCC*%
+            X1000000Y0D03*
+            %TO.N,GND*%
+            %TO.C,GND*%
+            X2000000Y0D03*
+        "})
+        .image();
+        let net = |index: usize| &image.objects[index].attributes.object[".N"][0];
+        assert!(Arc::ptr_eq(net(0), net(2)));
+        assert!(!Arc::ptr_eq(net(0), net(1)));
+        assert!(Arc::ptr_eq(
+            net(2),
+            &image.objects[2].attributes.object[".C"][0]
+        ));
+
+        // the names are shared too
+        let name = |index: usize| {
+            let object = &image.objects[index].attributes.object;
+            object.get_key_value(".N").unwrap().0
+        };
+        assert!(Arc::ptr_eq(name(0), name(2)));
+    }
+
+    #[test]
+    fn test_bounds() {
+        let image = layer(indoc! {"
+            D10*
+            X0Y0D02*
+            X2000000Y1000000D01*
+            G03*
+            X1000000Y0D02*
+            X0Y1000000I-1000000J0D01*
+            G01*
+            D11*
+            %LR90*%
+            X5000000Y0D03*
+            %LR0*%
+            %LS2*%
+            X5000000Y0D03*
+            G36*
+            X10000000Y0D02*
+            X12000000D01*
+            Y2000000D01*
+            X10000000D01*
+            Y0D01*
+            G37*
+        "})
+        .image();
+        let bounds: Vec<_> = image
+            .objects
+            .iter()
+            .map(|object| {
+                let Bounds { min, max } = object.bounds.unwrap();
+                [min.x, min.y, max.x, max.y].map(|value| (value * 1e9).round() / 1e9)
+            })
+            .collect();
+        assert_eq!(
+            bounds,
+            [
+                [-0.05, -0.05, 2.05, 1.05],
+                [-0.05, -0.05, 1.05, 1.05],
+                [4.0, -0.5, 6.0, 0.5],
+                [4.0, -2.0, 6.0, 2.0],
+                [10.0, 0.0, 12.0, 2.0],
+            ]
+        );
+
+        let query = Bounds {
+            min: point(5.5, 0.0),
+            max: point(11.0, 1.0),
+        };
+        let found: Vec<_> = image.objects_in(query).collect();
+        assert_eq!(found, [2, 3, 4]);
+        assert!(query.contains(point(5.5, 1.0)));
+        assert_eq!(query.union(&Bounds::default()).width(), 11.0);
+    }
+
+    #[test]
+    fn test_arc_bounds() {
+        let center = point(0.0, 0.0);
+        let (right, top) = (point(1.0, 0.0), point(0.0, 1.0));
+        let ccw = arc_bounds(right, top, center, InterpolationMode::CounterClockwise);
+        assert_eq!((ccw.min, ccw.max), (point(0.0, 0.0), point(1.0, 1.0)));
+        let cw = arc_bounds(right, top, center, InterpolationMode::Clockwise);
+        assert_eq!((cw.min, cw.max), (point(-1.0, -1.0), point(1.0, 1.0)));
+        let full = arc_bounds(right, right, center, InterpolationMode::Clockwise);
+        assert_eq!((full.width(), full.height()), (2.0, 2.0));
+    }
+}
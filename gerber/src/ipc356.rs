@@ -0,0 +1,626 @@
+//! IPC-D-356A netlists
+//!
+//! Bare-board electrical test compares the connections of a fabricated
+//! board against a netlist, which fabs expect in the fixed-column
+//! IPC-D-356A format. The netlist is built from the project: surface pads
+//! come from the outer copper layers and plated holes from the drill
+//! layers, each with the net and pin of its `.N` and `.P` attributes.
+//!
+//! Pads without a `.N` attribute get their net from the connectivity of
+//! the copper: a pad takes the net of any other object with one in the
+//! same piece of copper. Pads connected to nothing are written as `N/C`.
+//!
+//! Through-hole pads are only found from their holes, so a project
+//! without a plated drill layer lists surface pads only.
+
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::aperture::ApertureTemplate;
+use crate::copper::{object_polygons, Polygon};
+use crate::data::{Polarity, Unit};
+use crate::image::{Image, Object, Point, Shape};
+use crate::mesh::Mesh;
+use crate::project::{file_function, GerberProject};
+use crate::testpoint::Side;
+
+/// How close a hole must be to a pad to belong to it, in millimeters
+const SAME_POINT: f64 = 0.001;
+
+/// The longest net name a test record holds, longer names are aliased
+const NET_LENGTH: usize = 14;
+
+/// A pad or hole of the netlist
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NetlistEntry {
+    /// The net, `None` for pads connected to nothing
+    pub net: Option<String>,
+
+    /// The reference designator, from `.P` or `.C`
+    pub component: Option<String>,
+
+    /// The pin number, from `.P`
+    pub pin: Option<String>,
+
+    /// Center of the pad or hole in millimeters
+    pub at: Point,
+
+    /// Width and height of the pad before rotation, in millimeters
+    pub size: (f64, f64),
+
+    /// Rotation of the pad in degrees counter-clockwise
+    pub rotation: f64,
+
+    /// Diameter of the plated hole in millimeters, for through-hole pads
+    /// and vias
+    pub hole: Option<f64>,
+
+    pub via: bool,
+
+    /// The number of the copper layer of a surface pad, 0 for holes which
+    /// are accessible from both sides
+    pub access: u32,
+
+    /// Whether the soldermask leaves the pad bare on the top and bottom
+    /// side. Without a soldermask layer, pads are taken as bare on the
+    /// sides they are accessible from.
+    pub exposed: (bool, bool),
+
+    /// Index of the layer in [GerberProject::layers]
+    pub layer: usize,
+
+    /// Index of the pad or hole in the layer's [Image::objects]
+    pub object: usize,
+}
+
+impl GerberProject<'_> {
+    /// The surface pads and plated holes of the project
+    ///
+    /// Curves are approximated within `tolerance` millimeters to find
+    /// which objects are connected. Entries follow the order of the layers
+    /// and of the objects in each layer.
+    pub fn netlist(&self, tolerance: f64) -> Vec<NetlistEntry> {
+        let images = self.images();
+        let coppers: Vec<_> = self
+            .layers
+            .iter()
+            .zip(&images)
+            .enumerate()
+            .filter_map(|(index, (layer, image))| {
+                let number = copper_number(image)?;
+                let copper = layer.layer.copper(tolerance).polygons;
+                Some(CopperLayer {
+                    index,
+                    number,
+                    side: Side::of(image),
+                    image,
+                    connectivity: Connectivity::new(image, copper, tolerance),
+                })
+            })
+            .collect();
+        let masks: Vec<_> = images
+            .iter()
+            .filter(|image| {
+                file_function(image).and_then(|f| f.first().copied()) == Some("Soldermask")
+            })
+            .filter_map(|image| Some((Side::of(image)?, image)))
+            .collect();
+        let exposed = |side: Side, point: Point| {
+            let mut masks = masks.iter().filter(|(s, _)| *s == side).peekable();
+            masks.peek().is_none()
+                || masks.any(|(_, image)| {
+                    image.objects.iter().any(|object| {
+                        object.polarity == Polarity::Dark
+                            && object.bounds.is_some_and(|bounds| bounds.contains(point))
+                    })
+                })
+        };
+
+        let mut entries = Vec::new();
+        for copper in &coppers {
+            let Some(side) = copper.side else { continue };
+            for (index, object) in copper.image.objects.iter().enumerate() {
+                let surface = matches!(
+                    object.attributes.aperture_function(),
+                    Some("SMDPad" | "BGAPad" | "TestPad")
+                );
+                if !surface || object.polarity != Polarity::Dark {
+                    continue;
+                }
+                let Some(at) = center(object) else { continue };
+                let (size, rotation) = pad_size(copper.image, object);
+                let (component, pin) = pin(object);
+                entries.push(NetlistEntry {
+                    net: copper.net(object, at),
+                    component,
+                    pin,
+                    at,
+                    size,
+                    rotation,
+                    hole: None,
+                    via: false,
+                    access: copper.number,
+                    exposed: (
+                        side == Side::Top && exposed(Side::Top, at),
+                        side == Side::Bottom && exposed(Side::Bottom, at),
+                    ),
+                    layer: copper.index,
+                    object: index,
+                });
+            }
+        }
+
+        let drills = images.iter().enumerate().filter(|(_, image)| {
+            file_function(image).and_then(|f| f.first().copied()) == Some("Plated")
+        });
+        let mut outer: Vec<_> = coppers.iter().filter(|c| c.side.is_some()).collect();
+        outer.sort_by_key(|copper| copper.side != Some(Side::Top));
+        for (layer, image) in drills {
+            let unit = image.unit.unwrap_or(Unit::Millimeters);
+            for (index, object) in image.objects.iter().enumerate() {
+                let Shape::Flash { at, .. } = object.shape else {
+                    continue;
+                };
+                let Some(&ApertureTemplate::Circle { diameter, .. }) = template(image, object)
+                else {
+                    continue;
+                };
+                let via = object.attributes.aperture_function() == Some("ViaDrill");
+
+                // the pad of the hole, preferring the top side
+                let pad = outer.iter().find_map(|copper| {
+                    let pad = copper.image.objects.iter().find(|pad| {
+                        pad.polarity == Polarity::Dark
+                            && matches!(pad.shape, Shape::Flash { at: p, .. }
+                                if (p.x - at.x).hypot(p.y - at.y) <= SAME_POINT)
+                    })?;
+                    Some((copper, pad))
+                });
+                let hole = unit.to_mm(diameter) * object.scaling.0;
+                let (size, rotation) = match pad {
+                    Some((copper, pad)) => pad_size(copper.image, pad),
+                    None => ((hole, hole), 0.0),
+                };
+                let net = net(object)
+                    .map(str::to_string)
+                    .or_else(|| pad.and_then(|(copper, pad)| copper.net(pad, at)))
+                    .or_else(|| {
+                        coppers
+                            .iter()
+                            .find_map(|copper| copper.connectivity.net_at(at))
+                            .map(str::to_string)
+                    });
+                let (component, pin) = match pin(object) {
+                    (None, None) => pad.map_or((None, None), |(_, pad)| pin(pad)),
+                    found => found,
+                };
+                entries.push(NetlistEntry {
+                    net,
+                    component: if via { Some("VIA".into()) } else { component },
+                    pin: if via { None } else { pin },
+                    at,
+                    size,
+                    rotation,
+                    hole: Some(hole),
+                    via,
+                    access: 0,
+                    exposed: (exposed(Side::Top, at), exposed(Side::Bottom, at)),
+                    layer,
+                    object: index,
+                });
+            }
+        }
+        entries
+    }
+
+    /// Write the [netlist](GerberProject::netlist) as an IPC-D-356A file
+    ///
+    /// Coordinates and sizes are in micrometers (`UNITS CUST 1`). Net
+    /// names longer than the fourteen columns of a test record are written
+    /// as `NNAME` aliases.
+    ///
+    /// ```
+    /// use gerber::project::GerberProject;
+    /// use gerber::GerberLayer;
+    ///
+    /// let top = "%FSLAX26Y26*%\n%MOMM*%\n%TF.FileFunction,Copper,L1,Top*%\n\
+    ///            %TA.AperFunction,SMDPad,CuDef*%\n%ADD10R,1X0.5*%\nD10*\n\
+    ///            %TO.N,GND*%\n%TO.P,R1,2*%\nX2000000Y-1000000D03*\nM02*\n";
+    /// let mut project = GerberProject::new();
+    /// project.add("board-F_Cu.gbr", GerberLayer::parse(top).unwrap());
+    /// let netlist = project.to_ipc_d_356(0.001);
+    /// assert!(netlist.contains(
+    ///     "327GND              R1    -2          A01X+002000Y-001000X1000Y0500R000 S1"
+    /// ));
+    /// ```
+    pub fn to_ipc_d_356(&self, tolerance: f64) -> String {
+        let entries = self.netlist(tolerance);
+        let mut netlist = String::from("C  IPC-D-356A netlist\n");
+        let job = self
+            .layers
+            .iter()
+            .find_map(|layer| layer.layer.project_id()?.ok());
+        if let Some(job) = job {
+            parameter(&mut netlist, "JOB", &job.name);
+        }
+        parameter(&mut netlist, "UNITS", "CUST 1");
+        parameter(&mut netlist, "DIM", "N");
+
+        let mut aliases: Vec<&str> = Vec::new();
+        for net in entries.iter().filter_map(|entry| entry.net.as_deref()) {
+            if net.chars().count() > NET_LENGTH && !aliases.contains(&net) {
+                aliases.push(net);
+                parameter(&mut netlist, &format!("NNAME{}", aliases.len()), net);
+            }
+        }
+
+        for entry in &entries {
+            let net = match entry.net.as_deref() {
+                None => "N/C".to_string(),
+                Some(net) => match aliases.iter().position(|alias| *alias == net) {
+                    Some(index) => format!("NNAME{}", index + 1),
+                    None => net.to_string(),
+                },
+            };
+            let field = |value: &Option<String>, width: usize| {
+                let value: String = value.as_deref().unwrap_or("").chars().take(width).collect();
+                format!("{value:<width$}")
+            };
+            let record = if entry.hole.is_some() { "317" } else { "327" };
+            write!(
+                netlist,
+                "{record}{net:<14}   {}-{}{}",
+                field(&entry.component, 6),
+                field(&entry.pin, 4),
+                if entry.via { 'M' } else { ' ' },
+            )
+            .unwrap();
+            match entry.hole {
+                Some(hole) => write!(netlist, "D{:04}P", micrometers(hole, 9999)).unwrap(),
+                None => netlist.push_str("      "),
+            }
+            let rotation = (entry.rotation.round() as i64).rem_euclid(360);
+            let mask = u8::from(entry.exposed.0) + 2 * u8::from(entry.exposed.1);
+            writeln!(
+                netlist,
+                "A{:02}X{}Y{}X{:04}Y{:04}R{rotation:03} S{mask}",
+                entry.access.min(99),
+                coordinate(entry.at.x),
+                coordinate(entry.at.y),
+                micrometers(entry.size.0, 9999),
+                micrometers(entry.size.1, 9999),
+            )
+            .unwrap();
+        }
+        netlist.push_str("999\n");
+        netlist
+    }
+}
+
+/// A copper layer with the nets of its pieces of copper
+struct CopperLayer<'i> {
+    index: usize,
+    number: u32,
+
+    /// `None` for inner layers
+    side: Option<Side>,
+
+    image: &'i Image,
+    connectivity: Connectivity,
+}
+
+impl CopperLayer<'_> {
+    /// The net of a pad: its own, or that of the copper around `at`
+    fn net(&self, object: &Object, at: Point) -> Option<String> {
+        net(object)
+            .or_else(|| self.connectivity.net_at(at))
+            .map(str::to_string)
+    }
+}
+
+/// The pieces of copper of a layer, and the net of each
+struct Connectivity {
+    copper: Vec<Polygon>,
+    nets: Vec<Option<Arc<str>>>,
+}
+
+impl Connectivity {
+    /// Give each piece of copper the net of the first object in it which
+    /// has one
+    fn new(image: &Image, copper: Vec<Polygon>, tolerance: f64) -> Self {
+        let mut nets = vec![None; copper.len()];
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        for object in &image.objects {
+            let Some(name) = net(object) else { continue };
+            if object.polarity != Polarity::Dark {
+                continue;
+            }
+            let Some(point) = inside(object, unit, tolerance) else {
+                continue;
+            };
+            if let Some(index) = copper.iter().position(|piece| piece.contains(point)) {
+                nets[index].get_or_insert_with(|| Arc::from(name));
+            }
+        }
+        Connectivity { copper, nets }
+    }
+
+    fn net_at(&self, point: Point) -> Option<&str> {
+        let index = self.copper.iter().position(|piece| piece.contains(point))?;
+        self.nets[index].as_deref()
+    }
+}
+
+/// The number of a copper layer, from `.FileFunction,Copper,L<n>,...`
+fn copper_number(image: &Image) -> Option<u32> {
+    match file_function(image)?.as_slice() {
+        ["Copper", number, ..] => number.strip_prefix('L')?.parse().ok(),
+        _ => None,
+    }
+}
+
+/// The net of an object, `None` without `.N` or with an empty one
+fn net(object: &Object) -> Option<&str> {
+    object.attributes.net().filter(|net| !net.is_empty())
+}
+
+/// The reference designator and pin of an object
+fn pin(object: &Object) -> (Option<String>, Option<String>) {
+    let values = object.attributes.get(".P").unwrap_or_default();
+    let component = values
+        .first()
+        .map(|value| value.to_string())
+        .or_else(|| object.attributes.component().map(str::to_string));
+    (component, values.get(1).map(|value| value.to_string()))
+}
+
+fn template<'i>(image: &'i Image, object: &Object) -> Option<&'i ApertureTemplate<'static>> {
+    image.apertures.get(&object.source.aperture?)
+}
+
+/// The center of a pad: its flash point, or the center of its bounds
+fn center(object: &Object) -> Option<Point> {
+    match object.shape {
+        Shape::Flash { at, .. } => Some(at),
+        Shape::Region { .. } => {
+            let bounds = object.bounds?;
+            Some(Point {
+                x: (bounds.min.x + bounds.max.x) / 2.0,
+                y: (bounds.min.y + bounds.max.y) / 2.0,
+            })
+        }
+        Shape::Draw { .. } | Shape::Arc { .. } => None,
+    }
+}
+
+/// The width and height of a pad before rotation, and its rotation
+fn pad_size(image: &Image, object: &Object) -> ((f64, f64), f64) {
+    let unit = image.unit.unwrap_or(Unit::Millimeters);
+    let scale = |value: f64| unit.to_mm(value) * object.scaling.0;
+    let size = match (&object.shape, template(image, object)) {
+        (Shape::Flash { .. }, Some(template)) => match *template {
+            ApertureTemplate::Circle { diameter, .. }
+            | ApertureTemplate::Polygon { diameter, .. } => Some((diameter, diameter)),
+            ApertureTemplate::Rectangle { x, y, .. } | ApertureTemplate::Obround { x, y, .. } => {
+                Some((x, y))
+            }
+            ApertureTemplate::Macro { .. } => None,
+        },
+        _ => None,
+    };
+    match size {
+        Some((x, y)) => ((scale(x), scale(y)), object.rotation.0),
+        None => {
+            let size = object
+                .bounds
+                .map_or((0.0, 0.0), |bounds| (bounds.width(), bounds.height()));
+            (size, 0.0)
+        }
+    }
+}
+
+/// A point inside the copper of an object, if its shape is known
+///
+/// Flashes and strokes are centered on their aperture. A region gets the
+/// centroid of the largest triangle of its mesh, which is well inside it
+/// however concave it is.
+fn inside(object: &Object, unit: Unit, tolerance: f64) -> Option<Point> {
+    match object.shape {
+        Shape::Flash { at, .. } => Some(at),
+        Shape::Draw { start, .. } | Shape::Arc { start, .. } => Some(start),
+        Shape::Region { .. } => {
+            let polygons = object_polygons(object, None, &[], unit, tolerance)?;
+            let area = |[a, b, c]: &[[f32; 2]; 3]| {
+                ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])).abs()
+            };
+            let [a, b, c] = Mesh::new(&polygons)
+                .triangles()
+                .max_by(|p, q| area(p).total_cmp(&area(q)))?;
+            Some(Point {
+                x: (a[0] as f64 + b[0] as f64 + c[0] as f64) / 3.0,
+                y: (a[1] as f64 + b[1] as f64 + c[1] as f64) / 3.0,
+            })
+        }
+    }
+}
+
+fn parameter(netlist: &mut String, name: &str, value: &str) {
+    writeln!(netlist, "P  {name:<5} {value}").unwrap();
+}
+
+/// A length in whole micrometers, at most `max`
+fn micrometers(mm: f64, max: i64) -> i64 {
+    ((mm * 1000.0).round() as i64).clamp(0, max)
+}
+
+/// A signed coordinate in six digits of micrometers
+fn coordinate(mm: f64) -> String {
+    let um = ((mm * 1000.0).round() as i64).clamp(-999_999, 999_999);
+    let sign = if um < 0 { '-' } else { '+' };
+    format!("{sign}{:06}", um.abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    fn layer(attributes: &str, body: &str) -> GerberLayer<'static> {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{attributes}{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().into_owned()
+    }
+
+    fn project() -> GerberProject<'static> {
+        let mut project = GerberProject::new();
+        project.add(
+            "board-F_Cu.gbr",
+            layer(
+                "%TF.FileFunction,Copper,L1,Top*%\n",
+                indoc! {"
+                    %TA.AperFunction,SMDPad,CuDef*%
+                    %ADD10R,1X0.5*%
+                    %TA.AperFunction,ComponentPad*%
+                    %ADD11C,1.7*%
+                    %TA.AperFunction,ViaPad*%
+                    %ADD12C,0.6*%
+                    %TA.AperFunction,Conductor*%
+                    %ADD13C,0.2*%
+                    %TD*%
+                    D10*
+                    %TO.N,VCC*%
+                    %TO.P,R1,1*%
+                    X2000000Y1000000D03*
+                    %TD*%
+                    %TO.P,R1,2*%
+                    %LR90*%
+                    X4000000Y1000000D03*
+                    %LR0*%
+                    %TD*%
+                    %TO.N,A_VERY_LONG_NET_NAME*%
+                    X30000000Y0D03*
+                    %TD*%
+                    X40000000Y0D03*
+                    D11*
+                    X10000000Y1000000D03*
+                    D12*
+                    X20000000Y5000000D03*
+                    D13*
+                    %TO.N,GND*%
+                    G01*
+                    X4000000Y1000000D02*
+                    X10000000Y1000000D01*
+                    %TD*%
+                "},
+            ),
+        );
+        project.add(
+            "board-B_Cu.gbr",
+            layer(
+                "%TF.FileFunction,Copper,L2,Bot*%\n",
+                indoc! {"
+                    %TA.AperFunction,SMDPad,CuDef*%
+                    %ADD10C,0.8*%
+                    %TA.AperFunction,Conductor*%
+                    %ADD11C,0.2*%
+                    %TD*%
+                    D10*
+                    %TO.N,GND*%
+                    %TO.P,U1,3*%
+                    X5000000Y5000000D03*
+                    D11*
+                    G01*
+                    X5000000Y5000000D02*
+                    X20000000Y5000000D01*
+                    %TD*%
+                "},
+            ),
+        );
+        project.add(
+            "board-F_Mask.gbr",
+            layer(
+                "%TF.FileFunction,Soldermask,Top*%\n%TF.FilePolarity,Negative*%\n",
+                "%ADD10C,1.2*%\nD10*\nX2000000Y1000000D03*\nX4000000Y1000000D03*\n",
+            ),
+        );
+        project.add(
+            "board-PTH.gbr",
+            layer(
+                "%TF.FileFunction,Plated,1,2,PTH*%\n",
+                indoc! {"
+                    %TA.AperFunction,ComponentDrill*%
+                    %ADD10C,1*%
+                    %TA.AperFunction,ViaDrill*%
+                    %ADD11C,0.3*%
+                    %TD*%
+                    D10*
+                    %TO.P,J1,1*%
+                    X10000000Y1000000D03*
+                    %TD*%
+                    D11*
+                    X20000000Y5000000D03*
+                "},
+            ),
+        );
+        project
+    }
+
+    #[test]
+    fn test_netlist() {
+        let entries = project().netlist(0.001);
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.net.as_deref(),
+                    entry.component.as_deref(),
+                    entry.pin.as_deref(),
+                    entry.access,
+                    entry.exposed,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Some("VCC"), Some("R1"), Some("1"), 1, (true, false)),
+                // connected to GND by the track
+                (Some("GND"), Some("R1"), Some("2"), 1, (true, false)),
+                (Some("A_VERY_LONG_NET_NAME"), None, None, 1, (false, false)),
+                (None, None, None, 1, (false, false)),
+                (Some("GND"), Some("U1"), Some("3"), 2, (false, true)),
+                // the pin from the drill, the net from the track to the pad
+                (Some("GND"), Some("J1"), Some("1"), 0, (false, true)),
+                // the net from the track on the bottom
+                (Some("GND"), Some("VIA"), None, 0, (false, true)),
+            ]
+        );
+        assert_eq!(entries[1].size, (1.0, 0.5));
+        assert_eq!(entries[1].rotation, 90.0);
+        assert_eq!(entries[5].size, (1.7, 1.7));
+        assert_eq!(entries[5].hole, Some(1.0));
+        assert!(entries[6].via);
+    }
+
+    #[test]
+    fn test_ipc_d_356() {
+        assert_eq!(
+            project().to_ipc_d_356(0.001),
+            indoc! {"
+                C  IPC-D-356A netlist
+                P  UNITS CUST 1
+                P  DIM   N
+                P  NNAME1 A_VERY_LONG_NET_NAME
+                327VCC              R1    -1          A01X+002000Y+001000X1000Y0500R000 S1
+                327GND              R1    -2          A01X+004000Y+001000X1000Y0500R090 S1
+                327NNAME1                 -           A01X+030000Y+000000X1000Y0500R000 S0
+                327N/C                    -           A01X+040000Y+000000X1000Y0500R000 S0
+                327GND              U1    -3          A02X+005000Y+005000X0800Y0800R000 S2
+                317GND              J1    -1    D1000PA00X+010000Y+001000X1700Y1700R000 S2
+                317GND              VIA   -    MD0300PA00X+020000Y+005000X0600Y0600R000 S2
+                999
+            "}
+        );
+    }
+}
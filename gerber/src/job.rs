@@ -0,0 +1,355 @@
+//! Gerber job files
+//!
+//! A job file (`.gbrjob`) travels with the layers of a board and tells the
+//! fab what they are: the path, function and polarity of each file, the
+//! size of the board and the design rules it was laid out to. It is a JSON
+//! document in the format of the Gerber Job specification by Ucamco.
+//!
+//! Only the parts which can be derived from the layers, plus what the
+//! caller passes in [JobOptions], are written. Values are in millimeters.
+
+use crate::attribute::ProjectId;
+use crate::data::NM_PER_MM;
+use crate::decorate::{FilePolarity, GenerationSoftware};
+use crate::json_string;
+use crate::project::{board_size, copper_layers, file_function, file_polarity, GerberProject};
+
+/// What the job file says beyond the layers themselves
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct JobOptions {
+    /// The software writing the job file
+    ///
+    /// The specification requires this and the creation date. They are
+    /// left to the caller, so the same project always gives the same job
+    /// file.
+    pub generation_software: Option<GenerationSoftware>,
+
+    /// An ISO 8601 date and time, e.g. `2024-05-01T12:30:00+02:00`
+    pub creation_date: Option<String>,
+
+    /// The project, by default the `.ProjectId` of the first layer with a
+    /// valid one
+    pub project_id: Option<ProjectId>,
+
+    /// Thickness of the finished board in millimeters
+    pub board_thickness: Option<f64>,
+
+    pub design_rules: Vec<DesignRules>,
+}
+
+/// The minimum widths and clearances a set of layers was designed to, in
+/// millimeters, each left out when `None`
+#[derive(Clone, PartialEq, Debug)]
+pub struct DesignRules {
+    pub layers: RuleLayers,
+    pub pad_to_pad: Option<f64>,
+    pub pad_to_track: Option<f64>,
+    pub track_to_track: Option<f64>,
+    pub min_line_width: Option<f64>,
+    pub track_to_region: Option<f64>,
+    pub region_to_region: Option<f64>,
+}
+
+/// The copper layers [DesignRules] apply to
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum RuleLayers {
+    Outer,
+    Inner,
+    All,
+}
+
+impl GerberProject<'_> {
+    /// Write the job file of the project
+    ///
+    /// The number of copper layers counts the distinct layer numbers of
+    /// `.FileFunction,Copper` layers.
+    ///
+    /// ```
+    /// use gerber::job::JobOptions;
+    /// use gerber::project::GerberProject;
+    /// use gerber::GerberLayer;
+    ///
+    /// let top = "%FSLAX26Y26*%\n%MOMM*%\n%TF.FileFunction,Copper,L1,Top*%\n\
+    ///            %TF.FilePolarity,Positive*%\nM02*\n";
+    /// let mut project = GerberProject::new();
+    /// project.add("board-F_Cu.gbr", GerberLayer::parse(top).unwrap());
+    /// let job = project.to_gbrjob(&JobOptions::default());
+    /// assert!(job.contains(r#""FileFunction": "Copper,L1,Top""#));
+    /// assert!(job.contains(r#""LayerNumber": 1"#));
+    /// ```
+    pub fn to_gbrjob(&self, options: &JobOptions) -> String {
+        let images = self.images();
+
+        let header = object(
+            vec![
+                (
+                    "GenerationSoftware",
+                    options.generation_software.as_ref().map(|software| {
+                        object(
+                            vec![
+                                ("Vendor", Some(json_string(&software.vendor))),
+                                ("Application", Some(json_string(&software.application))),
+                                ("Version", software.version.as_deref().map(json_string)),
+                            ],
+                            2,
+                        )
+                    }),
+                ),
+                (
+                    "CreationDate",
+                    options.creation_date.as_deref().map(json_string),
+                ),
+            ],
+            1,
+        );
+
+        let project_id = options.project_id.clone().or_else(|| {
+            self.layers
+                .iter()
+                .find_map(|layer| layer.layer.project_id()?.ok())
+        });
+        let general = object(
+            vec![
+                (
+                    "ProjectId",
+                    project_id.map(|id| {
+                        object(
+                            vec![
+                                ("Name", Some(json_string(&id.name))),
+                                ("GUID", Some(json_string(&id.guid))),
+                                ("Revision", Some(json_string(&id.revision))),
+                            ],
+                            2,
+                        )
+                    }),
+                ),
+                (
+                    "Size",
+                    board_size(&images).map(|(x, y)| {
+                        object(vec![("X", Some(number(x))), ("Y", Some(number(y)))], 2)
+                    }),
+                ),
+                ("LayerNumber", Some(copper_layers(&images).to_string())),
+                ("BoardThickness", options.board_thickness.map(number)),
+            ],
+            1,
+        );
+
+        let rules: Vec<_> = options
+            .design_rules
+            .iter()
+            .map(|rules| {
+                let layers = match rules.layers {
+                    RuleLayers::Outer => "Outer",
+                    RuleLayers::Inner => "Inner",
+                    RuleLayers::All => "All",
+                };
+                object(
+                    vec![
+                        ("Layers", Some(json_string(layers))),
+                        ("PadToPad", rules.pad_to_pad.map(number)),
+                        ("PadToTrack", rules.pad_to_track.map(number)),
+                        ("TrackToTrack", rules.track_to_track.map(number)),
+                        ("MinLineWidth", rules.min_line_width.map(number)),
+                        ("TrackToRegion", rules.track_to_region.map(number)),
+                        ("RegionToRegion", rules.region_to_region.map(number)),
+                    ],
+                    2,
+                )
+            })
+            .collect();
+
+        let files = self
+            .layers
+            .iter()
+            .zip(&images)
+            .map(|(layer, image)| {
+                let polarity = file_polarity(image).map(|polarity| match polarity {
+                    FilePolarity::Positive => "Positive",
+                    FilePolarity::Negative => "Negative",
+                });
+                object(
+                    vec![
+                        ("Path", Some(json_string(&layer.path))),
+                        (
+                            "FileFunction",
+                            file_function(image).map(|function| json_string(&function.join(","))),
+                        ),
+                        ("FilePolarity", polarity.map(json_string)),
+                    ],
+                    2,
+                )
+            })
+            .collect();
+
+        let mut job = object(
+            vec![
+                ("Header", Some(header)),
+                ("GeneralSpecs", Some(general)),
+                ("DesignRules", (!rules.is_empty()).then(|| array(rules, 1))),
+                ("FilesAttributes", Some(array(files, 1))),
+            ],
+            0,
+        );
+        job.push('\n');
+        job
+    }
+}
+
+/// A JSON object of the fields which are set, at `depth` levels of
+/// indentation
+fn object(fields: Vec<(&str, Option<String>)>, depth: usize) -> String {
+    let fields = fields
+        .into_iter()
+        .filter_map(|(name, value)| Some(format!("{}: {}", json_string(name), value?)))
+        .collect();
+    block(('{', '}'), fields, depth)
+}
+
+/// A JSON array of the items, at `depth` levels of indentation
+fn array(items: Vec<String>, depth: usize) -> String {
+    block(('[', ']'), items, depth)
+}
+
+/// One item per line between the delimiters
+fn block((open, close): (char, char), items: Vec<String>, depth: usize) -> String {
+    if items.is_empty() {
+        return format!("{open}{close}");
+    }
+    let indent = "  ".repeat(depth + 1);
+    let items: Vec<_> = items.iter().map(|item| format!("{indent}{item}")).collect();
+    format!(
+        "{open}\n{}\n{}{close}",
+        items.join(",\n"),
+        "  ".repeat(depth)
+    )
+}
+
+/// A length in millimeters, rounded to the nanometer
+fn number(value: f64) -> String {
+    let nm = NM_PER_MM as f64;
+    format!("{}", (value * nm).round() / nm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    fn layer(attributes: &str, body: &str) -> GerberLayer<'static> {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{attributes}%ADD10C,0.1*%\nD10*\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().into_owned()
+    }
+
+    #[test]
+    fn test_job() {
+        let guid = "8b3a8e1c-2f70-4c2a-9c3d-5e9f0a1b2c3d";
+        let mut project = GerberProject::new();
+        for (path, function) in [
+            ("board-F_Cu.gbr", "Copper,L1,Top"),
+            ("board-B_Cu.gbr", "Copper,L2,Bot"),
+            ("board-B_Mask.gbr", "Soldermask,Bot"),
+        ] {
+            let attributes = format!(
+                "%TF.ProjectId,board,{guid},rev2*%\n\
+                 %TF.FileFunction,{function}*%\n%TF.FilePolarity,Positive*%\n"
+            );
+            project.add(path, layer(&attributes, "X1000000Y1000000D03*\n"));
+        }
+        project.add(
+            "board-Edge_Cuts.gbr",
+            layer(
+                "%TF.FileFunction,Profile,NP*%\n",
+                "G01*\nX0Y0D02*\nX49900000D01*\nY29900000D01*\nX0D01*\nY0D01*\n",
+            ),
+        );
+
+        let options = JobOptions {
+            generation_software: Some(GenerationSoftware {
+                vendor: "Acme".into(),
+                application: "Router \"Pro\"".into(),
+                version: None,
+            }),
+            creation_date: Some("2024-05-01T12:30:00+02:00".into()),
+            board_thickness: Some(1.6),
+            design_rules: vec![DesignRules {
+                layers: RuleLayers::Outer,
+                pad_to_pad: Some(0.2),
+                pad_to_track: None,
+                track_to_track: Some(0.15),
+                min_line_width: Some(0.1),
+                track_to_region: None,
+                region_to_region: None,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            project.to_gbrjob(&options),
+            indoc! {r#"
+                {
+                  "Header": {
+                    "GenerationSoftware": {
+                      "Vendor": "Acme",
+                      "Application": "Router \"Pro\""
+                    },
+                    "CreationDate": "2024-05-01T12:30:00+02:00"
+                  },
+                  "GeneralSpecs": {
+                    "ProjectId": {
+                      "Name": "board",
+                      "GUID": "8b3a8e1c-2f70-4c2a-9c3d-5e9f0a1b2c3d",
+                      "Revision": "rev2"
+                    },
+                    "Size": {
+                      "X": 50,
+                      "Y": 30
+                    },
+                    "LayerNumber": 2,
+                    "BoardThickness": 1.6
+                  },
+                  "DesignRules": [
+                    {
+                      "Layers": "Outer",
+                      "PadToPad": 0.2,
+                      "TrackToTrack": 0.15,
+                      "MinLineWidth": 0.1
+                    }
+                  ],
+                  "FilesAttributes": [
+                    {
+                      "Path": "board-F_Cu.gbr",
+                      "FileFunction": "Copper,L1,Top",
+                      "FilePolarity": "Positive"
+                    },
+                    {
+                      "Path": "board-B_Cu.gbr",
+                      "FileFunction": "Copper,L2,Bot",
+                      "FilePolarity": "Positive"
+                    },
+                    {
+                      "Path": "board-B_Mask.gbr",
+                      "FileFunction": "Soldermask,Bot",
+                      "FilePolarity": "Positive"
+                    },
+                    {
+                      "Path": "board-Edge_Cuts.gbr",
+                      "FileFunction": "Profile,NP"
+                    }
+                  ]
+                }
+            "#}
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(
+            GerberProject::new().to_gbrjob(&JobOptions::default()),
+            "{\n  \"Header\": {},\n  \"GeneralSpecs\": {\n    \"LayerNumber\": 0\n  },\n  \
+             \"FilesAttributes\": []\n}\n"
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Tokens for syntax highlighting
+//!
+//! [tokenize] splits a source into tokens without parsing it, so it never
+//! fails and works on incomplete or invalid text as it is being typed.
+//! The tokens cover the source without gaps, which makes them suitable for
+//! highlighting, and the `%` and `*` delimiters give editors enough
+//! structure for folding.
+
+use std::ops::Range;
+
+/// The kind of a [Token]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TokenKind {
+    /// A command code such as `G01`, `D10`, `M02` or `FS`
+    Code,
+
+    /// An X, Y, I or J coordinate with its value
+    Coordinate,
+
+    /// An attribute name, aperture template name or macro name
+    Name,
+
+    /// An attribute value
+    String,
+
+    /// A number in an aperture definition
+    Number,
+
+    /// Any other parameter of an extended command, e.g. `LAX26Y26`
+    Parameter,
+
+    /// `%`, `*`, `,` or the `X` separating numbers
+    Delimiter,
+
+    /// The text of a G04 comment or a macro comment primitive
+    Comment,
+
+    /// One or more CR and LF characters
+    LineEnding,
+
+    /// A character which cannot start a token
+    Unknown,
+}
+
+/// A token and its byte range in the source
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Token {
+    pub kind: TokenKind,
+    pub bytes: Range<usize>,
+}
+
+/// Split `src` into tokens
+///
+/// ```
+/// use gerber::lexer::{tokenize, TokenKind::*};
+///
+/// let kinds: Vec<_> = tokenize("X10Y20D01*").iter().map(|t| t.kind).collect();
+/// assert_eq!(kinds, [Coordinate, Coordinate, Code, Delimiter]);
+/// ```
+pub fn tokenize(src: &str) -> Vec<Token> {
+    let mut lexer = Lexer {
+        src,
+        position: 0,
+        tokens: Vec::new(),
+    };
+    while let Some(c) = lexer.rest().chars().next() {
+        match c {
+            '\r' | '\n' => lexer.line_ending(),
+            '%' => {
+                lexer.push(TokenKind::Delimiter, 1);
+                lexer.extended();
+            }
+            _ => lexer.word(),
+        }
+    }
+    lexer.tokens
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    position: usize,
+    tokens: Vec<Token>,
+}
+
+impl<'a> Lexer<'a> {
+    fn rest(&self) -> &'a str {
+        &self.src[self.position..]
+    }
+
+    /// Length in bytes of the start of the rest matching `f`
+    fn len_while(&self, f: impl Fn(char) -> bool) -> usize {
+        let rest = self.rest();
+        rest.find(|c| !f(c)).unwrap_or(rest.len())
+    }
+
+    /// Length in bytes of the start of the rest up to any of `stop`
+    fn len_until(&self, stop: &str) -> usize {
+        self.len_while(|c| !stop.contains(c))
+    }
+
+    fn push(&mut self, kind: TokenKind, len: usize) {
+        if len > 0 {
+            let bytes = self.position..self.position + len;
+            self.tokens.push(Token { kind, bytes });
+            self.position += len;
+        }
+    }
+
+    fn line_ending(&mut self) {
+        let len = self.len_while(|c| c == '\r' || c == '\n');
+        self.push(TokenKind::LineEnding, len);
+    }
+
+    /// A word command, up to its `*` or the end of the line
+    fn word(&mut self) {
+        while let Some(c) = self.rest().chars().next() {
+            match c {
+                '\r' | '\n' | '%' => return,
+                '*' => {
+                    self.push(TokenKind::Delimiter, 1);
+                    return;
+                }
+                _ if self.rest().starts_with("G04") => {
+                    self.push(TokenKind::Code, 3);
+                    let len = self.len_until("*%");
+                    self.push(TokenKind::Comment, len);
+                }
+                'A'..='Z' => {
+                    let kind = match c {
+                        'X' | 'Y' | 'I' | 'J' => TokenKind::Coordinate,
+                        _ => TokenKind::Code,
+                    };
+                    let value = self.len_after(1, |c| c.is_ascii_digit() || "+-.".contains(c));
+                    self.push(kind, 1 + value);
+                }
+                _ => self.push(TokenKind::Unknown, c.len_utf8()),
+            }
+        }
+    }
+
+    /// Length in bytes of the text matching `f` after the first `skip` bytes
+    fn len_after(&self, skip: usize, f: impl Fn(char) -> bool) -> usize {
+        let rest = &self.rest()[skip..];
+        rest.find(|c| !f(c)).unwrap_or(rest.len())
+    }
+
+    /// An extended command after its opening `%`, up to the closing `%`
+    fn extended(&mut self) {
+        let len = self.len_while(|c| c.is_ascii_uppercase()).min(2);
+        let code = &self.rest()[..len];
+        self.push(TokenKind::Code, len);
+        match code {
+            "TF" | "TA" | "TO" | "TD" => {
+                let len = self.len_until(",*%");
+                self.push(TokenKind::Name, len);
+                while self.rest().starts_with(',') {
+                    self.push(TokenKind::Delimiter, 1);
+                    let len = self.len_until(",*%");
+                    self.push(TokenKind::String, len);
+                }
+            }
+            "AD" => {
+                if self.rest().starts_with('D') {
+                    let len = self.len_after(1, |c| c.is_ascii_digit());
+                    self.push(TokenKind::Code, 1 + len);
+                }
+                let len = self.len_until(",*%");
+                self.push(TokenKind::Name, len);
+                if self.rest().starts_with(',') {
+                    self.push(TokenKind::Delimiter, 1);
+                    self.numbers();
+                }
+            }
+            "AM" => {
+                let len = self.len_until("*%");
+                self.push(TokenKind::Name, len);
+                self.macro_body();
+            }
+            _ => (),
+        }
+        self.rest_of_extended();
+    }
+
+    /// Numbers separated by `X`
+    fn numbers(&mut self) {
+        loop {
+            let len = self.len_while(|c| c.is_ascii_digit() || "+-.".contains(c));
+            self.push(TokenKind::Number, len);
+            if !self.rest().starts_with('X') {
+                return;
+            }
+            self.push(TokenKind::Delimiter, 1);
+        }
+    }
+
+    /// The blocks of an aperture macro, where primitive 0 is a comment
+    fn macro_body(&mut self) {
+        loop {
+            if self.rest().starts_with('*') {
+                self.push(TokenKind::Delimiter, 1);
+            }
+            self.line_ending();
+            if self.rest().is_empty() || self.rest().starts_with('%') {
+                return;
+            }
+            let len = self.len_until("*%");
+            let block = &self.rest()[..len];
+            let comment = block == "0" || block.starts_with("0 ");
+            let kind = if comment {
+                TokenKind::Comment
+            } else {
+                TokenKind::Parameter
+            };
+            self.push(kind, len);
+        }
+    }
+
+    fn rest_of_extended(&mut self) {
+        while let Some(c) = self.rest().chars().next() {
+            match c {
+                '%' => {
+                    self.push(TokenKind::Delimiter, 1);
+                    return;
+                }
+                '*' | ',' => self.push(TokenKind::Delimiter, 1),
+                '\r' | '\n' => self.line_ending(),
+                _ => {
+                    let len = self.len_until("*,%\r\n");
+                    self.push(TokenKind::Parameter, len);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenKind::*;
+
+    fn tokens(src: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(src)
+            .into_iter()
+            .map(|token| (token.kind, &src[token.bytes]))
+            .collect()
+    }
+
+    #[test]
+    fn test_word_commands() {
+        assert_eq!(
+            tokens("G04 a comment*\r\nD10*\nX-10Y+20I5J0D01*"),
+            [
+                (Code, "G04"),
+                (Comment, " a comment"),
+                (Delimiter, "*"),
+                (LineEnding, "\r\n"),
+                (Code, "D10"),
+                (Delimiter, "*"),
+                (LineEnding, "\n"),
+                (Coordinate, "X-10"),
+                (Coordinate, "Y+20"),
+                (Coordinate, "I5"),
+                (Coordinate, "J0"),
+                (Code, "D01"),
+                (Delimiter, "*"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extended_commands() {
+        assert_eq!(
+            tokens("%FSLAX26Y26*%%TF.Part,Single*%"),
+            [
+                (Delimiter, "%"),
+                (Code, "FS"),
+                (Parameter, "LAX26Y26"),
+                (Delimiter, "*"),
+                (Delimiter, "%"),
+                (Delimiter, "%"),
+                (Code, "TF"),
+                (Name, ".Part"),
+                (Delimiter, ","),
+                (String, "Single"),
+                (Delimiter, "*"),
+                (Delimiter, "%"),
+            ]
+        );
+        assert_eq!(
+            tokens("%ADD10R,0.5X1*%"),
+            [
+                (Delimiter, "%"),
+                (Code, "AD"),
+                (Code, "D10"),
+                (Name, "R"),
+                (Delimiter, ","),
+                (Number, "0.5"),
+                (Delimiter, "X"),
+                (Number, "1"),
+                (Delimiter, "*"),
+                (Delimiter, "%"),
+            ]
+        );
+        assert_eq!(
+            tokens("%AMBOX*\n0 a box*\n21,1,$1,$2,0,0,0*%"),
+            [
+                (Delimiter, "%"),
+                (Code, "AM"),
+                (Name, "BOX"),
+                (Delimiter, "*"),
+                (LineEnding, "\n"),
+                (Comment, "0 a box"),
+                (Delimiter, "*"),
+                (LineEnding, "\n"),
+                (Parameter, "21,1,$1,$2,0,0,0"),
+                (Delimiter, "*"),
+                (Delimiter, "%"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_incomplete() {
+        assert_eq!(
+            tokens("%TF.Pa"),
+            [(Delimiter, "%"), (Code, "TF"), (Name, ".Pa")]
+        );
+        assert_eq!(tokens("X1?"), [(Coordinate, "X1"), (Unknown, "?")]);
+    }
+
+    #[test]
+    fn test_covers_sample_files() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "gbr") {
+                continue;
+            }
+            let src = std::fs::read_to_string(path).unwrap();
+            let tokens = tokenize(&src);
+            let mut end = 0;
+            for token in &tokens {
+                assert_eq!(token.bytes.start, end);
+                assert_ne!(token.kind, Unknown, "{:?}", &src[token.bytes.clone()]);
+                end = token.bytes.end;
+            }
+            assert_eq!(end, src.len());
+        }
+    }
+}
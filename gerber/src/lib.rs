@@ -3,7 +3,35 @@
 //! ## Current Limitations
 //!
 //! * Does not implement the full specification
-//! * Does not expand unicode escape sequences
+//!
+//! ## Features
+//!
+//! * `arbitrary` - implements [arbitrary::Arbitrary] for the command model,
+//!   for use in structure-aware fuzz targets
+//! * `boolean` - unites the objects of a layer into polygons with
+//!   [i_overlay](https://crates.io/crates/i_overlay), and the analyses and
+//!   raster rendering built on the same geometry
+//! * `rayon` - converts objects to geometry and renders bands of rows on
+//!   all cores with [rayon](https://crates.io/crates/rayon), with the same
+//!   results as without
+//! * `serde` - implements `serde::Serialize` for the command model
+//! * `testutil` - exposes [proptest](https://crates.io/crates/proptest)
+//!   strategies which generate valid Gerber files
+//!
+//! JavaScript and Python bindings are the `gerber-wasm` and `gerber-python`
+//! crates of the workspace, so only they are built as dynamic libraries.
+//!
+//! ## Deterministic Output
+//!
+//! The same source gives the same output byte for byte, whatever the
+//! features, platform or number of threads:
+//!
+//! * objects, diagnostics and findings follow the order of the commands
+//! * aperture dictionaries iterate by D code or defining command, and
+//!   attribute maps by name, so serialized maps don't depend on the order
+//!   of `%AD`, `%TA` or `%TO` commands
+//! * hash maps are only used for lookups, never iterated into output
+//! * parallel work is collected in the order of its items
 //!
 //! ## Implementation Notes
 //!
@@ -28,23 +56,87 @@
 //!   [Ucamco Downloads](https://www.ucamco.com/en/gerber/downloads)
 //! [^2]: Groan... I didn't notice the pun until later.
 
+pub mod aperture;
 pub mod attribute;
 pub mod command;
+pub mod component;
+pub mod conformance;
+#[cfg(feature = "boolean")]
+pub mod copper;
 pub mod data;
+pub mod decorate;
+pub mod document;
+pub mod equivalence;
+pub mod fabrication;
+pub mod fiducial;
+#[cfg(feature = "boolean")]
+pub mod gds;
+#[cfg(feature = "boolean")]
+pub mod geojson;
+mod grammar;
+pub mod image;
+#[cfg(feature = "boolean")]
+pub mod ipc356;
+pub mod job;
+pub mod lexer;
+pub mod lint;
+pub mod macros;
+pub mod memory;
+pub mod merge;
+#[cfg(feature = "boolean")]
+pub mod mesh;
+pub mod modal;
+pub mod modernize;
+pub mod object;
+#[cfg(feature = "boolean")]
+mod parallel;
+pub mod paste;
+pub mod primitives;
+pub mod progress;
+pub mod project;
+#[cfg(feature = "boolean")]
+pub mod raster;
+pub mod redundant;
+pub mod registration;
+pub mod repair;
+pub mod reparse;
+pub mod revision;
+pub mod simplify;
+pub mod slot;
+pub mod snap;
+pub mod span;
+pub mod splice;
+pub mod statistics;
+pub mod template;
+pub mod testpoint;
+#[cfg(feature = "testutil")]
+pub mod testutil;
+#[cfg(feature = "boolean")]
+pub mod thermal;
+#[cfg(feature = "boolean")]
+pub mod thumbnail;
+pub mod validate;
+pub mod writer;
 
-use attribute::FileAttributeName;
-use command::{extended_command, simple_word_command, word_command};
+pub use document::{GerberDoc, ParseOptions};
+
+use aperture::ApertureTemplate;
+use attribute::{ApertureAttributeName, FileAttributeName, ObjectAttributeName};
+use command::{extended_command, simple_word_command, word_command, Commands};
+use span::{Span, Spanned};
+use std::ops::{ControlFlow, Range};
 use thiserror::Error;
 
 use crate::command::Command::{self, *};
 use crate::data::*;
+use crate::grammar::*;
 use nom::character::complete::char;
 use nom::{
     branch::alt,
-    bytes::complete::tag,
+    bytes::complete::{is_not, tag},
     character::complete::{anychar, line_ending},
-    combinator::{all_consuming, map, map_res, opt},
-    multi::many0,
+    combinator::{map, map_res, opt, value, verify},
+    multi::{many0, many1, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
 };
 
@@ -57,70 +149,240 @@ pub enum GerberError {
 
     #[error("parse error: {0}")]
     ParseError(String),
+
+    #[error("invalid aperture identifier {0}, expected D10 or above")]
+    ApertureId(String),
+
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error("invalid {0} attribute value {1:?}")]
+    Attribute(String, String),
+
+    #[error("lint configuration: {0}")]
+    Lint(String),
+
+    #[error("aperture macro {0}: {1}")]
+    Macro(String, String),
+
+    #[error("invalid polygon of {0} vertices, expected 3 to 12")]
+    PolygonVertices(f64),
+}
+
+/// A parsed layer
+///
+/// Strings borrow from the source, or once [owned](GerberLayer::into_owned)
+/// are shared, so a clone copies the commands but none of their strings.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GerberLayer<'a> {
+    commands: Vec<Command<'a>>,
+
+    /// Location in the source of each command
+    #[cfg_attr(feature = "serde", serde(skip))]
+    spans: Vec<Span>,
+}
+
+impl<'a> GerberLayer<'a> {
+    pub fn parse(src: &'a str) -> Result<Self, GerberError> {
+        let capacity = command_capacity(src);
+        let mut commands = Vec::with_capacity(capacity);
+        let mut ranges = Vec::with_capacity(capacity);
+        each_command(src, |command, bytes| {
+            commands.push(command);
+            ranges.push(bytes);
+            ControlFlow::Continue(())
+        })?;
+        let spans = span::spans(src, ranges);
+        Ok(GerberLayer { commands, spans })
+    }
+
+    /// Convert into a layer which does not borrow from the source
+    pub fn into_owned(self) -> GerberLayer<'static> {
+        GerberLayer {
+            commands: self.commands.into_iter().map(Command::into_owned).collect(),
+            spans: self.spans,
+        }
+    }
+
+    /// The commands in the layer, in file order
+    pub fn commands(&self) -> &[Command<'a>] {
+        &self.commands
+    }
+
+    /// Location in the source of the command at `index`
+    pub fn span(&self, index: usize) -> Option<&Span> {
+        self.spans.get(index)
+    }
+
+    /// The commands in the layer together with their location in the source
+    pub fn spanned(&self) -> impl Iterator<Item = Spanned<&Command<'a>>> + '_ {
+        self.commands
+            .iter()
+            .zip(&self.spans)
+            .map(|(node, span)| Spanned {
+                node,
+                span: span.clone(),
+            })
+    }
+
+    /// Evaluate the layer into graphical objects
+    pub fn image(&self) -> image::Image {
+        image::evaluate(&self.commands)
+    }
+
+    /// Evaluate the layer into graphical objects, with choices for
+    /// constructs whose meaning varies between tools
+    pub fn image_with_options(&self, options: &image::EvaluateOptions) -> image::Image {
+        image::evaluate_with_options(&self.commands, options)
+    }
+
+    /// Evaluate the layer one object at a time, in paint order, see
+    /// [image::objects]
+    pub fn objects(&self, options: &image::EvaluateOptions) -> image::Objects<'_> {
+        image::objects(&self.commands, options)
+    }
+
+    /// Locations in the source of the commands which created `object`
+    pub fn source_spans<'s>(&'s self, object: &image::Object) -> impl Iterator<Item = &'s Span> {
+        let source = &object.source;
+        source
+            .commands
+            .clone()
+            .chain(source.aperture)
+            .filter_map(|index| self.spans.get(index))
+    }
+
+    /// Classify the layer by the revision of the specification it relies on
+    pub fn revision(&self) -> revision::Revision {
+        revision::Revision::detect(&self.commands)
+    }
+
+    /// Check the layer against the semantic rules of the specification
+    pub fn validate(&self) -> Vec<validate::Diagnostic> {
+        validate::validate(&self.commands)
+    }
+
+    /// The project the layer belongs to, from `.ProjectId`
+    ///
+    /// `None` without the attribute, or [GerberError::Attribute] if its
+    /// value isn't a name, GUID and revision. A later `.ProjectId` replaces
+    /// an earlier one.
+    pub fn project_id(&self) -> Option<Result<attribute::ProjectId, GerberError>> {
+        let (_, values) = self
+            .commands
+            .file_attributes()
+            .filter(|(name, _)| **name == FileAttributeName::ProjectId)
+            .last()?;
+        let fields: Vec<_> = values.iter().map(EscapedString::unescape).collect();
+        Some(attribute::ProjectId::from_fields(&fields))
+    }
+}
+
+/// A JSON string literal
+pub(crate) fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
 }
 
-#[derive(Debug)]
-pub struct GerberLayer {
-    commands: Vec<Command>,
+/// An upper bound on the number of commands in `src`, for allocating the
+/// command list once instead of growing it
+///
+/// Every command ends with `*`, and counting them is a cheap pass over the
+/// bytes. Macros and stray asterisks in comments make it an overestimate.
+pub(crate) fn command_capacity(src: &str) -> usize {
+    src.bytes().filter(|&byte| byte == b'*').count()
 }
 
-impl GerberLayer {
-    pub fn parse(src: &str) -> Result<Self, GerberError> {
-        let (_, commands) = gerber(src).map_err(|e| GerberError::ParseError(format!("{:?}", e)))?;
-        Ok(GerberLayer { commands })
+/// Parse `src` one command at a time, passing each command and its byte
+/// range to `f` without collecting them
+///
+/// Returns [GerberError::Cancelled] if `f` breaks.
+pub(crate) fn each_command<'a>(
+    src: &'a str,
+    mut f: impl FnMut(Command<'a>, Range<usize>) -> ControlFlow<()>,
+) -> Result<(), GerberError> {
+    let error = |message: &str| GerberError::ParseError(message.to_string());
+    let offset = |rest: &str| src.len() - rest.len();
+
+    let mut input = src;
+    loop {
+        input = input.trim_start_matches(['\r', '\n']);
+        if input.is_empty() {
+            return Err(error("missing end of file"));
+        }
+        let start = offset(input);
+        let eof = end_of_file(input);
+        let done = eof.is_ok();
+        let (rest, command) = eof
+            .or_else(|_| command(input))
+            .map_err(|e| GerberError::ParseError(format!("{:?}", e)))?;
+        input = rest;
+        if done && !input.trim_start_matches(['\r', '\n']).is_empty() {
+            return Err(error("content after end of file"));
+        }
+        if f(command, start..offset(rest)).is_break() {
+            return Err(GerberError::Cancelled);
+        }
+        if done {
+            return Ok(());
+        }
     }
 }
 
-/// Parse a gerber file into a list of [Command]s
-fn gerber(input: &str) -> IResult<Vec<Command>> {
-    map(
-        all_consuming(pair(
-            many0(delimited(
-                many0(line_ending),
-                alt((
-                    comment,
-                    mode,
-                    format_specification,
-                    aperture_define,
-                    // aperture_macro,
-                    set_current_aperture,
-                    arc_init,
-                    set_linear,
-                    set_cw_circular,
-                    set_ccw_circular,
-                    plot_operation,
-                    move_operation,
-                    flash_operation,
-                    load_polarity,
-                    // load_mirroring,
-                    // load_rotation,
-                    // load_scaling,
-                    // region_statement,
-                    // ab_statement,
-                    // sr_statement,
-                    attribute_on_file,
-                    // attribute_on_aperture,
-                    // attribute_on_object,
-                    // attribute_delete,
-                )),
-                many0(line_ending),
-            )),
-            terminated(end_of_file, many0(line_ending)),
-        )),
-        // include the EndOfFile command in the list
-        |(mut commands, eof)| {
-            commands.push(eof);
-            commands
-        },
-    )(input)
+/// Parse any single command except [EndOfFile]
+pub(crate) fn command(input: &str) -> IResult<'_, Command<'_>> {
+    alt((
+        comment,
+        mode,
+        format_specification,
+        aperture_define,
+        aperture_macro,
+        set_current_aperture,
+        arc_init,
+        single_quadrant,
+        set_linear,
+        set_cw_circular,
+        set_ccw_circular,
+        plot_operation,
+        move_operation,
+        flash_operation,
+        alt((load_polarity, load_mirroring, load_rotation, load_scaling)),
+        region_statement,
+        // ab_statement,
+        // sr_statement,
+        attribute_on_file,
+        attribute_on_aperture,
+        attribute_on_object,
+        attribute_delete,
+    ))(input)
 }
 
-fn comment(input: &str) -> IResult<Command> {
-    word_command("G04", string, |_| Command::Comment)(input)
+fn comment(input: &str) -> IResult<'_, Command<'_>> {
+    word_command("G04", string, Command::Comment)(input)
 }
 
-fn mode(input: &str) -> IResult<Command> {
-    extended_command("MO", alt((tag("MM"), tag("IN"))), |_| Mode)(input)
+fn mode(input: &str) -> IResult<'_, Command<'_>> {
+    extended_command(
+        "MO",
+        alt((
+            value(Unit::Millimeters, tag("MM")),
+            value(Unit::Inches, tag("IN")),
+        )),
+        Mode,
+    )(input)
 }
 
 fn coordinate_digits(input: &str) -> IResult<u8> {
@@ -135,26 +397,32 @@ fn coordinate_digits(input: &str) -> IResult<u8> {
     })(input)
 }
 
-fn format_specification(input: &str) -> IResult<Command> {
+fn format_specification(input: &str) -> IResult<'_, Command<'_>> {
     extended_command(
         "FSLAX",
         separated_pair(coordinate_digits, tag("Y"), coordinate_digits),
-        |(_, _)| FormatSpecification,
+        |(x, y)| {
+            let format = |integer| CoordinateFormat {
+                integer,
+                decimal: 6,
+            };
+            FormatSpecification(format(x), format(y))
+        },
     )(input)
 }
 
-fn aperture_define_circle(input: &str) -> IResult<Command> {
+fn aperture_define_circle(input: &str) -> IResult<'_, Command<'_>> {
     extended_command(
         "AD",
         pair(
             terminated(aperture_identifier, pair(tag("C,"), many0(line_ending))),
             pair(decimal, opt(preceded(char('X'), decimal))),
         ),
-        |(_, _)| ApertureDefine,
+        |(id, (diameter, hole))| ApertureDefine(id, ApertureTemplate::Circle { diameter, hole }),
     )(input)
 }
 
-fn aperture_define_rectangle(input: &str) -> IResult<Command> {
+fn aperture_define_rectangle(input: &str) -> IResult<'_, Command<'_>> {
     extended_command(
         "AD",
         pair(
@@ -164,11 +432,11 @@ fn aperture_define_rectangle(input: &str) -> IResult<Command> {
                 opt(preceded(char('X'), decimal)),
             ),
         ),
-        |(_, _)| ApertureDefine,
+        |(id, ((x, y), hole))| ApertureDefine(id, ApertureTemplate::Rectangle { x, y, hole }),
     )(input)
 }
 
-fn aperture_define_obround(input: &str) -> IResult<Command> {
+fn aperture_define_obround(input: &str) -> IResult<'_, Command<'_>> {
     extended_command(
         "AD",
         pair(
@@ -178,46 +446,74 @@ fn aperture_define_obround(input: &str) -> IResult<Command> {
                 opt(preceded(char('X'), decimal)),
             ),
         ),
-        |(_, _)| ApertureDefine,
+        |(id, ((x, y), hole))| ApertureDefine(id, ApertureTemplate::Obround { x, y, hole }),
     )(input)
 }
 
-fn aperture_define_polygon(input: &str) -> IResult<Command> {
+/// The number of vertices of a polygon aperture, an integer from 3 to 12
+fn polygon_vertices(input: &str) -> IResult<'_, f64> {
+    verify(decimal, |vertices: &f64| {
+        (3.0..=12.0).contains(vertices) && vertices.fract() == 0.0
+    })(input)
+}
+
+fn aperture_define_polygon(input: &str) -> IResult<'_, Command<'_>> {
     extended_command(
         "AD",
         pair(
             terminated(aperture_identifier, pair(tag("P,"), many0(line_ending))),
             pair(
-                separated_pair(decimal, char('X'), decimal),
+                separated_pair(decimal, char('X'), polygon_vertices),
                 opt(preceded(
                     char('X'),
                     pair(decimal, opt(preceded(char('X'), decimal))),
                 )),
             ),
         ),
-        |(_, _)| ApertureDefine,
+        |(id, ((diameter, vertices), rest))| {
+            let (rotation, hole) = match rest {
+                Some((rotation, hole)) => (Some(rotation), hole),
+                None => (None, None),
+            };
+            ApertureDefine(
+                id,
+                ApertureTemplate::Polygon {
+                    diameter,
+                    vertices,
+                    rotation,
+                    hole,
+                },
+            )
+        },
     )(input)
 }
 
-fn aperture_define_macro(input: &str) -> IResult<Command> {
+fn aperture_define_macro(input: &str) -> IResult<'_, Command<'_>> {
     map(
         delimited(
             tag("%AD"),
             tuple((
                 aperture_identifier,
-                name,
-                opt(preceded(
-                    char(','),
-                    pair(decimal, opt(preceded(char('X'), decimal))),
-                )),
+                // the names of the standard templates are reserved, so an
+                // invalid standard aperture isn't read as a macro
+                verify(name, |name: &str| !matches!(name, "C" | "R" | "O" | "P")),
+                opt(preceded(char(','), separated_list1(char('X'), decimal))),
             )),
             tag("*%"),
         ),
-        |(_, _, _)| ApertureDefine,
+        |(id, name, parameters)| {
+            ApertureDefine(
+                id,
+                ApertureTemplate::Macro {
+                    name: name.into(),
+                    parameters: parameters.unwrap_or_default(),
+                },
+            )
+        },
     )(input)
 }
 
-fn aperture_define(input: &str) -> IResult<Command> {
+fn aperture_define(input: &str) -> IResult<'_, Command<'_>> {
     alt((
         aperture_define_circle,
         aperture_define_rectangle,
@@ -227,124 +523,182 @@ fn aperture_define(input: &str) -> IResult<Command> {
     ))(input)
 }
 
-fn aperture_macro(input: &str) -> IResult<Command> {
-    todo!()
+/// `%AM` and the statements of its body
+///
+/// Each word must be a comment, a variable definition or a primitive
+/// with valid expressions, see [macros::ApertureMacro], so the body of a
+/// parsed command can always be evaluated. Words may be split over lines.
+fn aperture_macro(input: &str) -> IResult<'_, Command<'_>> {
+    map(
+        delimited(
+            tag("%AM"),
+            pair(terminated(name, tag("*")), many1(macro_word)),
+            pair(many0(line_ending), tag("%")),
+        ),
+        |(name, statements)| ApertureMacro(name.into(), statements),
+    )(input)
 }
 
-fn set_current_aperture(input: &str) -> IResult<Command> {
-    map(terminated(aperture_identifier, tag("*")), |_| {
-        SetCurrentAperture
-    })(input)
+/// A word of a macro body and the `*` ending it
+fn macro_word(input: &str) -> IResult<'_, macros::MacroStatement> {
+    preceded(
+        many0(line_ending),
+        terminated(map_res(is_not("*%"), macros::statement), tag("*")),
+    )(input)
+}
+
+fn set_current_aperture(input: &str) -> IResult<'_, Command<'_>> {
+    map(
+        terminated(aperture_identifier, tag("*")),
+        SetCurrentAperture,
+    )(input)
 }
 
-fn arc_init(input: &str) -> IResult<Command> {
+fn arc_init(input: &str) -> IResult<'_, Command<'_>> {
     simple_word_command("G75", ArcInit)(input)
 }
 
-fn set_linear(input: &str) -> IResult<Command> {
+fn single_quadrant(input: &str) -> IResult<'_, Command<'_>> {
+    simple_word_command("G74", SingleQuadrant)(input)
+}
+
+fn set_linear(input: &str) -> IResult<'_, Command<'_>> {
     simple_word_command("G01", SetLinear)(input)
 }
 
-fn set_cw_circular(input: &str) -> IResult<Command> {
+fn set_cw_circular(input: &str) -> IResult<'_, Command<'_>> {
     simple_word_command("G02", SetCWCircular)(input)
 }
 
-fn set_ccw_circular(input: &str) -> IResult<Command> {
+fn set_ccw_circular(input: &str) -> IResult<'_, Command<'_>> {
     simple_word_command("G03", SetCCWCircular)(input)
 }
 
-fn plot_operation(input: &str) -> IResult<Command> {
+fn coordinates(input: &str) -> IResult<'_, Coordinates> {
     map(
-        terminated(
-            tuple((
-                opt(preceded(tag("X"), integer)),
-                opt(preceded(tag("Y"), integer)),
-                opt(pair(
-                    preceded(tag("I"), integer),
-                    preceded(tag("J"), integer),
-                )),
-            )),
-            tag("D01*"),
+        pair(
+            opt(preceded(tag("X"), coordinate)),
+            opt(preceded(tag("Y"), coordinate)),
         ),
-        |_| Plot,
+        |(x, y)| Coordinates { x, y },
     )(input)
 }
 
-fn move_operation(input: &str) -> IResult<Command> {
+fn plot_operation(input: &str) -> IResult<'_, Command<'_>> {
     map(
-        terminated(
-            pair(
-                opt(preceded(tag("X"), integer)),
-                opt(preceded(tag("Y"), integer)),
-            ),
-            tag("D02*"),
-        ),
-        |_| Move,
+        terminated(pair(coordinates, offset), tag("D01*")),
+        |(coordinates, offset)| Plot(coordinates, offset),
     )(input)
 }
 
-fn flash_operation(input: &str) -> IResult<Command> {
+/// The I and J arc center offsets, either of which may be left out as
+/// zero, or `None` without both
+fn offset(input: &str) -> IResult<'_, Option<Offset>> {
     map(
-        terminated(
-            pair(
-                opt(preceded(tag("X"), integer)),
-                opt(preceded(tag("Y"), integer)),
-            ),
-            tag("D03*"),
+        pair(
+            opt(preceded(tag("I"), coordinate)),
+            opt(preceded(tag("J"), coordinate)),
         ),
-        |_| Flash,
+        |offset| match offset {
+            (None, None) => None,
+            (i, j) => Some(Offset {
+                i: i.unwrap_or(0),
+                j: j.unwrap_or(0),
+            }),
+        },
     )(input)
 }
 
-fn load_polarity(input: &str) -> IResult<Command> {
-    extended_command("LP", alt((tag("C"), tag("D"))), |_| LoadPolarity)(input)
+fn move_operation(input: &str) -> IResult<'_, Command<'_>> {
+    map(terminated(coordinates, tag("D02*")), Move)(input)
 }
 
-fn load_mirroring(input: &str) -> IResult<Command> {
-    todo!()
+fn flash_operation(input: &str) -> IResult<'_, Command<'_>> {
+    map(terminated(coordinates, tag("D03*")), Flash)(input)
 }
 
-fn load_rotation(input: &str) -> IResult<Command> {
-    todo!()
+fn load_polarity(input: &str) -> IResult<'_, Command<'_>> {
+    extended_command(
+        "LP",
+        alt((
+            value(Polarity::Clear, tag("C")),
+            value(Polarity::Dark, tag("D")),
+        )),
+        LoadPolarity,
+    )(input)
 }
 
-fn load_scaling(input: &str) -> IResult<Command> {
-    todo!()
+fn load_mirroring(input: &str) -> IResult<'_, Command<'_>> {
+    extended_command(
+        "LM",
+        alt((
+            value(Mirroring::None, tag("N")),
+            // XY before X, which is its prefix
+            value(Mirroring::XY, tag("XY")),
+            value(Mirroring::X, tag("X")),
+            value(Mirroring::Y, tag("Y")),
+        )),
+        LoadMirroring,
+    )(input)
 }
 
-fn region_statement(input: &str) -> IResult<Command> {
-    todo!()
+fn load_rotation(input: &str) -> IResult<'_, Command<'_>> {
+    extended_command("LR", map(decimal, Rotation), LoadRotation)(input)
+}
+
+fn load_scaling(input: &str) -> IResult<'_, Command<'_>> {
+    extended_command("LS", map(unsigned_decimal, Scaling), LoadScaling)(input)
+}
+
+fn region_statement(input: &str) -> IResult<'_, Command<'_>> {
+    alt((
+        simple_word_command("G36", StartRegion),
+        simple_word_command("G37", EndRegion),
+    ))(input)
 }
 
-fn ab_statement(input: &str) -> IResult<Command> {
+fn ab_statement(input: &str) -> IResult<'_, Command<'_>> {
     todo!()
 }
 
-fn sr_statement(input: &str) -> IResult<Command> {
+fn sr_statement(input: &str) -> IResult<'_, Command<'_>> {
     todo!()
 }
 
-fn attribute_on_file(input: &str) -> IResult<Command> {
+fn attribute_on_file(input: &str) -> IResult<'_, Command<'_>> {
     extended_command(
         "TF",
         pair(FileAttributeName::parse, many0(preceded(tag(","), field))),
-        |_| AttributeOnFile,
+        |(name, values)| AttributeOnFile(name, values),
     )(input)
 }
 
-fn attribute_on_aperture(input: &str) -> IResult<Command> {
-    todo!()
+fn attribute_on_aperture(input: &str) -> IResult<'_, Command<'_>> {
+    extended_command(
+        "TA",
+        pair(
+            ApertureAttributeName::parse,
+            many0(preceded(tag(","), field)),
+        ),
+        |(name, values)| AttributeOnAperture(name, values),
+    )(input)
 }
 
-fn attribute_on_object(input: &str) -> IResult<Command> {
-    todo!()
+fn attribute_on_object(input: &str) -> IResult<'_, Command<'_>> {
+    extended_command(
+        "TO",
+        pair(ObjectAttributeName::parse, many0(preceded(tag(","), field))),
+        |(name, values)| AttributeOnObject(name, values),
+    )(input)
 }
 
-fn attribute_delete(input: &str) -> IResult<Command> {
-    todo!()
+fn attribute_delete(input: &str) -> IResult<'_, Command<'_>> {
+    extended_command("TD", opt(name), |name| {
+        AttributeDelete(name.map(SharedStr::Borrowed))
+    })(input)
 }
 
-fn end_of_file(input: &str) -> IResult<Command> {
+pub(crate) fn end_of_file(input: &str) -> IResult<'_, Command<'_>> {
     simple_word_command("M02", EndOfFile)(input)
 }
 
@@ -355,8 +709,8 @@ mod test {
 
     #[test]
     fn test_example() {
-        assert_eq!(
-            // gerber(indoc! {"
+        let doc = GerberDoc::parse(
+            // indoc! {"
             //     G04 Different command styles*
             //     %FSLAX26Y26*%
             //     %MOMM*%
@@ -375,27 +729,283 @@ mod test {
             //     D11*
             //     X0Y2000000D03*
             //     M02*
-            // "}),
-            gerber(indoc! {"
+            // "},
+            indoc! {"
                 G04 Different command styles*
                 %FSLAX26Y26*%
                 %MOMM*%
                 M02*
-            "}),
-            Ok(("", vec![Comment, FormatSpecification, Mode, EndOfFile,]))
+            "},
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            doc.commands(),
+            [
+                Comment(EscapedString::new_unescaped(" Different command styles")),
+                FormatSpecification(
+                    CoordinateFormat {
+                        integer: 2,
+                        decimal: 6
+                    },
+                    CoordinateFormat {
+                        integer: 2,
+                        decimal: 6
+                    }
+                ),
+                Mode(Unit::Millimeters),
+                EndOfFile,
+            ]
         );
+        assert_eq!(doc.unit, Some(Unit::Millimeters));
+        assert!(doc.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_spans() {
+        let src = "%FSLAX26Y26*%\r\n%MOMM*%\nM02*\n";
+        let layer = GerberLayer::parse(src).unwrap();
+        let bytes = |index| layer.span(index).map(|span| span.bytes.clone());
+        assert_eq!(bytes(0), Some(0..13));
+        assert_eq!(bytes(1), Some(15..22));
+        assert_eq!(bytes(2), Some(23..27));
+        assert_eq!(bytes(3), None);
+
+        let lines: Vec<_> = layer.spanned().map(|spanned| spanned.span.line).collect();
+        assert_eq!(lines, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_parse_capacity() {
+        let src = "G04 note*\n%FSLAX26Y26*%\n%MOMM*%\nM02*\n";
+        assert_eq!(command_capacity(src), 4);
+        let layer = GerberLayer::parse(src).unwrap();
+        assert_eq!(layer.commands.len(), 4);
+        assert_eq!(layer.commands.capacity(), 4);
+
+        assert!(GerberLayer::parse("%MOMM*%\n").is_err());
+        assert!(GerberLayer::parse("M02*\nG04 after*\n").is_err());
+    }
+
+    #[test]
+    fn test_clone_shares_strings() {
+        let src = "G04 note*\n%FSLAX26Y26*%\n%TO.N,GND*%\nM02*\n";
+        let layer = GerberLayer::parse(src).unwrap().into_owned();
+        let clone = layer.clone();
+        assert_eq!(clone.commands, layer.commands);
+
+        let strings = |layer: &GerberLayer| match &layer.commands[..3] {
+            [Comment(comment), _, AttributeOnObject(_, values)] => {
+                [comment.raw().as_ptr(), values[0].raw().as_ptr()]
+            }
+            _ => panic!("unexpected commands"),
+        };
+        assert_eq!(strings(&clone), strings(&layer));
+    }
+
+    /// Apertures and attributes defined out of order, with nets shared
+    /// between objects and a clear object
+    const UNORDERED: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %TF.Part,Single*%
+        %TF.FileFunction,Copper,L1,Top*%
+        %TA.AperFunction,SMDPad,CuDef*%
+        %ADD11R,1X0.5*%
+        %TD.AperFunction*%
+        %ADD10C,0.2*%
+        D11*
+        %TO.P,R1,1*%
+        %TO.N,VCC*%
+        X0Y0D03*
+        %TO.N,GND*%
+        %TO.C,R2*%
+        X2000000Y0D03*
+        %TD*%
+        D10*
+        X0Y0D02*
+        X2000000Y1000000D01*
+        %LPC*%
+        X1000000Y500000D03*
+        M02*
+    "};
+
+    #[test]
+    fn test_deterministic_output() {
+        let layer = GerberLayer::parse(UNORDERED).unwrap();
+        let image = layer.image();
+        let names: Vec<_> = image.file_attributes.keys().map(AsRef::as_ref).collect();
+        assert_eq!(names, [".FileFunction", ".Part"]);
+        let names: Vec<_> = image.objects[1]
+            .attributes
+            .object
+            .keys()
+            .map(AsRef::as_ref)
+            .collect();
+        assert_eq!(names, [".C", ".N", ".P"]);
+        let statistics = statistics::Statistics::from_source(UNORDERED).unwrap();
+        let ids: Vec<_> = statistics
+            .apertures
+            .keys()
+            .map(|id| id.to_string())
+            .collect();
+        assert_eq!(ids, ["D10", "D11"]);
+
+        // hash maps are seeded afresh each time, so repeated runs would
+        // differ if any were iterated into the output
+        let outputs = || {
+            let layer = GerberLayer::parse(UNORDERED).unwrap();
+            [
+                format!("{:?}", layer.image()),
+                format!("{:?}", statistics::Statistics::from_source(UNORDERED)),
+                format!("{:?}", layer.validate()),
+                format!("{:?}", layer.redundant_flashes()),
+                conformance::report(UNORDERED).to_string(),
+            ]
+        };
+        let first = outputs();
+        for _ in 0..8 {
+            assert_eq!(outputs(), first);
+        }
+    }
+
+    #[cfg(feature = "boolean")]
+    #[test]
+    fn test_deterministic_geometry() {
+        let outputs = || {
+            let layer = GerberLayer::parse(UNORDERED).unwrap();
+            let copper = layer.copper(0.001);
+            [
+                copper.to_gerber().into_bytes(),
+                copper.to_svg([0, 0, 0, 255]).into_bytes(),
+                layer.to_geojson(0.001).into_bytes(),
+                format!("{:?}", layer.meshes(&Default::default())).into_bytes(),
+                layer.render(&Default::default()).to_png(),
+            ]
+        };
+        let first = outputs();
+        for _ in 0..8 {
+            assert_eq!(outputs(), first);
+        }
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), r#""a\"b\\c\n\u0001""#);
+    }
+
+    #[test]
+    fn test_command_code() {
+        let layer = GerberLayer::parse("%FSLAX26Y26*%\n%MOMM*%\nD10*\nM02*\n").unwrap();
+        let codes: Vec<_> = layer.commands().iter().map(Command::code).collect();
+        assert_eq!(
+            codes,
+            vec![command::FS, command::MO, command::D, command::M02]
+        );
+    }
+
+    #[test]
+    fn test_parse_one() {
+        let layer = GerberLayer::parse(indoc! {r"
+            G04 Every parsed command\u00B0*
+            %FSLAX26Y26*%
+            %MOMM*%
+            %TF.FileFunction,Copper,L1,Top*%
+            %TA.AperFunction,Conductor*%
+            %ADD10C,0.1*%
+            %ADD11P,1X6X15X0.2*%
+            %TD*%
+            D10*
+            %TO.N,GND*%
+            %LPC*%
+            %LMXY*%
+            %LR90*%
+            %LS1.5*%
+            G75*
+            G01*
+            X0Y0D02*
+            X1000000Y0D01*
+            G02*
+            X0Y-1000000I-1000000J0D01*
+            G36*
+            G37*
+            X0Y0D03*
+            M02*
+        "})
+        .unwrap();
+        for command in layer.commands() {
+            let text = command.to_string();
+            assert_eq!(Command::parse_one(&text).unwrap(), *command);
+            assert_eq!(text.parse::<Command>().unwrap(), *command);
+        }
+
+        assert_eq!(
+            Command::parse_one("\r\nD10*\r\n").unwrap(),
+            SetCurrentAperture(ApertureId::new(10).unwrap())
+        );
+        for invalid in [
+            "",
+            "\n",
+            "D10",
+            "D10*X0Y0D03*",
+            "D10* ",
+            "%MOMM*%%MOIN*%",
+            "M02*M02*",
+        ] {
+            assert!(
+                matches!(Command::parse_one(invalid), Err(GerberError::ParseError(_))),
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_statement() {
+        let layer = GerberLayer::parse(indoc! {"
+            G04 Both syntax classes*
+            %FSLAX26Y26*%
+            %MOMM*%
+            %TA.AperFunction,Conductor*%
+            %ADD10C,0.1*%
+            D10*
+            X0Y0D02*
+            X1000000Y0D01*
+            M02*
+        "})
+        .unwrap();
+        for command in layer.commands() {
+            let statement = command::Statement::from(command.clone());
+            assert_eq!(
+                matches!(statement, command::Statement::Extended(_)),
+                command.is_extended()
+            );
+            assert_eq!(Command::from(statement), *command);
+        }
     }
 
     #[test]
     fn test_comment() {
-        assert_eq!(comment("G04 Single line comment*"), Ok(("", Comment)));
-        assert_eq!(comment("G04*"), Ok(("", Comment)));
+        assert_eq!(
+            comment("G04 Single line comment*"),
+            Ok((
+                "",
+                Comment(EscapedString::new_unescaped(" Single line comment"))
+            ))
+        );
+        assert_eq!(
+            comment("G04*"),
+            Ok(("", Comment(EscapedString::new_unescaped(""))))
+        );
+        assert_eq!(
+            comment(r"G04 50\u00B5m*"),
+            Ok(("", Comment(EscapedString::new_escaped(r" 50\u00B5m"))))
+        );
     }
 
     #[test]
     fn test_mode() {
-        assert_eq!(mode("%MOMM*%"), Ok(("", Mode)));
-        assert_eq!(mode("%MOIN*%"), Ok(("", Mode)));
+        assert_eq!(mode("%MOMM*%"), Ok(("", Mode(Unit::Millimeters))));
+        assert_eq!(mode("%MOIN*%"), Ok(("", Mode(Unit::Inches))));
     }
 
     #[test]
@@ -415,10 +1025,69 @@ mod test {
     fn test_format_specification() {
         assert_eq!(
             format_specification("%FSLAX16Y66*%"),
-            Ok(("", FormatSpecification))
+            Ok((
+                "",
+                FormatSpecification(
+                    CoordinateFormat {
+                        integer: 1,
+                        decimal: 6
+                    },
+                    CoordinateFormat {
+                        integer: 6,
+                        decimal: 6
+                    }
+                )
+            ))
         )
     }
 
+    #[test]
+    fn test_operations() {
+        let xy = |x, y| Coordinates {
+            x: Some(x),
+            y: Some(y),
+        };
+        assert_eq!(
+            plot_operation("X100Y-200I50J0D01*"),
+            Ok(("", Plot(xy(100, -200), Some(Offset { i: 50, j: 0 }))))
+        );
+        assert_eq!(
+            plot_operation("Y5D01*"),
+            Ok((
+                "",
+                Plot(
+                    Coordinates {
+                        x: None,
+                        y: Some(5)
+                    },
+                    None
+                )
+            ))
+        );
+        assert_eq!(
+            plot_operation("X100J-50D01*"),
+            Ok((
+                "",
+                Plot(
+                    Coordinates {
+                        x: Some(100),
+                        y: None
+                    },
+                    Some(Offset { i: 0, j: -50 })
+                )
+            ))
+        );
+        assert!(plot_operation("X100D02*").is_err());
+        assert_eq!(
+            move_operation("X123456789012Y0D02*"),
+            Ok(("", Move(xy(123456789012, 0))))
+        );
+        assert_eq!(
+            flash_operation("D03*"),
+            Ok(("", Flash(Coordinates::default())))
+        );
+    }
+
     #[test]
     fn test_set_linear() {
         assert_eq!(set_linear("G01*"), Ok(("", SetLinear)));
@@ -434,42 +1103,335 @@ mod test {
         assert_eq!(set_ccw_circular("G03*"), Ok(("", SetCCWCircular)));
     }
 
+    #[test]
+    fn test_load_transformations() {
+        assert_eq!(
+            load_polarity("%LPC*%"),
+            Ok(("", LoadPolarity(Polarity::Clear)))
+        );
+        assert_eq!(
+            load_polarity("%LPD*%"),
+            Ok(("", LoadPolarity(Polarity::Dark)))
+        );
+        assert_eq!(
+            load_mirroring("%LMXY*%"),
+            Ok(("", LoadMirroring(Mirroring::XY)))
+        );
+        assert_eq!(
+            load_mirroring("%LMX*%"),
+            Ok(("", LoadMirroring(Mirroring::X)))
+        );
+        assert_eq!(
+            load_mirroring("%LMN*%"),
+            Ok(("", LoadMirroring(Mirroring::None)))
+        );
+        assert_eq!(
+            load_rotation("%LR-45.0*%"),
+            Ok(("", LoadRotation(Rotation(-45.0))))
+        );
+        assert_eq!(
+            load_scaling("%LS0.8*%"),
+            Ok(("", LoadScaling(Scaling(0.8))))
+        );
+        assert!(load_scaling("%LS-1*%").is_err());
+        assert!(load_mirroring("%LMZ*%").is_err());
+    }
+
+    #[test]
+    fn test_region_statement() {
+        assert_eq!(region_statement("G36*"), Ok(("", StartRegion)));
+        assert_eq!(region_statement("G37*"), Ok(("", EndRegion)));
+    }
+
     #[test]
     fn test_arc_init() {
         assert_eq!(arc_init("G75*"), Ok(("", ArcInit)));
+        assert_eq!(single_quadrant("G74*"), Ok(("", SingleQuadrant)));
+    }
+
+    #[test]
+    fn test_attribute_on_file() {
+        assert_eq!(
+            attribute_on_file("%TF.Part,Single*%"),
+            Ok((
+                "",
+                AttributeOnFile(
+                    FileAttributeName::Part,
+                    vec![EscapedString::new_unescaped("Single")]
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_attribute_on_aperture() {
+        assert_eq!(
+            attribute_on_aperture("%TA.AperFunction,Other,SpecialDrill*%"),
+            Ok((
+                "",
+                AttributeOnAperture(
+                    ApertureAttributeName::AperFunction,
+                    vec![
+                        EscapedString::new_unescaped("Other"),
+                        EscapedString::new_unescaped("SpecialDrill")
+                    ]
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_attribute_on_object() {
+        assert_eq!(
+            attribute_on_object("%TO.C,R1*%"),
+            Ok((
+                "",
+                AttributeOnObject(
+                    ObjectAttributeName::C,
+                    vec![EscapedString::new_unescaped("R1")]
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_attribute_delete() {
+        assert_eq!(attribute_delete("%TD*%"), Ok(("", AttributeDelete(None))));
+        assert_eq!(
+            attribute_delete("%TD.AperFunction*%"),
+            Ok(("", AttributeDelete(Some(".AperFunction".into()))))
+        );
+    }
+
+    #[test]
+    fn test_set_current_aperture() {
+        assert_eq!(
+            set_current_aperture("D10*"),
+            Ok(("", SetCurrentAperture(into_aperture_id(10))))
+        );
+        assert!(set_current_aperture("D01*").is_err());
+        assert_eq!(
+            set_current_aperture("D0012*"),
+            Ok(("", SetCurrentAperture(into_aperture_id(12))))
+        );
+    }
+
+    #[test]
+    fn test_aperture_macro() {
+        let src = indoc! {"
+            %AMDonut*
+            0 ring of diameter $1 around a hole of $2*
+            $3=$1
+            -$2*
+            1,1,$1,0,0*
+            1,0,$2,0,0*
+            %"};
+        let Ok(("", command)) = aperture_macro(src) else {
+            panic!("expected a macro");
+        };
+        let ApertureMacro(name, statements) = &command else {
+            panic!("expected a macro");
+        };
+        assert_eq!(name, "Donut");
+        assert_eq!(
+            statements
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "0 ring of diameter $1 around a hole of $2",
+                "$3=$1-$2",
+                "1,1,$1,0,0",
+                "1,0,$2,0,0"
+            ]
+        );
+        assert_eq!(Command::parse_one(&command.to_string()).unwrap(), command);
+
+        let command =
+            Command::parse_one("%AMM*\n$4=($1-$2)x-(1+$3)/2*\n2,1,$4,0,0,1,1,0*%").unwrap();
+        assert_eq!(
+            command.to_string(),
+            "%AMM*\n$4=($1-$2)x-(1+$3)/2*\n2,1,$4,0,0,1,1,0*%"
+        );
+
+        for invalid in [
+            "%AMDonut*%",
+            "%AMDonut*1,1,$1,0,0%",
+            "%AMDonut*1,1,$1+,0,0*%",
+            "%AMDonut*$0=1*%",
+        ] {
+            assert!(aperture_macro(invalid).is_err(), "{invalid:?}");
+        }
+
+        // flashes of a macro aperture
+        let layer = GerberLayer::parse(indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %AMDonut*
+            1,1,$1,0,0*
+            1,0,$2,0,0*%
+            %ADD10Donut,1X0.5*%
+            D10*
+            X0Y0D03*
+            M02*
+        "})
+        .unwrap();
+        let macros = layer.aperture_macros();
+        let primitives = macros[0].evaluate(&[1.0, 0.5]).unwrap();
+        assert_eq!(primitives[1].exposure(), Polarity::Clear);
     }
 
     #[test]
     fn test_aperture_define() {
-        assert_eq!(aperture_define("%ADD10C,0.1*%"), Ok(("", ApertureDefine)));
+        use ApertureTemplate::*;
+        let define = |id, template| Ok(("", ApertureDefine(into_aperture_id(id), template)));
+        assert_eq!(
+            aperture_define("%ADD10C,0.1*%"),
+            define(
+                10,
+                Circle {
+                    diameter: 0.1,
+                    hole: None
+                }
+            )
+        );
         assert_eq!(
             aperture_define("%ADD10C,7.500000*%"),
-            Ok(("", ApertureDefine))
+            define(
+                10,
+                Circle {
+                    diameter: 7.5,
+                    hole: None
+                }
+            )
+        );
+        assert_eq!(
+            aperture_define("%ADD11C,0.6X0.2*%"),
+            define(
+                11,
+                Circle {
+                    diameter: 0.6,
+                    hole: Some(0.2)
+                }
+            )
         );
-        assert_eq!(aperture_define("%ADD11C,0.6*%"), Ok(("", ApertureDefine)));
         assert_eq!(
             aperture_define("%ADD12R,0.6X0.6*%"),
-            Ok(("", ApertureDefine))
+            define(
+                12,
+                Rectangle {
+                    x: 0.6,
+                    y: 0.6,
+                    hole: None
+                }
+            )
         );
         assert_eq!(
             aperture_define("%ADD13R,0.4X1.00*%"),
-            Ok(("", ApertureDefine))
+            define(
+                13,
+                Rectangle {
+                    x: 0.4,
+                    y: 1.0,
+                    hole: None
+                }
+            )
         );
         assert_eq!(
-            aperture_define("%ADD14R,1.00X0.4*%"),
-            Ok(("", ApertureDefine))
+            aperture_define("%ADD14R,1.00X0.4X0.1*%"),
+            define(
+                14,
+                Rectangle {
+                    x: 1.0,
+                    y: 0.4,
+                    hole: Some(0.1)
+                }
+            )
         );
         assert_eq!(
             aperture_define("%ADD15O,0.4X01.00*%"),
-            Ok(("", ApertureDefine))
+            define(
+                15,
+                Obround {
+                    x: 0.4,
+                    y: 1.0,
+                    hole: None
+                }
+            )
         );
         assert_eq!(
             aperture_define("%ADD16P,1.00X3*%"),
-            Ok(("", ApertureDefine))
+            define(
+                16,
+                Polygon {
+                    diameter: 1.0,
+                    vertices: 3.0,
+                    rotation: None,
+                    hole: None
+                }
+            )
         );
+        assert_eq!(
+            aperture_define("%ADD17P,1.00X6X45*%"),
+            define(
+                17,
+                Polygon {
+                    diameter: 1.0,
+                    vertices: 6.0,
+                    rotation: Some(45.0),
+                    hole: None
+                }
+            )
+        );
+        for vertices in ["2", "2.5", "-3", "13", "4000000000"] {
+            let src = format!("%ADD18P,1X{vertices}*%");
+            assert!(aperture_define(&src).is_err(), "{src}");
+        }
         assert_eq!(
             aperture_define("%ADD19THERMAL80*%"),
-            Ok(("", ApertureDefine))
+            define(
+                19,
+                Macro {
+                    name: "THERMAL80".into(),
+                    parameters: vec![]
+                }
+            )
+        );
+        assert_eq!(
+            aperture_define("%ADD20DONUT,0.5X0.2X0.1*%"),
+            define(
+                20,
+                Macro {
+                    name: "DONUT".into(),
+                    parameters: vec![0.5, 0.2, 0.1]
+                }
+            )
         );
     }
+
+    #[test]
+    fn test_project_id() {
+        let layer = |attributes: &str| {
+            let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{attributes}M02*\n");
+            GerberLayer::parse(&src).unwrap().into_owned()
+        };
+        assert!(layer("").project_id().is_none());
+
+        let id = layer(indoc! {r"
+            %TF.ProjectId,Old,00000000-0000-0000-0000-000000000000,1*%
+            %TF.ProjectId,My\u002CBoard,8b3a8e1c-2f70-4c2a-9c3d-5e9f0a1b2c3d,2*%
+        "})
+        .project_id();
+        let id = id.unwrap().unwrap();
+        assert_eq!(
+            (id.name.as_str(), id.guid.as_str(), id.revision.as_str()),
+            ("My,Board", "8b3a8e1c-2f70-4c2a-9c3d-5e9f0a1b2c3d", "2")
+        );
+
+        let invalid = layer("%TF.ProjectId,Board,8b3a8e1c2f704c2a9c3d5e9f0a1b2c3d,2*%\n");
+        assert!(matches!(
+            invalid.project_id(),
+            Some(Err(GerberError::Attribute(..)))
+        ));
+    }
 }
@@ -0,0 +1,237 @@
+//! Lint levels for validation
+//!
+//! Each rule of [validate](crate::validate) is a lint with an id, such as
+//! `unused_aperture`, and a default severity. As with clippy, [Lints]
+//! raises a lint to an error, lowers it to a warning or disables it, set
+//! in [ParseOptions::lints](crate::ParseOptions::lints) or read from a
+//! config file of `id = "level"` lines:
+//!
+//! ```text
+//! # fab-specific rules
+//! unused_aperture = "allow"
+//! small_coordinates = "deny"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::validate::{Diagnostic, Severity};
+use crate::GerberError;
+
+/// A rule of [validate](crate::validate)
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Lint {
+    /// The name of the lint in [Lints] and config files
+    pub id: &'static str,
+
+    /// The severity of a violation unless configured otherwise
+    pub severity: Severity,
+}
+
+/// Every lint, one for each [DiagnosticKind](crate::validate::DiagnosticKind)
+pub const LINTS: &[Lint] = &[
+    error("missing_format_specification"),
+    error("duplicate_format_specification"),
+    error("late_format_specification"),
+    error("missing_mode"),
+    error("duplicate_mode"),
+    error("late_mode"),
+    error("missing_arc_init"),
+    error("missing_interpolation_mode"),
+    error("inconsistent_arc"),
+    warning("ambiguous_arc_center"),
+    warning("single_quadrant_mode"),
+    error("undefined_aperture"),
+    error("missing_current_aperture"),
+    warning("unused_aperture"),
+    warning("redefined_aperture"),
+    warning("zero_size_draw"),
+    warning("zero_length_draw"),
+    warning("degenerate_aperture"),
+    error("invalid_aperture"),
+    error("coordinate_out_of_range"),
+    warning("small_coordinates"),
+    warning("legacy_format_specification"),
+];
+
+const fn error(id: &'static str) -> Lint {
+    Lint {
+        id,
+        severity: Severity::Error,
+    }
+}
+
+const fn warning(id: &'static str) -> Lint {
+    Lint {
+        id,
+        severity: Severity::Warning,
+    }
+}
+
+/// What to do with violations of a lint
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum LintLevel {
+    /// Don't report them
+    Allow,
+
+    /// Report them as warnings
+    Warn,
+
+    /// Report them as errors
+    Deny,
+}
+
+impl fmt::Display for LintLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny",
+        })
+    }
+}
+
+impl std::str::FromStr for LintLevel {
+    type Err = GerberError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(LintLevel::Allow),
+            "warn" => Ok(LintLevel::Warn),
+            "deny" => Ok(LintLevel::Deny),
+            _ => Err(GerberError::Lint(format!(
+                "unknown level {s:?}, expected allow, warn or deny"
+            ))),
+        }
+    }
+}
+
+/// Levels of lints which differ from their default severity
+///
+/// ```
+/// use gerber::lint::{LintLevel, Lints};
+/// use gerber::validate::Severity;
+/// use gerber::GerberLayer;
+///
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\n%ADD11C,0.2*%\nD10*\nX0Y0D03*\nM02*\n";
+/// let diagnostics = GerberLayer::parse(src).unwrap().validate();
+/// assert_eq!(diagnostics[0].severity, Severity::Warning);
+///
+/// let mut lints = Lints::default();
+/// lints.set("unused_aperture", LintLevel::Deny).unwrap();
+/// assert_eq!(lints.apply(diagnostics.clone())[0].severity, Severity::Error);
+///
+/// let lints = Lints::from_config("unused_aperture = \"allow\"\n").unwrap();
+/// assert!(lints.apply(diagnostics).is_empty());
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Lints {
+    levels: BTreeMap<&'static str, LintLevel>,
+}
+
+impl Lints {
+    /// Set the level of the lint `id`, returning [GerberError::Lint] if
+    /// there is no such lint
+    pub fn set(&mut self, id: &str, level: LintLevel) -> Result<(), GerberError> {
+        let lint = LINTS
+            .iter()
+            .find(|lint| lint.id == id)
+            .ok_or_else(|| GerberError::Lint(format!("unknown lint {id:?}")))?;
+        self.levels.insert(lint.id, level);
+        Ok(())
+    }
+
+    /// The configured level of the lint `id`, `None` for its default
+    pub fn level(&self, id: &str) -> Option<LintLevel> {
+        self.levels.get(id).copied()
+    }
+
+    /// Read lint levels from a config file
+    ///
+    /// Each line sets a lint as `id = "level"`, quotes optional, which is
+    /// also a TOML table. Blank lines, `#` comments and `[section]`
+    /// headers are ignored. Errors name the offending line.
+    pub fn from_config(config: &str) -> Result<Self, GerberError> {
+        let mut lints = Lints::default();
+        for (number, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+            let at_line = |e: GerberError| match e {
+                GerberError::Lint(message) => {
+                    GerberError::Lint(format!("line {}: {message}", number + 1))
+                }
+                e => e,
+            };
+            let (id, level) = line
+                .split_once('=')
+                .ok_or_else(|| at_line(GerberError::Lint("expected id = level".into())))?;
+            let unquote = |s: &str| s.trim().trim_matches('"').to_string();
+            let level = unquote(level).parse().map_err(at_line)?;
+            lints.set(&unquote(id), level).map_err(at_line)?;
+        }
+        Ok(lints)
+    }
+
+    /// Apply the levels to diagnostics, changing their severity and
+    /// removing those of allowed lints
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                match self.level(diagnostic.kind.lint().id) {
+                    Some(LintLevel::Allow) => return None,
+                    Some(LintLevel::Warn) => diagnostic.severity = Severity::Warning,
+                    Some(LintLevel::Deny) => diagnostic.severity = Severity::Error,
+                    None => (),
+                }
+                Some(diagnostic)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::DiagnosticKind;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_lints() {
+        let ids: HashSet<_> = LINTS.iter().map(|lint| lint.id).collect();
+        assert_eq!(ids.len(), LINTS.len());
+        assert_eq!(DiagnosticKind::MissingMode.lint().id, "missing_mode");
+        assert_eq!(
+            DiagnosticKind::SmallCoordinates.lint().id,
+            "small_coordinates"
+        );
+        assert_eq!(DiagnosticKind::ZeroLengthDraw.lint().id, "zero_length_draw");
+        assert_eq!(
+            DiagnosticKind::CoordinateOutOfRange(1).lint().severity,
+            Severity::Error
+        );
+
+        let lints = Lints::from_config(
+            "[lints]\n# comment\n\nmissing_mode = deny\nzero_length_draw = \"allow\" # noisy\n",
+        )
+        .unwrap();
+        assert_eq!(lints.level("zero_length_draw"), Some(LintLevel::Allow));
+        assert_eq!(lints.level("missing_mode"), Some(LintLevel::Deny));
+        assert_eq!(lints.level("unused_aperture"), None);
+
+        for (config, message) in [
+            ("unused_apertures = allow", "line 1: unknown lint"),
+            ("\nunused_aperture = off", "line 2: unknown level"),
+            ("unused_aperture", "line 1: expected"),
+        ] {
+            let error = Lints::from_config(config).unwrap_err().to_string();
+            assert!(error.contains(message), "{error}");
+        }
+    }
+}
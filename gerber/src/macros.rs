@@ -0,0 +1,1020 @@
+//! Aperture macro bodies
+//!
+//! An `%AM` command defines a shape as a list of primitives, such as
+//! circles, lines and outlines, whose sizes are arithmetic expressions of
+//! variables. `$1`, `$2`, ... are the parameters of the `%AD` command using
+//! the macro, and the body may define further variables from them.
+//! [ApertureMacro] is the structured form of the command, and
+//! [ApertureMacro::evaluate] gives the primitives of one aperture. With
+//! the `boolean` feature, `ApertureMacro::polygons` combines them into the
+//! outline of the aperture.
+//!
+//! The current primitives of the specification are supported: circle (1),
+//! vector line (20), center line (21), outline (4), polygon (5) and
+//! thermal (7). Of the primitives removed from the specification, the
+//! lines 2 and 22 are still common and are read as a vector line and a
+//! center line. The moiré (6) is rejected.
+//!
+//! Some EDA tools write bloated bodies, with sizes spelled out as long
+//! expressions of constants and variables no primitive reads.
+//! [ApertureMacro::simplify] folds them into the shortest equivalent body.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter};
+use std::hash::{Hash, Hasher};
+
+use crate::command::Command::*;
+use crate::data::Polarity;
+use crate::grammar::{positive_integer, unsigned_decimal};
+use crate::image::Point;
+use crate::{GerberError, GerberLayer, IResult};
+use nom::{
+    branch::alt,
+    character::complete::{char, one_of},
+    combinator::{all_consuming, map},
+    multi::fold_many0,
+    sequence::{delimited, pair, preceded},
+};
+
+/// The body of an aperture macro
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ApertureMacro {
+    pub name: String,
+    pub statements: Vec<MacroStatement>,
+}
+
+/// A word of an aperture macro body
+///
+/// Written as in the file, without the `*` ending the word.
+#[derive(Clone, PartialEq, PartialOrd, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum MacroStatement {
+    /// Primitive code 0, with the text after it
+    Comment(String),
+
+    /// `$n=expression`, with `n` from 1
+    Variable(
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_variable))] u32,
+        Expression,
+    ),
+
+    /// A primitive code and its modifiers
+    Primitive(u32, Vec<Expression>),
+}
+
+/// An arithmetic expression of numbers and macro variables
+///
+/// Written with the parentheses its tree needs, so a parsed expression
+/// writes as it was read apart from spaces and redundant parentheses.
+#[derive(Clone, PartialEq, PartialOrd, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Expression {
+    /// Not negative, as read, negative numbers are negated
+    Number(#[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_number))] f64),
+
+    /// `$n`, with `n` from 1
+    Variable(#[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_variable))] u32),
+
+    Negate(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+/// An arithmetic operator, `+`, `-`, `x` or `/`
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// A primitive of an evaluated macro
+///
+/// Sizes and coordinates are in the unit of the file, relative to the
+/// flash point. Rotations are in degrees counter-clockwise about the
+/// origin of the macro, not the center of the primitive. A clear
+/// exposure erases the primitives before it.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum MacroPrimitive {
+    /// Code 1
+    Circle {
+        exposure: Polarity,
+        diameter: f64,
+        center: Point,
+        rotation: f64,
+    },
+
+    /// Code 20, a line with square ends of `width`
+    VectorLine {
+        exposure: Polarity,
+        width: f64,
+        start: Point,
+        end: Point,
+        rotation: f64,
+    },
+
+    /// Code 21, a rectangle around `center`
+    CenterLine {
+        exposure: Polarity,
+        width: f64,
+        height: f64,
+        center: Point,
+        rotation: f64,
+    },
+
+    /// Code 4, a polygon through `points`, without the repeated first
+    /// point which closes it in the macro
+    Outline {
+        exposure: Polarity,
+        points: Vec<Point>,
+        rotation: f64,
+    },
+
+    /// Code 5, a regular polygon with its first vertex on the X axis
+    /// through `center`
+    Polygon {
+        exposure: Polarity,
+
+        /// From 3 to 12, as checked by [ApertureMacro::evaluate]
+        #[cfg_attr(
+            feature = "arbitrary",
+            arbitrary(with = |u: &mut arbitrary::Unstructured| u.int_in_range(3..=12))
+        )]
+        vertices: u32,
+        center: Point,
+        diameter: f64,
+        rotation: f64,
+    },
+
+    /// Code 7, a ring between the `outer` and `inner` diameters cut into
+    /// four by a cross of `gap` wide along the axes, always dark
+    Thermal {
+        center: Point,
+        outer: f64,
+        inner: f64,
+        gap: f64,
+        rotation: f64,
+    },
+}
+
+impl MacroPrimitive {
+    /// Whether the primitive adds to or erases the shape
+    pub fn exposure(&self) -> Polarity {
+        match *self {
+            Self::Circle { exposure, .. }
+            | Self::VectorLine { exposure, .. }
+            | Self::CenterLine { exposure, .. }
+            | Self::Outline { exposure, .. }
+            | Self::Polygon { exposure, .. } => exposure,
+            Self::Thermal { .. } => Polarity::Dark,
+        }
+    }
+
+    /// True when the primitive has no area, e.g. a zero-width line
+    pub fn is_degenerate(&self) -> bool {
+        match self {
+            Self::Circle { diameter, .. } | Self::Polygon { diameter, .. } => *diameter == 0.0,
+            Self::VectorLine {
+                width, start, end, ..
+            } => *width == 0.0 || start == end,
+            Self::CenterLine { width, height, .. } => *width == 0.0 || *height == 0.0,
+            Self::Outline { points, .. } => {
+                // twice the signed area, by the shoelace formula
+                let next = points.iter().cycle().skip(1);
+                let area: f64 = points
+                    .iter()
+                    .zip(next)
+                    .map(|(a, b)| a.x * b.y - b.x * a.y)
+                    .sum();
+                area == 0.0
+            }
+            Self::Thermal { outer, inner, .. } => outer <= inner,
+        }
+    }
+
+    /// The radius of a circle around the origin of the macro which contains
+    /// the primitive, whatever its rotation
+    ///
+    /// Exact for circles, polygons and outlines, and at most a little
+    /// larger for the others.
+    pub fn radius(&self) -> f64 {
+        let distance = |point: &Point| point.x.hypot(point.y);
+        match self {
+            Self::Circle {
+                diameter, center, ..
+            }
+            | Self::Polygon {
+                diameter, center, ..
+            } => distance(center) + diameter / 2.0,
+            Self::VectorLine {
+                width, start, end, ..
+            } => distance(start).max(distance(end)) + width / 2.0,
+            Self::CenterLine {
+                width,
+                height,
+                center,
+                ..
+            } => distance(center) + width.hypot(*height) / 2.0,
+            Self::Outline { points, .. } => points.iter().map(distance).fold(0.0, f64::max),
+            Self::Thermal { center, outer, .. } => distance(center) + outer / 2.0,
+        }
+    }
+
+    /// The rotation about the origin of the macro, in degrees
+    pub fn rotation(&self) -> f64 {
+        match *self {
+            Self::Circle { rotation, .. }
+            | Self::VectorLine { rotation, .. }
+            | Self::CenterLine { rotation, .. }
+            | Self::Outline { rotation, .. }
+            | Self::Polygon { rotation, .. }
+            | Self::Thermal { rotation, .. } => rotation,
+        }
+    }
+}
+
+impl Expression {
+    /// The value of the expression, taking `variables[n - 1]` for `$n`
+    ///
+    /// Variables which are neither passed nor defined are zero, as in
+    /// most viewers.
+    pub fn evaluate(&self, variables: &[f64]) -> f64 {
+        self.value(&|n| {
+            let index = (n as usize).checked_sub(1);
+            index.and_then(|i| variables.get(i)).copied().unwrap_or(0.0)
+        })
+    }
+
+    /// The expression with constant subexpressions replaced by their value,
+    /// and variables in `constants` by theirs
+    ///
+    /// A subexpression is only folded to a finite value, so the result
+    /// still writes as a valid expression.
+    fn folded(&self, constants: &BTreeMap<u32, f64>) -> Self {
+        let number = |value: f64| value.is_finite().then_some(Self::Number(value));
+        match self {
+            Self::Number(_) => self.clone(),
+            Self::Variable(n) => match constants.get(n) {
+                Some(&value) => Self::Number(value),
+                None => self.clone(),
+            },
+            Self::Negate(operand) => match operand.folded(constants) {
+                Self::Number(value) => Self::Number(-value),
+                operand => Self::Negate(operand.into()),
+            },
+            Self::Binary(left, operator, right) => {
+                let folded = Self::Binary(
+                    left.folded(constants).into(),
+                    *operator,
+                    right.folded(constants).into(),
+                );
+                match &folded {
+                    Self::Binary(left, _, right)
+                        if matches!((&**left, &**right), (Self::Number(_), Self::Number(_))) =>
+                    {
+                        number(folded.value(&|_| 0.0)).unwrap_or(folded)
+                    }
+                    _ => folded,
+                }
+            }
+        }
+    }
+
+    /// Add the numbers of the variables the expression reads to `variables`
+    fn variables(&self, variables: &mut BTreeSet<u32>) {
+        match self {
+            Self::Number(_) => (),
+            Self::Variable(n) => {
+                variables.insert(*n);
+            }
+            Self::Negate(operand) => operand.variables(variables),
+            Self::Binary(left, _, right) => {
+                left.variables(variables);
+                right.variables(variables);
+            }
+        }
+    }
+
+    fn value(&self, variable: &dyn Fn(u32) -> f64) -> f64 {
+        match self {
+            Self::Number(value) => *value,
+            Self::Variable(n) => variable(*n),
+            Self::Negate(operand) => -operand.value(variable),
+            Self::Binary(left, operator, right) => {
+                let (left, right) = (left.value(variable), right.value(variable));
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                }
+            }
+        }
+    }
+}
+
+/// Only generates numbers the parser reads: finite and not negative
+#[cfg(feature = "arbitrary")]
+fn arbitrary_number(u: &mut arbitrary::Unstructured) -> arbitrary::Result<f64> {
+    let value = <f64 as arbitrary::Arbitrary>::arbitrary(u)?.abs();
+    Ok(if value.is_finite() { value } else { 0.0 })
+}
+
+/// Only generates variable numbers the parser accepts, from 1 to
+/// `i32::MAX`
+#[cfg(feature = "arbitrary")]
+fn arbitrary_variable(u: &mut arbitrary::Unstructured) -> arbitrary::Result<u32> {
+    u.int_in_range(1..=i32::MAX as u32)
+}
+
+impl Operator {
+    /// How tightly the operator binds, higher first
+    fn precedence(self) -> u8 {
+        match self {
+            Self::Add | Self::Subtract => 1,
+            Self::Multiply | Self::Divide => 2,
+        }
+    }
+}
+
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            // 0.0 and -0.0 compare equal, so must hash equal
+            Self::Number(value) => if *value == 0.0 { 0.0f64 } else { *value }
+                .to_bits()
+                .hash(state),
+            Self::Variable(n) => n.hash(state),
+            Self::Negate(operand) => operand.hash(state),
+            Self::Binary(left, operator, right) => {
+                left.hash(state);
+                operator.hash(state);
+                right.hash(state);
+            }
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "x",
+            Self::Divide => "/",
+        })
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(value) => write!(f, "{value}"),
+            Self::Variable(n) => write!(f, "${n}"),
+            Self::Negate(operand) => match **operand {
+                Self::Binary(..) => write!(f, "-({operand})"),
+                _ => write!(f, "-{operand}"),
+            },
+            Self::Binary(left, operator, right) => {
+                // operators are left associative, so a right operand of the
+                // same precedence needs parentheses too
+                let precedence = operator.precedence();
+                match **left {
+                    Self::Binary(_, inner, _) if inner.precedence() < precedence => {
+                        write!(f, "({left})")?
+                    }
+                    _ => write!(f, "{left}")?,
+                }
+                write!(f, "{operator}")?;
+                match **right {
+                    Self::Binary(_, inner, _) if inner.precedence() <= precedence => {
+                        write!(f, "({right})")
+                    }
+                    _ => write!(f, "{right}"),
+                }
+            }
+        }
+    }
+}
+
+impl Display for MacroStatement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Comment(text) if text.is_empty() => f.write_str("0"),
+            Self::Comment(text) => write!(f, "0 {text}"),
+            Self::Variable(n, expression) => write!(f, "${n}={expression}"),
+            Self::Primitive(code, modifiers) => {
+                write!(f, "{code}")?;
+                for modifier in modifiers {
+                    write!(f, ",{modifier}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl ApertureMacro {
+    /// Parse the words of an `%AM` body, without the `*` ending each
+    ///
+    /// ```
+    /// use gerber::data::Polarity;
+    /// use gerber::image::Point;
+    /// use gerber::macros::{ApertureMacro, MacroPrimitive};
+    ///
+    /// let words = ["0 a circle and a hole", "$3=$1/2", "1,1,$1,0,0", "1,0,$3,0,0"];
+    /// let pad = ApertureMacro::parse("Pad", &words).unwrap();
+    /// let primitives = pad.evaluate(&[1.5]).unwrap();
+    /// assert_eq!(
+    ///     primitives[1],
+    ///     MacroPrimitive::Circle {
+    ///         exposure: Polarity::Clear,
+    ///         diameter: 0.75,
+    ///         center: Point::default(),
+    ///         rotation: 0.0,
+    ///     }
+    /// );
+    /// ```
+    pub fn parse<S: AsRef<str>>(name: &str, words: &[S]) -> Result<Self, GerberError> {
+        let statements = words
+            .iter()
+            .map(|word| {
+                statement(word.as_ref()).map_err(|message| {
+                    GerberError::Macro(
+                        name.to_string(),
+                        format!("{message} in {:?}", word.as_ref()),
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            name: name.to_string(),
+            statements,
+        })
+    }
+
+    /// True when an aperture with the `parameters` of its `%AD` command
+    /// creates no image: it has no primitives, or only clear or degenerate
+    /// ones
+    ///
+    /// Returns an error if the macro can't be evaluated.
+    pub fn is_degenerate(&self, parameters: &[f64]) -> Result<bool, GerberError> {
+        let primitives = self.evaluate(parameters)?;
+        Ok(primitives
+            .iter()
+            .all(|primitive| primitive.exposure() == Polarity::Clear || primitive.is_degenerate()))
+    }
+
+    /// The macro called `name` in `macros`, or [GerberError::Macro] if
+    /// there is none
+    pub fn find<'m>(macros: &'m [ApertureMacro], name: &str) -> Result<&'m Self, GerberError> {
+        macros
+            .iter()
+            .find(|body| body.name == name)
+            .ok_or_else(|| GerberError::Macro(name.to_string(), "not defined".into()))
+    }
+
+    /// The primitives of an aperture with the `parameters` of its `%AD`
+    /// command, in order
+    pub fn evaluate(&self, parameters: &[f64]) -> Result<Vec<MacroPrimitive>, GerberError> {
+        // variables of the body replace parameters of the same number,
+        // whose numbers may be far beyond the parameters
+        let mut defined = BTreeMap::new();
+        let mut primitives = Vec::new();
+        for statement in &self.statements {
+            let variable = |n: u32| match defined.get(&n) {
+                Some(&value) => value,
+                None => Expression::Variable(n).evaluate(parameters),
+            };
+            match statement {
+                MacroStatement::Comment(_) => (),
+                MacroStatement::Variable(n, expression) => {
+                    let value = expression.value(&variable);
+                    defined.insert(*n, value);
+                }
+                MacroStatement::Primitive(code, modifiers) => {
+                    let values: Vec<_> = modifiers.iter().map(|m| m.value(&variable)).collect();
+                    let primitive = primitive(*code, &values)
+                        .map_err(|message| GerberError::Macro(self.name.clone(), message))?;
+                    primitives.push(primitive);
+                }
+            }
+        }
+        Ok(primitives)
+    }
+
+    /// The macro with the same primitives for any parameters, in fewer or
+    /// shorter statements
+    ///
+    /// * constant expressions are folded, including variables defined as
+    ///   constants
+    /// * variables which no later statement reads are removed
+    /// * the deprecated lines 2 and 22 become the vector line 20 and the
+    ///   center line 21
+    ///
+    /// Comments are kept.
+    ///
+    /// ```
+    /// use gerber::macros::ApertureMacro;
+    ///
+    /// let words = ["$2=0.5x2", "$3=$1x4", "1,1,$2+$1,0,0", "22,1,2,1,0,0,0"];
+    /// let body = ApertureMacro::parse("Pad", &words).unwrap().simplify();
+    /// let words: Vec<_> = body.statements.iter().map(|s| s.to_string()).collect();
+    /// assert_eq!(words, ["1,1,1+$1,0,0", "21,1,2,1,1,0.5,0"]);
+    /// ```
+    pub fn simplify(&self) -> Self {
+        // forward: fold, keeping the values of variables defined as constants
+        let mut constants = BTreeMap::new();
+        let mut statements = Vec::new();
+        for statement in &self.statements {
+            statements.push(match statement {
+                MacroStatement::Comment(_) => statement.clone(),
+                MacroStatement::Variable(n, expression) => {
+                    let expression = expression.folded(&constants);
+                    match expression {
+                        Expression::Number(value) => constants.insert(*n, value),
+                        _ => constants.remove(n),
+                    };
+                    MacroStatement::Variable(*n, expression)
+                }
+                MacroStatement::Primitive(code, modifiers) => {
+                    let (code, modifiers) = normalized(*code, modifiers);
+                    let modifiers = modifiers.iter().map(|m| m.folded(&constants)).collect();
+                    MacroStatement::Primitive(code, modifiers)
+                }
+            });
+        }
+
+        // backward: drop definitions which are redefined or never read
+        let mut read = BTreeSet::new();
+        let mut live = Vec::new();
+        for statement in statements.into_iter().rev() {
+            match &statement {
+                MacroStatement::Comment(_) => (),
+                MacroStatement::Variable(n, expression) => {
+                    if !read.remove(n) {
+                        continue;
+                    }
+                    expression.variables(&mut read);
+                }
+                MacroStatement::Primitive(_, modifiers) => {
+                    for modifier in modifiers {
+                        modifier.variables(&mut read);
+                    }
+                }
+            }
+            live.push(statement);
+        }
+        live.reverse();
+        Self {
+            name: self.name.clone(),
+            statements: live,
+        }
+    }
+}
+
+impl GerberLayer<'_> {
+    /// [Simplify](ApertureMacro::simplify) the body of every `%AM` command,
+    /// returning how many changed
+    pub fn simplify_macros(&mut self) -> usize {
+        let mut changed = 0;
+        for command in &mut self.commands {
+            if let ApertureMacro(name, statements) = command {
+                let body = ApertureMacro {
+                    name: name.to_string(),
+                    statements: std::mem::take(statements),
+                };
+                let simplified = body.simplify();
+                changed += usize::from(simplified != body);
+                *statements = simplified.statements;
+            }
+        }
+        changed
+    }
+
+    /// The aperture macros defined by the layer, in file order
+    pub fn aperture_macros(&self) -> Vec<ApertureMacro> {
+        self.commands
+            .iter()
+            .filter_map(|command| match command {
+                ApertureMacro(name, statements) => Some(ApertureMacro {
+                    name: name.to_string(),
+                    statements: statements.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Parse a word of a macro body, with a message if it is invalid
+pub(crate) fn statement(word: &str) -> Result<MacroStatement, String> {
+    // line breaks and spaces may split long words
+    let word = word.trim();
+    if let Some(text) = word.strip_prefix('0').filter(|text| {
+        // "0 text" is a comment, "0,..." would be a primitive with code 0
+        text.is_empty() || text.starts_with(char::is_whitespace)
+    }) {
+        return Ok(MacroStatement::Comment(text.trim().to_string()));
+    }
+    let word: String = word.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Some((variable, value)) = word.split_once('=') {
+        let n = variable
+            .strip_prefix('$')
+            .and_then(|n| n.parse::<i32>().ok())
+            .filter(|&n| n > 0)
+            .map(|n| n as u32)
+            .ok_or("invalid variable")?;
+        return Ok(MacroStatement::Variable(n, parse_expression(value)?));
+    }
+    let mut fields = word.split(',');
+    let code = fields
+        .next()
+        .and_then(|code| code.parse().ok())
+        .ok_or("invalid primitive code")?;
+    let modifiers = fields.map(parse_expression).collect::<Result<_, _>>()?;
+    Ok(MacroStatement::Primitive(code, modifiers))
+}
+
+fn parse_expression(input: &str) -> Result<Expression, String> {
+    all_consuming(expression)(input)
+        .map(|(_, expression)| expression)
+        .map_err(|_| format!("invalid expression {input:?}"))
+}
+
+/// Terms joined by `+` and `-`, left to right
+fn expression(input: &str) -> IResult<'_, Expression> {
+    let (input, first) = term(input)?;
+    fold_many0(
+        pair(
+            alt((
+                map(char('+'), |_| Operator::Add),
+                map(char('-'), |_| Operator::Subtract),
+            )),
+            term,
+        ),
+        move || first.clone(),
+        |left, (operator, right)| Expression::Binary(left.into(), operator, right.into()),
+    )(input)
+}
+
+/// Factors joined by `x` and `/`, which bind tighter
+fn term(input: &str) -> IResult<'_, Expression> {
+    let (input, first) = factor(input)?;
+    fold_many0(
+        pair(
+            alt((
+                map(one_of("xX"), |_| Operator::Multiply),
+                map(char('/'), |_| Operator::Divide),
+            )),
+            factor,
+        ),
+        move || first.clone(),
+        |left, (operator, right)| Expression::Binary(left.into(), operator, right.into()),
+    )(input)
+}
+
+fn factor(input: &str) -> IResult<'_, Expression> {
+    alt((
+        map(preceded(char('-'), factor), |operand| {
+            Expression::Negate(operand.into())
+        }),
+        preceded(char('+'), factor),
+        delimited(char('('), expression, char(')')),
+        map(preceded(char('$'), positive_integer), |n| {
+            Expression::Variable(n as u32)
+        }),
+        map(unsigned_decimal, Expression::Number),
+    ))(input)
+}
+
+/// The current primitive for a deprecated code and its modifiers
+///
+/// Modifiers too few for the primitive are left for [primitive] to reject.
+fn normalized(code: u32, modifiers: &[Expression]) -> (u32, Vec<Expression>) {
+    match code {
+        2 => (20, modifiers.to_vec()),
+        // from the lower left corner to the center
+        22 if modifiers.len() >= 6 => {
+            let half = |size: &Expression| {
+                Expression::Binary(
+                    size.clone().into(),
+                    Operator::Divide,
+                    Expression::Number(2.0).into(),
+                )
+            };
+            let corner = |i: usize, size: usize| {
+                Expression::Binary(
+                    modifiers[i].clone().into(),
+                    Operator::Add,
+                    half(&modifiers[size]).into(),
+                )
+            };
+            let mut center = modifiers.to_vec();
+            center[3] = corner(3, 1);
+            center[4] = corner(4, 2);
+            (21, center)
+        }
+        _ => (code, modifiers.to_vec()),
+    }
+}
+
+/// Build a primitive from its code and evaluated modifiers
+fn primitive(code: u32, values: &[f64]) -> Result<MacroPrimitive, String> {
+    let required = match code {
+        1 => 4,
+        2 | 20 => 7,
+        21 | 22 => 6,
+        4 => 4,
+        5 => 6,
+        7 => 6,
+        _ => return Err(format!("unsupported primitive code {code}")),
+    };
+    if values.len() < required {
+        return Err(format!(
+            "primitive {code} has {} modifiers, expected {required}",
+            values.len()
+        ));
+    }
+    // a thermal has no exposure, its first two modifiers are the center
+    let exposure = match (code, values[0]) {
+        (7, _) | (_, 1.0) => Polarity::Dark,
+        (_, 0.0) => Polarity::Clear,
+        (_, value) => return Err(format!("invalid exposure {value}")),
+    };
+    let point = |i: usize| Point {
+        x: values[i],
+        y: values[i + 1],
+    };
+    // the rotation of a circle is optional
+    let rotation = |i: usize| values.get(i).copied().unwrap_or(0.0);
+    Ok(match code {
+        1 => MacroPrimitive::Circle {
+            exposure,
+            diameter: values[1],
+            center: point(2),
+            rotation: rotation(4),
+        },
+        2 | 20 => MacroPrimitive::VectorLine {
+            exposure,
+            width: values[1],
+            start: point(2),
+            end: point(4),
+            rotation: rotation(6),
+        },
+        21 => MacroPrimitive::CenterLine {
+            exposure,
+            width: values[1],
+            height: values[2],
+            center: point(3),
+            rotation: rotation(5),
+        },
+        // a center line given by its lower left corner
+        22 => MacroPrimitive::CenterLine {
+            exposure,
+            width: values[1],
+            height: values[2],
+            center: Point {
+                x: values[3] + values[1] / 2.0,
+                y: values[4] + values[2] / 2.0,
+            },
+            rotation: rotation(5),
+        },
+        4 => {
+            let vertices = values[1];
+            let count = vertices as usize;
+            if vertices < 3.0 || vertices.fract() != 0.0 || values.len() != 2 * count + 5 {
+                return Err(format!(
+                    "outline of {vertices} vertices has {} modifiers",
+                    values.len()
+                ));
+            }
+            MacroPrimitive::Outline {
+                exposure,
+                points: (0..count).map(|i| point(2 + 2 * i)).collect(),
+                rotation: rotation(2 * count + 4),
+            }
+        }
+        5 => {
+            let vertices = values[1];
+            if !(3.0..=12.0).contains(&vertices) || vertices.fract() != 0.0 {
+                return Err(format!("polygon of {vertices} vertices, expected 3 to 12"));
+            }
+            MacroPrimitive::Polygon {
+                exposure,
+                vertices: vertices as u32,
+                center: point(2),
+                diameter: values[4],
+                rotation: rotation(5),
+            }
+        }
+        7 => MacroPrimitive::Thermal {
+            center: point(0),
+            outer: values[2],
+            inner: values[3],
+            gap: values[4],
+            rotation: rotation(5),
+        },
+        _ => unreachable!("checked above"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::template::{MacroTemplate, RoundedRectangle};
+
+    #[test]
+    fn test_expression() {
+        let evaluate = |input| parse_expression(input).unwrap().evaluate(&[2.0, 3.0]);
+        assert_eq!(evaluate("1+2x3"), 7.0);
+        assert_eq!(evaluate("(1+2)X3"), 9.0);
+        assert_eq!(evaluate("$2-$1-1"), 0.0);
+        assert_eq!(evaluate("-$1/4"), -0.5);
+        assert_eq!(evaluate("--.5"), 0.5);
+        assert_eq!(evaluate("$9"), 0.0);
+        assert!(parse_expression("1+").is_err());
+        assert!(parse_expression("$0").is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let pad = RoundedRectangle {
+            width: 2.0,
+            height: 1.0,
+            radius: 0.25,
+        };
+        let body = ApertureMacro::parse(pad.name(), &pad.content()).unwrap();
+        assert_eq!(body.statements.len(), 7);
+        let primitives = body.evaluate(&pad.parameters()).unwrap();
+        assert_eq!(
+            primitives[0],
+            MacroPrimitive::CenterLine {
+                exposure: Polarity::Dark,
+                width: 2.0,
+                height: 0.5,
+                center: Point::default(),
+                rotation: 0.0,
+            }
+        );
+        assert_eq!(
+            primitives[5],
+            MacroPrimitive::Circle {
+                exposure: Polarity::Dark,
+                diameter: 0.5,
+                center: Point { x: 0.75, y: -0.25 },
+                rotation: 0.0,
+            }
+        );
+
+        let words = ["$2=$1x2", "4,1,3,0,0,$2,0,0,$2,0,0,45", "7,0,0,1,0.8,0.1,0"];
+        let primitives = ApertureMacro::parse("M", &words)
+            .unwrap()
+            .evaluate(&[0.5])
+            .unwrap();
+        assert_eq!(
+            primitives[0],
+            MacroPrimitive::Outline {
+                exposure: Polarity::Dark,
+                points: vec![
+                    Point { x: 0.0, y: 0.0 },
+                    Point { x: 1.0, y: 0.0 },
+                    Point { x: 0.0, y: 1.0 },
+                ],
+                rotation: 45.0,
+            }
+        );
+        assert_eq!(primitives[1].exposure(), Polarity::Dark);
+
+        for (word, message) in [
+            ("6,0,0,1,0.1,0.1,2,0.1,1,0", "unsupported primitive code 6"),
+            ("1,1,0.5", "has 2 modifiers"),
+            ("1,2,0.5,0,0", "invalid exposure"),
+            ("4,1,3,0,0,1,0,0,1,0", "outline of 3 vertices"),
+            ("5,1,4000000000,0,0,1,0", "polygon of 4000000000 vertices"),
+            ("5,1,2.5,0,0,1,0", "polygon of 2.5 vertices"),
+        ] {
+            let body = ApertureMacro::parse("M", &[word]).unwrap();
+            let error = body.evaluate(&[]).unwrap_err().to_string();
+            assert!(error.contains(message), "{error}");
+        }
+        assert!(ApertureMacro::parse("M", &["1,1,0.5x"]).is_err());
+
+        // the variable number doesn't size anything
+        let body = ApertureMacro::parse("M", &["$2000000000=$1x2", "1,1,$2000000000,0,0"]);
+        let primitives = body.unwrap().evaluate(&[0.5]).unwrap();
+        assert!(
+            matches!(primitives[0], MacroPrimitive::Circle { diameter, .. } if diameter == 1.0)
+        );
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        for seed in 0..64u8 {
+            let raw: Vec<u8> = (0..256u32)
+                .map(|i| (i as u8).wrapping_mul(seed) ^ seed)
+                .collect();
+            let mut u = Unstructured::new(&raw);
+            let statement = MacroStatement::arbitrary(&mut u).unwrap();
+            if !matches!(statement, MacroStatement::Comment(_)) {
+                assert_eq!(super::statement(&statement.to_string()), Ok(statement));
+            }
+            if let MacroPrimitive::Polygon { vertices, .. } =
+                MacroPrimitive::arbitrary(&mut u).unwrap()
+            {
+                assert!((3..=12).contains(&vertices));
+            }
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        let simplify = |words: &[&str]| {
+            let body = ApertureMacro::parse("M", words).unwrap();
+            let simplified = body.simplify();
+            for parameters in [[0.0, 0.0], [0.5, 2.0]] {
+                assert_eq!(
+                    simplified.evaluate(&parameters).unwrap(),
+                    body.evaluate(&parameters).unwrap()
+                );
+            }
+            let words: Vec<_> = simplified
+                .statements
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            words
+        };
+
+        // folding, through variables defined as constants
+        assert_eq!(
+            simplify(&["1,1,(1+2)x0.5,-(2-1),$1x2", "$3=2/4", "1,0,$3x$2,$3,0"]),
+            ["1,1,1.5,-1,$1x2", "1,0,0.5x$2,0.5,0"]
+        );
+        assert_eq!(simplify(&["1,1,1/0,0,0"]), ["1,1,1/0,0,0"]);
+
+        // dead variables, whether never read or redefined before a read
+        assert_eq!(
+            simplify(&[
+                "0 pad",
+                "$3=$1x2",
+                "$4=$2",
+                "$3=$1+$2",
+                "$5=$3x2",
+                "1,1,$5,0,0"
+            ]),
+            ["0 pad", "$3=$1+$2", "$5=$3x2", "1,1,$5,0,0"]
+        );
+        assert_eq!(
+            simplify(&["$2=$1", "$1=$2x2", "1,1,$1,0,0"]),
+            ["$2=$1", "$1=$2x2", "1,1,$1,0,0"]
+        );
+
+        // deprecated lines
+        assert_eq!(
+            simplify(&["2,1,0.5,0,0,1,0,0", "22,0,$1,1,-1,0,30"]),
+            ["20,1,0.5,0,0,1,0,0", "21,0,$1,1,-1+$1/2,0.5,30"]
+        );
+    }
+
+    #[test]
+    fn test_deprecated_lines() {
+        let words = ["2,1,0.5,0,0,1,0,0", "22,0,2,1,-1,0,30"];
+        let primitives = ApertureMacro::parse("M", &words)
+            .unwrap()
+            .evaluate(&[])
+            .unwrap();
+        assert_eq!(
+            primitives[0],
+            MacroPrimitive::VectorLine {
+                exposure: Polarity::Dark,
+                width: 0.5,
+                start: Point::default(),
+                end: Point { x: 1.0, y: 0.0 },
+                rotation: 0.0,
+            }
+        );
+        assert_eq!(
+            primitives[1],
+            MacroPrimitive::CenterLine {
+                exposure: Polarity::Clear,
+                width: 2.0,
+                height: 1.0,
+                center: Point { x: 0.0, y: 0.5 },
+                rotation: 30.0,
+            }
+        );
+    }
+}
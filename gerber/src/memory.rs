@@ -0,0 +1,408 @@
+//! Approximate heap usage
+//!
+//! Services processing untrusted uploads can check [estimate] before
+//! parsing, and the [MemoryUsage] of the layer or image before the next,
+//! more expensive step, to keep each upload within a memory budget.
+//!
+//! Sizes are approximate: vectors count their capacity, maps count their
+//! entries without node overhead, and maps shared between objects count
+//! once. Strings borrowed from the source don't count, as the source is
+//! held by the caller.
+
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::ops::{Add, AddAssign};
+use std::sync::Arc;
+
+use crate::aperture::ApertureTemplate;
+use crate::attribute::{ApertureAttributeName, FileAttributeName, ObjectAttributeName};
+use crate::command::Command::{self, *};
+use crate::data::{EscapedString, SharedStr};
+use crate::image::{AttributeMap, Image, Object, Shape};
+use crate::macros::{Expression, MacroStatement};
+use crate::span::Span;
+use crate::GerberLayer;
+
+/// The approximate heap usage of a layer, image or copper
+///
+/// ```
+/// use gerber::GerberLayer;
+///
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\nD10*\nX0Y0D03*\nM02*\n";
+/// let layer = GerberLayer::parse(src).unwrap();
+/// let usage = layer.memory_usage();
+/// assert_eq!(usage.items, 6);
+/// assert!(usage.bytes >= 6 * std::mem::size_of::<gerber::command::Command>());
+/// assert!(usage.bytes <= gerber::memory::estimate(src).bytes);
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MemoryUsage {
+    /// Commands of a layer, objects of an image or polygons of copper
+    pub items: usize,
+
+    /// Bytes of strings owned rather than borrowed from the source
+    pub string_bytes: usize,
+
+    /// Points of region contours or polygon rings
+    pub vertices: usize,
+
+    /// Heap bytes in all, including the strings
+    pub bytes: usize,
+}
+
+impl Add for MemoryUsage {
+    type Output = MemoryUsage;
+
+    fn add(self, other: MemoryUsage) -> MemoryUsage {
+        MemoryUsage {
+            items: self.items + other.items,
+            string_bytes: self.string_bytes + other.string_bytes,
+            vertices: self.vertices + other.vertices,
+            bytes: self.bytes + other.bytes,
+        }
+    }
+}
+
+impl AddAssign for MemoryUsage {
+    fn add_assign(&mut self, other: MemoryUsage) {
+        *self = *self + other;
+    }
+}
+
+impl MemoryUsage {
+    fn string(&mut self, capacity: usize) {
+        self.string_bytes += capacity;
+        self.bytes += capacity;
+    }
+
+    /// Count the strings which are owned
+    fn strings<'c>(&mut self, values: impl IntoIterator<Item = &'c SharedStr<'c>>) {
+        for value in values {
+            if let SharedStr::Shared(value) = value {
+                self.string(value.len());
+            }
+        }
+    }
+
+    fn escaped(&mut self, value: &EscapedString) {
+        match value {
+            EscapedString::Unescaped(value) | EscapedString::Escaped(value) => {
+                self.strings([value])
+            }
+        }
+    }
+
+    fn vec<T>(&mut self, values: &Vec<T>) {
+        self.bytes += values.capacity() * size_of::<T>();
+    }
+
+    fn template(&mut self, template: &ApertureTemplate) {
+        if let ApertureTemplate::Macro { name, parameters } = template {
+            self.strings([name]);
+            self.vec(parameters);
+        }
+    }
+
+    /// Count the comments and the expression trees of a macro body
+    fn statements(&mut self, statements: &Vec<MacroStatement>) {
+        fn expression(usage: &mut MemoryUsage, value: &Expression) {
+            match value {
+                Expression::Number(_) | Expression::Variable(_) => {}
+                Expression::Negate(operand) => {
+                    usage.bytes += size_of::<Expression>();
+                    expression(usage, operand);
+                }
+                Expression::Binary(left, _, right) => {
+                    usage.bytes += 2 * size_of::<Expression>();
+                    expression(usage, left);
+                    expression(usage, right);
+                }
+            }
+        }
+
+        self.vec(statements);
+        for statement in statements {
+            match statement {
+                MacroStatement::Comment(text) => self.string(text.capacity()),
+                MacroStatement::Variable(_, value) => expression(self, value),
+                MacroStatement::Primitive(_, modifiers) => {
+                    self.vec(modifiers);
+                    for modifier in modifiers {
+                        expression(self, modifier);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Count a map, and the interned strings not in `strings` yet
+    fn map(&mut self, map: &AttributeMap, strings: &mut HashSet<*const str>) {
+        for (name, values) in map {
+            self.bytes += size_of::<Arc<str>>() + size_of::<Vec<Arc<str>>>();
+            self.vec(values);
+            for string in std::iter::once(name).chain(values) {
+                if strings.insert(Arc::as_ptr(string)) {
+                    self.bytes += 2 * size_of::<usize>();
+                    self.string(string.len());
+                }
+            }
+        }
+    }
+
+    fn command(&mut self, command: &Command) {
+        match command {
+            Comment(text) => self.escaped(text),
+            ApertureDefine(_, template) => self.template(template),
+            ApertureMacro(name, body) => {
+                self.strings([name]);
+                self.statements(body);
+            }
+            AttributeOnFile(name, values) => {
+                match name {
+                    FileAttributeName::UnknownStandardName(name)
+                    | FileAttributeName::UserDefinedName(name) => self.strings([name]),
+                    _ => (),
+                }
+                self.attribute_values(values);
+            }
+            AttributeOnAperture(name, values) => {
+                match name {
+                    ApertureAttributeName::UnknownStandardName(name)
+                    | ApertureAttributeName::UserDefinedName(name) => self.strings([name]),
+                    _ => (),
+                }
+                self.attribute_values(values);
+            }
+            AttributeOnObject(name, values) => {
+                match name {
+                    ObjectAttributeName::UnknownStandardName(name)
+                    | ObjectAttributeName::UserDefinedName(name) => self.strings([name]),
+                    _ => (),
+                }
+                self.attribute_values(values);
+            }
+            AttributeDelete(Some(name)) => self.strings([name]),
+            _ => (),
+        }
+    }
+
+    fn attribute_values(&mut self, values: &Vec<EscapedString>) {
+        self.vec(values);
+        values.iter().for_each(|value| self.escaped(value));
+    }
+
+    fn object(&mut self, object: &Object) {
+        if let Shape::Region { contours } = &object.shape {
+            self.vec(contours);
+            for contour in contours {
+                self.vec(&contour.segments);
+                self.vertices += contour.segments.len() + 1;
+            }
+        }
+    }
+}
+
+/// An upper estimate of the usage of the layer parsed from `src`, without
+/// parsing it
+///
+/// Every command ends with `*`, so there are at most as many commands as
+/// asterisks, and attribute and macro values are separated by `,` or `*`.
+/// Vectors are allowed to have grown to twice their length, or four for
+/// short lists. Strings are assumed borrowed.
+pub fn estimate(src: &str) -> MemoryUsage {
+    let commands = crate::command_capacity(src);
+    let commas = src.bytes().filter(|&byte| byte == b',').count();
+    let values = 4 * commands + 2 * commas;
+    MemoryUsage {
+        items: commands,
+        bytes: 2 * commands * (size_of::<Command>() + size_of::<Span>())
+            + values * size_of::<EscapedString>(),
+        ..Default::default()
+    }
+}
+
+impl GerberLayer<'_> {
+    /// The approximate heap usage of the commands and their spans
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            items: self.commands.len(),
+            ..Default::default()
+        };
+        usage.vec(&self.commands);
+        usage.vec(&self.spans);
+        self.commands
+            .iter()
+            .for_each(|command| usage.command(command));
+        usage
+    }
+}
+
+impl Image {
+    /// The approximate heap usage of the objects, attributes and aperture
+    /// templates
+    ///
+    /// Region vertices are counted once per segment end, plus the start,
+    /// and interned attribute strings once however many maps hold them.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            items: self.objects.len(),
+            ..Default::default()
+        };
+        usage.vec(&self.objects);
+        let mut strings = HashSet::new();
+        usage.map(&self.file_attributes, &mut strings);
+        for template in self.apertures.values() {
+            usage.bytes += size_of::<usize>() + size_of::<ApertureTemplate>();
+            usage.template(template);
+        }
+
+        let mut maps = HashSet::new();
+        for object in &self.objects {
+            usage.object(object);
+            for map in [&object.attributes.aperture, &object.attributes.object] {
+                if maps.insert(Arc::as_ptr(map)) {
+                    usage.bytes += 2 * size_of::<usize>();
+                    usage.map(map, &mut strings);
+                }
+            }
+        }
+        usage
+    }
+}
+
+#[cfg(feature = "boolean")]
+impl crate::copper::Copper {
+    /// The approximate heap usage of the polygons
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            items: self.polygons.len(),
+            ..Default::default()
+        };
+        usage.vec(&self.polygons);
+        usage.vec(&self.skipped);
+        for polygon in &self.polygons {
+            usage.vec(&polygon.holes);
+            for ring in std::iter::once(&polygon.exterior).chain(&polygon.holes) {
+                usage.vec(ring);
+                usage.vertices += ring.len();
+            }
+        }
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const SRC: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        G04 a comment*
+        %TF.FileFunction,Copper,L1,Top*%
+        %TO.N,GND*%
+        %ADD10C,0.1*%
+        D10*
+        G01*
+        G36*
+        X0Y0D02*
+        X1000000Y0D01*
+        X0Y1000000D01*
+        X0Y0D01*
+        G37*
+        X0Y0D03*
+        X1000000Y0D03*
+        M02*
+    "};
+
+    #[test]
+    fn test_layer() {
+        let layer = GerberLayer::parse(SRC).unwrap();
+        let borrowed = layer.memory_usage();
+        assert_eq!(borrowed.items, layer.commands().len());
+        assert_eq!(borrowed.string_bytes, 0);
+        assert!(borrowed.bytes <= estimate(SRC).bytes);
+
+        // owned strings count
+        let owned = layer.into_owned().memory_usage();
+        assert_eq!(owned.items, borrowed.items);
+        assert_eq!(
+            owned.string_bytes,
+            " a comment".len() + "Copper".len() + 2 + 3 + 3
+        );
+        assert_eq!(owned.bytes, borrowed.bytes + owned.string_bytes);
+    }
+
+    #[test]
+    fn test_image() {
+        let image = GerberLayer::parse(SRC).unwrap().image();
+        let usage = image.memory_usage();
+        assert_eq!(usage.items, 3);
+        assert_eq!(usage.vertices, 4);
+        // the file attribute, and the net shared by all three objects
+        assert_eq!(
+            usage.string_bytes,
+            ".FileFunction".len() + "CopperL1Top".len() + ".N".len() + "GND".len()
+        );
+        assert!(usage.bytes >= 3 * size_of::<Object>() + usage.string_bytes);
+
+        // interned strings count once across maps
+        let image = GerberLayer::parse(indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            %TO.N,GND*%
+            X0Y0D03*
+            %TO.C,R1*%
+            X0Y0D03*
+            %TO.C,GND*%
+            X0Y0D03*
+            M02*
+        "})
+        .unwrap()
+        .image();
+        assert_eq!(
+            image.memory_usage().string_bytes,
+            ".N".len() + "GND".len() + ".C".len() + "R1".len()
+        );
+    }
+
+    #[cfg(feature = "boolean")]
+    #[test]
+    fn test_copper() {
+        let copper = GerberLayer::parse(SRC).unwrap().copper(0.01);
+        let usage = copper.memory_usage();
+        assert_eq!(usage.items, copper.polygons.len());
+        let vertices: usize = copper.polygons.iter().map(|p| p.exterior.len()).sum();
+        assert_eq!(usage.vertices, vertices);
+        assert!(usage.bytes >= vertices * size_of::<crate::image::Point>());
+    }
+
+    #[test]
+    fn test_command_size() {
+        // operations are the bulk of most layers, so a variant growing
+        // past its coordinates should be boxed rather than grow them all
+        assert_eq!(size_of::<crate::data::Coordinates>(), 32);
+        assert!(size_of::<Command>() <= 64);
+        let flashes = GerberLayer::parse("%FSLAX26Y26*%\nX0Y0D03*\nX1Y1D03*\nM02*\n").unwrap();
+        let per_flash = flashes.memory_usage().bytes / flashes.commands().len();
+        assert_eq!(per_flash, size_of::<Command>() + size_of::<Span>());
+    }
+
+    #[test]
+    fn test_add() {
+        let a = MemoryUsage {
+            items: 1,
+            string_bytes: 2,
+            vertices: 3,
+            bytes: 4,
+        };
+        let mut b = a;
+        b += a;
+        assert_eq!(b, a + a);
+        assert_eq!(b.bytes, 8);
+    }
+}
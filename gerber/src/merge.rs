@@ -0,0 +1,195 @@
+//! Merging of collinear draws
+//!
+//! Some exporters write a straight track as a polyline of many short draws.
+//! Consecutive linear draws which continue in the same direction can be
+//! replaced by a single draw from the start of the first to the end of the
+//! last, without changing the image.
+
+use std::mem;
+
+use crate::command::Command::*;
+use crate::data::{Coordinates, InterpolationMode};
+use crate::span::Span;
+use crate::GerberLayer;
+
+impl GerberLayer<'_> {
+    /// Merge chains of collinear linear draws, returning the number of
+    /// draws removed
+    ///
+    /// Only draws which directly follow each other are merged, so the
+    /// aperture, polarity and attributes are the same for the whole chain.
+    /// Contours in regions are merged in the same way. The merged draw's
+    /// span covers the text of the draws it replaced.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\nD10*\nG01*\n\
+    ///            X0Y0D02*\nX1000000D01*\nX2000000D01*\nX3000000D01*\nM02*\n";
+    /// let mut layer = GerberLayer::parse(src).unwrap();
+    /// assert_eq!(layer.merge_collinear_draws(), 2);
+    /// assert_eq!(layer.image().objects.len(), 1);
+    /// ```
+    pub fn merge_collinear_draws(&mut self) -> usize {
+        let mut commands = Vec::with_capacity(self.commands.len());
+        let mut spans: Vec<Span> = Vec::with_capacity(self.spans.len());
+        let mut interpolation = None;
+        let mut point = (0, 0);
+
+        // the start of the last command if it is a linear draw
+        let mut draw_start = None;
+
+        let mut merged = 0;
+        for (command, span) in mem::take(&mut self.commands)
+            .into_iter()
+            .zip(mem::take(&mut self.spans))
+        {
+            let start = point;
+            let mut linear_draw = false;
+            match &command {
+                SetLinear | SetCWCircular | SetCCWCircular => {
+                    interpolation = command.interpolation_mode()
+                }
+                Plot(coordinates, None) if interpolation == Some(InterpolationMode::Linear) => {
+                    point = (
+                        coordinates.x.unwrap_or(point.0),
+                        coordinates.y.unwrap_or(point.1),
+                    );
+                    linear_draw = true;
+                }
+                Plot(coordinates, _) | Move(coordinates) | Flash(coordinates) => {
+                    point = (
+                        coordinates.x.unwrap_or(point.0),
+                        coordinates.y.unwrap_or(point.1),
+                    );
+                }
+                _ => (),
+            }
+
+            if let (true, Some(previous_start)) = (linear_draw, draw_start) {
+                if continues(previous_start, start, point) {
+                    // the merged draw gives both coordinates, as the
+                    // omitted ones referred to the removed draw's end
+                    commands.pop();
+                    let previous = spans.pop().unwrap();
+                    commands.push(Plot(
+                        Coordinates {
+                            x: Some(point.0),
+                            y: Some(point.1),
+                        },
+                        None,
+                    ));
+                    spans.push(Span {
+                        bytes: previous.bytes.start..span.bytes.end,
+                        line: previous.line,
+                        end_line: span.end_line,
+                    });
+                    merged += 1;
+                    continue;
+                }
+            }
+            draw_start = linear_draw.then_some(start);
+            commands.push(command);
+            spans.push(span);
+        }
+
+        self.commands = commands;
+        self.spans = spans;
+        merged
+    }
+}
+
+/// True if the draw from `b` to `c` continues the draw from `a` to `b` in
+/// the same direction
+fn continues(a: (i64, i64), b: (i64, i64), c: (i64, i64)) -> bool {
+    let first = ((b.0 - a.0) as i128, (b.1 - a.1) as i128);
+    let second = ((c.0 - b.0) as i128, (c.1 - b.1) as i128);
+    let cross = first.0 * second.1 - first.1 * second.0;
+    let dot = first.0 * second.0 + first.1 * second.1;
+    cross == 0 && dot > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const HEADER: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10C,0.1*%
+        D10*
+        G01*
+    "};
+
+    fn merged(body: &str) -> (String, usize) {
+        let src = format!("{HEADER}{body}M02*\n");
+        let mut layer = GerberLayer::parse(&src).unwrap();
+        let image = layer.image();
+        let merged = layer.merge_collinear_draws();
+        let after = layer.image();
+        assert_eq!(after.objects.len(), image.objects.len() - merged);
+        let text = layer
+            .spanned()
+            .filter(|spanned| matches!(spanned.node, Plot(..)))
+            .map(|spanned| src[spanned.span.bytes].replace('\n', " "))
+            .collect::<Vec<_>>()
+            .join("|");
+        (text, merged)
+    }
+
+    #[test]
+    fn test_merge() {
+        let body = indoc! {"
+            X0Y0D02*
+            X1000000D01*
+            X2000000D01*
+            X2000000Y1000000D01*
+            Y2000000D01*
+            X3000000Y3000000D01*
+            X4000000Y4000000D01*
+        "};
+        let (text, merged) = merged(body);
+        assert_eq!(merged, 3);
+        assert_eq!(
+            text,
+            "X1000000D01* X2000000D01*|\
+             X2000000Y1000000D01* Y2000000D01*|\
+             X3000000Y3000000D01* X4000000Y4000000D01*"
+        );
+    }
+
+    #[test]
+    fn test_merged_coordinates() {
+        let src = format!("{HEADER}X0Y0D02*\nX1000000D01*\nX2000000D01*\nY5D01*\nM02*\n");
+        let mut layer = GerberLayer::parse(&src).unwrap();
+        let before = layer.image();
+        layer.merge_collinear_draws();
+        // the draw omitting X still ends where it did
+        assert_eq!(
+            layer.image().objects.last().unwrap().shape,
+            before.objects.last().unwrap().shape
+        );
+    }
+
+    #[test]
+    fn test_not_merged() {
+        let body = indoc! {"
+            X0Y0D02*
+            X1000000D01*
+            X0D01*
+            X1000000Y1D01*
+            D03*
+            X2000000Y1D01*
+            G02*
+            X3000000Y1I1J0D01*
+            G01*
+            X4000000D01*
+            %LPC*%
+            X5000000D01*
+        "};
+        // backtracking, a bend, a flash between, an arc and a polarity
+        // change all stop a chain
+        assert_eq!(merged(body).1, 0);
+    }
+}
@@ -0,0 +1,840 @@
+//! Triangle meshes for GPU rendering
+//!
+//! Polygons are cut into triangles by ear clipping, after joining each
+//! hole to the exterior by a bridge, following
+//! [earcut](https://github.com/mapbox/earcut). A [Mesh] is laid out as a
+//! vertex buffer of `[f32; 2]` and an index buffer of `u32`, ready for
+//! upload to wgpu or WebGL as it is.
+//!
+//! Ear clipping takes time quadratic in the vertices of a polygon, which is
+//! nothing for pads and tracks but can be noticeable for large pours.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::copper::{ConvertOptions, Copper, Polygon};
+use crate::data::{Polarity, Unit};
+use crate::image::{Object, Point};
+use crate::{parallel, GerberLayer};
+
+/// Triangles as a vertex buffer and an index buffer
+///
+/// ```
+/// use gerber::GerberLayer;
+///
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,2X1*%\nD10*\nX0Y0D03*\nM02*\n";
+/// let mesh = GerberLayer::parse(src).unwrap().copper(0.001).mesh();
+/// assert_eq!(mesh.vertices.len(), 4);
+/// assert_eq!(mesh.indices.len(), 6);
+/// assert_eq!(mesh.area(), 2.0);
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Mesh {
+    /// Points in millimeters
+    pub vertices: Vec<[f32; 2]>,
+
+    /// Three indices into the vertices for each triangle, counter-clockwise
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// The triangles of `polygons` in one mesh
+    pub fn new<'a>(polygons: impl IntoIterator<Item = &'a Polygon>) -> Self {
+        let mut mesh = Mesh::default();
+        polygons.into_iter().for_each(|polygon| mesh.add(polygon));
+        mesh
+    }
+
+    /// Add the triangles of a polygon
+    ///
+    /// The vertices are those of [Polygon::to_f32], so shared corners of
+    /// triangles are exactly the same points.
+    pub fn add(&mut self, polygon: &Polygon) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(polygon.to_f32().into_iter().flatten());
+        self.indices.extend(triangulate(polygon, first));
+    }
+
+    /// The triangles, as their corners
+    pub fn triangles(&self) -> impl Iterator<Item = [[f32; 2]; 3]> + '_ {
+        self.indices
+            .chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|corner| self.vertices[triangle[corner] as usize]))
+    }
+
+    /// The total area of the triangles in square millimeters
+    pub fn area(&self) -> f64 {
+        self.triangles()
+            .map(|[a, b, c]| {
+                let [a, b, c] = [a, b, c].map(|[x, y]| (x as f64, y as f64));
+                ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)) / 2.0
+            })
+            .sum()
+    }
+}
+
+impl Copper {
+    /// The triangles of the polygons
+    pub fn mesh(&self) -> Mesh {
+        Mesh::new(&self.polygons)
+    }
+}
+
+/// The objects of a layer with the same polarity and net, between two
+/// changes of polarity, united
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MeshGroup {
+    pub polarity: Polarity,
+
+    /// The `.N` attribute of the objects
+    pub net: Option<Arc<str>>,
+
+    pub mesh: Mesh,
+}
+
+impl GerberLayer<'_> {
+    /// Triangles grouped by polarity and net, in order of their first
+    /// object, so a viewer can color or hide nets on the GPU
+    ///
+    /// Groups are split at each change of polarity, so the objects of a
+    /// group come from one run of the same polarity. Drawing the groups in
+    /// order, clear ones in the background color, composites them as the
+    /// layer does. Objects whose geometry is unknown, i.e. flashes and
+    /// draws of apertures whose macro is undefined or can't be evaluated,
+    /// are left out.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    /// use gerber::data::Polarity;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,1X1*%\nD10*\n\
+    ///            %TO.N,GND*%\nX0Y0D03*\n%TO.N,VCC*%\nX2000000Y0D03*\n\
+    ///            %TO.N,GND*%\nX4000000Y0D03*\nM02*\n";
+    /// let groups = GerberLayer::parse(src).unwrap().meshes(&Default::default());
+    /// let nets: Vec<_> = groups.iter().map(|group| group.net.as_deref()).collect();
+    /// assert_eq!(nets, [Some("GND"), Some("VCC")]);
+    /// assert_eq!(groups[0].polarity, Polarity::Dark);
+    /// assert_eq!(groups[0].mesh.area(), 2.0);
+    /// ```
+    pub fn meshes(&self, options: &ConvertOptions) -> Vec<MeshGroup> {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let mut groups = HashMap::new();
+        let mut keys = Vec::new();
+        let mut objects: Vec<Vec<&Object>> = Vec::new();
+        let mut run = 0;
+        for (index, object) in image.objects.iter().enumerate() {
+            if index > 0 && image.objects[index - 1].polarity != object.polarity {
+                run += 1;
+            }
+            let net = object
+                .attributes
+                .get(".N")
+                .and_then(|values| values.first());
+            let key = (run, object.polarity, net.cloned());
+            let group = *groups.entry(key.clone()).or_insert_with(|| {
+                keys.push(key);
+                objects.push(Vec::new());
+                objects.len() - 1
+            });
+            objects[group].push(object);
+        }
+        let groups = keys.into_iter().zip(objects).collect();
+        parallel::map(groups, |((_, polarity, net), objects)| {
+            let polygons = self.unite(objects, unit, options);
+            MeshGroup {
+                polarity,
+                net,
+                mesh: Mesh::new(&polygons),
+            }
+        })
+    }
+}
+
+/// The triangles of a polygon whose first vertex is `first`
+fn triangulate(polygon: &Polygon, first: u32) -> Vec<u32> {
+    let mut earcut = Earcut::default();
+    let Some(mut outer) = earcut.ring(&polygon.exterior, first, true) else {
+        return Vec::new();
+    };
+    if earcut.nodes[outer].next == earcut.nodes[outer].prev {
+        return Vec::new();
+    }
+
+    // bridge the holes from left to right
+    let mut holes = Vec::new();
+    let mut start = first + polygon.exterior.len() as u32;
+    for hole in &polygon.holes {
+        if let Some(node) = earcut.ring(hole, start, false) {
+            holes.push(earcut.leftmost(node));
+        }
+        start += hole.len() as u32;
+    }
+    holes.sort_by(|&a, &b| earcut.nodes[a].x.total_cmp(&earcut.nodes[b].x));
+    for hole in holes {
+        outer = earcut.eliminate_hole(hole, outer);
+    }
+
+    earcut.clip(outer, Pass::Ears);
+    earcut.triangles
+}
+
+/// A vertex in the circular lists of the rings being cut
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    /// Index of the vertex in the mesh
+    i: u32,
+    x: f64,
+    y: f64,
+    prev: usize,
+    next: usize,
+}
+
+/// What to try when no ear is left, each after the one before
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Pass {
+    Ears,
+    Filtered,
+    Cured,
+}
+
+#[derive(Default)]
+struct Earcut {
+    nodes: Vec<Node>,
+    triangles: Vec<u32>,
+}
+
+impl Earcut {
+    /// Link a ring, counter-clockwise for the exterior and clockwise for a
+    /// hole, returning its last node
+    fn ring(&mut self, points: &[Point], first: u32, exterior: bool) -> Option<usize> {
+        let area: f64 = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| a.x * b.y - b.x * a.y)
+            .sum();
+        let mut vertices: Vec<_> = (first..).zip(points).collect();
+        if (area > 0.0) != exterior {
+            vertices.reverse();
+        }
+        let mut last = None;
+        for (i, point) in vertices {
+            last = Some(self.insert(i, *point, last));
+        }
+        let last = last?;
+        let next = self.nodes[last].next;
+        if self.equals(last, next) {
+            self.remove(last);
+            return Some(next);
+        }
+        Some(last)
+    }
+
+    fn insert(&mut self, i: u32, point: Point, last: Option<usize>) -> usize {
+        let node = self.nodes.len();
+        let (prev, next) = match last {
+            Some(last) => (last, self.nodes[last].next),
+            None => (node, node),
+        };
+        self.nodes.push(Node {
+            i,
+            x: point.x,
+            y: point.y,
+            prev,
+            next,
+        });
+        self.nodes[prev].next = node;
+        self.nodes[next].prev = node;
+        node
+    }
+
+    fn remove(&mut self, node: usize) {
+        let Node { prev, next, .. } = self.nodes[node];
+        self.nodes[next].prev = prev;
+        self.nodes[prev].next = next;
+    }
+
+    fn equals(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.nodes[a], &self.nodes[b]);
+        a.x == b.x && a.y == b.y
+    }
+
+    /// Twice the signed area of a triangle, negative when counter-clockwise
+    fn area(&self, p: usize, q: usize, r: usize) -> f64 {
+        let (p, q, r) = (&self.nodes[p], &self.nodes[q], &self.nodes[r]);
+        (q.y - p.y) * (r.x - q.x) - (q.x - p.x) * (r.y - q.y)
+    }
+
+    /// True if `p` is inside or on the counter-clockwise triangle `a`, `b`,
+    /// `c`, and not at `a`
+    fn in_triangle(&self, a: usize, b: usize, c: usize, p: usize) -> bool {
+        let (a, b, c, p) = (
+            &self.nodes[a],
+            &self.nodes[b],
+            &self.nodes[c],
+            &self.nodes[p],
+        );
+        let (ax, ay, bx, by, cx, cy) = (a.x, a.y, b.x, b.y, c.x, c.y);
+        !(ax == p.x && ay == p.y) && point_in_triangle([ax, ay, bx, by, cx, cy], p.x, p.y)
+    }
+
+    fn leftmost(&self, start: usize) -> usize {
+        let mut leftmost = start;
+        let mut p = start;
+        loop {
+            let (node, best) = (&self.nodes[p], &self.nodes[leftmost]);
+            if node.x < best.x || (node.x == best.x && node.y < best.y) {
+                leftmost = p;
+            }
+            p = node.next;
+            if p == start {
+                return leftmost;
+            }
+        }
+    }
+
+    /// Remove duplicate and collinear points from `start` up to `end`,
+    /// returning a node which is still linked
+    fn filter(&mut self, start: usize, end: Option<usize>) -> usize {
+        let mut end = end.unwrap_or(start);
+        let mut p = start;
+        loop {
+            let Node { prev, next, .. } = self.nodes[p];
+            let again = self.equals(p, next) || self.area(prev, p, next) == 0.0;
+            if again {
+                self.remove(p);
+                p = prev;
+                end = prev;
+                if p == self.nodes[p].next {
+                    return end;
+                }
+            } else {
+                p = next;
+            }
+            if !again && p == end {
+                return end;
+            }
+        }
+    }
+
+    /// Cut ears off the ring of `ear` until a triangle is left, trying
+    /// harder after each pass around finds none
+    fn clip(&mut self, mut ear: usize, pass: Pass) {
+        let mut stop = ear;
+        while self.nodes[ear].prev != self.nodes[ear].next {
+            let Node { prev, next, .. } = self.nodes[ear];
+            if self.is_ear(ear) {
+                let i = |node: usize| self.nodes[node].i;
+                self.triangles.extend([i(prev), i(ear), i(next)]);
+                self.remove(ear);
+                // skipping the next vertex leaves fewer slivers
+                ear = self.nodes[next].next;
+                stop = ear;
+                continue;
+            }
+            ear = next;
+            if ear == stop {
+                match pass {
+                    Pass::Ears => {
+                        let ear = self.filter(ear, None);
+                        self.clip(ear, Pass::Filtered);
+                    }
+                    Pass::Filtered => {
+                        let ear = self.filter(ear, None);
+                        let ear = self.cure_local_intersections(ear);
+                        self.clip(ear, Pass::Cured);
+                    }
+                    Pass::Cured => self.split_clip(ear),
+                }
+                return;
+            }
+        }
+    }
+
+    fn is_ear(&self, ear: usize) -> bool {
+        let Node {
+            prev: a, next: c, ..
+        } = self.nodes[ear];
+        if self.area(a, ear, c) >= 0.0 {
+            return false;
+        }
+        // no reflex vertex may be inside
+        let mut p = self.nodes[c].next;
+        while p != a {
+            let Node { prev, next, .. } = self.nodes[p];
+            if self.in_triangle(a, ear, c, p) && self.area(prev, p, next) >= 0.0 {
+                return false;
+            }
+            p = next;
+        }
+        true
+    }
+
+    /// Join a hole to the outer ring, returning a node of the joined ring
+    fn eliminate_hole(&mut self, hole: usize, outer: usize) -> usize {
+        let Some(bridge) = self.hole_bridge(hole, outer) else {
+            return outer;
+        };
+        let reverse = self.split(bridge, hole);
+        self.filter(reverse, Some(self.nodes[reverse].next));
+        self.filter(bridge, Some(self.nodes[bridge].next))
+    }
+
+    /// The outer vertex to join the leftmost vertex of a hole to
+    fn hole_bridge(&self, hole: usize, outer: usize) -> Option<usize> {
+        let Node { x: hx, y: hy, .. } = self.nodes[hole];
+        let mut qx = f64::NEG_INFINITY;
+        let mut bridge = None;
+
+        // the nearest edge crossed by a ray to the left of the hole
+        let mut p = outer;
+        loop {
+            let (node, next) = (&self.nodes[p], &self.nodes[self.nodes[p].next]);
+            if hy <= node.y && hy >= next.y && next.y != node.y {
+                let x = node.x + (hy - node.y) * (next.x - node.x) / (next.y - node.y);
+                if x <= hx && x > qx {
+                    qx = x;
+                    let m = if node.x < next.x { p } else { node.next };
+                    if x == hx {
+                        // the hole touches the edge
+                        return Some(m);
+                    }
+                    bridge = Some(m);
+                }
+            }
+            p = node.next;
+            if p == outer {
+                break;
+            }
+        }
+        let mut m = bridge?;
+
+        // a vertex inside the triangle of the hole point, the crossing and
+        // the edge's end would block the bridge, so take the one at the
+        // smallest angle to the ray
+        let stop = m;
+        let Node { x: mx, y: my, .. } = self.nodes[m];
+        let triangle = if hy < my {
+            [hx, hy, mx, my, qx, hy]
+        } else {
+            [qx, hy, mx, my, hx, hy]
+        };
+        let mut min_tan = f64::INFINITY;
+        let mut p = m;
+        loop {
+            let Node { x, y, next, .. } = self.nodes[p];
+            if hx >= x && x >= mx && hx != x && point_in_triangle(triangle, x, y) {
+                let tan = (hy - y).abs() / (hx - x);
+                let better = tan < min_tan
+                    || (tan == min_tan
+                        && (x > self.nodes[m].x
+                            || (x == self.nodes[m].x && self.sector_contains_sector(m, p))));
+                if self.locally_inside(p, hole) && better {
+                    m = p;
+                    min_tan = tan;
+                }
+            }
+            p = next;
+            if p == stop {
+                return Some(m);
+            }
+        }
+    }
+
+    /// True if the sector of `m` contains the sector of `p`, for bridges
+    /// from the same point
+    fn sector_contains_sector(&self, m: usize, p: usize) -> bool {
+        let (m_node, p_node) = (self.nodes[m], self.nodes[p]);
+        self.area(m_node.prev, m, p_node.prev) < 0.0 && self.area(p_node.next, m, m_node.next) < 0.0
+    }
+
+    /// True if the diagonal from `a` to `b` starts inside the polygon
+    fn locally_inside(&self, a: usize, b: usize) -> bool {
+        let Node { prev, next, .. } = self.nodes[a];
+        if self.area(prev, a, next) < 0.0 {
+            self.area(a, b, next) >= 0.0 && self.area(a, prev, b) >= 0.0
+        } else {
+            self.area(a, b, prev) < 0.0 || self.area(a, next, b) < 0.0
+        }
+    }
+
+    /// True if the middle of the diagonal from `a` to `b` is inside
+    fn middle_inside(&self, a: usize, b: usize) -> bool {
+        let (px, py) = (
+            (self.nodes[a].x + self.nodes[b].x) / 2.0,
+            (self.nodes[a].y + self.nodes[b].y) / 2.0,
+        );
+        let mut inside = false;
+        let mut p = a;
+        loop {
+            let (node, next) = (&self.nodes[p], &self.nodes[self.nodes[p].next]);
+            if (node.y > py) != (next.y > py)
+                && next.y != node.y
+                && px < (next.x - node.x) * (py - node.y) / (next.y - node.y) + node.x
+            {
+                inside = !inside;
+            }
+            p = node.next;
+            if p == a {
+                return inside;
+            }
+        }
+    }
+
+    /// True if the segments `p1`-`q1` and `p2`-`q2` intersect
+    fn intersects(&self, p1: usize, q1: usize, p2: usize, q2: usize) -> bool {
+        let sign = |area: f64| area.partial_cmp(&0.0);
+        let o1 = sign(self.area(p1, q1, p2));
+        let o2 = sign(self.area(p1, q1, q2));
+        let o3 = sign(self.area(p2, q2, p1));
+        let o4 = sign(self.area(p2, q2, q1));
+        let zero = Some(std::cmp::Ordering::Equal);
+        (o1 != o2 && o3 != o4)
+            || (o1 == zero && self.on_segment(p1, p2, q1))
+            || (o2 == zero && self.on_segment(p1, q2, q1))
+            || (o3 == zero && self.on_segment(p2, p1, q2))
+            || (o4 == zero && self.on_segment(p2, q1, q2))
+    }
+
+    /// True if `q`, collinear with `p` and `r`, is between them
+    fn on_segment(&self, p: usize, q: usize, r: usize) -> bool {
+        let (p, q, r) = (&self.nodes[p], &self.nodes[q], &self.nodes[r]);
+        q.x <= p.x.max(r.x) && q.x >= p.x.min(r.x) && q.y <= p.y.max(r.y) && q.y >= p.y.min(r.y)
+    }
+
+    /// True if the diagonal from `a` to `b` crosses an edge of the ring
+    fn intersects_polygon(&self, a: usize, b: usize) -> bool {
+        let (ai, bi) = (self.nodes[a].i, self.nodes[b].i);
+        let mut p = a;
+        loop {
+            let next = self.nodes[p].next;
+            let (pi, ni) = (self.nodes[p].i, self.nodes[next].i);
+            if pi != ai && ni != ai && pi != bi && ni != bi && self.intersects(p, next, a, b) {
+                return true;
+            }
+            p = next;
+            if p == a {
+                return false;
+            }
+        }
+    }
+
+    fn is_valid_diagonal(&self, a: usize, b: usize) -> bool {
+        let (a_node, b_node) = (self.nodes[a], self.nodes[b]);
+        self.nodes[a_node.next].i != b_node.i
+            && self.nodes[a_node.prev].i != b_node.i
+            && !self.intersects_polygon(a, b)
+            && ((self.locally_inside(a, b)
+                && self.locally_inside(b, a)
+                && self.middle_inside(a, b)
+                && (self.area(a_node.prev, a, b_node.prev) != 0.0
+                    || self.area(a, b_node.prev, b) != 0.0))
+                || (self.equals(a, b)
+                    && self.area(a_node.prev, a, a_node.next) > 0.0
+                    && self.area(b_node.prev, b, b_node.next) > 0.0))
+    }
+
+    /// Clip the triangles of small self-intersections, returning a node
+    /// which is still linked
+    fn cure_local_intersections(&mut self, mut start: usize) -> usize {
+        let mut p = start;
+        loop {
+            let a = self.nodes[p].prev;
+            let next = self.nodes[p].next;
+            let b = self.nodes[next].next;
+            if !self.equals(a, b)
+                && self.intersects(a, p, next, b)
+                && self.locally_inside(a, b)
+                && self.locally_inside(b, a)
+            {
+                let i = |node: usize| self.nodes[node].i;
+                self.triangles.extend([i(a), i(p), i(b)]);
+                self.remove(p);
+                self.remove(next);
+                p = b;
+                start = b;
+            }
+            p = self.nodes[p].next;
+            if p == start {
+                return self.filter(p, None);
+            }
+        }
+    }
+
+    /// Split the ring along a valid diagonal and clip both halves
+    fn split_clip(&mut self, start: usize) {
+        let mut a = start;
+        loop {
+            let mut b = self.nodes[self.nodes[a].next].next;
+            while b != self.nodes[a].prev {
+                if self.nodes[a].i != self.nodes[b].i && self.is_valid_diagonal(a, b) {
+                    let c = self.split(a, b);
+                    let a = self.filter(a, Some(self.nodes[a].next));
+                    let c = self.filter(c, Some(self.nodes[c].next));
+                    self.clip(a, Pass::Ears);
+                    self.clip(c, Pass::Ears);
+                    return;
+                }
+                b = self.nodes[b].next;
+            }
+            a = self.nodes[a].next;
+            if a == start {
+                return;
+            }
+        }
+    }
+
+    /// Link `a` to `b` by two copies of the diagonal, splitting the ring in
+    /// two or joining two rings, and return the copy of `b`
+    fn split(&mut self, a: usize, b: usize) -> usize {
+        let (a2, b2) = (self.nodes.len(), self.nodes.len() + 1);
+        self.nodes.push(self.nodes[a]);
+        self.nodes.push(self.nodes[b]);
+        let an = self.nodes[a].next;
+        let bp = self.nodes[b].prev;
+
+        self.nodes[a].next = b;
+        self.nodes[b].prev = a;
+        self.nodes[a2].next = an;
+        self.nodes[an].prev = a2;
+        self.nodes[b2].next = a2;
+        self.nodes[a2].prev = b2;
+        self.nodes[bp].next = b2;
+        self.nodes[b2].prev = bp;
+        b2
+    }
+}
+
+/// True if `(x, y)` is inside or on the triangle of corners `[ax, ay, bx,
+/// by, cx, cy]`, counter-clockwise
+fn point_in_triangle([ax, ay, bx, by, cx, cy]: [f64; 6], x: f64, y: f64) -> bool {
+    (cx - x) * (ay - y) >= (ax - x) * (cy - y)
+        && (ax - x) * (by - y) >= (bx - x) * (ay - y)
+        && (bx - x) * (cy - y) >= (cx - x) * (by - y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn layer(body: &str) -> GerberLayer<'static> {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().into_owned()
+    }
+
+    fn ring(points: &[(f64, f64)]) -> Vec<Point> {
+        points.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    /// Check the triangles are counter-clockwise and cover `area`
+    fn check(mesh: &Mesh, area: f64) {
+        assert_eq!(mesh.indices.len() % 3, 0);
+        assert!(mesh
+            .indices
+            .iter()
+            .all(|&index| (index as usize) < mesh.vertices.len()));
+        let single = Mesh {
+            vertices: mesh.vertices.clone(),
+            indices: Vec::new(),
+        };
+        for triangle in mesh.indices.chunks_exact(3) {
+            let single = Mesh {
+                indices: triangle.to_vec(),
+                ..single.clone()
+            };
+            assert!(single.area() >= 0.0, "clockwise triangle {triangle:?}");
+        }
+        assert!(
+            (mesh.area() - area).abs() <= 1e-5 * area.max(1.0),
+            "{} != {area}",
+            mesh.area()
+        );
+    }
+
+    #[test]
+    fn test_square_with_holes() {
+        let polygon = Polygon {
+            exterior: ring(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]),
+            holes: vec![
+                ring(&[(1.0, 1.0), (1.0, 3.0), (3.0, 3.0), (3.0, 1.0)]),
+                ring(&[(6.0, 6.0), (6.0, 9.0), (9.0, 9.0), (9.0, 6.0)]),
+            ],
+        };
+        let mesh = Mesh::new([&polygon]);
+        assert_eq!(mesh.vertices.len(), 12);
+        // n + 2h - 2 triangles for n vertices and h holes
+        assert_eq!(mesh.indices.len(), 3 * (12 + 4 - 2));
+        check(&mesh, polygon.area());
+    }
+
+    #[test]
+    fn test_concave() {
+        // a comb of 12 vertices, wound clockwise and closed, which is taken
+        // as it is
+        let polygon = Polygon {
+            exterior: ring(&[
+                (0.0, 0.0),
+                (0.0, 3.0),
+                (1.0, 3.0),
+                (1.0, 1.0),
+                (2.0, 1.0),
+                (2.0, 3.0),
+                (3.0, 3.0),
+                (3.0, 1.0),
+                (4.0, 1.0),
+                (4.0, 3.0),
+                (5.0, 3.0),
+                (5.0, 0.0),
+                (0.0, 0.0),
+            ]),
+            holes: Vec::new(),
+        };
+        let mesh = Mesh::new([&polygon]);
+        assert_eq!(mesh.indices.len(), 3 * 10);
+        check(&mesh, 11.0);
+    }
+
+    #[test]
+    fn test_copper() {
+        // round pads with holes, a ring of tracks around a cutout, and a
+        // clear slot through a pour
+        let copper = layer(indoc! {"
+            %ADD10C,2X1*%
+            %ADD11C,0.5*%
+            %ADD12R,0.3X0.3*%
+            D10*
+            X0Y0D03*
+            X3000000Y0D03*
+            D11*
+            G01*
+            X-2000000Y2000000D02*
+            X5000000Y2000000D01*
+            X5000000Y6000000D01*
+            X-2000000Y6000000D01*
+            X-2000000Y2000000D01*
+            G36*
+            X0Y3000000D02*
+            X3000000Y3000000D01*
+            X3000000Y5000000D01*
+            X0Y5000000D01*
+            X0Y3000000D01*
+            G37*
+            %LPC*%
+            D12*
+            X1000000Y4000000D02*
+            X2000000Y4000000D01*
+            X2600000Y4000000D03*
+        "})
+        .copper(0.001);
+        assert!(copper
+            .polygons
+            .iter()
+            .any(|polygon| polygon.holes.len() == 2));
+        check(&copper.mesh(), copper.area());
+    }
+
+    #[test]
+    fn test_groups() {
+        let layer = layer(indoc! {"
+            %ADD10R,1X1*%
+            %ADD11C,1*%
+            D10*
+            %TO.N,GND*%
+            X0Y0D03*
+            X500000Y0D03*
+            %TO.N,VCC*%
+            X3000000Y0D03*
+            %TD.N*%
+            %LPC*%
+            X0Y0D03*
+            %LPD*%
+            %TO.N,GND*%
+            D11*
+            X10000000Y0D03*
+        "});
+        let groups = layer.meshes(&ConvertOptions {
+            tolerance: 0.001,
+            ..Default::default()
+        });
+        let keys: Vec<_> = groups
+            .iter()
+            .map(|group| (group.polarity, group.net.as_deref()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                (Polarity::Dark, Some("GND")),
+                (Polarity::Dark, Some("VCC")),
+                (Polarity::Clear, None),
+                (Polarity::Dark, Some("GND")),
+            ]
+        );
+        // the overlapping pads are united, the disk after the clear pad is not
+        let disk = self::layer("%ADD11C,1*%\nD11*\nX0Y0D03*\n")
+            .copper(0.001)
+            .area();
+        check(&groups[0].mesh, 1.5);
+        check(&groups[1].mesh, 1.0);
+        check(&groups[2].mesh, 1.0);
+        check(&groups[3].mesh, disk);
+    }
+
+    #[test]
+    fn test_paint_order() {
+        // a pad with a hole, and a smaller pad in the hole, all on one net
+        let layer = layer(indoc! {"
+            %ADD10R,3X3*%
+            %ADD11R,2X2*%
+            %ADD12R,1X1*%
+            %TO.N,GND*%
+            D10*
+            X0Y0D03*
+            %LPC*%
+            D11*
+            X0Y0D03*
+            %LPD*%
+            D12*
+            X0Y0D03*
+        "});
+        let groups = layer.meshes(&ConvertOptions {
+            tolerance: 0.001,
+            ..Default::default()
+        });
+        let keys: Vec<_> = groups
+            .iter()
+            .map(|group| (group.polarity, group.net.as_deref()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                (Polarity::Dark, Some("GND")),
+                (Polarity::Clear, Some("GND")),
+                (Polarity::Dark, Some("GND")),
+            ]
+        );
+        check(&groups[0].mesh, 9.0);
+        check(&groups[1].mesh, 4.0);
+        check(&groups[2].mesh, 1.0);
+        // drawn in order, the groups cover what the copper does
+        let copper = layer.copper(0.001).area();
+        let painted = groups[0].mesh.area() - groups[1].mesh.area() + groups[2].mesh.area();
+        assert!((copper - painted).abs() < 1e-9, "{copper} != {painted}");
+    }
+
+    #[test]
+    fn test_degenerate() {
+        let line = Polygon {
+            exterior: ring(&[(0.0, 0.0), (1.0, 0.0), (2.0, 0.0)]),
+            holes: Vec::new(),
+        };
+        assert!(Mesh::new([&line]).indices.is_empty());
+        let empty = Polygon {
+            exterior: Vec::new(),
+            holes: Vec::new(),
+        };
+        assert_eq!(Mesh::new([&empty]), Mesh::default());
+    }
+}
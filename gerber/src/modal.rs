@@ -0,0 +1,116 @@
+//! Modal coordinates
+//!
+//! An operation may leave out X or Y, which then keeps its value from the
+//! previous operation, e.g. `Y0D01*` draws straight down from the current
+//! point. The [image](crate::image) and the other interpreters of this
+//! crate track the current point, but a tool which reads single commands,
+//! or moves and deletes them, needs every coordinate written out.
+
+use crate::command::Command::*;
+use crate::data::Coordinates;
+use crate::GerberLayer;
+
+impl GerberLayer<'_> {
+    /// Fill in the X and Y coordinates which operations leave out,
+    /// returning the number of coordinates added
+    ///
+    /// The current point is undefined before the first operation, and
+    /// like the image this takes it as the origin. Arc offsets are not
+    /// modal and stay as they are. Spans still refer to the original
+    /// source.
+    ///
+    /// ```
+    /// use gerber::command::Command;
+    /// use gerber::data::Coordinates;
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\nD10*\n\
+    ///            X1000000Y2000000D02*\nX3000000D01*\nY0D03*\nM02*\n";
+    /// let mut layer = GerberLayer::parse(src).unwrap();
+    /// assert_eq!(layer.explicit_coordinates(), 2);
+    /// assert_eq!(
+    ///     layer.commands()[6],
+    ///     Command::Flash(Coordinates { x: Some(3000000), y: Some(0) })
+    /// );
+    /// ```
+    pub fn explicit_coordinates(&mut self) -> usize {
+        let mut point = (0, 0);
+        let mut added = 0;
+        for command in &mut self.commands {
+            let coordinates = match command {
+                Plot(coordinates, _) | Move(coordinates) | Flash(coordinates) => coordinates,
+                _ => continue,
+            };
+            let Coordinates { x, y } = coordinates;
+            for (value, current) in [(x, &mut point.0), (y, &mut point.1)] {
+                match value {
+                    Some(value) => *current = *value,
+                    None => {
+                        *value = Some(*current);
+                        added += 1;
+                    }
+                }
+            }
+        }
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn test_explicit_coordinates() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            D03*
+            X1000000D02*
+            Y1000000D01*
+            G75*
+            G03*
+            X0I-1000000J0D01*
+            G01*
+            G36*
+            Y0D02*
+            X2000000D01*
+            Y2000000D01*
+            X0D01*
+            G37*
+            M02*
+        "};
+        let expected = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X0Y0D03*
+            X1000000Y0D02*
+            X1000000Y1000000D01*
+            G75*
+            G03*
+            X0Y1000000I-1000000J0D01*
+            G01*
+            G36*
+            X0Y0D02*
+            X2000000Y0D01*
+            X2000000Y2000000D01*
+            X0Y2000000D01*
+            G37*
+            M02*
+        "};
+        let original = GerberLayer::parse(src).unwrap();
+        let mut layer = original.clone();
+        assert_eq!(layer.explicit_coordinates(), 9);
+        assert_eq!(
+            layer.commands,
+            GerberLayer::parse(expected).unwrap().commands
+        );
+        assert!(layer.equivalent_to(&original, 0.0));
+        assert_eq!(layer.explicit_coordinates(), 0);
+    }
+}
@@ -0,0 +1,388 @@
+//! Rewrite deprecated constructs into their current equivalents
+//!
+//! Like the [conformance](crate::conformance) report this works on the
+//! source text, since the strict parser rejects the deprecated commands.
+//! Everything which is not rewritten is copied verbatim.
+
+use std::ops::Range;
+
+use crate::conformance::statements;
+
+/// A single rewrite applied by [modernize]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Rewrite {
+    /// Byte range of the original command in the source
+    pub span: Range<usize>,
+
+    /// The text which replaced it, empty if the command was dropped
+    pub replacement: String,
+}
+
+/// The result of [modernize]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Modernized {
+    /// The rewritten file
+    pub source: String,
+
+    /// The rewrites which were applied, in source order
+    pub rewrites: Vec<Rewrite>,
+}
+
+/// Rewrite deprecated constructs in the source of a Gerber file
+///
+/// * `G54Dnn*` becomes `Dnn*`
+/// * `G70*` and `G71*` become `%MOIN*%` and `%MOMM*%`
+/// * `%IPPOS*%` is dropped since it is the default, `%IPNEG*%` is kept
+///   because inverting the image can't be expressed without rewriting it
+/// * `%LN...*%` becomes a comment, it never affected the image
+/// * comment attributes (`G04 #@! TF...*`) become attribute commands
+/// * `%FS` with trailing zero omission, incremental coordinates or fewer
+///   than six decimals becomes `%FSLAXn6Yn6*%`, see
+///   [convert_legacy_format]
+pub fn modernize(src: &str) -> Modernized {
+    rewrite(src, true)
+}
+
+/// Convert a file using a legacy `%FS` to the current format, leaving
+/// everything else as it is
+///
+/// The coordinates following such a `%FS` are rewritten as absolute
+/// values with leading zeros omitted and six decimals, which is the only
+/// format the parser accepts. Trailing zero omission pads the written
+/// digits with zeros up to the declared number of digits, so `X15` in
+/// `%FSTAX24Y24*%` is 15 mm, and incremental coordinates are added to
+/// the current point. Arc offsets are relative either way.
+///
+/// ```
+/// use gerber::modernize::convert_legacy_format;
+///
+/// let src = "%FSTIX24Y24*%\n%MOMM*%\nX15Y2D02*\nX1D01*\nM02*\n";
+/// assert_eq!(
+///     convert_legacy_format(src).source,
+///     "%FSLAX26Y26*%\n%MOMM*%\nX15000000Y20000000D02*\nX25000000D01*\nM02*\n"
+/// );
+/// ```
+pub fn convert_legacy_format(src: &str) -> Modernized {
+    rewrite(src, false)
+}
+
+impl Modernized {
+    /// The byte range in the original source of `range` in the rewritten
+    /// source
+    ///
+    /// Offsets within a rewritten command map to the start or end of the
+    /// original command.
+    pub fn original_range(&self, range: Range<usize>) -> Range<usize> {
+        self.original_offset(range.start, false)..self.original_offset(range.end, true)
+    }
+
+    fn original_offset(&self, offset: usize, end: bool) -> usize {
+        // the rewritten source is this much longer than the original so far
+        let mut shift = 0isize;
+        for rewrite in &self.rewrites {
+            let start = rewrite.span.start.saturating_add_signed(shift);
+            if offset < start || (offset == start && !end) {
+                break;
+            }
+            if offset <= start + rewrite.replacement.len() {
+                return if end && offset > start {
+                    rewrite.span.end
+                } else {
+                    rewrite.span.start
+                };
+            }
+            shift += rewrite.replacement.len() as isize - rewrite.span.len() as isize;
+        }
+        offset.saturating_add_signed(-shift)
+    }
+}
+
+/// Rewrite the source, all deprecated constructs or only legacy formats
+fn rewrite(src: &str, all: bool) -> Modernized {
+    let mut source = String::with_capacity(src.len());
+    let mut rewrites = Vec::new();
+    let mut copied = 0;
+    let mut format: Option<LegacyFormat> = None;
+    for statement in statements(src) {
+        let replacement = match (statement.extended, statement.words.as_slice()) {
+            (true, [word]) if word.starts_with("FS") => {
+                format = LegacyFormat::parse(word);
+                format.as_ref().map(LegacyFormat::specification)
+            }
+            (true, words) if all => modern_extended(words),
+            (true, _) => None,
+            (false, words) => {
+                let word = words.first().copied().unwrap_or_default();
+                let converted = format.as_mut().and_then(|format| format.convert(word));
+                let modern = all
+                    .then(|| modern_word(converted.as_deref().unwrap_or(word)))
+                    .flatten();
+                modern.or(converted.map(|word| format!("{word}*")))
+            }
+        };
+        let Some(replacement) = replacement else {
+            continue;
+        };
+        let mut span = statement.span;
+        if replacement.is_empty() {
+            // don't leave a blank line where the command used to be
+            let rest = &src[span.end..];
+            span.end += rest.len() - rest.trim_start_matches(['\r', '\n']).len();
+        }
+        source.push_str(&src[copied..span.start]);
+        source.push_str(&replacement);
+        copied = span.end;
+        rewrites.push(Rewrite { span, replacement });
+    }
+    source.push_str(&src[copied..]);
+    Modernized { source, rewrites }
+}
+
+/// A `%FS` the parser rejects, and the current point in the coordinates
+/// it declares
+struct LegacyFormat {
+    trailing_zeros_omitted: bool,
+    incremental: bool,
+
+    /// The integer and decimal digits of X and Y
+    x: (u32, u32),
+    y: (u32, u32),
+
+    /// The absolute current point, with six decimals
+    point: (i64, i64),
+}
+
+impl LegacyFormat {
+    /// Parse a `FS` word, `None` if it is current or not understood
+    fn parse(word: &str) -> Option<Self> {
+        let rest = word.strip_prefix("FS")?;
+        let trailing_zeros_omitted = match rest.get(..1)? {
+            "L" => false,
+            "T" => true,
+            _ => return None,
+        };
+        let incremental = match rest.get(1..2)? {
+            "A" => false,
+            "I" => true,
+            _ => return None,
+        };
+        let (x, y) = rest.get(2..)?.strip_prefix('X')?.split_once('Y')?;
+        let digits = |digits: &str| {
+            let mut chars = digits.chars().map(|c| c.to_digit(10));
+            match (chars.next()??, chars.next()??, chars.next()) {
+                (integer @ 1..=6, decimal @ 0..=6, None) => Some((integer, decimal)),
+                _ => None,
+            }
+        };
+        let format = Self {
+            trailing_zeros_omitted,
+            incremental,
+            x: digits(x)?,
+            y: digits(y)?,
+            point: (0, 0),
+        };
+        let current = !trailing_zeros_omitted && !incremental && format.x.1 == 6 && format.y.1 == 6;
+        (!current).then_some(format)
+    }
+
+    /// The current `%FS` command with the same integer digits
+    fn specification(&self) -> String {
+        format!("%FSLAX{}6Y{}6*%", self.x.0, self.y.0)
+    }
+
+    /// The word with its coordinates converted, `None` if it has none
+    fn convert(&mut self, word: &str) -> Option<String> {
+        if word.starts_with("G04") {
+            return None;
+        }
+        let mut converted = String::with_capacity(word.len() + 16);
+        let mut rest = word;
+        while let Some(letter) = rest.chars().next() {
+            rest = &rest[letter.len_utf8()..];
+            let digits = match letter {
+                'X' | 'I' => self.x,
+                'Y' | 'J' => self.y,
+                _ => {
+                    converted.push(letter);
+                    continue;
+                }
+            };
+            let sign = usize::from(rest.starts_with(['+', '-']));
+            let end = rest[sign..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(rest.len(), |end| sign + end);
+            let (number, after) = rest.split_at(end);
+            rest = after;
+            let Some(mut value) = self.value(number, digits) else {
+                converted.push(letter);
+                converted.push_str(number);
+                continue;
+            };
+            let current = match letter {
+                'X' => Some(&mut self.point.0),
+                'Y' => Some(&mut self.point.1),
+                _ => None,
+            };
+            if let Some(current) = current {
+                if self.incremental {
+                    value += *current;
+                }
+                *current = value;
+            }
+            converted.push(letter);
+            converted.push_str(&value.to_string());
+        }
+        (converted != word).then_some(converted)
+    }
+
+    /// A written number with six decimals
+    fn value(&self, number: &str, (integer, decimal): (u32, u32)) -> Option<i64> {
+        let (negative, digits) = match number.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, number.trim_start_matches('+')),
+        };
+        if digits.is_empty() {
+            return None;
+        }
+        let mut value: i64 = digits.parse().ok()?;
+        let written = digits.len() as u32;
+        if self.trailing_zeros_omitted && written < integer + decimal {
+            value = value.checked_mul(10i64.pow(integer + decimal - written))?;
+        }
+        value = value.checked_mul(10i64.pow(6 - decimal))?;
+        Some(if negative { -value } else { value })
+    }
+}
+
+fn modern_extended(words: &[&str]) -> Option<String> {
+    match words {
+        ["IPPOS"] => Some(String::new()),
+        [name] if name.starts_with("LN") => Some(format!("G04 LN {}*", &name[2..])),
+        _ => None,
+    }
+}
+
+fn modern_word(word: &str) -> Option<String> {
+    if let Some(aperture) = word.strip_prefix("G54") {
+        return aperture.starts_with('D').then(|| format!("{aperture}*"));
+    }
+    match word {
+        "G70" => return Some("%MOIN*%".to_string()),
+        "G71" => return Some("%MOMM*%".to_string()),
+        _ => (),
+    }
+    let attribute = word.strip_prefix("G04")?.trim_start().strip_prefix("#@!")?;
+    let attribute = attribute.trim_start();
+    ["TF", "TA", "TO", "TD"]
+        .iter()
+        .any(|code| attribute.starts_with(code))
+        .then(|| format!("%{attribute}*%"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::report;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    #[test]
+    fn test_modernize() {
+        let src = indoc! {"
+            G04 #@! TF.Part,Single*
+            %LNTOP*%
+            %IPPOS*%
+            %FSLAX26Y26*%
+            G71*
+            %ADD10C,0.1*%
+            G54D10*
+            X0Y0D03*
+            M02*
+        "};
+        let modern = modernize(src);
+        assert_eq!(
+            modern.source,
+            indoc! {"
+                %TF.Part,Single*%
+                G04 LN TOP*
+                %FSLAX26Y26*%
+                %MOMM*%
+                %ADD10C,0.1*%
+                D10*
+                X0Y0D03*
+                M02*
+            "}
+        );
+        assert_eq!(modern.rewrites.len(), 5);
+        assert!(report(&modern.source).is_conformant());
+        assert!(GerberLayer::parse(&modern.source).is_ok());
+    }
+
+    #[test]
+    fn test_legacy_format() {
+        let src = indoc! {"
+            %FSTIX24Y34*%
+            %MOIN*%
+            G04 X1 is a comment*
+            %ADD10C,0.01*%
+            G54D10*
+            X15Y-2D02*
+            G01*
+            X-5D01*
+            Y+1I05J0D01*
+            M02*
+        "};
+        let expected = indoc! {"
+            %FSLAX26Y36*%
+            %MOIN*%
+            G04 X1 is a comment*
+            %ADD10C,0.01*%
+            D10*
+            X15000000Y-200000000D02*
+            G01*
+            X-35000000D01*
+            Y-100000000I5000000J0D01*
+            M02*
+        "};
+        let modern = modernize(src);
+        assert_eq!(modern.source, expected);
+        assert_eq!(modern.rewrites.len(), 5);
+        assert!(GerberLayer::parse(&modern.source).is_ok());
+
+        // only the format
+        let converted = convert_legacy_format(src);
+        assert_eq!(converted.rewrites.len(), 4);
+        assert!(converted.source.contains("G54D10*"));
+
+        // which does nothing for current files
+        let current = "%FSLAX26Y26*%\nX15Y2D02*\n%FSLAX24Y24*%\nX15Y2D02*\nM02*\n";
+        assert_eq!(
+            convert_legacy_format(current).source,
+            "%FSLAX26Y26*%\nX15Y2D02*\n%FSLAX26Y26*%\nX1500Y200D02*\nM02*\n"
+        );
+    }
+
+    #[test]
+    fn test_original_range() {
+        let src = "%FSTAX24Y24*%\nX1Y2D02*\nD03*\nM02*\n";
+        let converted = convert_legacy_format(src);
+        let layer = GerberLayer::parse(&converted.source).unwrap();
+        let original: Vec<_> = (0..layer.commands().len())
+            .map(|index| {
+                let span = layer.span(index).unwrap();
+                &src[converted.original_range(span.bytes.clone())]
+            })
+            .collect();
+        assert_eq!(original, ["%FSTAX24Y24*%", "X1Y2D02*", "D03*", "M02*"]);
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let src = "%IPNEG*%\nG04 plain comment*\nM02*\n";
+        let modern = modernize(src);
+        assert_eq!(modern.source, src);
+        assert!(modern.rewrites.is_empty());
+    }
+}
@@ -0,0 +1,339 @@
+//! Typed views of evaluated objects
+//!
+//! [Object](crate::image::Object) keeps the geometry of every kind of
+//! object in one [Shape], next to the graphics state. The views here split
+//! it by kind, with accessors in millimeters and the aperture template
+//! resolved, so renderers and exporters don't need to match on shapes or
+//! look up apertures themselves.
+//!
+//! ```
+//! use gerber::object::View;
+//! use gerber::GerberLayer;
+//!
+//! let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.2*%\nD10*\n\
+//!            X0Y0D02*\nX5000000Y0D01*\nM02*\n";
+//! let image = GerberLayer::parse(src).unwrap().image();
+//! let Some(View::Draw(draw)) = image.views().next() else {
+//!     panic!("expected a draw")
+//! };
+//! assert_eq!(draw.length(), 5.0);
+//! assert_eq!(draw.width(), Some(0.2));
+//! ```
+
+use crate::aperture::ApertureTemplate;
+use crate::data::{ApertureId, InterpolationMode, Unit};
+use crate::image::{ArcGeometry, Attributes, Contour, Image, Object, Point, Shape};
+
+/// An object of an image, by kind
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum View<'i> {
+    Draw(Draw<'i>),
+    Arc(Arc<'i>),
+    Flash(Flash<'i>),
+    Region(Region<'i>),
+}
+
+/// A straight line stroked with an aperture
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Draw<'i> {
+    object: &'i Object,
+    stroke: Stroke<'i>,
+}
+
+/// A circular arc stroked with an aperture
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Arc<'i> {
+    object: &'i Object,
+    stroke: Stroke<'i>,
+}
+
+/// An aperture image placed at a point
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Flash<'i> {
+    object: &'i Object,
+    template: Option<&'i ApertureTemplate<'static>>,
+}
+
+/// An area bounded by one or more contours
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Region<'i> {
+    object: &'i Object,
+}
+
+/// The aperture of a draw or arc, and the unit of its template
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Stroke<'i> {
+    template: Option<&'i ApertureTemplate<'static>>,
+    unit: Unit,
+}
+
+impl Stroke<'_> {
+    fn width(&self, object: &Object) -> Option<f64> {
+        match self.template? {
+            ApertureTemplate::Circle { diameter, .. } => {
+                Some(self.unit.to_mm(*diameter) * object.scaling.0)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Image {
+    /// The view of the object at `index`
+    pub fn view(&self, index: usize) -> Option<View<'_>> {
+        let object = self.objects.get(index)?;
+        let template = object
+            .source
+            .aperture
+            .and_then(|index| self.apertures.get(&index));
+        let stroke = Stroke {
+            template,
+            unit: self.unit.unwrap_or(Unit::Millimeters),
+        };
+        Some(match object.shape {
+            Shape::Draw { .. } => View::Draw(Draw { object, stroke }),
+            Shape::Arc { .. } => View::Arc(Arc { object, stroke }),
+            Shape::Flash { .. } => View::Flash(Flash { object, template }),
+            Shape::Region { .. } => View::Region(Region { object }),
+        })
+    }
+
+    /// The views of the objects, in the order they were created
+    pub fn views(&self) -> impl Iterator<Item = View<'_>> {
+        (0..self.objects.len()).filter_map(|index| self.view(index))
+    }
+}
+
+impl<'i> View<'i> {
+    /// The object with its graphics state
+    pub fn object(&self) -> &'i Object {
+        match self {
+            View::Draw(draw) => draw.object,
+            View::Arc(arc) => arc.object,
+            View::Flash(flash) => flash.object,
+            View::Region(region) => region.object,
+        }
+    }
+
+    /// The aperture and object attributes attached to the object
+    pub fn attributes(&self) -> &'i Attributes {
+        &self.object().attributes
+    }
+}
+
+impl<'i> Draw<'i> {
+    pub fn start(&self) -> Point {
+        match self.object.shape {
+            Shape::Draw { start, .. } => start,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn end(&self) -> Point {
+        match self.object.shape {
+            Shape::Draw { end, .. } => end,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn length(&self) -> f64 {
+        let (start, end) = (self.start(), self.end());
+        (end.x - start.x).hypot(end.y - start.y)
+    }
+
+    /// The diameter of the circular aperture, scaled, or `None` for other
+    /// apertures which have no single width
+    pub fn width(&self) -> Option<f64> {
+        self.stroke.width(self.object)
+    }
+
+    pub fn aperture(&self) -> ApertureId {
+        match self.object.shape {
+            Shape::Draw { aperture, .. } => aperture,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn template(&self) -> Option<&'i ApertureTemplate<'static>> {
+        self.stroke.template
+    }
+
+    pub fn attributes(&self) -> &'i Attributes {
+        &self.object.attributes
+    }
+}
+
+impl<'i> Arc<'i> {
+    pub fn start(&self) -> Point {
+        match self.object.shape {
+            Shape::Arc { start, .. } => start,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn end(&self) -> Point {
+        match self.object.shape {
+            Shape::Arc { end, .. } => end,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn center(&self) -> Point {
+        match self.object.shape {
+            Shape::Arc { center, .. } => center,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The distance from the center to the start point
+    pub fn radius(&self) -> f64 {
+        self.geometry().radius
+    }
+
+    /// The radius, angles and sweep of the arc
+    pub fn geometry(&self) -> ArcGeometry {
+        self.object.shape.arc_geometry().unwrap()
+    }
+
+    pub fn direction(&self) -> InterpolationMode {
+        match self.object.shape {
+            Shape::Arc { direction, .. } => direction,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The diameter of the circular aperture, scaled, or `None` for other
+    /// apertures which have no single width
+    pub fn width(&self) -> Option<f64> {
+        self.stroke.width(self.object)
+    }
+
+    pub fn aperture(&self) -> ApertureId {
+        match self.object.shape {
+            Shape::Arc { aperture, .. } => aperture,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn template(&self) -> Option<&'i ApertureTemplate<'static>> {
+        self.stroke.template
+    }
+
+    pub fn attributes(&self) -> &'i Attributes {
+        &self.object.attributes
+    }
+}
+
+impl<'i> Flash<'i> {
+    pub fn position(&self) -> Point {
+        match self.object.shape {
+            Shape::Flash { at, .. } => at,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn aperture(&self) -> ApertureId {
+        match self.object.shape {
+            Shape::Flash { aperture, .. } => aperture,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The template of the aperture, in the unit of the layer and without
+    /// the object's transformations
+    pub fn template(&self) -> Option<&'i ApertureTemplate<'static>> {
+        self.template
+    }
+
+    pub fn attributes(&self) -> &'i Attributes {
+        &self.object.attributes
+    }
+}
+
+impl<'i> Region<'i> {
+    pub fn contours(&self) -> &'i [Contour] {
+        match &self.object.shape {
+            Shape::Region { contours } => contours,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn attributes(&self) -> &'i Attributes {
+        &self.object.attributes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    #[test]
+    fn test_views() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOIN*%
+            %TA.AperFunction,Conductor*%
+            %ADD10C,0.01*%
+            %TD*%
+            %ADD11R,0.1X0.2*%
+            %LS0.5*%
+            D10*
+            X0Y0D02*
+            X1000000Y0D01*
+            G75*
+            G03*
+            X0Y1000000I-1000000J0D01*
+            %LS1*%
+            D11*
+            X2000000Y2000000D03*
+            G01*
+            G36*
+            X0Y0D02*
+            X1000000Y0D01*
+            X0Y1000000D01*
+            X0Y0D01*
+            G37*
+            M02*
+        "};
+        let image = GerberLayer::parse(src).unwrap().image();
+        let views: Vec<_> = image.views().collect();
+        assert_eq!(views.len(), 4);
+
+        let View::Draw(draw) = views[0] else {
+            panic!("{:?}", views[0])
+        };
+        assert_eq!(draw.end(), Point { x: 25.4, y: 0.0 });
+        assert_eq!(draw.length(), 25.4);
+        assert!((draw.width().unwrap() - 0.127).abs() < 1e-9);
+        assert_eq!(draw.aperture(), ApertureId::new(10).unwrap());
+        assert_eq!(draw.attributes().aperture_function(), Some("Conductor"));
+
+        let View::Arc(arc) = views[1] else {
+            panic!("{:?}", views[1])
+        };
+        assert_eq!(arc.center(), Point { x: 0.0, y: 0.0 });
+        assert_eq!(arc.radius(), 25.4);
+        assert_eq!(arc.direction(), InterpolationMode::CounterClockwise);
+        assert_eq!(arc.geometry().sweep, std::f64::consts::FRAC_PI_2);
+        assert!(arc.width().is_some());
+
+        let View::Flash(flash) = views[2] else {
+            panic!("{:?}", views[2])
+        };
+        assert_eq!(flash.position(), Point { x: 50.8, y: 50.8 });
+        assert!(matches!(
+            flash.template(),
+            Some(ApertureTemplate::Rectangle { .. })
+        ));
+        assert_eq!(flash.attributes().aperture_function(), None);
+
+        let View::Region(region) = views[3] else {
+            panic!("{:?}", views[3])
+        };
+        assert_eq!(region.contours().len(), 1);
+        assert_eq!(region.contours()[0].segments.len(), 3);
+        assert!(std::ptr::eq(views[3].object(), &image.objects[3]));
+    }
+}
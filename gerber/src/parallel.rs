@@ -0,0 +1,37 @@
+//! Data parallelism behind the `rayon` feature
+//!
+//! Work is split into independent items whose results are collected in
+//! the order of the items, so the output is the same whatever the number
+//! of threads. Without the feature the items are processed in turn.
+
+/// Apply `f` to each item, keeping the order
+pub(crate) fn map<T, U>(items: Vec<T>, f: impl Fn(T) -> U + Sync + Send) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+{
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        items.into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        items.into_iter().map(f).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_keeps_order() {
+        let items: Vec<usize> = (0..10_000).collect();
+        let squares = map(items, |item| item * item);
+        assert!(squares
+            .iter()
+            .enumerate()
+            .all(|(i, &square)| square == i * i));
+    }
+}
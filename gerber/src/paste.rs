@@ -0,0 +1,247 @@
+//! Solder paste statistics
+//!
+//! Stencil manufacturers ask for the paste area per aperture, the total
+//! paste volume and the smallest aperture. [PasteStatistics] collects these
+//! from a paste layer (`.FileFunction,Paste`), where each pad is usually a
+//! flash and larger pads are sometimes regions.
+//!
+//! Overlapping pads are counted once for each pad, as the layer is not
+//! united first.
+
+use std::collections::BTreeMap;
+use std::f64::consts::TAU;
+
+use crate::command::Command::*;
+use crate::data::{ApertureId, InterpolationMode, Unit};
+use crate::image::{Contour, Point, Segment, Shape};
+use crate::GerberLayer;
+
+/// Paste statistics of a layer, in millimeters
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PasteStatistics {
+    /// Flashed paste by aperture
+    pub apertures: BTreeMap<ApertureId, AperturePaste>,
+
+    /// Area of paste regions in square millimeters
+    pub region_area: f64,
+
+    /// The aperture with the smallest dimension, and that dimension
+    pub smallest: Option<(ApertureId, f64)>,
+
+    /// Objects whose area is unknown, i.e. draws, arcs and flashes of
+    /// macro apertures
+    pub unsupported: usize,
+}
+
+/// Paste flashed with one aperture
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AperturePaste {
+    pub flashes: usize,
+
+    /// Total area of the flashes in square millimeters
+    pub area: f64,
+
+    /// Smallest width of a flash in millimeters, see
+    /// [min_dimension](crate::aperture::ApertureTemplate::min_dimension)
+    pub min_dimension: f64,
+}
+
+impl PasteStatistics {
+    /// Total paste area in square millimeters
+    pub fn area(&self) -> f64 {
+        self.apertures.values().map(|paste| paste.area).sum::<f64>() + self.region_area
+    }
+
+    /// Paste volume in cubic millimeters for a stencil `thickness`
+    /// millimeters thick
+    pub fn volume(&self, thickness: f64) -> f64 {
+        self.area() * thickness
+    }
+}
+
+impl GerberLayer<'_> {
+    /// Collect paste statistics, treating the layer as a paste layer
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,1X0.5*%\nD10*\n\
+    ///            X0Y0D03*\nX2000000Y0D03*\nM02*\n";
+    /// let paste = GerberLayer::parse(src).unwrap().paste_statistics();
+    /// assert_eq!(paste.area(), 1.0);
+    /// assert_eq!(paste.volume(0.12), 0.12);
+    /// assert_eq!(paste.smallest.unwrap().1, 0.5);
+    /// ```
+    pub fn paste_statistics(&self) -> PasteStatistics {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let mut statistics = PasteStatistics::default();
+        for object in &image.objects {
+            match &object.shape {
+                Shape::Flash { aperture, .. } => {
+                    let template = match object.source.aperture.map(|index| &self.commands[index]) {
+                        Some(ApertureDefine(_, template)) => template,
+                        _ => continue,
+                    };
+                    let (Some(area), Some(dimension)) = (template.area(), template.min_dimension())
+                    else {
+                        statistics.unsupported += 1;
+                        continue;
+                    };
+                    let scale = unit.to_mm(object.scaling.0);
+                    let dimension = dimension * scale;
+                    let paste = statistics
+                        .apertures
+                        .entry(*aperture)
+                        .or_insert(AperturePaste {
+                            min_dimension: dimension,
+                            ..Default::default()
+                        });
+                    paste.flashes += 1;
+                    paste.area += area * scale * scale;
+                    paste.min_dimension = paste.min_dimension.min(dimension);
+                }
+                Shape::Region { contours } => {
+                    statistics.region_area += contours.iter().map(contour_area).sum::<f64>();
+                }
+                Shape::Draw { .. } | Shape::Arc { .. } => statistics.unsupported += 1,
+            }
+        }
+        statistics.smallest = statistics
+            .apertures
+            .iter()
+            .map(|(id, paste)| (*id, paste.min_dimension))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        statistics
+    }
+}
+
+/// The area enclosed by a contour, exact for arcs
+///
+/// Each arc adds the circular segment between it and its chord to the
+/// area of the polygon of the segment end points.
+fn contour_area(contour: &Contour) -> f64 {
+    let cross = |a: Point, b: Point| a.x * b.y - b.x * a.y;
+    let mut twice_area = 0.0;
+    let mut from = contour.start;
+    for segment in &contour.segments {
+        match *segment {
+            Segment::Line { end } => {
+                twice_area += cross(from, end);
+                from = end;
+            }
+            Segment::Arc {
+                end,
+                center,
+                direction,
+            } => {
+                let radius = (from.x - center.x).hypot(from.y - center.y);
+                let start_angle = (from.y - center.y).atan2(from.x - center.x);
+                let end_angle = (end.y - center.y).atan2(end.x - center.x);
+                let mut sweep = (end_angle - start_angle).rem_euclid(TAU);
+                if sweep == 0.0 {
+                    sweep = TAU;
+                }
+                if direction == InterpolationMode::Clockwise {
+                    sweep -= TAU;
+                }
+                twice_area += cross(from, end) + radius * radius * (sweep - sweep.sin());
+                from = end;
+            }
+        }
+    }
+    twice_area.abs() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use std::f64::consts::PI;
+
+    fn paste(body: &str) -> PasteStatistics {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().paste_statistics()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn test_apertures() {
+        let paste = paste(indoc! {"
+            %ADD10C,1*%
+            %ADD11O,2X1*%
+            %ADD12P,2X4*%
+            %ADD13R,1X1X0.5*%
+            D10*
+            X0Y0D03*
+            X0Y0D03*
+            D11*
+            X0Y0D03*
+            D12*
+            X0Y0D03*
+            D13*
+            X0Y0D03*
+            %LS0.5*%
+            D11*
+            X0Y0D03*
+        "});
+        let area = |id| paste.apertures[&ApertureId::new(id).unwrap()].area;
+        assert_close(area(10), 2.0 * PI / 4.0);
+        assert_close(area(11), 1.25 * (1.0 + PI / 4.0));
+        assert_close(area(12), 2.0);
+        assert_close(area(13), 1.0 - PI / 16.0);
+        assert_eq!(paste.apertures[&ApertureId::new(11).unwrap()].flashes, 2);
+
+        // the scaled obround is the smallest
+        let (id, dimension) = paste.smallest.unwrap();
+        assert_eq!(id, ApertureId::new(11).unwrap());
+        assert_close(dimension, 0.5);
+    }
+
+    #[test]
+    fn test_inches() {
+        let src = "%FSLAX26Y26*%\n%MOIN*%\n%ADD10R,0.1X0.1*%\nD10*\nX0Y0D03*\nM02*\n";
+        let paste = GerberLayer::parse(src).unwrap().paste_statistics();
+        assert_close(paste.area(), 2.54 * 2.54);
+    }
+
+    #[test]
+    fn test_regions() {
+        // a 2x2 square with a half disc of radius 1 on its right side
+        let paste = paste(indoc! {"
+            G01*
+            G36*
+            X0Y0D02*
+            X2000000Y0D01*
+            G75*
+            G03*
+            X2000000Y2000000I0J1000000D01*
+            G01*
+            X0Y2000000D01*
+            X0Y0D01*
+            G37*
+        "});
+        assert_close(paste.region_area, 4.0 + PI / 2.0);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let paste = paste(indoc! {"
+            %ADD10C,1*%
+            %ADD11BOX,1*%
+            D10*
+            G01*
+            X0Y0D02*
+            X1000000Y0D01*
+            D11*
+            X0Y0D03*
+        "});
+        assert_eq!(paste.unsupported, 2);
+        assert_eq!(paste.area(), 0.0);
+    }
+}
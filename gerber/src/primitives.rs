@@ -0,0 +1,119 @@
+//! Parsers for the data types shared by all commands
+//!
+//! These are the building blocks of the command parsers, following the
+//! grammar in §3.4 of the specification. They are public so tools parsing
+//! related formats, such as Excellon drill files or aperture macro
+//! expressions, can reuse them.
+//!
+//! Each parser returns the unparsed rest of the input with the value, or a
+//! [GerberError::ParseError] when the input does not start with one.
+//!
+//! ```
+//! use gerber::primitives::{aperture_identifier, decimal};
+//!
+//! assert_eq!(decimal("-1.5X").unwrap(), ("X", -1.5));
+//! assert_eq!(aperture_identifier("D10*").unwrap().0, "*");
+//! assert!(decimal("X").is_err());
+//! ```
+
+use crate::data::{ApertureId, EscapedString};
+use crate::{grammar, GerberError, IResult};
+
+/// The rest of the input and the parsed value, or why parsing failed
+pub type Parsed<'a, T> = Result<(&'a str, T), GerberError>;
+
+/// Convert the result of an internal parser
+fn parsed<T>(result: IResult<'_, T>) -> Parsed<'_, T> {
+    result.map_err(|e| GerberError::ParseError(format!("{:?}", e)))
+}
+
+/// Parse an non-negative integer to an i32
+///
+/// Integers too large for an i32 fail to parse rather than wrap or panic.
+pub fn unsigned_integer(input: &str) -> Parsed<'_, i32> {
+    parsed(grammar::unsigned_integer(input))
+}
+
+/// Parse a positive integer to an i32
+///
+/// Integers too large for an i32 fail to parse, e.g. the number of an
+/// aperture `D99999999999`.
+pub fn positive_integer(input: &str) -> Parsed<'_, i32> {
+    parsed(grammar::positive_integer(input))
+}
+
+/// Parse an integer to an i32
+///
+/// Integers out of the range of an i32 fail to parse.
+pub fn integer(input: &str) -> Parsed<'_, i32> {
+    parsed(grammar::integer(input))
+}
+
+/// Parse a coordinate to an i64
+///
+/// Coordinates are wider than [integer] as the format allows up to 12 digits.
+pub fn coordinate(input: &str) -> Parsed<'_, i64> {
+    parsed(grammar::coordinate(input))
+}
+
+/// Parse a positive decimal to an f64
+pub fn unsigned_decimal(input: &str) -> Parsed<'_, f64> {
+    parsed(grammar::unsigned_decimal(input))
+}
+
+/// Parse a decimal to an f64
+pub fn decimal(input: &str) -> Parsed<'_, f64> {
+    parsed(grammar::decimal(input))
+}
+
+/// Parse an aperture identifier, e.g. `D10`
+///
+/// Identifiers below D10 are reserved and rejected.
+pub fn aperture_identifier(input: &str) -> Parsed<'_, ApertureId> {
+    parsed(grammar::aperture_identifier(input))
+}
+
+/// Parse a user defined name
+pub fn user_name(input: &str) -> Parsed<'_, &str> {
+    parsed(grammar::user_name(input))
+}
+
+/// Parse a system defined name
+pub fn system_name(input: &str) -> Parsed<'_, &str> {
+    parsed(grammar::system_name(input))
+}
+
+/// Parse a system or user defined name
+pub fn name(input: &str) -> Parsed<'_, &str> {
+    parsed(grammar::name(input))
+}
+
+/// Parse a field
+pub fn field(input: &str) -> Parsed<'_, EscapedString<'_>> {
+    parsed(grammar::field(input))
+}
+
+/// Parse a string
+pub fn string(input: &str) -> Parsed<'_, EscapedString<'_>> {
+    parsed(grammar::string(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsed() {
+        assert_eq!(integer("-12X").unwrap(), ("X", -12));
+        assert_eq!(coordinate("123456789012").unwrap(), ("", 123456789012));
+        assert_eq!(name(".FileFunction,Copper").unwrap().0, ",Copper");
+        assert!(matches!(
+            integer("99999999999"),
+            Err(GerberError::ParseError(_))
+        ));
+        assert!(matches!(
+            aperture_identifier("D9"),
+            Err(GerberError::ParseError(_))
+        ));
+    }
+}
@@ -0,0 +1,177 @@
+//! Progress reporting and cancellation for long operations
+//!
+//! The `_with_progress` variants of [GerberLayer] methods call a callback
+//! after each command. The callback returns [ControlFlow::Break] to cancel,
+//! in which case the operation returns [GerberError::Cancelled]. The
+//! callback is called often, so a GUI should throttle how often it redraws.
+
+use std::ops::ControlFlow;
+
+use crate::command::Command;
+use crate::image::{self, Image};
+use crate::{command_capacity, each_command, span, GerberError, GerberLayer};
+
+/// How far an operation has got
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Progress {
+    /// Bytes of the source processed so far
+    pub bytes: usize,
+
+    /// Bytes in the whole source
+    pub total_bytes: usize,
+
+    /// Commands parsed or evaluated so far
+    pub commands: usize,
+}
+
+impl Progress {
+    /// The fraction completed, from 0 to 1
+    pub fn fraction(&self) -> f64 {
+        if self.total_bytes == 0 {
+            1.0
+        } else {
+            self.bytes as f64 / self.total_bytes as f64
+        }
+    }
+}
+
+impl<'a> GerberLayer<'a> {
+    /// [Parse](GerberLayer::parse), reporting progress after each command
+    ///
+    /// ```
+    /// use std::ops::ControlFlow;
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\nM02*\n";
+    /// let mut last = None;
+    /// let layer = GerberLayer::parse_with_progress(src, |progress| {
+    ///     last = Some(progress);
+    ///     ControlFlow::Continue(())
+    /// })
+    /// .unwrap();
+    /// assert_eq!(layer.commands().len(), 3);
+    /// assert_eq!(last.unwrap().fraction(), 1.0);
+    ///
+    /// let cancelled = GerberLayer::parse_with_progress(src, |_| ControlFlow::Break(()));
+    /// assert!(cancelled.is_err());
+    /// ```
+    pub fn parse_with_progress(
+        src: &'a str,
+        mut progress: impl FnMut(Progress) -> ControlFlow<()>,
+    ) -> Result<Self, GerberError> {
+        let capacity = command_capacity(src);
+        let mut commands = Vec::with_capacity(capacity);
+        let mut ranges = Vec::with_capacity(capacity);
+        each_command(src, |command, bytes| {
+            // trailing line endings are consumed along with the end of file
+            let end = match command {
+                Command::EndOfFile => src.len(),
+                _ => bytes.end,
+            };
+            commands.push(command);
+            ranges.push(bytes);
+            progress(Progress {
+                bytes: end,
+                total_bytes: src.len(),
+                commands: commands.len(),
+            })
+        })?;
+
+        let spans = span::spans(src, ranges);
+        Ok(GerberLayer { commands, spans })
+    }
+
+    /// [Evaluate](GerberLayer::image) the layer, reporting progress after
+    /// each command
+    pub fn image_with_progress(
+        &self,
+        mut progress: impl FnMut(Progress) -> ControlFlow<()>,
+    ) -> Result<Image, GerberError> {
+        let total_bytes = self.spans.last().map_or(0, |span| span.bytes.end);
+        image::evaluate_with(&self.commands, |index| {
+            progress(Progress {
+                bytes: self.spans.get(index).map_or(0, |span| span.bytes.end),
+                total_bytes,
+                commands: index + 1,
+            })
+        })
+        .ok_or(GerberError::Cancelled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const SRC: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10C,0.1*%
+        D10*
+        X0Y0D02*
+        X1000000Y0D01*
+        M02*
+    "};
+
+    #[test]
+    fn test_parse_with_progress() {
+        let mut reports = Vec::new();
+        let layer = GerberLayer::parse_with_progress(SRC, |progress| {
+            reports.push(progress);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        let expected = GerberLayer::parse(SRC).unwrap();
+        assert_eq!(layer.commands, expected.commands);
+        assert_eq!(layer.spans, expected.spans);
+
+        assert_eq!(reports.len(), 7);
+        assert!(reports.windows(2).all(|w| w[0].bytes < w[1].bytes));
+        assert_eq!(reports[0].bytes, "%FSLAX26Y26*%".len());
+        assert_eq!(reports[6].bytes, SRC.len());
+        assert_eq!(reports[6].commands, 7);
+    }
+
+    #[test]
+    fn test_parse_cancelled() {
+        let mut calls = 0;
+        let result = GerberLayer::parse_with_progress(SRC, |progress| {
+            calls += 1;
+            if progress.commands == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(matches!(result, Err(GerberError::Cancelled)));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let continue_ = |_| ControlFlow::Continue(());
+        assert!(GerberLayer::parse_with_progress("%MOMM*%\n", continue_).is_err());
+        assert!(GerberLayer::parse_with_progress("M02*\nD10*\n", continue_).is_err());
+        assert!(GerberLayer::parse_with_progress("D1O*\nM02*\n", continue_).is_err());
+    }
+
+    #[test]
+    fn test_image_with_progress() {
+        let layer = GerberLayer::parse(SRC).unwrap();
+        let mut last = None;
+        let image = layer
+            .image_with_progress(|progress| {
+                last = Some(progress);
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(image, layer.image());
+        assert_eq!(last.unwrap().commands, 7);
+        assert_eq!(last.unwrap().fraction(), 1.0);
+
+        let cancelled = layer.image_with_progress(|_| ControlFlow::Break(()));
+        assert!(matches!(cancelled, Err(GerberError::Cancelled)));
+    }
+}
@@ -0,0 +1,106 @@
+//! The layers of a board
+//!
+//! A fab receives a board as a set of Gerber files, one per layer, which
+//! share a coordinate frame and together with the profile describe the
+//! board. [GerberProject] keeps the layers with the paths they are sent
+//! under, for the deliverables which describe the set as a whole, such as
+//! the [job file](crate::job).
+
+use std::collections::BTreeSet;
+
+use crate::decorate::FilePolarity;
+use crate::image::Image;
+use crate::registration::{extent, profile};
+use crate::GerberLayer;
+
+/// The layers of a board
+#[derive(Clone, Debug, Default)]
+pub struct GerberProject<'a> {
+    pub layers: Vec<ProjectLayer<'a>>,
+}
+
+/// A layer of a [GerberProject]
+#[derive(Clone, Debug)]
+pub struct ProjectLayer<'a> {
+    /// The path of the file relative to the project, e.g.
+    /// `board-F_Cu.gbr`
+    pub path: String,
+
+    pub layer: GerberLayer<'a>,
+}
+
+impl<'a> GerberProject<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a layer sent as `path`
+    pub fn add(&mut self, path: impl Into<String>, layer: GerberLayer<'a>) {
+        self.layers.push(ProjectLayer {
+            path: path.into(),
+            layer,
+        });
+    }
+
+    /// The image of each layer
+    pub fn images(&self) -> Vec<Image> {
+        self.layers
+            .iter()
+            .map(|layer| layer.layer.image())
+            .collect()
+    }
+
+    /// The width and height of the board in millimeters
+    ///
+    /// The board is the extent of the profile layer, which is found as by
+    /// [check_profiles](crate::registration::check_profiles). `None`
+    /// without a profile.
+    ///
+    /// ```
+    /// use gerber::project::GerberProject;
+    /// use gerber::GerberLayer;
+    ///
+    /// let profile = "%FSLAX26Y26*%\n%MOMM*%\n%TF.FileFunction,Profile,NP*%\n%ADD10C,0.1*%\n\
+    ///                D10*\nG01*\nX0Y0D02*\nX10000000D01*\nY5000000D01*\nX0D01*\nY0D01*\nM02*\n";
+    /// let mut project = GerberProject::new();
+    /// project.add("board-Edge_Cuts.gbr", GerberLayer::parse(profile).unwrap());
+    /// let (width, height) = project.board_size().unwrap();
+    /// assert!((width - 10.1).abs() < 1e-9 && (height - 5.1).abs() < 1e-9);
+    /// ```
+    pub fn board_size(&self) -> Option<(f64, f64)> {
+        board_size(&self.images())
+    }
+}
+
+/// The size of the board drawn by the profile among `images`
+pub(crate) fn board_size(images: &[Image]) -> Option<(f64, f64)> {
+    let board = extent(&images[profile(images)?])?;
+    Some((board.width(), board.height()))
+}
+
+/// The number of distinct layer numbers of `.FileFunction,Copper` layers
+pub(crate) fn copper_layers(images: &[Image]) -> usize {
+    let copper: BTreeSet<&str> = images
+        .iter()
+        .filter_map(file_function)
+        .filter(|function| function.first() == Some(&"Copper"))
+        .filter_map(|function| function.get(1).copied())
+        .collect();
+    copper.len()
+}
+
+/// The fields of `.FileFunction`, e.g. `["Copper", "L1", "Top"]`
+pub(crate) fn file_function(image: &Image) -> Option<Vec<&str>> {
+    let values = image.file_attributes.get(".FileFunction")?;
+    Some(values.iter().map(AsRef::as_ref).collect())
+}
+
+/// The value of `.FilePolarity`, if it is valid
+pub(crate) fn file_polarity(image: &Image) -> Option<FilePolarity> {
+    let values = image.file_attributes.get(".FilePolarity")?;
+    match values.first().map(AsRef::as_ref) {
+        Some("Positive") => Some(FilePolarity::Positive),
+        Some("Negative") => Some(FilePolarity::Negative),
+        _ => None,
+    }
+}
@@ -0,0 +1,936 @@
+//! Raster rendering
+//!
+//! Objects are painted in order into a coverage mask with `supersampling`
+//! × `supersampling` samples per pixel: dark objects set the samples they
+//! cover and clear objects unset them, exactly as the layer is defined.
+//! Each pixel's coverage then mixes the foreground over the background
+//! color. With one sample per pixel the edges are aliased, which is fast
+//! enough for previews; documentation images use more.
+//!
+//! Object geometry is shared with [copper](crate::copper), so curves are
+//! approximated within the `tolerance` of the [RenderOptions].
+
+use std::ops::{ControlFlow, Range};
+
+use crate::command::Command::*;
+use crate::copper::object_paths;
+use crate::data::{Polarity, Unit};
+use crate::image::Point;
+use crate::{parallel, GerberError, GerberLayer};
+
+/// How a layer is rendered
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RenderOptions {
+    /// Resolution in pixels per millimeter
+    pub pixels_per_mm: f64,
+
+    /// Samples per pixel along each axis, 1 for no anti-aliasing
+    pub supersampling: u32,
+
+    /// Color of dark areas as RGBA
+    pub foreground: [u8; 4],
+
+    /// Color of clear areas as RGBA, transparent for layers which are to
+    /// be [composited](Raster::composite)
+    pub background: [u8; 4],
+
+    pub format: PixelFormat,
+
+    /// How closely curves are approximated, in millimeters
+    pub tolerance: f64,
+}
+
+impl Default for RenderOptions {
+    /// Black on white at 10 pixels per millimeter, 4× supersampled
+    fn default() -> Self {
+        RenderOptions {
+            pixels_per_mm: 10.0,
+            supersampling: 4,
+            foreground: [0, 0, 0, 255],
+            background: [255, 255, 255, 255],
+            format: PixelFormat::Rgba,
+            tolerance: 0.001,
+        }
+    }
+}
+
+/// The layout of pixels in a [Raster]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum PixelFormat {
+    /// One byte of luma per pixel, ignoring alpha
+    Gray,
+
+    /// Four bytes per pixel, red, green, blue and straight alpha
+    #[default]
+    Rgba,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Gray => 1,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+/// A rendered image, rows from top to bottom
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Raster {
+    pub width: usize,
+    pub height: usize,
+    pub format: PixelFormat,
+    pub pixels: Vec<u8>,
+
+    /// The layer coordinates of the lower left corner in millimeters
+    pub origin: Point,
+    pub pixels_per_mm: f64,
+}
+
+impl Raster {
+    /// The bytes of the pixel in column `x` and row `y`
+    pub fn pixel(&self, x: usize, y: usize) -> &[u8] {
+        let size = self.format.bytes_per_pixel();
+        let start = (y * self.width + x) * size;
+        &self.pixels[start..start + size]
+    }
+
+    /// Paint `top` over this raster, e.g. a layer over the layer below it
+    ///
+    /// Layers rendered on a transparent background stack correctly, as
+    /// their clear areas are transparent rather than painted in the
+    /// background color.
+    ///
+    /// # Panics
+    ///
+    /// If the rasters are not both RGBA and of the same size.
+    pub fn composite(&mut self, top: &Raster) {
+        assert!(
+            self.format == PixelFormat::Rgba
+                && top.format == PixelFormat::Rgba
+                && (self.width, self.height) == (top.width, top.height),
+            "can only composite RGBA rasters of the same size"
+        );
+        for (below, above) in self.pixels.chunks_mut(4).zip(top.pixels.chunks(4)) {
+            let t = above[3] as f64 / 255.0;
+            let below_alpha = below[3] as f64 / 255.0 * (1.0 - t);
+            let alpha = t + below_alpha;
+            for channel in 0..3 {
+                below[channel] = if alpha > 0.0 {
+                    ((above[channel] as f64 * t + below[channel] as f64 * below_alpha) / alpha)
+                        .round() as u8
+                } else {
+                    0
+                };
+            }
+            below[3] = (alpha * 255.0).round() as u8;
+        }
+    }
+
+    /// Encode the raster as a PNG image
+    ///
+    /// The image data is stored without compression, which keeps the
+    /// encoder small and is fine for thumbnails and tests, but large
+    /// rasters are better encoded with an image library.
+    pub fn to_png(&self) -> Vec<u8> {
+        let color_type = match self.format {
+            PixelFormat::Gray => 0,
+            PixelFormat::Rgba => 6,
+        };
+        let mut header = Vec::with_capacity(13);
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // 8 bits per sample, deflate, adaptive filtering, no interlace
+        header.extend([8, color_type, 0, 0, 0]);
+
+        // each row starts with filter type 0, none
+        let row = self.width * self.format.bytes_per_pixel();
+        let mut data = Vec::with_capacity(self.height * (row + 1));
+        for pixels in self.pixels.chunks(row.max(1)).take(self.height) {
+            data.push(0);
+            data.extend(pixels);
+        }
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&data));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// A zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window, no preset dictionary, fastest
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let length = block.len() as u16;
+        zlib.push(last);
+        zlib.extend(length.to_le_bytes());
+        zlib.extend((!length).to_le_bytes());
+        zlib.extend(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend(((b << 16) | a).to_be_bytes());
+    zlib
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+impl GerberLayer<'_> {
+    /// Render the layer, sized to fit its objects
+    ///
+    /// Objects whose geometry is unknown, i.e. flashes and draws of
+    /// apertures whose macro is undefined or can't be evaluated, are left
+    /// out.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    /// use gerber::raster::{PixelFormat, RenderOptions};
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,2X1*%\nD10*\nX0Y0D03*\nM02*\n";
+    /// let options = RenderOptions {
+    ///     format: PixelFormat::Gray,
+    ///     ..Default::default()
+    /// };
+    /// let raster = GerberLayer::parse(src).unwrap().render(&options);
+    /// assert_eq!((raster.width, raster.height), (20, 10));
+    /// assert_eq!(raster.pixel(10, 5), [0]);
+    /// ```
+    pub fn render(&self, options: &RenderOptions) -> Raster {
+        self.renderer(options.tolerance).render(options)
+    }
+
+    /// Prepare the layer for rendering many times, e.g. as tiles
+    ///
+    /// The `tolerance` of the options passed to the renderer is not used,
+    /// as curves are approximated here.
+    pub fn renderer(&self, tolerance: f64) -> Renderer {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let macros = self.aperture_macros();
+        let objects = parallel::map(image.objects.iter().collect(), |object| {
+            let template = match object.source.aperture.map(|index| &self.commands[index]) {
+                Some(ApertureDefine(_, template)) => Some(template),
+                _ => None,
+            };
+            let paths = object_paths(object, template, &macros, unit, tolerance)?;
+            let bounds = bounds(paths.iter().flatten())?;
+            Some(Paths {
+                polarity: object.polarity,
+                paths,
+                bounds,
+            })
+        });
+        let objects: Vec<_> = objects.into_iter().flatten().collect();
+        let bounds = bounds(
+            objects
+                .iter()
+                .flat_map(|object| [&object.bounds.0, &object.bounds.1]),
+        );
+        Renderer { objects, bounds }
+    }
+}
+
+/// A layer prepared for rendering
+#[derive(Clone, Debug)]
+pub struct Renderer {
+    objects: Vec<Paths>,
+    bounds: Option<(Point, Point)>,
+}
+
+/// The fill paths of an object
+#[derive(Clone, Debug)]
+struct Paths {
+    polarity: Polarity,
+    paths: Vec<Vec<Point>>,
+    bounds: (Point, Point),
+}
+
+impl Renderer {
+    /// The lower left and upper right corners of the objects, in
+    /// millimeters, or `None` if there are none
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        self.bounds
+    }
+
+    /// Render the whole layer, sized to fit its objects
+    pub fn render(&self, options: &RenderOptions) -> Raster {
+        let Some((min, max)) = self.bounds else {
+            return self.render_tile(Point::default(), 0, 0, options);
+        };
+        let size = |length: f64| (length * options.pixels_per_mm).ceil().max(1.0) as usize;
+        self.render_tile(min, size(max.x - min.x), size(max.y - min.y), options)
+    }
+
+    /// Render a `width` × `height` pixel rectangle of the layer whose lower
+    /// left corner is at `origin`, in millimeters
+    ///
+    /// Tiles are independent of each other, and tiles whose origins lie on
+    /// the same pixel grid fit together seamlessly: for a slippy map with
+    /// 256 pixel tiles, tile (column, row) has its origin at
+    /// `(column * 256 / pixels_per_mm, row * 256 / pixels_per_mm)`.
+    /// Objects outside the tile are skipped without being rasterized.
+    ///
+    /// The tile is painted in bands of rows, in parallel with the `rayon`
+    /// feature. Every band paints the objects in order, so the pixels are
+    /// the same either way.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    /// use gerber::image::Point;
+    /// use gerber::raster::{PixelFormat, RenderOptions};
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,1*%\nD10*\nX5000000Y5000000D03*\nM02*\n";
+    /// let renderer = GerberLayer::parse(src).unwrap().renderer(0.001);
+    /// let options = RenderOptions::default();
+    /// let tile = renderer.render_tile(Point { x: 4.0, y: 4.0 }, 8, 8, &options);
+    /// // the upper right of the tile is the inside of the pad
+    /// assert_eq!(tile.pixel(7, 0), [0, 0, 0, 255]);
+    /// assert_eq!(tile.pixel(0, 7), [255; 4]);
+    /// ```
+    pub fn render_tile(
+        &self,
+        origin: Point,
+        width: usize,
+        height: usize,
+        options: &RenderOptions,
+    ) -> Raster {
+        let bands = (0..height)
+            .step_by(BAND_ROWS)
+            .map(|start| start..(start + BAND_ROWS).min(height))
+            .collect();
+        let bands = parallel::map(bands, |rows| {
+            let mut canvas = Canvas::new(origin, width, height, rows, options);
+            for object in &self.objects {
+                canvas.paint(object);
+            }
+            canvas.pixels(options)
+        });
+        Raster {
+            width,
+            height,
+            format: options.format,
+            pixels: bands.concat(),
+            origin,
+            pixels_per_mm: options.pixels_per_mm,
+        }
+    }
+
+    /// [Render a tile](Renderer::render_tile), calling `progress` after
+    /// every `every` objects and once at the end
+    ///
+    /// The callback can show the [partial raster](PartialRaster::raster)
+    /// painted so far, and returns [ControlFlow::Break] to cancel, in which
+    /// case this returns [GerberError::Cancelled].
+    ///
+    /// ```
+    /// use std::ops::ControlFlow;
+    /// use gerber::GerberLayer;
+    /// use gerber::raster::RenderOptions;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,1*%\nD10*\nX0Y0D03*\nX2000000Y0D03*\nM02*\n";
+    /// let renderer = GerberLayer::parse(src).unwrap().renderer(0.001);
+    /// let options = RenderOptions::default();
+    /// let (origin, _) = renderer.bounds().unwrap();
+    /// let mut painted = Vec::new();
+    /// let raster = renderer.render_tile_with_progress(origin, 30, 10, &options, 1, |partial| {
+    ///     painted.push(partial.objects);
+    ///     ControlFlow::Continue(())
+    /// });
+    /// assert!(raster.is_ok());
+    /// assert_eq!(painted, [1, 2]);
+    /// ```
+    pub fn render_tile_with_progress(
+        &self,
+        origin: Point,
+        width: usize,
+        height: usize,
+        options: &RenderOptions,
+        every: usize,
+        mut progress: impl FnMut(&PartialRaster) -> ControlFlow<()>,
+    ) -> Result<Raster, GerberError> {
+        let mut canvas = Canvas::new(origin, width, height, 0..height, options);
+        let every = every.max(1);
+        for (index, object) in self.objects.iter().enumerate() {
+            canvas.paint(object);
+            let painted = index + 1;
+            if painted % every == 0 || painted == self.objects.len() {
+                let partial = PartialRaster {
+                    objects: painted,
+                    total_objects: self.objects.len(),
+                    canvas: &canvas,
+                    options,
+                };
+                if progress(&partial).is_break() {
+                    return Err(GerberError::Cancelled);
+                }
+            }
+        }
+        Ok(canvas.raster(options))
+    }
+}
+
+/// A tile being rendered, passed to the progress callback
+pub struct PartialRaster<'a> {
+    /// Objects painted so far
+    pub objects: usize,
+
+    /// Objects with known geometry in the layer
+    pub total_objects: usize,
+
+    canvas: &'a Canvas,
+    options: &'a RenderOptions,
+}
+
+impl PartialRaster<'_> {
+    /// The fraction completed, from 0 to 1
+    pub fn fraction(&self) -> f64 {
+        if self.total_objects == 0 {
+            1.0
+        } else {
+            self.objects as f64 / self.total_objects as f64
+        }
+    }
+
+    /// The objects painted so far, as they would be rendered on their own
+    pub fn raster(&self) -> Raster {
+        self.canvas.raster(self.options)
+    }
+}
+
+/// Pixel rows painted together by [Renderer::render_tile]
+const BAND_ROWS: usize = 64;
+
+/// The mask of a band of a tile's rows, and the mapping from layer
+/// coordinates to samples of the tile
+struct Canvas {
+    mask: Mask,
+    origin: Point,
+    width: usize,
+    height: usize,
+
+    /// The pixel rows of the tile painted, from the top
+    rows: Range<usize>,
+    samples: usize,
+
+    /// Samples per millimeter
+    scale: f64,
+
+    /// The layer coordinates of the upper right corner
+    top_right: Point,
+}
+
+impl Canvas {
+    fn new(
+        origin: Point,
+        width: usize,
+        height: usize,
+        rows: Range<usize>,
+        options: &RenderOptions,
+    ) -> Self {
+        let samples = options.supersampling.max(1) as usize;
+        Canvas {
+            mask: Mask::new(width * samples, rows.start * samples..rows.end * samples),
+            origin,
+            width,
+            height,
+            rows,
+            samples,
+            scale: options.pixels_per_mm * samples as f64,
+            top_right: Point {
+                x: origin.x + width as f64 / options.pixels_per_mm,
+                y: origin.y + height as f64 / options.pixels_per_mm,
+            },
+        }
+    }
+
+    /// Paint an object, unless it is outside the band
+    fn paint(&mut self, object: &Paths) {
+        let (min, max) = object.bounds;
+        let (origin, top_right) = (self.origin, self.top_right);
+        let top = top_right.y - self.mask.rows.start as f64 / self.scale;
+        let bottom = top_right.y - self.mask.rows.end as f64 / self.scale;
+        if max.x < origin.x || min.x > top_right.x || max.y < bottom || min.y > top {
+            return;
+        }
+        // sample space, y down
+        let paths: Vec<Vec<(f64, f64)>> = object
+            .paths
+            .iter()
+            .map(|path| {
+                path.iter()
+                    .map(|point| {
+                        (
+                            (point.x - origin.x) * self.scale,
+                            (top_right.y - point.y) * self.scale,
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+        self.mask.fill(&paths, object.polarity == Polarity::Dark);
+    }
+
+    /// The raster of the whole tile, painted as one band
+    fn raster(&self, options: &RenderOptions) -> Raster {
+        Raster {
+            width: self.width,
+            height: self.height,
+            format: options.format,
+            pixels: self.pixels(options),
+            origin: self.origin,
+            pixels_per_mm: options.pixels_per_mm,
+        }
+    }
+
+    /// The pixels of the band's rows
+    fn pixels(&self, options: &RenderOptions) -> Vec<u8> {
+        let (width, samples) = (self.width, self.samples);
+        let format = options.format;
+        let mut pixels = Vec::with_capacity(width * self.rows.len() * format.bytes_per_pixel());
+        let area = (samples * samples) as f64;
+        for y in 0..self.rows.len() {
+            for x in 0..width {
+                let mut covered = 0;
+                for row in y * samples..(y + 1) * samples {
+                    covered += self.mask.samples[row][x * samples..(x + 1) * samples]
+                        .iter()
+                        .filter(|&&dark| dark)
+                        .count();
+                }
+                let color = mix(
+                    options.background,
+                    options.foreground,
+                    covered as f64 / area,
+                );
+                match format {
+                    PixelFormat::Gray => pixels.push(luma(color)),
+                    PixelFormat::Rgba => pixels.extend(color),
+                }
+            }
+        }
+        pixels
+    }
+}
+
+/// The lower left and upper right corners of some points
+fn bounds<'a>(points: impl IntoIterator<Item = &'a Point>) -> Option<(Point, Point)> {
+    let mut points = points.into_iter();
+    let first = *points.next()?;
+    Some(points.fold((first, first), |(min, max), point| {
+        (
+            Point {
+                x: min.x.min(point.x),
+                y: min.y.min(point.y),
+            },
+            Point {
+                x: max.x.max(point.x),
+                y: max.y.max(point.y),
+            },
+        )
+    }))
+}
+
+/// Which samples of some rows are dark
+struct Mask {
+    width: usize,
+
+    /// The sample rows held, from the top of the tile
+    rows: Range<usize>,
+    samples: Vec<Vec<bool>>,
+}
+
+impl Mask {
+    fn new(width: usize, rows: Range<usize>) -> Self {
+        Mask {
+            width,
+            samples: vec![vec![false; width]; rows.len()],
+            rows,
+        }
+    }
+
+    /// Set the samples whose centers are inside the paths by the non-zero
+    /// rule to `value`
+    fn fill(&mut self, paths: &[Vec<(f64, f64)>], value: bool) {
+        let edges: Vec<_> = paths
+            .iter()
+            .flat_map(|path| path.iter().zip(path.iter().cycle().skip(1)))
+            .filter(|(a, b)| a.1 != b.1)
+            .collect();
+        let (top, bottom) = edges
+            .iter()
+            .flat_map(|(a, b)| [a.1, b.1])
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(top, bottom), y| {
+                (top.min(y), bottom.max(y))
+            });
+        if edges.is_empty() {
+            return;
+        }
+        let first = ((top - 0.5).ceil().max(0.0) as usize).max(self.rows.start);
+        let last = (((bottom - 0.5).floor() + 1.0).max(0.0) as usize).min(self.rows.end);
+
+        let mut crossings = Vec::new();
+        for row in first..last {
+            let samples = &mut self.samples[row - self.rows.start];
+            let y = row as f64 + 0.5;
+            crossings.clear();
+            for (a, b) in &edges {
+                if (a.1 <= y) != (b.1 <= y) {
+                    let x = a.0 + (y - a.1) / (b.1 - a.1) * (b.0 - a.0);
+                    crossings.push((x, if b.1 > a.1 { 1 } else { -1 }));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding == 0 {
+                    continue;
+                }
+                // the samples with centers from pair[0] to pair[1]
+                let start = (pair[0].0 - 0.5).ceil().max(0.0) as usize;
+                let end = ((pair[1].0 - 0.5).ceil().max(0.0) as usize).min(self.width);
+                if start < end {
+                    samples[start..end].fill(value);
+                }
+            }
+        }
+    }
+}
+
+/// Mix two colors in premultiplied alpha, `t` of the way to `to`
+fn mix(from: [u8; 4], to: [u8; 4], t: f64) -> [u8; 4] {
+    let alpha = |color: [u8; 4]| color[3] as f64 / 255.0;
+    let a = alpha(from) * (1.0 - t) + alpha(to) * t;
+    let mut mixed = [0; 4];
+    if a > 0.0 {
+        for channel in 0..3 {
+            let value = (from[channel] as f64 * alpha(from) * (1.0 - t)
+                + to[channel] as f64 * alpha(to) * t)
+                / a;
+            mixed[channel] = value.round() as u8;
+        }
+    }
+    mixed[3] = (a * 255.0).round() as u8;
+    mixed
+}
+
+/// The Rec. 601 luma of a color
+fn luma(color: [u8; 4]) -> u8 {
+    (0.299 * color[0] as f64 + 0.587 * color[1] as f64 + 0.114 * color[2] as f64).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn render(body: &str, options: &RenderOptions) -> Raster {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().render(options)
+    }
+
+    fn gray(supersampling: u32) -> RenderOptions {
+        RenderOptions {
+            pixels_per_mm: 1.0,
+            supersampling,
+            format: PixelFormat::Gray,
+            ..Default::default()
+        }
+    }
+
+    fn rows(raster: &Raster) -> Vec<&[u8]> {
+        raster.pixels.chunks(raster.width).collect()
+    }
+
+    #[test]
+    fn test_clear() {
+        // a square with a clear hole in the lower left and a dark pad in it
+        let raster = render(
+            indoc! {"
+                %ADD10R,4X4*%
+                %ADD11R,2X2*%
+                %ADD12R,1X1*%
+                D10*
+                X2000000Y2000000D03*
+                %LPC*%
+                D11*
+                X1000000Y1000000D03*
+                %LPD*%
+                D12*
+                X500000Y500000D03*
+            "},
+            &gray(1),
+        );
+        assert_eq!(
+            rows(&raster),
+            [[0, 0, 0, 0], [0, 0, 0, 0], [255, 255, 0, 0], [0, 255, 0, 0],]
+        );
+    }
+
+    #[test]
+    fn test_anti_aliasing() {
+        // the second pad covers three quarters of the middle pixel and a
+        // quarter of the last
+        let body = "%ADD10R,1X1*%\nD10*\nX0Y0D03*\nX1250000Y0D03*\n";
+        let aliased = render(body, &gray(1));
+        let smooth = render(body, &gray(4));
+        assert_eq!(aliased.pixels, [0, 0, 255]);
+        assert_eq!(smooth.pixels, [0, 64, 191]);
+    }
+
+    #[test]
+    fn test_colors() {
+        let options = RenderOptions {
+            pixels_per_mm: 1.0,
+            supersampling: 2,
+            foreground: [255, 0, 0, 255],
+            background: [0, 0, 0, 0],
+            ..Default::default()
+        };
+        let raster = render("%ADD10R,1X2*%\nD10*\nX0Y0D03*\nX1500000Y0D03*\n", &options);
+        assert_eq!((raster.width, raster.height), (3, 2));
+        assert_eq!(raster.pixel(0, 0), [255, 0, 0, 255]);
+        // half covered, with straight alpha the color stays red
+        assert_eq!(raster.pixel(1, 0), [255, 0, 0, 128]);
+        assert_eq!(raster.origin, Point { x: -0.5, y: -1.0 });
+    }
+
+    #[test]
+    fn test_empty() {
+        let raster = render("", &RenderOptions::default());
+        assert_eq!((raster.width, raster.height), (0, 0));
+        assert!(raster.pixels.is_empty());
+    }
+
+    #[test]
+    fn test_tiles() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,1.7*%
+            %ADD11R,0.5X0.5*%
+            D10*
+            G01*
+            X1000000Y1000000D02*
+            X3000000Y3000000D01*
+            %LPC*%
+            D11*
+            X2000000Y2000000D03*
+            M02*
+        "};
+        let renderer = GerberLayer::parse(src).unwrap().renderer(0.001);
+        let options = RenderOptions {
+            pixels_per_mm: 2.0,
+            supersampling: 3,
+            ..Default::default()
+        };
+        let (min, _) = renderer.bounds().unwrap();
+        let whole = renderer.render_tile(min, 8, 8, &options);
+
+        // four 4 × 4 tiles put together give the same pixels
+        let mut tiled = vec![0; whole.pixels.len()];
+        for (column, row) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let origin = Point {
+                x: min.x + column as f64 * 2.0,
+                y: min.y + row as f64 * 2.0,
+            };
+            let tile = renderer.render_tile(origin, 4, 4, &options);
+            for y in 0..4 {
+                for x in 0..4 {
+                    let (x_whole, y_whole) = (column * 4 + x, (1 - row) * 4 + y);
+                    let start = (y_whole * 8 + x_whole) * 4;
+                    tiled[start..start + 4].copy_from_slice(tile.pixel(x, y));
+                }
+            }
+        }
+        assert_eq!(tiled, whole.pixels);
+        assert!(whole.pixels.chunks(4).any(|pixel| pixel[0] == 0));
+        assert!(whole.pixels.chunks(4).any(|pixel| pixel[0] == 255));
+    }
+
+    #[test]
+    fn test_bands() {
+        // a disk with a clear slot across it, over several bands of rows
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,30*%
+            %ADD11C,1*%
+            D10*
+            X0Y0D03*
+            %LPC*%
+            D11*
+            G01*
+            X-10000000Y-10000000D02*
+            X10000000Y10000000D01*
+            M02*
+        "};
+        let renderer = GerberLayer::parse(src).unwrap().renderer(0.001);
+        let options = RenderOptions {
+            pixels_per_mm: 10.0,
+            ..gray(2)
+        };
+        let bands = renderer.render(&options);
+        assert_eq!(bands.height, 300);
+        assert!(bands.height > 2 * BAND_ROWS);
+
+        // the same as painting all rows at once
+        let (min, _) = renderer.bounds().unwrap();
+        let mut canvas = Canvas::new(min, 300, 300, 0..300, &options);
+        renderer
+            .objects
+            .iter()
+            .for_each(|object| canvas.paint(object));
+        assert_eq!(bands, canvas.raster(&options));
+        assert!(bands.pixels.contains(&0) && bands.pixels.contains(&255));
+    }
+
+    #[test]
+    fn test_progress() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10R,1X1*%
+            D10*
+            X0Y0D03*
+            X1000000Y0D03*
+            X2000000Y0D03*
+            X3000000Y0D03*
+            X4000000Y0D03*
+            M02*
+        "};
+        let renderer = GerberLayer::parse(src).unwrap().renderer(0.001);
+        let options = gray(1);
+        let (origin, _) = renderer.bounds().unwrap();
+
+        let mut partials = Vec::new();
+        let raster = renderer
+            .render_tile_with_progress(origin, 5, 1, &options, 2, |partial| {
+                partials.push((partial.objects, partial.raster().pixels));
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(
+            partials,
+            [
+                (2, vec![0, 0, 255, 255, 255]),
+                (4, vec![0, 0, 0, 0, 255]),
+                (5, vec![0; 5]),
+            ]
+        );
+        assert_eq!(raster, renderer.render_tile(origin, 5, 1, &options));
+
+        let cancelled = renderer.render_tile_with_progress(origin, 5, 1, &options, 1, |partial| {
+            if partial.fraction() > 0.5 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(matches!(cancelled, Err(GerberError::Cancelled)));
+    }
+
+    #[test]
+    fn test_composite() {
+        let transparent = |foreground| RenderOptions {
+            pixels_per_mm: 1.0,
+            supersampling: 1,
+            foreground,
+            background: [0; 4],
+            ..Default::default()
+        };
+        let plane = "%ADD10R,3X1*%\nD10*\nX1500000Y500000D03*\n";
+        // a negative layer: a dark area with a clear hole in its middle
+        let negative = "%ADD10R,3X1*%\n%ADD11R,1X1*%\nD10*\nX1500000Y500000D03*\n\
+                        %LPC*%\nD11*\nX1500000Y500000D03*\n";
+        let mut below = render(plane, &transparent([255, 0, 0, 255]));
+        let above = render(negative, &transparent([0, 0, 255, 128]));
+        below.composite(&above);
+        assert_eq!(below.pixel(0, 0), [127, 0, 128, 255]);
+        // the hole shows the layer below
+        assert_eq!(below.pixel(1, 0), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_png() {
+        let raster = render("%ADD10R,2X1*%\nD10*\nX0Y0D03*\n", &gray(1));
+        let png = raster.to_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+        // walk the chunks, checking their checksums
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (body, crc) = rest[4..].split_at(4 + length);
+            assert_eq!(
+                crc32(body),
+                u32::from_be_bytes(crc[..4].try_into().unwrap())
+            );
+            chunks.push((&body[..4], &body[4..]));
+            rest = &crc[4..];
+        }
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 1, 8, 0, 0, 0, 0]);
+
+        // a single stored block holding the filtered row
+        let zlib = chunks[1].1;
+        assert_eq!(&zlib[2..7], [1, 3, 0, !3, 0xff]);
+        assert_eq!(&zlib[7..10], [0, 0, 0]);
+        // the Adler-32 of the row
+        assert_eq!(&zlib[10..], [0, 3, 0, 1]);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn test_mix() {
+        let (black, white) = ([0, 0, 0, 255], [255, 255, 255, 255]);
+        assert_eq!(mix(black, white, 0.5), [128, 128, 128, 255]);
+        assert_eq!(mix([0; 4], [255, 0, 0, 255], 0.25), [255, 0, 0, 64]);
+        assert_eq!(luma(white), 255);
+    }
+}
@@ -0,0 +1,680 @@
+//! Detection and removal of redundant objects
+//!
+//! Panelization scripts and some exporters repeat objects, or place small
+//! pads on top of larger ones. Such objects don't change the image, but
+//! they make files larger and slower to plot.
+//!
+//! An object is only redundant if the object covering it has the same
+//! polarity and no object of the other polarity is created between the
+//! two, as that object could clear or darken the overlap differently.
+
+use std::collections::HashMap;
+
+use crate::aperture::ApertureTemplate;
+use crate::command::Command::{self, *};
+use crate::data::{Mirroring, Polarity, Rotation, Scaling, Unit};
+use crate::image::{Image, Object, Point, Shape};
+use crate::GerberLayer;
+
+/// Tolerance in millimeters when comparing geometry
+const EPSILON: f64 = 1e-9;
+
+/// Why an object is redundant
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Redundancy {
+    /// The same aperture is flashed at the same point, or drawn along the
+    /// same path
+    Duplicate,
+
+    /// Another object covers the whole object
+    Covered,
+}
+
+/// An object which can be removed without changing the image
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Redundant {
+    pub redundancy: Redundancy,
+
+    /// Index of the redundant object in the [Image]
+    pub object: usize,
+
+    /// Index of the object which makes it redundant
+    pub covered_by: usize,
+
+    /// Index of the operation which created the redundant object
+    pub command: usize,
+}
+
+impl GerberLayer<'_> {
+    /// Find flashes which duplicate or are covered by other flashes
+    ///
+    /// Only flashes without mirroring, rotation or scaling are checked for
+    /// coverage, and macro apertures are never considered to cover or be
+    /// covered.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    /// use gerber::redundant::Redundancy;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,1*%\nD10*\nX0Y0D03*\nD03*\nM02*\n";
+    /// let redundant = GerberLayer::parse(src).unwrap().redundant_flashes();
+    /// assert_eq!(redundant[0].redundancy, Redundancy::Duplicate);
+    /// ```
+    pub fn redundant_flashes(&self) -> Vec<Redundant> {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let flashes: Vec<Pad> = image
+            .objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, object)| Pad::new(index, object, &self.commands, unit))
+            .collect();
+        let polarity = PolarityCounts::new(&image);
+
+        // a pad can only be covered by one whose center is within its extent
+        let cell = flashes
+            .iter()
+            .filter_map(|flash| flash.outer.map(|outer| outer.extent()))
+            .fold(EPSILON, f64::max);
+        let key = |point: Point| {
+            (
+                (point.x / cell).floor() as i64,
+                (point.y / cell).floor() as i64,
+            )
+        };
+        let mut grid: HashMap<_, Vec<usize>> = HashMap::new();
+        for (index, flash) in flashes.iter().enumerate() {
+            grid.entry(key(flash.at)).or_default().push(index);
+        }
+
+        let mut redundant = Vec::new();
+        for flash in &flashes {
+            let (x, y) = key(flash.at);
+            let neighbours = (x - 1..=x + 1)
+                .flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y)))
+                .filter_map(|key| grid.get(&key))
+                .flatten()
+                .map(|&index| &flashes[index]);
+
+            // prefer a duplicate over a covering, then the earliest object,
+            // so the result doesn't depend on the order of the neighbours
+            let found = neighbours
+                .filter(|other| {
+                    other.object != flash.object
+                        && polarity.unchanged_between(&image, flash.object, other.object)
+                })
+                .filter_map(|other| {
+                    if flash.duplicates(other) {
+                        // the first of a set of duplicates is kept
+                        (other.object < flash.object).then_some((Redundancy::Duplicate, other))
+                    } else {
+                        flash
+                            .covered_by(other)
+                            .then_some((Redundancy::Covered, other))
+                    }
+                })
+                .min_by_key(|(redundancy, other)| (*redundancy, other.object));
+            if let Some((redundancy, other)) = found {
+                redundant.push(Redundant {
+                    redundancy,
+                    object: flash.object,
+                    covered_by: other.object,
+                    command: flash.command,
+                });
+            }
+        }
+        redundant.sort_by_key(|redundant| redundant.object);
+        redundant
+    }
+
+    /// Find draws and arcs which duplicate or are covered by other strokes
+    ///
+    /// A draw in the opposite direction counts as a duplicate. Coverage is
+    /// only checked for straight draws with an untransformed circle, whose
+    /// strokes are capsules; other strokes are only checked for exact
+    /// duplicates.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,1*%\nD10*\nG01*\n\
+    ///            X0Y0D02*\nX5000000D01*\nX1000000D01*\nM02*\n";
+    /// let redundant = GerberLayer::parse(src).unwrap().redundant_draws();
+    /// assert_eq!(redundant[0].object, 1);
+    /// ```
+    pub fn redundant_draws(&self) -> Vec<Redundant> {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let mut strokes: Vec<Stroke> = image
+            .objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, object)| Stroke::new(index, object, &self.commands, unit))
+            .collect();
+        let polarity = PolarityCounts::new(&image);
+
+        // sorted along x, a stroke can only be covered by one starting
+        // before it or at the same x
+        strokes.sort_by(|a, b| a.min_x.total_cmp(&b.min_x));
+        let mut redundant = Vec::new();
+        for (position, stroke) in strokes.iter().enumerate() {
+            let overlapping = strokes[..position]
+                .iter()
+                .rev()
+                .chain(
+                    strokes[position + 1..]
+                        .iter()
+                        .take_while(|other| other.min_x <= stroke.min_x + EPSILON),
+                )
+                .filter(|other| {
+                    other.max_x + EPSILON >= stroke.max_x
+                        && polarity.unchanged_between(&image, stroke.object, other.object)
+                });
+            let found = overlapping
+                .filter_map(|other| {
+                    if stroke.duplicates(other) {
+                        (other.object < stroke.object).then_some((Redundancy::Duplicate, other))
+                    } else {
+                        stroke
+                            .covered_by(other)
+                            .then_some((Redundancy::Covered, other))
+                    }
+                })
+                .min_by_key(|(redundancy, other)| (*redundancy, other.object));
+            if let Some((redundancy, other)) = found {
+                redundant.push(Redundant {
+                    redundancy,
+                    object: stroke.object,
+                    covered_by: other.object,
+                    command: stroke.command,
+                });
+            }
+        }
+        redundant.sort_by_key(|redundant| redundant.object);
+        redundant
+    }
+
+    /// Remove the operations which created `redundant` objects, returning
+    /// how many were removed
+    ///
+    /// An operation is replaced by a D02 move to its end point, so the
+    /// coordinates of later operations keep their meaning.
+    pub fn remove_redundant(&mut self, redundant: &[Redundant]) -> usize {
+        let mut removed = 0;
+        for redundant in redundant {
+            let command = &mut self.commands[redundant.command];
+            if let Flash(coordinates) | Plot(coordinates, _) = command {
+                *command = Move(*coordinates);
+                removed += 1;
+            }
+        }
+        removed
+    }
+}
+
+/// Numbers of dark and clear objects created before each object, so the
+/// polarities between two objects can be checked in constant time
+struct PolarityCounts(Vec<[usize; 2]>);
+
+impl PolarityCounts {
+    fn new(image: &Image) -> Self {
+        let mut counts = vec![[0, 0]];
+        for object in &image.objects {
+            let mut next = *counts.last().unwrap();
+            next[object.polarity as usize] += 1;
+            counts.push(next);
+        }
+        Self(counts)
+    }
+
+    /// True if objects `a` and `b` and every object between them have the
+    /// same polarity
+    fn unchanged_between(&self, image: &Image, a: usize, b: usize) -> bool {
+        let polarity = image.objects[a].polarity;
+        let (first, last) = (a.min(b), a.max(b));
+        let other = match polarity {
+            Polarity::Dark => Polarity::Clear,
+            Polarity::Clear => Polarity::Dark,
+        } as usize;
+        image.objects[b].polarity == polarity && self.0[last + 1][other] == self.0[first][other]
+    }
+}
+
+/// A flashed pad with the geometry used to compare it to others
+struct Pad<'a> {
+    object: usize,
+    command: usize,
+    at: Point,
+    source: &'a Object,
+
+    /// A shape containing the pad, if its geometry is known
+    outer: Option<Outline>,
+
+    /// A shape contained in the pad, if its geometry is known
+    inner: Option<Outline>,
+}
+
+impl<'a> Pad<'a> {
+    fn new(index: usize, object: &'a Object, commands: &[Command], unit: Unit) -> Option<Self> {
+        let Shape::Flash { at, .. } = object.shape else {
+            return None;
+        };
+        let transformed = object.mirroring != Mirroring::None
+            || object.rotation != Rotation::default()
+            || object.scaling != Scaling::default();
+        let template = match object.source.aperture.map(|index| &commands[index]) {
+            Some(ApertureDefine(_, template)) if !transformed => Some(template),
+            _ => None,
+        };
+        Some(Self {
+            object: index,
+            command: object.source.commands.start,
+            at,
+            source: object,
+            outer: template.and_then(|template| Outline::outer(template, unit)),
+            inner: template.and_then(|template| Outline::inner(template, unit)),
+        })
+    }
+
+    /// The same aperture with the same transformation at the same point
+    fn duplicates(&self, other: &Pad) -> bool {
+        let (a, b) = (self.source, other.source);
+        a.shape == b.shape
+            && a.mirroring == b.mirroring
+            && a.rotation == b.rotation
+            && a.scaling == b.scaling
+    }
+
+    /// True if `other` covers this flash. When the two cover each other
+    /// only the later one is covered, so one of them is always kept.
+    fn covered_by(&self, other: &Pad) -> bool {
+        let contains = |outer: &Pad, inner: &Pad| match (outer.inner, inner.outer) {
+            (Some(outer_shape), Some(inner_shape)) => {
+                outer_shape.contains(outer.at, inner_shape, inner.at)
+            }
+            _ => false,
+        };
+        contains(other, self) && !(contains(self, other) && other.object > self.object)
+    }
+}
+
+/// A draw or arc with the geometry used to compare it to others
+struct Stroke<'a> {
+    object: usize,
+    command: usize,
+    source: &'a Object,
+
+    /// A draw with a round aperture as its segment and radius
+    capsule: Option<(Point, Point, f64)>,
+
+    /// The extent along x, for sweeping
+    min_x: f64,
+    max_x: f64,
+}
+
+impl<'a> Stroke<'a> {
+    fn new(index: usize, object: &'a Object, commands: &[Command], unit: Unit) -> Option<Self> {
+        let (start, end) = match object.shape {
+            Shape::Draw { start, end, .. } => (start, end),
+            Shape::Arc { start, end, .. } => (start, end),
+            _ => return None,
+        };
+        let transformed = object.mirroring != Mirroring::None
+            || object.rotation != Rotation::default()
+            || object.scaling != Scaling::default();
+        let radius = match object.source.aperture.map(|index| &commands[index]) {
+            Some(ApertureDefine(
+                _,
+                ApertureTemplate::Circle {
+                    diameter,
+                    hole: None,
+                },
+            )) if !transformed => Some(unit.to_mm(diameter / 2.0)),
+            _ => None,
+        };
+        let capsule = match object.shape {
+            Shape::Draw { .. } => radius.map(|radius| (start, end, radius)),
+            _ => None,
+        };
+        // arcs and other apertures only need their extent for duplicates
+        let margin = radius.unwrap_or(0.0);
+        Some(Self {
+            object: index,
+            command: object.source.commands.start,
+            source: object,
+            capsule,
+            min_x: start.x.min(end.x) - margin,
+            max_x: start.x.max(end.x) + margin,
+        })
+    }
+
+    /// The same aperture and transformation along the same path
+    fn duplicates(&self, other: &Stroke) -> bool {
+        let (a, b) = (self.source, other.source);
+        let same_path = match (&a.shape, &b.shape) {
+            (
+                Shape::Draw {
+                    start,
+                    end,
+                    aperture,
+                },
+                Shape::Draw {
+                    start: other_start,
+                    end: other_end,
+                    aperture: other_aperture,
+                },
+            ) => {
+                aperture == other_aperture
+                    && ((start, end) == (other_start, other_end)
+                        || (start, end) == (other_end, other_start))
+            }
+            (a, b) => a == b,
+        };
+        same_path
+            && a.mirroring == b.mirroring
+            && a.rotation == b.rotation
+            && a.scaling == b.scaling
+    }
+
+    /// True if `other` covers this stroke. When the two cover each other
+    /// only the later one is covered, so one of them is always kept.
+    fn covered_by(&self, other: &Stroke) -> bool {
+        let contains = |outer: &Stroke, inner: &Stroke| match (outer.capsule, inner.capsule) {
+            (Some((start, end, radius)), Some((inner_start, inner_end, inner_radius))) => {
+                // a capsule is convex, so it contains the capsule around
+                // another segment if it contains both of its end circles
+                [inner_start, inner_end].iter().all(|&point| {
+                    distance_to_segment(point, start, end) + inner_radius <= radius + EPSILON
+                })
+            }
+            _ => false,
+        };
+        contains(other, self) && !(contains(self, other) && other.object > self.object)
+    }
+}
+
+/// The distance from `point` to the segment from `start` to `end`
+pub(crate) fn distance_to_segment(point: Point, start: Point, end: Point) -> f64 {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((point.x - start.x) * dx + (point.y - start.y) * dy) / length).clamp(0.0, 1.0)
+    };
+    (point.x - start.x - t * dx).hypot(point.y - start.y - t * dy)
+}
+
+/// A circle or axis-aligned rectangle centered on a flash, in millimeters
+#[derive(Copy, Clone, Debug)]
+enum Outline {
+    Circle { radius: f64 },
+    Rectangle { half_x: f64, half_y: f64 },
+}
+
+impl Outline {
+    /// The smallest outline containing the aperture
+    fn outer(template: &ApertureTemplate, unit: Unit) -> Option<Self> {
+        let outline = match *template {
+            ApertureTemplate::Circle { diameter, .. }
+            | ApertureTemplate::Polygon { diameter, .. } => Self::Circle {
+                radius: diameter / 2.0,
+            },
+            ApertureTemplate::Rectangle { x, y, .. } | ApertureTemplate::Obround { x, y, .. } => {
+                Self::Rectangle {
+                    half_x: x / 2.0,
+                    half_y: y / 2.0,
+                }
+            }
+            ApertureTemplate::Macro { .. } => return None,
+        };
+        Some(outline.to_mm(unit))
+    }
+
+    /// An outline inside the aperture, or none if it has a hole
+    fn inner(template: &ApertureTemplate, unit: Unit) -> Option<Self> {
+        let outline = match *template {
+            ApertureTemplate::Circle {
+                diameter,
+                hole: None,
+            } => Self::Circle {
+                radius: diameter / 2.0,
+            },
+            ApertureTemplate::Rectangle { x, y, hole: None } => Self::Rectangle {
+                half_x: x / 2.0,
+                half_y: y / 2.0,
+            },
+            // the straight part between the rounded ends
+            ApertureTemplate::Obround { x, y, hole: None } => Self::Rectangle {
+                half_x: (x - x.min(y)) / 2.0,
+                half_y: (y - x.min(y)) / 2.0,
+            }
+            .max(Self::Circle {
+                radius: x.min(y) / 2.0,
+            }),
+            // the inscribed circle
+            ApertureTemplate::Polygon {
+                diameter,
+                vertices,
+                hole: None,
+                ..
+            } => Self::Circle {
+                radius: diameter / 2.0 * (std::f64::consts::PI / vertices).cos(),
+            },
+            _ => return None,
+        };
+        Some(outline.to_mm(unit))
+    }
+
+    fn to_mm(self, unit: Unit) -> Self {
+        match self {
+            Self::Circle { radius } => Self::Circle {
+                radius: unit.to_mm(radius),
+            },
+            Self::Rectangle { half_x, half_y } => Self::Rectangle {
+                half_x: unit.to_mm(half_x),
+                half_y: unit.to_mm(half_y),
+            },
+        }
+    }
+
+    /// The larger of two outlines with the same center, by area
+    fn max(self, other: Self) -> Self {
+        if self.area() >= other.area() {
+            self
+        } else {
+            other
+        }
+    }
+
+    fn area(&self) -> f64 {
+        match *self {
+            Self::Circle { radius } => std::f64::consts::PI * radius * radius,
+            Self::Rectangle { half_x, half_y } => 4.0 * half_x * half_y,
+        }
+    }
+
+    /// The largest distance from the center to the edge
+    fn extent(&self) -> f64 {
+        match *self {
+            Self::Circle { radius } => radius,
+            Self::Rectangle { half_x, half_y } => half_x.hypot(half_y),
+        }
+    }
+
+    /// True if this outline at `at` contains `inner` at `inner_at`
+    fn contains(&self, at: Point, inner: Outline, inner_at: Point) -> bool {
+        let dx = (inner_at.x - at.x).abs();
+        let dy = (inner_at.y - at.y).abs();
+        match (*self, inner) {
+            (Self::Circle { radius }, Self::Circle { radius: r }) => {
+                dx.hypot(dy) + r <= radius + EPSILON
+            }
+            (Self::Circle { radius }, Self::Rectangle { half_x, half_y }) => {
+                (dx + half_x).hypot(dy + half_y) <= radius + EPSILON
+            }
+            (Self::Rectangle { half_x, half_y }, Self::Circle { radius: r }) => {
+                dx + r <= half_x + EPSILON && dy + r <= half_y + EPSILON
+            }
+            (
+                Self::Rectangle { half_x, half_y },
+                Self::Rectangle {
+                    half_x: x,
+                    half_y: y,
+                },
+            ) => dx + x <= half_x + EPSILON && dy + y <= half_y + EPSILON,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const HEADER: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10C,1*%
+        %ADD11R,2X2*%
+        %ADD12C,0.5*%
+        %ADD13R,1X1X0.2*%
+    "};
+
+    fn redundant(body: &str) -> Vec<(Redundancy, usize, usize)> {
+        let src = format!("{HEADER}{body}M02*\n");
+        GerberLayer::parse(&src)
+            .unwrap()
+            .redundant_flashes()
+            .into_iter()
+            .map(|r| (r.redundancy, r.object, r.covered_by))
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let body = indoc! {"
+            D10*
+            X0Y0D03*
+            X0Y0D03*
+            X1000000Y0D03*
+            X0Y0D03*
+        "};
+        assert_eq!(
+            redundant(body),
+            [(Redundancy::Duplicate, 1, 0), (Redundancy::Duplicate, 3, 0)]
+        );
+    }
+
+    #[test]
+    fn test_covered() {
+        let body = indoc! {"
+            D11*
+            X0Y0D03*
+            D12*
+            X500000Y500000D03*
+            X800000Y0D03*
+            D10*
+            X5000000Y0D03*
+            D13*
+            X5000000Y0D03*
+            D12*
+            X5000000Y0D03*
+        "};
+        // the circle partly outside the square is kept, and the square
+        // with a hole doesn't cover anything
+        assert_eq!(
+            redundant(body),
+            [(Redundancy::Covered, 1, 0), (Redundancy::Covered, 5, 3)]
+        );
+    }
+
+    #[test]
+    fn test_equal_pads_keep_one() {
+        let body = indoc! {"
+            %ADD14C,1*%
+            D10*
+            X0Y0D03*
+            D14*
+            X0Y0D03*
+        "};
+        assert_eq!(redundant(body), [(Redundancy::Covered, 1, 0)]);
+    }
+
+    #[test]
+    fn test_polarity() {
+        let body = indoc! {"
+            D10*
+            X0Y0D03*
+            %LPC*%
+            X3000000Y0D03*
+            %LPD*%
+            X0Y0D03*
+            %LPC*%
+            X0Y0D03*
+        "};
+        // the clear flash between the dark ones doesn't overlap them, but
+        // any clear object in between prevents removal
+        assert!(redundant(body).is_empty());
+    }
+
+    fn redundant_draws(body: &str) -> Vec<(Redundancy, usize, usize)> {
+        let src = format!("{HEADER}G01*\n{body}M02*\n");
+        GerberLayer::parse(&src)
+            .unwrap()
+            .redundant_draws()
+            .into_iter()
+            .map(|r| (r.redundancy, r.object, r.covered_by))
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_draws() {
+        let body = indoc! {"
+            D11*
+            X0Y0D02*
+            X1000000D01*
+            X0D01*
+            X1000000D01*
+            Y1000000D01*
+        "};
+        // square apertures only compare exactly, in either direction
+        assert_eq!(
+            redundant_draws(body),
+            [(Redundancy::Duplicate, 1, 0), (Redundancy::Duplicate, 2, 0)]
+        );
+    }
+
+    #[test]
+    fn test_covered_draws() {
+        let body = indoc! {"
+            D10*
+            X0Y0D02*
+            X10000000D01*
+            D12*
+            X2000000Y200000D02*
+            X8000000Y-200000D01*
+            X8000000Y400000D02*
+            X9000000D01*
+            D10*
+            X-100000Y0D02*
+            X5000000D01*
+        "};
+        // the thin draw within the thick one is covered, the one reaching
+        // past its edge isn't, and the draw overhanging its end isn't
+        assert_eq!(redundant_draws(body), [(Redundancy::Covered, 1, 0)]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let src = format!("{HEADER}D10*\nX0Y0D03*\nD03*\nY1000000D03*\nM02*\n");
+        let mut layer = GerberLayer::parse(&src).unwrap();
+        let redundant = layer.redundant_flashes();
+        assert_eq!(layer.remove_redundant(&redundant), 1);
+        assert!(layer.redundant_flashes().is_empty());
+        assert_eq!(layer.image().objects.len(), 2);
+        assert_eq!(layer.commands[8], Move(Default::default()));
+    }
+}
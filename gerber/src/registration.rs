@@ -0,0 +1,472 @@
+//! Registration of layers sharing coordinates
+//!
+//! Layers with the same `.SameCoordinates` identifier promise that their
+//! images line up without any offset, so a fab can stack them as they
+//! are. That only holds if they use the same unit and coordinate format,
+//! and place their objects in the same frame as the board profile. A
+//! layer exported from a different origin, or in inches where its siblings
+//! are in millimeters, breaks the registration and has to be caught before
+//! the fab plots it.
+//!
+//! Independently of the attribute, the [profile](check_profiles) of a
+//! layer set is the frame all layers are drawn in. Layers which draw
+//! their own copy of the board outline should agree with it, and nothing
+//! should extend beyond it.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::command::Command::*;
+use crate::data::{CoordinateFormat, Unit};
+use crate::image::{Bounds, Image, Object};
+use crate::GerberLayer;
+
+/// A layer out of registration with the others of its group
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Mismatch {
+    /// The `.SameCoordinates` identifier, `None` for layers with the
+    /// attribute but no identifier
+    pub group: Option<String>,
+
+    /// Index of the mismatched layer in the layers checked
+    pub layer: usize,
+
+    /// Index of the layer it is compared to: the first layer of the group,
+    /// or for [Alignment](MismatchKind::Alignment) the profile layer
+    pub reference: usize,
+
+    pub kind: MismatchKind,
+}
+
+/// How a layer is out of registration
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum MismatchKind {
+    /// The layers are in different units, set by `%MO`
+    Unit {
+        expected: Option<Unit>,
+        found: Option<Unit>,
+    },
+
+    /// The layers have different coordinate formats, set by `%FS`
+    Format {
+        expected: Option<(CoordinateFormat, CoordinateFormat)>,
+        found: Option<(CoordinateFormat, CoordinateFormat)>,
+    },
+
+    /// The objects of the layer extend beyond the board profile by
+    /// `overhang` millimeters, as if it were drawn from another origin
+    Alignment { overhang: f64 },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let group = self.group.as_deref().unwrap_or("");
+        write!(f, "layer {} in group {group:?}: ", self.layer)?;
+        match &self.kind {
+            MismatchKind::Unit { expected, found } => write!(
+                f,
+                "unit {found:?} differs from {expected:?} of layer {}",
+                self.reference
+            ),
+            MismatchKind::Format { expected, found } => write!(
+                f,
+                "coordinate format {found:?} differs from {expected:?} of layer {}",
+                self.reference
+            ),
+            MismatchKind::Alignment { overhang } => write!(
+                f,
+                "extends {overhang} mm beyond the profile of layer {}",
+                self.reference
+            ),
+        }
+    }
+}
+
+/// Check that the layers sharing a `.SameCoordinates` identifier are in
+/// registration
+///
+/// Layers without the attribute are skipped. Within a group, the unit and
+/// coordinate format of each layer are compared to the first layer. If
+/// the group has a profile layer (`.FileFunction,Profile`), the objects of
+/// the other layers must lie within the bounds of its objects, give or
+/// take `tolerance` millimeters. Objects of macro apertures, whose bounds
+/// are unknown, are left out of the comparison.
+///
+/// ```
+/// use gerber::registration::{check_same_coordinates, MismatchKind};
+/// use gerber::GerberLayer;
+///
+/// let header = "%FSLAX26Y26*%\n%TF.SameCoordinates,board*%\n";
+/// let mm = format!("{header}%MOMM*%\nM02*\n");
+/// let inch = format!("{header}%MOIN*%\nM02*\n");
+/// let layers = [GerberLayer::parse(&mm).unwrap(), GerberLayer::parse(&inch).unwrap()];
+/// let mismatches = check_same_coordinates(&layers, 0.1);
+/// assert_eq!(mismatches[0].layer, 1);
+/// assert!(matches!(mismatches[0].kind, MismatchKind::Unit { .. }));
+/// ```
+pub fn check_same_coordinates<'a, 'b: 'a>(
+    layers: impl IntoIterator<Item = &'a GerberLayer<'b>>,
+    tolerance: f64,
+) -> Vec<Mismatch> {
+    let mut groups: BTreeMap<Option<String>, Vec<Layer>> = BTreeMap::new();
+    for (index, layer) in layers.into_iter().enumerate() {
+        let image = layer.image();
+        let Some(values) = image.file_attributes.get(".SameCoordinates") else {
+            continue;
+        };
+        let group = values
+            .first()
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string());
+        let format = layer.commands.iter().find_map(|command| match command {
+            FormatSpecification(x, y) => Some((*x, *y)),
+            _ => None,
+        });
+        groups.entry(group).or_default().push(Layer {
+            index,
+            format,
+            image,
+        });
+    }
+
+    let mut mismatches = Vec::new();
+    for (group, layers) in groups {
+        let mut push = |layer: usize, reference: usize, kind| {
+            mismatches.push(Mismatch {
+                group: group.clone(),
+                layer,
+                reference,
+                kind,
+            })
+        };
+        let first = &layers[0];
+        for layer in &layers[1..] {
+            if layer.image.unit != first.image.unit {
+                let kind = MismatchKind::Unit {
+                    expected: first.image.unit,
+                    found: layer.image.unit,
+                };
+                push(layer.index, first.index, kind);
+            }
+            if layer.format != first.format {
+                let kind = MismatchKind::Format {
+                    expected: first.format,
+                    found: layer.format,
+                };
+                push(layer.index, first.index, kind);
+            }
+        }
+
+        let Some(profile) = layers.iter().find(|layer| layer.is_profile()) else {
+            continue;
+        };
+        let Some(board) = extent(&profile.image) else {
+            continue;
+        };
+        for layer in &layers {
+            if layer.index == profile.index {
+                continue;
+            }
+            let Some(bounds) = extent(&layer.image) else {
+                continue;
+            };
+            let overhang = beyond(&board, &bounds);
+            if overhang > tolerance {
+                push(
+                    layer.index,
+                    profile.index,
+                    MismatchKind::Alignment { overhang },
+                );
+            }
+        }
+    }
+    mismatches
+}
+
+/// A layer which disagrees with the board profile
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ProfileIssue {
+    /// Index of the layer in the layers checked
+    pub layer: usize,
+
+    /// Index of the layer the profile was taken from
+    pub reference: usize,
+
+    pub kind: ProfileIssueKind,
+}
+
+/// How a layer disagrees with the board profile
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ProfileIssueKind {
+    /// The outline drawn on the layer has bounds which differ from those of
+    /// the profile by up to `difference` millimeters
+    Disagrees { difference: f64 },
+
+    /// Objects of the layer extend up to `overhang` millimeters beyond the
+    /// profile
+    Outside {
+        /// Indices in [Image::objects]
+        objects: Vec<usize>,
+        overhang: f64,
+    },
+}
+
+impl fmt::Display for ProfileIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "layer {}: ", self.layer)?;
+        match &self.kind {
+            ProfileIssueKind::Disagrees { difference } => write!(
+                f,
+                "outline differs by {difference} mm from the profile of layer {}",
+                self.reference
+            ),
+            ProfileIssueKind::Outside { objects, overhang } => write!(
+                f,
+                "{} objects extend up to {overhang} mm beyond the profile of layer {}",
+                objects.len(),
+                self.reference
+            ),
+        }
+    }
+}
+
+/// Check each layer against the board profile
+///
+/// The profile is the first layer with `.FileFunction,Profile`, or
+/// without one, the first layer drawing an outline with
+/// `.AperFunction,Profile`. A layer with such an outline of its own is
+/// flagged if its bounds differ from the profile's by more than
+/// `tolerance` millimeters. Any other object reaching more than
+/// `tolerance` beyond the profile is flagged too. The comparison is of
+/// bounding boxes, so objects in concave corners of a board aren't found.
+///
+/// ```
+/// use gerber::registration::{check_profiles, ProfileIssueKind};
+/// use gerber::GerberLayer;
+///
+/// let profile = "%FSLAX26Y26*%\n%MOMM*%\n%TF.FileFunction,Profile,NP*%\n%ADD10C,0.1*%\n\
+///                D10*\nG01*\nX0Y0D02*\nX10000000D01*\nY10000000D01*\nX0D01*\nY0D01*\nM02*\n";
+/// let copper = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,1*%\nD10*\nX12000000Y5000000D03*\nM02*\n";
+/// let layers = [GerberLayer::parse(profile).unwrap(), GerberLayer::parse(copper).unwrap()];
+/// let issues = check_profiles(&layers, 0.1);
+/// assert_eq!(issues[0].layer, 1);
+/// assert!(matches!(issues[0].kind, ProfileIssueKind::Outside { .. }));
+/// ```
+pub fn check_profiles<'a, 'b: 'a>(
+    layers: impl IntoIterator<Item = &'a GerberLayer<'b>>,
+    tolerance: f64,
+) -> Vec<ProfileIssue> {
+    let images: Vec<Image> = layers.into_iter().map(GerberLayer::image).collect();
+    let Some(reference) = profile(&images) else {
+        return Vec::new();
+    };
+    let Some(board) = extent(&images[reference]) else {
+        return Vec::new();
+    };
+
+    let mut issues = Vec::new();
+    for (index, image) in images.iter().enumerate() {
+        if index == reference {
+            continue;
+        }
+        let mut issue = |kind| {
+            issues.push(ProfileIssue {
+                layer: index,
+                reference,
+                kind,
+            })
+        };
+        let outline = image
+            .objects
+            .iter()
+            .filter(|object| is_outline(object))
+            .filter_map(|object| object.bounds)
+            .reduce(|a, b| a.union(&b));
+        if let Some(outline) = outline {
+            let difference = [
+                board.min.x - outline.min.x,
+                board.min.y - outline.min.y,
+                board.max.x - outline.max.x,
+                board.max.y - outline.max.y,
+            ]
+            .into_iter()
+            .map(f64::abs)
+            .fold(0.0, f64::max);
+            if difference > tolerance {
+                issue(ProfileIssueKind::Disagrees { difference });
+            }
+        }
+
+        let mut objects = Vec::new();
+        let mut overhang = 0.0f64;
+        for (index, object) in image.objects.iter().enumerate() {
+            let Some(bounds) = object.bounds.filter(|_| !is_outline(object)) else {
+                continue;
+            };
+            let beyond = beyond(&board, &bounds);
+            if beyond > tolerance {
+                objects.push(index);
+                overhang = overhang.max(beyond);
+            }
+        }
+        if !objects.is_empty() {
+            issue(ProfileIssueKind::Outside { objects, overhang });
+        }
+    }
+    issues
+}
+
+/// The index of the profile layer, as described for [check_profiles]
+pub(crate) fn profile(images: &[Image]) -> Option<usize> {
+    images
+        .iter()
+        .position(|image| function(image) == Some("Profile"))
+        .or_else(|| {
+            images
+                .iter()
+                .position(|image| image.objects.iter().any(is_outline))
+        })
+}
+
+fn is_outline(object: &Object) -> bool {
+    object.attributes.aperture_function() == Some("Profile")
+}
+
+/// A layer of a group
+struct Layer {
+    index: usize,
+    format: Option<(CoordinateFormat, CoordinateFormat)>,
+    image: Image,
+}
+
+impl Layer {
+    fn is_profile(&self) -> bool {
+        function(&self.image) == Some("Profile")
+    }
+}
+
+/// The first field of `.FileFunction`
+fn function(image: &Image) -> Option<&str> {
+    image
+        .file_attributes
+        .get(".FileFunction")?
+        .first()
+        .map(AsRef::as_ref)
+}
+
+/// How far `bounds` reaches beyond `board`, zero if it is inside
+fn beyond(board: &Bounds, bounds: &Bounds) -> f64 {
+    [
+        board.min.x - bounds.min.x,
+        board.min.y - bounds.min.y,
+        bounds.max.x - board.max.x,
+        bounds.max.y - board.max.y,
+    ]
+    .into_iter()
+    .fold(0.0, f64::max)
+}
+
+/// The bounds of all objects with known bounds
+pub(crate) fn extent(image: &Image) -> Option<Bounds> {
+    image
+        .objects
+        .iter()
+        .filter_map(|object| object.bounds)
+        .reduce(|a, b| a.union(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(attributes: &str, body: &str) -> GerberLayer<'static> {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{attributes}%ADD10C,0.1*%\nD10*\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().into_owned()
+    }
+
+    const OUTLINE: &str = "G01*\nX0Y0D02*\nX10000000D01*\nY10000000D01*\nX0D01*\nY0D01*\n";
+
+    #[test]
+    fn test_alignment() {
+        let layers = [
+            layer("%TF.SameCoordinates,A*%\n", "X5000000Y5000000D03*\n"),
+            layer(
+                "%TF.SameCoordinates,A*%\n%TF.FileFunction,Profile,NP*%\n",
+                OUTLINE,
+            ),
+            // drawn from the center of the board rather than its corner
+            layer(
+                "%TF.SameCoordinates,A*%\n",
+                "X8000000Y8000000D03*\nX13000000Y13000000D03*\n",
+            ),
+            // a different group, and a layer without the attribute
+            layer("%TF.SameCoordinates,B*%\n", "X50000000Y0D03*\n"),
+            layer("", "X50000000Y0D03*\n"),
+        ];
+        let mismatches = check_same_coordinates(&layers, 0.1);
+        assert_eq!(mismatches.len(), 1, "{mismatches:?}");
+        let mismatch = &mismatches[0];
+        assert_eq!(
+            (
+                mismatch.group.as_deref(),
+                mismatch.layer,
+                mismatch.reference
+            ),
+            (Some("A"), 2, 1)
+        );
+        let MismatchKind::Alignment { overhang } = mismatch.kind else {
+            panic!("{mismatch}");
+        };
+        assert!((overhang - 3.0).abs() < 1e-9, "{overhang}");
+        assert_eq!(
+            mismatch.to_string(),
+            format!("layer 2 in group \"A\": extends {overhang} mm beyond the profile of layer 1")
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let other =
+            GerberLayer::parse("%FSLAX36Y36*%\n%MOMM*%\n%TF.SameCoordinates*%\nM02*\n").unwrap();
+        let layers = [layer("%TF.SameCoordinates*%\n", ""), other];
+        let mismatches = check_same_coordinates(&layers, 0.1);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].group, None);
+        assert!(matches!(mismatches[0].kind, MismatchKind::Format { .. }));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let profile = "%TA.AperFunction,Profile*%\n";
+        let layers = [
+            layer("", "X5000000Y5000000D03*\n"),
+            // a copper layer with its own outline, no profile layer
+            layer(profile, OUTLINE),
+            // an outline 0.5 mm larger, and a pad on the edge
+            layer(
+                profile,
+                "G01*\nX-500000Y-500000D02*\nX10500000D01*\nY10500000D01*\nX-500000D01*\n\
+                 Y-500000D01*\n%TD*%\n%ADD11C,1*%\nD11*\nX10000000Y5000000D03*\n",
+            ),
+        ];
+        let issues = check_profiles(&layers, 0.1);
+        assert_eq!(issues.len(), 2, "{issues:?}");
+        assert!(issues
+            .iter()
+            .all(|issue| (issue.layer, issue.reference) == (2, 1)));
+        assert_eq!(
+            issues[0].kind,
+            ProfileIssueKind::Disagrees { difference: 0.5 }
+        );
+        let ProfileIssueKind::Outside { objects, overhang } = &issues[1].kind else {
+            panic!("{}", issues[1]);
+        };
+        assert_eq!(objects, &[4]);
+        assert!((overhang - 0.45).abs() < 1e-9);
+        assert!(check_profiles(&layers[..1], 0.1).is_empty());
+    }
+}
@@ -0,0 +1,593 @@
+//! Opt-in repair of common CAM generator bugs
+//!
+//! [repair] parses like [GerberLayer::parse] but tolerates a handful of
+//! well-known malformations, fixing up the command stream and reporting
+//! each change it made. [repair_regions] does the same for the geometry of
+//! an evaluated image, untangling region contours which break the contour
+//! rules.
+
+use crate::command::Command::{self, *};
+use crate::image::{self, Contour, Image, Point, Segment, Shape};
+use crate::span::{self, Span};
+use crate::{command, end_of_file, GerberError, GerberLayer};
+
+/// How close points must be to count as the same, in millimeters
+const EPSILON: f64 = 1e-6;
+
+/// A change applied by [repair]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Repair {
+    /// `G75*` was inserted before the first arc, at this command index
+    InsertedArcInit { index: usize },
+
+    /// `%FS` followed the first operation and was moved ahead of it
+    MovedFormatSpecification { from: usize, to: usize },
+
+    /// An additional `M02*` at this byte offset was removed
+    RemovedDuplicateEndOfFile { offset: usize },
+
+    /// Content following `M02*`, starting at this byte offset, was removed
+    RemovedTrailingContent { offset: usize },
+
+    /// The file ended without `M02*`, so one was appended
+    AppendedEndOfFile,
+}
+
+/// A change applied by [repair_regions] to the region at index `object`
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum RegionRepair {
+    /// A line of zero length ending at `at` was removed
+    RemovedZeroLengthSegment { object: usize, at: Point },
+
+    /// A contour ran out to `at` and straight back, enclosing no area, and
+    /// the spike was cut off
+    RemovedSpike { object: usize, at: Point },
+
+    /// A contour crossed itself at `at`, like a bow-tie, and was split there
+    /// into two contours
+    SplitContour { object: usize, at: Point },
+}
+
+/// The result of [repair]
+#[derive(Debug)]
+pub struct Repaired<'a> {
+    pub layer: GerberLayer<'a>,
+    pub repairs: Vec<Repair>,
+}
+
+/// Parse a gerber file, repairing common malformations
+///
+/// Errors which don't match a known malformation are still reported.
+pub fn repair(src: &str) -> Result<Repaired<'_>, GerberError> {
+    let mut commands = Vec::new();
+    let mut ranges = Vec::new();
+    let mut repairs = Vec::new();
+    let offset = |rest: &str| src.len() - rest.len();
+
+    let mut input = skip_line_endings(src);
+    loop {
+        if input.is_empty() {
+            commands.push(EndOfFile);
+            ranges.push(src.len()..src.len());
+            repairs.push(Repair::AppendedEndOfFile);
+            break;
+        }
+        if let Ok((rest, eof)) = end_of_file(input) {
+            commands.push(eof);
+            ranges.push(offset(input)..offset(rest));
+            input = rest;
+            break;
+        }
+        let (rest, command) =
+            command(input).map_err(|e| GerberError::ParseError(format!("{:?}", e)))?;
+        commands.push(command);
+        ranges.push(offset(input)..offset(rest));
+        input = skip_line_endings(rest);
+    }
+
+    loop {
+        input = skip_line_endings(input);
+        if input.is_empty() {
+            break;
+        }
+        match end_of_file(input) {
+            Ok((rest, _)) => {
+                repairs.push(Repair::RemovedDuplicateEndOfFile {
+                    offset: offset(input),
+                });
+                input = rest;
+            }
+            Err(_) => {
+                repairs.push(Repair::RemovedTrailingContent {
+                    offset: offset(input),
+                });
+                break;
+            }
+        }
+    }
+
+    let mut spans = span::spans(src, ranges);
+    move_format_specification(&mut commands, &mut spans, &mut repairs);
+    insert_arc_init(&mut commands, &mut spans, &mut repairs);
+
+    Ok(Repaired {
+        layer: GerberLayer { commands, spans },
+        repairs,
+    })
+}
+
+fn skip_line_endings(input: &str) -> &str {
+    input.trim_start_matches(['\r', '\n'])
+}
+
+fn is_operation(command: &Command) -> bool {
+    matches!(command, Plot(..) | Move(..) | Flash(..))
+}
+
+fn move_format_specification(
+    commands: &mut Vec<Command>,
+    spans: &mut Vec<Span>,
+    repairs: &mut Vec<Repair>,
+) {
+    let Some(first_operation) = commands.iter().position(is_operation) else {
+        return;
+    };
+    let Some(from) = commands
+        .iter()
+        .position(|c| matches!(c, FormatSpecification(..)))
+    else {
+        return;
+    };
+    if from > first_operation {
+        let fs = commands.remove(from);
+        commands.insert(first_operation, fs);
+        let span = spans.remove(from);
+        spans.insert(first_operation, span);
+        repairs.push(Repair::MovedFormatSpecification {
+            from,
+            to: first_operation,
+        });
+    }
+}
+
+fn insert_arc_init(commands: &mut Vec<Command>, spans: &mut Vec<Span>, repairs: &mut Vec<Repair>) {
+    let mut circular = false;
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            // single quadrant arcs need no G75, and inserting one would change them
+            ArcInit | SingleQuadrant => return,
+            SetLinear => circular = false,
+            SetCWCircular | SetCCWCircular => circular = true,
+            Plot(..) if circular => {
+                commands.insert(index, ArcInit);
+                // the inserted command has no source text, so give it an empty span
+                let start = spans[index].bytes.start;
+                let line = spans[index].line;
+                spans.insert(
+                    index,
+                    Span {
+                        bytes: start..start,
+                        line,
+                        end_line: line,
+                    },
+                );
+                repairs.push(Repair::InsertedArcInit { index });
+                return;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Repair the region contours of an image, reporting each change
+///
+/// Spikes and zero length lines are removed and contours crossing
+/// themselves are split at the crossing, so that every contour bounds a
+/// simple polygon. Only crossings between straight lines are split, and
+/// contours which don't end where they start are left alone. Contours
+/// left without segments are removed, and the bounds of repaired regions
+/// are updated.
+///
+/// ```
+/// use gerber::repair::{repair_regions, RegionRepair};
+/// use gerber::image::Point;
+/// use gerber::GerberLayer;
+///
+/// // a bow-tie crossing itself at (1, 1)
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\nG01*\nG36*\nX0Y0D02*\nX2000000Y2000000D01*\n\
+///            X2000000Y0D01*\nX0Y2000000D01*\nX0Y0D01*\nG37*\nM02*\n";
+/// let mut image = GerberLayer::parse(src).unwrap().image();
+/// let at = Point { x: 1.0, y: 1.0 };
+/// assert_eq!(
+///     repair_regions(&mut image),
+///     [RegionRepair::SplitContour { object: 0, at }]
+/// );
+/// ```
+pub fn repair_regions(image: &mut Image) -> Vec<RegionRepair> {
+    let mut repairs = Vec::new();
+    for (index, object) in image.objects.iter_mut().enumerate() {
+        let Shape::Region { contours } = &mut object.shape else {
+            continue;
+        };
+        let count = repairs.len();
+        let mut repaired = Vec::new();
+        for contour in contours.drain(..) {
+            repair_contour(contour, index, &mut repaired, &mut repairs);
+        }
+        *contours = repaired;
+        if repairs.len() > count {
+            object.bounds = image::shape_bounds(&object.shape);
+        }
+    }
+    repairs
+}
+
+/// A contour as a ring of segments with their start points
+type Ring = Vec<(Point, Segment)>;
+
+fn repair_contour(
+    contour: Contour,
+    object: usize,
+    contours: &mut Vec<Contour>,
+    repairs: &mut Vec<RegionRepair>,
+) {
+    let mut from = contour.start;
+    let mut ring = Ring::new();
+    for segment in contour.segments {
+        let end = segment_end(&segment);
+        ring.push((from, segment));
+        from = end;
+    }
+    if !same(from, contour.start) {
+        contours.push(Contour {
+            start: contour.start,
+            segments: ring.into_iter().map(|(_, segment)| segment).collect(),
+        });
+        return;
+    }
+
+    let mut pending = vec![ring];
+    while let Some(mut ring) = pending.pop() {
+        remove_spikes(&mut ring, object, repairs);
+        if let Some((at, first, second)) = split(&ring) {
+            repairs.push(RegionRepair::SplitContour { object, at });
+            pending.push(second);
+            pending.push(first);
+        } else if let Some(&(start, _)) = ring.first() {
+            contours.push(Contour {
+                start,
+                segments: ring.into_iter().map(|(_, segment)| segment).collect(),
+            });
+        }
+    }
+}
+
+/// Remove zero length lines, and lines doubling back along the line before
+fn remove_spikes(ring: &mut Ring, object: usize, repairs: &mut Vec<RegionRepair>) {
+    ring.retain(|(start, segment)| match *segment {
+        Segment::Line { end } if same(*start, end) => {
+            repairs.push(RegionRepair::RemovedZeroLengthSegment { object, at: end });
+            false
+        }
+        _ => true,
+    });
+
+    let mut i = 0;
+    while ring.len() >= 2 && i < ring.len() {
+        let next = (i + 1) % ring.len();
+        let (a, b, c) = match (&ring[i], &ring[next]) {
+            ((a, Segment::Line { end: b }), (_, Segment::Line { end: c })) => (*a, *b, *c),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let (ab, bc) = (sub(b, a), sub(c, b));
+        let backwards = dot(ab, bc) < 0.0;
+        let collinear = cross(ab, bc).abs() <= EPSILON * length(ab).max(length(bc));
+        if !(backwards && collinear) {
+            i += 1;
+            continue;
+        }
+        repairs.push(RegionRepair::RemovedSpike { object, at: b });
+        if same(a, c) {
+            ring.remove(i.max(next));
+            ring.remove(i.min(next));
+        } else {
+            ring[i].1 = Segment::Line { end: c };
+            ring.remove(next);
+        }
+        // the removal may have made a new spike with the line before
+        i = i.saturating_sub(1).min(ring.len());
+    }
+}
+
+/// Split a ring at the first crossing of two of its lines, into the loop
+/// between the lines and the rest
+fn split(ring: &Ring) -> Option<(Point, Ring, Ring)> {
+    let n = ring.len();
+    let line = |i: usize| match ring[i] {
+        (start, Segment::Line { end }) => Some((start, end)),
+        _ => None,
+    };
+    for i in 0..n {
+        let Some((p1, p2)) = line(i) else { continue };
+        for j in i + 2..n {
+            if i == 0 && j == n - 1 {
+                continue;
+            }
+            let Some((q1, q2)) = line(j) else { continue };
+            let Some(at) = crossing(p1, p2, q1, q2) else {
+                continue;
+            };
+            let mut first = vec![(at, Segment::Line { end: p2 })];
+            first.extend(ring[i + 1..j].iter().cloned());
+            first.push((q1, Segment::Line { end: at }));
+
+            let mut second = vec![(at, Segment::Line { end: q2 })];
+            second.extend(ring[j + 1..].iter().cloned());
+            second.extend(ring[..i].iter().cloned());
+            second.push((p1, Segment::Line { end: at }));
+            return Some((at, first, second));
+        }
+    }
+    None
+}
+
+/// Where the lines from `p1` to `p2` and from `q1` to `q2` cross, if they
+/// cross away from their ends
+fn crossing(p1: Point, p2: Point, q1: Point, q2: Point) -> Option<Point> {
+    let (p, q) = (sub(p2, p1), sub(q2, q1));
+    let side = |d: Point, len: f64, point: Point, origin: Point| {
+        let c = cross(d, sub(point, origin));
+        if c.abs() <= EPSILON * len {
+            0.0
+        } else {
+            c.signum()
+        }
+    };
+    let (d1, d2) = (side(q, length(q), p1, q1), side(q, length(q), p2, q1));
+    let (d3, d4) = (side(p, length(p), q1, p1), side(p, length(p), q2, p1));
+    if d1 * d2 >= 0.0 || d3 * d4 >= 0.0 {
+        return None;
+    }
+    let t = cross(q, sub(p1, q1)) / cross(q, sub(p1, p2));
+    Some(Point {
+        x: p1.x + t * p.x,
+        y: p1.y + t * p.y,
+    })
+}
+
+fn segment_end(segment: &Segment) -> Point {
+    match *segment {
+        Segment::Line { end } | Segment::Arc { end, .. } => end,
+    }
+}
+
+fn same(a: Point, b: Point) -> bool {
+    length(sub(a, b)) <= EPSILON
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    Point {
+        x: a.x - b.x,
+        y: a.y - b.y,
+    }
+}
+
+fn dot(a: Point, b: Point) -> f64 {
+    a.x * b.x + a.y * b.y
+}
+
+fn cross(a: Point, b: Point) -> f64 {
+    a.x * b.y - a.y * b.x
+}
+
+fn length(a: Point) -> f64 {
+    a.x.hypot(a.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aperture::ApertureTemplate;
+    use crate::data::{into_aperture_id, CoordinateFormat, Coordinates, Offset, Unit};
+    use indoc::indoc;
+
+    const FORMAT: CoordinateFormat = CoordinateFormat {
+        integer: 2,
+        decimal: 6,
+    };
+
+    fn xy(x: i64, y: i64) -> Coordinates {
+        Coordinates {
+            x: Some(x),
+            y: Some(y),
+        }
+    }
+
+    #[test]
+    fn test_repair() {
+        let src = indoc! {"
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X0Y0D02*
+            %FSLAX26Y26*%
+            G02*
+            X100Y100I50J0D01*
+            M02*
+            M02*
+            garbage
+        "};
+        assert!(GerberLayer::parse(src).is_err());
+
+        let repaired = repair(src).unwrap();
+        assert_eq!(
+            repaired.layer.commands,
+            vec![
+                Mode(Unit::Millimeters),
+                ApertureDefine(
+                    into_aperture_id(10),
+                    ApertureTemplate::Circle {
+                        diameter: 0.1,
+                        hole: None
+                    }
+                ),
+                SetCurrentAperture(into_aperture_id(10)),
+                FormatSpecification(FORMAT, FORMAT),
+                Move(xy(0, 0)),
+                SetCWCircular,
+                ArcInit,
+                Plot(xy(100, 100), Some(Offset { i: 50, j: 0 })),
+                EndOfFile
+            ]
+        );
+        assert_eq!(
+            repaired.repairs,
+            vec![
+                Repair::RemovedDuplicateEndOfFile {
+                    offset: src.rfind("M02").unwrap()
+                },
+                Repair::RemovedTrailingContent {
+                    offset: src.find("garbage").unwrap()
+                },
+                Repair::MovedFormatSpecification { from: 4, to: 3 },
+                Repair::InsertedArcInit { index: 6 },
+            ]
+        );
+
+        let span = |index| &src[repaired.layer.span(index).unwrap().bytes.clone()];
+        assert_eq!(span(3), "%FSLAX26Y26*%");
+        assert_eq!(span(6), "");
+        assert_eq!(span(7), "X100Y100I50J0D01*");
+    }
+
+    #[test]
+    fn test_missing_end_of_file() {
+        let repaired = repair("%FSLAX26Y26*%\n%MOMM*%\n").unwrap();
+        assert_eq!(
+            repaired.layer.commands,
+            vec![
+                FormatSpecification(FORMAT, FORMAT),
+                Mode(Unit::Millimeters),
+                EndOfFile
+            ]
+        );
+        assert_eq!(repaired.repairs, vec![Repair::AppendedEndOfFile]);
+    }
+
+    #[test]
+    fn test_nothing_to_repair() {
+        let src = "%FSLAX26Y26*%\n%MOMM*%\nM02*\n";
+        assert!(repair(src).unwrap().repairs.is_empty());
+    }
+
+    fn region(body: &str) -> Image {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\nG01*\nG36*\n{body}G37*\nM02*\n");
+        GerberLayer::parse(&src).unwrap().image()
+    }
+
+    fn point(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    fn vertices(contour: &Contour) -> Vec<Point> {
+        contour.segments.iter().map(segment_end).collect()
+    }
+
+    #[test]
+    fn test_split_bow_tie() {
+        let mut image = region(indoc! {"
+            X0Y0D02*
+            X2000000Y2000000D01*
+            X2000000Y0D01*
+            X0Y2000000D01*
+            X0Y0D01*
+        "});
+        let repairs = repair_regions(&mut image);
+        let at = point(1.0, 1.0);
+        assert_eq!(repairs, [RegionRepair::SplitContour { object: 0, at }]);
+
+        let Shape::Region { contours } = &image.objects[0].shape else {
+            panic!()
+        };
+        assert_eq!(contours.len(), 2);
+        assert_eq!(contours[0].start, at);
+        assert_eq!(
+            vertices(&contours[0]),
+            [point(2.0, 2.0), point(2.0, 0.0), at]
+        );
+        assert_eq!(contours[1].start, at);
+        assert_eq!(
+            vertices(&contours[1]),
+            [point(0.0, 2.0), point(0.0, 0.0), at]
+        );
+    }
+
+    #[test]
+    fn test_remove_spikes() {
+        // a square with a spike out of its top, a spike back down its right
+        // side, and a repeated corner
+        let mut image = region(indoc! {"
+            X0Y0D02*
+            X2000000Y0D01*
+            X2000000Y2000000D01*
+            X2000000Y1000000D01*
+            X2000000Y2000000D01*
+            X2000000Y2000000D01*
+            X1000000Y2000000D01*
+            X1000000Y3000000D01*
+            X1000000Y2000000D01*
+            X0Y2000000D01*
+            X0Y0D01*
+        "});
+        let repairs = repair_regions(&mut image);
+        assert_eq!(
+            repairs,
+            [
+                RegionRepair::RemovedZeroLengthSegment {
+                    object: 0,
+                    at: point(2.0, 2.0)
+                },
+                RegionRepair::RemovedSpike {
+                    object: 0,
+                    at: point(2.0, 2.0)
+                },
+                RegionRepair::RemovedSpike {
+                    object: 0,
+                    at: point(1.0, 3.0)
+                },
+            ]
+        );
+        let Shape::Region { contours } = &image.objects[0].shape else {
+            panic!()
+        };
+        assert_eq!(contours.len(), 1);
+        let vertices = vertices(&contours[0]);
+        assert!(vertices.contains(&point(2.0, 2.0)));
+        assert!(!vertices.contains(&point(1.0, 3.0)));
+        assert_eq!(image.objects[0].bounds.unwrap().max, point(2.0, 2.0));
+    }
+
+    #[test]
+    fn test_valid_regions_unchanged() {
+        let body = indoc! {"
+            X0Y0D02*
+            X2000000Y0D01*
+            G75*
+            G03*
+            X0Y0I-1000000J0D01*
+            G01*
+            X3000000Y0D02*
+            X4000000Y0D01*
+            X4000000Y1000000D01*
+            X3000000Y0D01*
+        "};
+        let mut image = region(body);
+        assert!(repair_regions(&mut image).is_empty());
+        assert_eq!(image, region(body));
+    }
+}
@@ -0,0 +1,230 @@
+//! Incremental reparsing for editors
+//!
+//! After an edit, only the commands overlapping the edited text are parsed
+//! again. Parsing stops as soon as it reaches the start of a command which
+//! followed the edit, and the remaining commands are kept with their spans
+//! shifted. Each command is parsed independently of the others, so a
+//! command whose text did not change parses to the same result.
+
+use std::ops::Range;
+
+use crate::span::Span;
+use crate::{command, end_of_file, GerberError, GerberLayer};
+
+/// A change to the source text
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Edit {
+    /// Byte range of the replaced text in the old source
+    pub range: Range<usize>,
+
+    /// Length in bytes of the text which replaced it
+    pub len: usize,
+}
+
+impl<'a> GerberLayer<'a> {
+    /// Update the layer for `src`, which is the old source with `edit`
+    /// applied
+    ///
+    /// The result is the same as [GerberLayer::parse] on `src`, but the
+    /// work is proportional to the size of the edit rather than the file.
+    /// Unchanged commands keep borrowing the old source. Use
+    /// [into_owned](GerberLayer::into_owned) first if it will not outlive
+    /// the layer.
+    ///
+    /// ```
+    /// use gerber::reparse::Edit;
+    /// use gerber::GerberLayer;
+    ///
+    /// let old = "%FSLAX26Y26*%\n%MOMM*%\nD10*\nM02*\n";
+    /// let new = "%FSLAX26Y26*%\n%MOMM*%\nD11*\nM02*\n";
+    /// let edit = Edit { range: 23..25, len: 2 };
+    ///
+    /// let layer = GerberLayer::parse(old).unwrap().reparse(new, &edit).unwrap();
+    /// assert_eq!(layer.commands(), GerberLayer::parse(new).unwrap().commands());
+    /// ```
+    pub fn reparse<'b>(self, src: &'b str, edit: &Edit) -> Result<GerberLayer<'b>, GerberError>
+    where
+        'a: 'b,
+    {
+        let error = |message: &str| GerberError::ParseError(message.to_string());
+        let delta = edit.len as isize - edit.range.len() as isize;
+        let edit_end = edit.range.start + edit.len;
+        let offset = |rest: &str| src.len() - rest.len();
+
+        let mut old_commands = self.commands.into_iter();
+        let mut old_spans = self.spans.into_iter();
+
+        // commands ending before the edit are unchanged, and one ending
+        // where the edit starts may be extended by it. An edit after the
+        // last command still reparses M02 to check nothing follows it.
+        let first = old_spans
+            .as_slice()
+            .iter()
+            .position(|span| span.bytes.end >= edit.range.start)
+            .unwrap_or(old_spans.len().saturating_sub(1));
+        let mut commands: Vec<_> = old_commands.by_ref().take(first).collect();
+        let mut spans: Vec<_> = old_spans.by_ref().take(first).collect();
+
+        let start = spans.last().map_or(0, |span| span.bytes.end);
+        let mut lines = LineCounter {
+            src,
+            offset: start,
+            line: spans.last().map_or(1, |span| span.end_line),
+        };
+
+        let mut input = src
+            .get(start..)
+            .ok_or_else(|| error("edit is out of range"))?;
+        loop {
+            input = input.trim_start_matches(['\r', '\n']);
+            let position = offset(input);
+
+            // resynchronize with the first old command starting after the edit
+            if position >= edit_end {
+                while let Some(span) = old_spans.as_slice().first() {
+                    let shifted = span.bytes.start as isize + delta;
+                    if span.bytes.start >= edit.range.end && shifted >= position as isize {
+                        break;
+                    }
+                    old_spans.next();
+                    old_commands.next();
+                }
+                if let Some(span) = old_spans.as_slice().first() {
+                    if span.bytes.start as isize + delta == position as isize {
+                        let line_delta = lines.line_at(position) as isize - span.line as isize;
+                        commands.extend(old_commands);
+                        spans.extend(old_spans.map(|span| shift(span, delta, line_delta)));
+                        break;
+                    }
+                }
+            }
+
+            if input.is_empty() {
+                return Err(error("missing end of file"));
+            }
+            if let Ok((rest, eof)) = end_of_file(input) {
+                commands.push(eof);
+                spans.push(lines.span(position..offset(rest)));
+                if !rest.trim_start_matches(['\r', '\n']).is_empty() {
+                    return Err(error("content after end of file"));
+                }
+                break;
+            }
+            let (rest, command) =
+                command(input).map_err(|e| GerberError::ParseError(format!("{:?}", e)))?;
+            commands.push(command);
+            spans.push(lines.span(position..offset(rest)));
+            input = rest;
+        }
+
+        Ok(GerberLayer { commands, spans })
+    }
+}
+
+/// Move a span by `delta` bytes and `line_delta` lines
+fn shift(span: Span, delta: isize, line_delta: isize) -> Span {
+    let bytes = span.bytes;
+    Span {
+        bytes: (bytes.start as isize + delta) as usize..(bytes.end as isize + delta) as usize,
+        line: (span.line as isize + line_delta) as usize,
+        end_line: (span.end_line as isize + line_delta) as usize,
+    }
+}
+
+/// Counts lines forward from a known position, so only the reparsed text
+/// is scanned
+struct LineCounter<'a> {
+    src: &'a str,
+    offset: usize,
+    line: usize,
+}
+
+impl LineCounter<'_> {
+    /// The line at `offset`, which must not be before the previous call
+    fn line_at(&mut self, offset: usize) -> usize {
+        self.line += self.src[self.offset..offset].matches('\n').count();
+        self.offset = offset;
+        self.line
+    }
+
+    fn span(&mut self, bytes: Range<usize>) -> Span {
+        let line = self.line_at(bytes.start);
+        let end_line = self.line_at(bytes.end.saturating_sub(1).max(bytes.start));
+        Span {
+            bytes,
+            line,
+            end_line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const SRC: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10C,0.1*%
+        D10*
+        X0Y0D02*
+        X1000000Y0D01*
+        X1000000Y1000000D01*
+        M02*
+    "};
+
+    /// Apply an edit and check the reparse matches a full parse
+    fn check(old: &str, range: Range<usize>, text: &str) {
+        let new = format!("{}{}{}", &old[..range.start], text, &old[range.end..]);
+        let edit = Edit {
+            range,
+            len: text.len(),
+        };
+        let expected = GerberLayer::parse(&new).unwrap();
+        let reparsed = GerberLayer::parse(old)
+            .unwrap()
+            .reparse(&new, &edit)
+            .unwrap();
+        assert_eq!(reparsed.commands, expected.commands, "{new}");
+        assert_eq!(reparsed.spans, expected.spans, "{new}");
+    }
+
+    #[test]
+    fn test_reparse() {
+        let at = |text: &str| SRC.find(text).unwrap();
+
+        // change a coordinate
+        let x = at("X1000000Y0") + 1;
+        check(SRC, x..x + 7, "2500000");
+        // insert a command, adding a line
+        let d10 = at("D10*");
+        check(SRC, d10..d10, "G01*\n");
+        // delete a command and its line
+        let start = at("X0Y0");
+        check(SRC, start..start + "X0Y0D02*\n".len(), "");
+        // extend the end of a command
+        let end = at("D10*") + 3;
+        check(SRC, end..end + 1, "1*");
+        // join two lines
+        let newline = at("%MOMM*%") - 1;
+        check(SRC, newline..newline + 1, "");
+        // replace everything
+        check(SRC, 0..SRC.len(), "%FSLAX26Y26*%\n%MOIN*%\nM02*\n");
+        // edit after the last command
+        check(SRC, SRC.len()..SRC.len(), "\n\n");
+    }
+
+    #[test]
+    fn test_reparse_error() {
+        let layer = GerberLayer::parse(SRC).unwrap();
+        let new = SRC.replace("D10*", "D1O*");
+        let edit = Edit {
+            range: SRC.find("D10*").unwrap()..SRC.find("D10*").unwrap() + 4,
+            len: 4,
+        };
+        assert!(layer.reparse(&new, &edit).is_err());
+    }
+}
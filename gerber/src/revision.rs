@@ -0,0 +1,100 @@
+//! Specification revision detection
+
+use crate::attribute::FileAttributeName;
+use crate::command::Command::{self, *};
+
+/// The revision of the Gerber format a file relies on
+///
+/// Each revision is a superset of the previous one, so a file is classified
+/// by the newest feature it uses.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Revision {
+    /// Image only, no attributes
+    X1,
+
+    /// Adds file, aperture and object attributes
+    X2,
+
+    /// Adds component information, either as component object attributes
+    /// or as a component layer (`.FileFunction,Component`)
+    X3,
+}
+
+impl Revision {
+    /// Classify a list of commands
+    pub fn detect(commands: &[Command]) -> Self {
+        commands
+            .iter()
+            .map(Self::required_by)
+            .max()
+            .unwrap_or(Self::X1)
+    }
+
+    /// The oldest revision which supports a single command
+    fn required_by(command: &Command) -> Self {
+        match command {
+            AttributeOnObject(name, _) if name.is_component() => Self::X3,
+            AttributeOnFile(FileAttributeName::FileFunction, values)
+                if values.first().is_some_and(|v| v.unescape() == "Component") =>
+            {
+                Self::X3
+            }
+            AttributeOnFile(..)
+            | AttributeOnAperture(..)
+            | AttributeOnObject(..)
+            | AttributeDelete(..) => Self::X2,
+            _ => Self::X1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    #[test]
+    fn test_revision() {
+        let x1 = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            M02*
+        "};
+        assert_eq!(GerberLayer::parse(x1).unwrap().revision(), Revision::X1);
+
+        let x2 = indoc! {"
+            %TF.Part,Single*%
+            %FSLAX26Y26*%
+            %MOMM*%
+            %TO.N,GND*%
+            %TD*%
+            M02*
+        "};
+        assert_eq!(GerberLayer::parse(x2).unwrap().revision(), Revision::X2);
+
+        let x3_object = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %TO.C,R1*%
+            %TD*%
+            M02*
+        "};
+        assert_eq!(
+            GerberLayer::parse(x3_object).unwrap().revision(),
+            Revision::X3
+        );
+
+        let x3_layer = indoc! {"
+            %TF.FileFunction,Component,L1,Top*%
+            %FSLAX26Y26*%
+            %MOMM*%
+            M02*
+        "};
+        assert_eq!(
+            GerberLayer::parse(x3_layer).unwrap().revision(),
+            Revision::X3
+        );
+    }
+}
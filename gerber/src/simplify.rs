@@ -0,0 +1,218 @@
+//! Simplification of evaluated geometry
+//!
+//! Huge layers have far more vertices than a zoomed-out view can show. The
+//! [Douglas-Peucker](https://en.wikipedia.org/wiki/Ramer%E2%80%93Douglas%E2%80%93Peucker_algorithm)
+//! algorithm keeps a subset of the vertices such that no removed vertex is
+//! farther than `tolerance` millimeters from the simplified line, so a
+//! viewer can serve reduced geometry with a tolerance of about a pixel.
+//!
+//! Simplified rings keep their winding, but narrow features may collapse
+//! and neighbouring edges may cross, so the result is meant for display
+//! rather than for further boolean operations.
+
+use crate::image::Point;
+use crate::redundant::distance_to_segment;
+
+/// Simplify an open polyline, keeping its first and last points
+///
+/// ```
+/// use gerber::image::Point;
+/// use gerber::simplify::simplify_polyline;
+///
+/// let points = [(0.0, 0.0), (1.0, 0.01), (2.0, -0.01), (3.0, 0.0), (3.0, 1.0)]
+///     .map(|(x, y)| Point { x, y });
+/// let simplified = simplify_polyline(&points, 0.1);
+/// assert_eq!(simplified, [points[0], points[3], points[4]]);
+/// ```
+pub fn simplify_polyline(points: &[Point], tolerance: f64) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    mark(points, 0, points.len() - 1, tolerance, &mut keep);
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+/// Simplify a closed ring, given without repeating its first point
+///
+/// The ring is split at the vertex farthest from its first vertex, and the
+/// two halves are simplified as polylines. Rings collapsing to fewer than
+/// three points are returned empty.
+///
+/// ```
+/// use gerber::image::Point;
+/// use gerber::simplify::simplify_ring;
+///
+/// // a square with a nearly collinear point on each side
+/// let ring = [(0.0, 0.0), (1.0, 0.01), (2.0, 0.0), (2.0, 2.0), (1.0, 2.01), (0.0, 2.0)]
+///     .map(|(x, y)| Point { x, y });
+/// assert_eq!(simplify_ring(&ring, 0.1).len(), 4);
+/// assert!(simplify_ring(&ring, 5.0).is_empty());
+/// ```
+pub fn simplify_ring(ring: &[Point], tolerance: f64) -> Vec<Point> {
+    if ring.len() < 3 {
+        return Vec::new();
+    }
+    let first = ring[0];
+    let far = (1..ring.len())
+        .max_by(|&a, &b| {
+            let distance = |i: usize| (ring[i].x - first.x).hypot(ring[i].y - first.y);
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap_or(0);
+
+    let mut closed = ring.to_vec();
+    closed.push(first);
+    let mut keep = vec![false; closed.len()];
+    keep[0] = true;
+    keep[far] = true;
+    mark(&closed, 0, far, tolerance, &mut keep);
+    mark(&closed, far, closed.len() - 1, tolerance, &mut keep);
+
+    let simplified: Vec<Point> = ring
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect();
+    if simplified.len() < 3 {
+        return Vec::new();
+    }
+    simplified
+}
+
+/// Mark the vertices strictly between `first` and `last` which are kept
+///
+/// A stack rather than recursion keeps long, finely curved paths from
+/// overflowing the call stack.
+fn mark(points: &[Point], first: usize, last: usize, tolerance: f64, keep: &mut [bool]) {
+    let mut stack = vec![(first, last)];
+    while let Some((first, last)) = stack.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| {
+                (
+                    i,
+                    distance_to_segment(points[i], points[first], points[last]),
+                )
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, distance)) = farthest {
+            if distance > tolerance {
+                keep[index] = true;
+                stack.push((first, index));
+                stack.push((index, last));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "boolean")]
+impl crate::copper::Polygon {
+    /// Simplify the exterior and holes within `tolerance` millimeters
+    ///
+    /// Holes collapsing to nothing are removed, and if the exterior
+    /// collapses the whole polygon is gone.
+    pub fn simplify(&self, tolerance: f64) -> Option<Self> {
+        let exterior = simplify_ring(&self.exterior, tolerance);
+        if exterior.is_empty() {
+            return None;
+        }
+        let holes = self
+            .holes
+            .iter()
+            .map(|hole| simplify_ring(hole, tolerance))
+            .filter(|hole| !hole.is_empty())
+            .collect();
+        Some(Self { exterior, holes })
+    }
+}
+
+#[cfg(feature = "boolean")]
+impl crate::copper::Copper {
+    /// Simplify every polygon within `tolerance` millimeters
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,10*%\nD10*\nX0Y0D03*\nM02*\n";
+    /// let copper = GerberLayer::parse(src).unwrap().copper(0.0001);
+    /// let simplified = copper.simplify(0.1);
+    /// assert!(simplified.polygons[0].exterior.len() < copper.polygons[0].exterior.len() / 10);
+    /// assert!(simplified.area() > copper.area() * 0.95);
+    /// ```
+    pub fn simplify(&self, tolerance: f64) -> Self {
+        Self {
+            polygons: self
+                .polygons
+                .iter()
+                .filter_map(|polygon| polygon.simplify(tolerance))
+                .collect(),
+            skipped: self.skipped.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(coordinates: &[(f64, f64)]) -> Vec<Point> {
+        coordinates.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    #[test]
+    fn test_polyline() {
+        let line = points(&[(0.0, 0.0), (1.0, 0.5), (2.0, 0.0)]);
+        assert_eq!(simplify_polyline(&line, 0.4), line);
+        assert_eq!(simplify_polyline(&line, 0.6), [line[0], line[2]]);
+        assert_eq!(simplify_polyline(&line[..2], 10.0), line[..2]);
+
+        // the farthest point is kept first, then the points beside it
+        let zigzag = points(&[(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 3.0), (4.0, 0.0)]);
+        assert_eq!(
+            simplify_polyline(&zigzag, 1.5),
+            [zigzag[0], zigzag[3], zigzag[4]]
+        );
+        assert_eq!(simplify_polyline(&zigzag, 0.5), zigzag);
+    }
+
+    #[test]
+    fn test_ring() {
+        let circle: Vec<Point> = (0..1000)
+            .map(|i| {
+                let angle = std::f64::consts::TAU * i as f64 / 1000.0;
+                Point {
+                    x: 5.0 * angle.cos(),
+                    y: 5.0 * angle.sin(),
+                }
+            })
+            .collect();
+        let simplified = simplify_ring(&circle, 0.01);
+        assert!(simplified.len() < 100, "{}", simplified.len());
+        assert!(simplified.iter().all(|point| circle.contains(point)));
+        assert!(simplify_ring(&circle[..2], 0.0).is_empty());
+    }
+
+    #[cfg(feature = "boolean")]
+    #[test]
+    fn test_polygon() {
+        use crate::copper::Polygon;
+
+        let polygon = Polygon {
+            exterior: points(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]),
+            holes: vec![
+                points(&[(1.0, 1.0), (1.0, 1.05), (1.05, 1.05)]),
+                points(&[(4.0, 4.0), (4.0, 6.0), (6.0, 6.0), (6.0, 4.0)]),
+            ],
+        };
+        let simplified = polygon.simplify(0.1).unwrap();
+        assert_eq!(simplified.exterior, polygon.exterior);
+        assert_eq!(simplified.holes, polygon.holes[1..]);
+        assert!(polygon.simplify(20.0).is_none());
+    }
+}
@@ -0,0 +1,315 @@
+//! Slots and internal cutouts
+//!
+//! Design rules treat non-round holes differently from drills: they are
+//! routed rather than drilled, need larger clearances and are priced
+//! separately. A drill or rout layer (`.FileFunction,Plated,...` or
+//! `NonPlated,...`) draws a slot as a stroke of a round tool, or flashes it
+//! with an oblong aperture. A profile layer draws cutouts as closed paths
+//! inside the board outline.
+
+use std::f64::consts::TAU;
+
+use crate::aperture::ApertureTemplate;
+use crate::command::Command::*;
+use crate::data::{InterpolationMode, Unit};
+use crate::image::{Point, Shape};
+use crate::GerberLayer;
+
+/// A non-round hole, in millimeters
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Slot {
+    /// The center line, or for a flash its center and orientation
+    pub path: SlotPath,
+
+    /// Width of the slot, the diameter of the tool
+    pub width: f64,
+
+    /// Overall length of the slot including its rounded ends
+    pub length: f64,
+
+    /// Whether the slot is plated, from `.FileFunction`
+    pub plated: Option<bool>,
+
+    /// Index of the object in [Image::objects](crate::image::Image::objects)
+    pub object: usize,
+}
+
+/// The center line of a slot
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum SlotPath {
+    /// A straight slot
+    Line { start: Point, end: Point },
+
+    /// A curved slot
+    Arc {
+        start: Point,
+        end: Point,
+        center: Point,
+        direction: InterpolationMode,
+    },
+}
+
+impl GerberLayer<'_> {
+    /// Find the slots of a drill or rout layer
+    ///
+    /// Draws and arcs of round apertures are routed slots. Flashes of
+    /// obround and rectangular apertures are slots along their longer
+    /// side. Flashes of round apertures are plain drills and macro
+    /// apertures are skipped.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%TF.FileFunction,NonPlated,1,2,NPTH,Route*%\n\
+    ///            %ADD10C,1*%\nD10*\nG01*\nX0Y0D02*\nX3000000Y4000000D01*\nM02*\n";
+    /// let slots = GerberLayer::parse(src).unwrap().slots();
+    /// assert_eq!(slots[0].width, 1.0);
+    /// assert_eq!(slots[0].length, 6.0);
+    /// assert_eq!(slots[0].plated, Some(false));
+    /// ```
+    pub fn slots(&self) -> Vec<Slot> {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let plated = image
+            .file_attributes
+            .get(".FileFunction")
+            .and_then(|function| match function.first()?.as_ref() {
+                "Plated" => Some(true),
+                "NonPlated" => Some(false),
+                _ => None,
+            });
+
+        let mut slots = Vec::new();
+        for (index, object) in image.objects.iter().enumerate() {
+            let Some(ApertureDefine(_, template)) =
+                object.source.aperture.map(|index| &self.commands[index])
+            else {
+                continue;
+            };
+            let size = |value: f64| unit.to_mm(value) * object.scaling.0;
+            let (path, width, length) = match (&object.shape, template) {
+                (Shape::Draw { start, end, .. }, ApertureTemplate::Circle { diameter, .. }) => {
+                    let width = size(*diameter);
+                    let length = (end.x - start.x).hypot(end.y - start.y) + width;
+                    let path = SlotPath::Line {
+                        start: *start,
+                        end: *end,
+                    };
+                    (path, width, length)
+                }
+                (
+                    Shape::Arc {
+                        start,
+                        end,
+                        center,
+                        direction,
+                        ..
+                    },
+                    ApertureTemplate::Circle { diameter, .. },
+                ) => {
+                    let width = size(*diameter);
+                    let radius = (start.x - center.x).hypot(start.y - center.y);
+                    let start_angle = (start.y - center.y).atan2(start.x - center.x);
+                    let end_angle = (end.y - center.y).atan2(end.x - center.x);
+                    let mut sweep = (end_angle - start_angle).rem_euclid(TAU);
+                    if direction == &InterpolationMode::Clockwise {
+                        sweep = (TAU - sweep).rem_euclid(TAU);
+                    }
+                    if sweep == 0.0 {
+                        sweep = TAU;
+                    }
+                    let path = SlotPath::Arc {
+                        start: *start,
+                        end: *end,
+                        center: *center,
+                        direction: *direction,
+                    };
+                    (path, width, radius * sweep + width)
+                }
+                (
+                    Shape::Flash { at, .. },
+                    ApertureTemplate::Obround { x, y, .. }
+                    | ApertureTemplate::Rectangle { x, y, .. },
+                ) if x != y => {
+                    let (x, y) = (size(*x), size(*y));
+                    // the center line of the slot, along its longer side
+                    let half = (x.max(y) - x.min(y)) / 2.0;
+                    let (mut dx, mut dy) = if x > y { (half, 0.0) } else { (0.0, half) };
+                    let (sin, cos) = object.rotation.0.to_radians().sin_cos();
+                    (dx, dy) = (dx * cos - dy * sin, dx * sin + dy * cos);
+                    let path = SlotPath::Line {
+                        start: Point {
+                            x: at.x - dx,
+                            y: at.y - dy,
+                        },
+                        end: Point {
+                            x: at.x + dx,
+                            y: at.y + dy,
+                        },
+                    };
+                    (path, x.min(y), x.max(y))
+                }
+                _ => continue,
+            };
+            slots.push(Slot {
+                path,
+                width,
+                length,
+                plated,
+                object: index,
+            });
+        }
+        slots
+    }
+}
+
+/// A hole in the board, in millimeters
+#[cfg(feature = "boolean")]
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Cutout {
+    /// The outline, approximated within the tolerance
+    pub outline: Vec<Point>,
+
+    /// The lower left corner of the bounding box
+    pub min: Point,
+
+    /// The upper right corner of the bounding box
+    pub max: Point,
+
+    /// Area in square millimeters
+    pub area: f64,
+}
+
+#[cfg(feature = "boolean")]
+impl Cutout {
+    /// Width of the bounding box
+    pub fn width(&self) -> f64 {
+        self.max.x - self.min.x
+    }
+
+    /// Height of the bounding box
+    pub fn height(&self) -> f64 {
+        self.max.y - self.min.y
+    }
+}
+
+#[cfg(feature = "boolean")]
+impl GerberLayer<'_> {
+    /// Find the internal cutouts of a profile layer
+    ///
+    /// These are the holes of the [profile](GerberLayer::profile), and
+    /// `tolerance` is passed on to it.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\nD10*\nG01*\nX0Y0D02*\n\
+    ///            X10000000D01*\nY10000000D01*\nX0D01*\nY0D01*\nX2000000Y2000000D02*\n\
+    ///            X5000000D01*\nY3000000D01*\nX2000000D01*\nY2000000D01*\nM02*\n";
+    /// let cutouts = GerberLayer::parse(src).unwrap().cutouts(0.001);
+    /// assert_eq!((cutouts[0].width(), cutouts[0].height()), (3.0, 1.0));
+    /// assert_eq!(cutouts[0].area, 3.0);
+    /// ```
+    pub fn cutouts(&self, tolerance: f64) -> Vec<Cutout> {
+        self.profile(tolerance)
+            .into_iter()
+            .flat_map(|polygon| polygon.holes)
+            .map(|outline| {
+                let hole = crate::copper::Polygon {
+                    exterior: outline,
+                    holes: Vec::new(),
+                };
+                let area = hole.area();
+                let (mut min, mut max) = (hole.exterior[0], hole.exterior[0]);
+                for point in &hole.exterior {
+                    min = Point {
+                        x: min.x.min(point.x),
+                        y: min.y.min(point.y),
+                    };
+                    max = Point {
+                        x: max.x.max(point.x),
+                        y: max.y.max(point.y),
+                    };
+                }
+                Cutout {
+                    outline: hole.exterior,
+                    min,
+                    max,
+                    area,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use std::f64::consts::PI;
+
+    fn slots(body: &str) -> Vec<Slot> {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().slots()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn test_routed() {
+        let slots = slots(indoc! {"
+            %TF.FileFunction,Plated,1,4,PTH,Drill*%
+            %ADD10C,0.8*%
+            D10*
+            X0Y0D03*
+            G01*
+            X0Y0D02*
+            X2000000Y0D01*
+            G75*
+            G02*
+            X1000000Y1000000D02*
+            X2000000Y0I0J-1000000D01*
+        "});
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].object, 1);
+        assert_close(slots[0].length, 2.8);
+        assert_close(slots[1].length, PI / 2.0 + 0.8);
+        assert!(slots.iter().all(|slot| slot.plated == Some(true)));
+    }
+
+    #[test]
+    fn test_flashed() {
+        let slots = slots(indoc! {"
+            %ADD10O,1X3*%
+            %ADD11R,2X2*%
+            D10*
+            X0Y0D03*
+            %LR90*%
+            X5000000Y0D03*
+            D11*
+            X0Y0D03*
+        "});
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].width, 1.0);
+        assert_eq!(slots[0].length, 3.0);
+        assert_eq!(
+            slots[0].path,
+            SlotPath::Line {
+                start: Point { x: 0.0, y: -1.0 },
+                end: Point { x: 0.0, y: 1.0 },
+            }
+        );
+        let SlotPath::Line { start, end } = slots[1].path else {
+            panic!("{:?}", slots[1].path);
+        };
+        // rotated to horizontal
+        assert_close(start.x, 6.0);
+        assert_close(end.x, 4.0);
+        assert!(slots[0].plated.is_none());
+    }
+}
@@ -0,0 +1,165 @@
+//! Snap coordinates to a grid
+//!
+//! Generators often write coordinates with floating-point noise, e.g.
+//! `X1000001` where `X1000000` was meant. Snapping them to a grid before
+//! diffing or deduplicating makes such coordinates compare equal.
+
+use crate::command::Command::*;
+use crate::data::{CoordinateFormat, Coordinates, Offset, Unit};
+use crate::GerberLayer;
+
+impl GerberLayer<'_> {
+    /// Round every coordinate to the nearest multiple of `grid`
+    /// millimeters, returning the number of coordinates which changed
+    ///
+    /// Arc centers are snapped too, and their offsets recomputed from the
+    /// snapped start point. A grid finer than the resolution of the
+    /// coordinate format changes nothing. Spans still refer to the original
+    /// source.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\nD10*\nX1000001Y-1999D03*\nM02*\n";
+    /// let mut layer = GerberLayer::parse(src).unwrap();
+    /// // 1 µm
+    /// assert_eq!(layer.snap_to_grid(0.001), 2);
+    /// ```
+    pub fn snap_to_grid(&mut self, grid: f64) -> usize {
+        let mut unit = Unit::Millimeters;
+        let default = CoordinateFormat {
+            integer: 6,
+            decimal: 6,
+        };
+        let mut format = (default, default);
+
+        // the current point before and after snapping
+        let mut original = (0, 0);
+        let mut snapped = (0, 0);
+
+        let mut changed = 0;
+        for command in &mut self.commands {
+            let snap = |value: i64, format: CoordinateFormat| {
+                let step = grid / unit.to_mm(10f64.powi(-(format.decimal as i32)));
+                if step <= 1.0 || !step.is_finite() {
+                    return value;
+                }
+                ((value as f64 / step).round() * step).round() as i64
+            };
+            let mut snap_xy = |coordinates: &mut Coordinates| {
+                for (value, format) in [
+                    (&mut coordinates.x, format.0),
+                    (&mut coordinates.y, format.1),
+                ] {
+                    if let Some(value) = value {
+                        let new = snap(*value, format);
+                        if new != *value {
+                            *value = new;
+                            changed += 1;
+                        }
+                    }
+                }
+            };
+            match command {
+                Mode(new) => unit = *new,
+                FormatSpecification(x, y) => format = (*x, *y),
+                Plot(coordinates, offset) => {
+                    let start = (original, snapped);
+                    original = (
+                        coordinates.x.unwrap_or(original.0),
+                        coordinates.y.unwrap_or(original.1),
+                    );
+                    snap_xy(coordinates);
+                    snapped = (
+                        coordinates.x.unwrap_or(snapped.0),
+                        coordinates.y.unwrap_or(snapped.1),
+                    );
+                    if let Some(offset) = offset {
+                        let ((x, y), (snapped_x, snapped_y)) = start;
+                        let new = Offset {
+                            i: snap(x + offset.i, format.0) - snapped_x,
+                            j: snap(y + offset.j, format.1) - snapped_y,
+                        };
+                        changed += (new.i != offset.i) as usize + (new.j != offset.j) as usize;
+                        *offset = new;
+                    }
+                }
+                Move(coordinates) | Flash(coordinates) => {
+                    original = (
+                        coordinates.x.unwrap_or(original.0),
+                        coordinates.y.unwrap_or(original.1),
+                    );
+                    snap_xy(coordinates);
+                    snapped = (
+                        coordinates.x.unwrap_or(snapped.0),
+                        coordinates.y.unwrap_or(snapped.1),
+                    );
+                }
+                _ => (),
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use indoc::indoc;
+
+    fn snapped(src: &str, grid: f64) -> (Vec<Command<'_>>, usize) {
+        let mut layer = GerberLayer::parse(src).unwrap();
+        let changed = layer.snap_to_grid(grid);
+        (layer.commands, changed)
+    }
+
+    #[test]
+    fn test_snap() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X1000001Y1999999D02*
+            X3000000D01*
+            G75*
+            G03*
+            X2000000Y3000000I-999999J1000001D01*
+            M02*
+        "};
+        let expected = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X1000000Y2000000D02*
+            X3000000D01*
+            G75*
+            G03*
+            X2000000Y3000000I-1000000J1000000D01*
+            M02*
+        "};
+        let (commands, changed) = snapped(src, 0.001);
+        assert_eq!(commands, GerberLayer::parse(expected).unwrap().commands);
+        assert_eq!(changed, 4);
+    }
+
+    #[test]
+    fn test_snap_inches() {
+        // 0.1 mm is 3937.0078 units of 0.000001 inch
+        let src = "%FSLAX26Y26*%\n%MOIN*%\nX3938Y7875D02*\nM02*\n";
+        let (commands, _) = snapped(src, 0.1);
+        let Move(coordinates) = commands[2] else {
+            panic!("{:?}", commands[2]);
+        };
+        assert_eq!(coordinates.x, Some(3937));
+        assert_eq!(coordinates.y, Some(7874));
+    }
+
+    #[test]
+    fn test_grid_finer_than_format() {
+        let src = "%FSLAX26Y26*%\n%MOMM*%\nX1001Y1D02*\nM02*\n";
+        assert_eq!(snapped(src, 0.0000001).1, 0);
+    }
+}
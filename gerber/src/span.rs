@@ -0,0 +1,155 @@
+//! Locations of commands in the source
+
+use std::fmt;
+use std::ops::Range;
+
+/// The text a command was parsed from
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Span {
+    /// Byte range in the source
+    pub bytes: Range<usize>,
+
+    /// 1-based line the command starts on
+    pub line: usize,
+
+    /// 1-based line the command ends on, the same as `line` unless the
+    /// command spans several lines
+    pub end_line: usize,
+}
+
+/// A value together with the span of the text it was parsed from
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+/// A 1-based position in the source
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LineColumn {
+    pub line: usize,
+
+    /// Counted in characters, not bytes
+    pub column: usize,
+}
+
+impl fmt::Display for LineColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Converts byte offsets in a source to lines and columns
+///
+/// Building the index scans the source once. Each lookup is then a binary
+/// search, so reporting many diagnostics stays cheap.
+///
+/// ```
+/// use gerber::span::LineIndex;
+///
+/// let index = LineIndex::new("G04 first*\nG04 second*\n");
+/// assert_eq!(index.line_column(15).to_string(), "2:5");
+/// ```
+#[derive(Clone, Debug)]
+pub struct LineIndex<'a> {
+    src: &'a str,
+
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let lines = src.bytes().filter(|&byte| byte == b'\n').count() + 1;
+        let mut line_starts = Vec::with_capacity(lines);
+        line_starts.push(0);
+        line_starts.extend(src.match_indices('\n').map(|(i, _)| i + 1));
+        Self { src, line_starts }
+    }
+
+    /// The 1-based line containing the byte at `offset`
+    pub fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset)
+    }
+
+    /// The position of the byte at `offset`
+    pub fn line_column(&self, offset: usize) -> LineColumn {
+        let line = self.line(offset);
+        let start = self.line_starts[line - 1];
+        let column = self.src[start..offset].chars().count() + 1;
+        LineColumn { line, column }
+    }
+
+    /// The positions of the start and end of `span`
+    pub fn span(&self, span: &Span) -> Range<LineColumn> {
+        self.line_column(span.bytes.start)..self.line_column(span.bytes.end)
+    }
+}
+
+/// Add line numbers to byte ranges of `src`
+pub(crate) fn spans(src: &str, ranges: impl IntoIterator<Item = Range<usize>>) -> Vec<Span> {
+    let index = LineIndex::new(src);
+    ranges
+        .into_iter()
+        .map(|bytes| {
+            // the last byte, as a span ending with a line ending doesn't reach the next line
+            let end_line = index.line(bytes.end.saturating_sub(1).max(bytes.start));
+            Span {
+                line: index.line(bytes.start),
+                end_line,
+                bytes,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        let src = "G04 a*\r\n%TF.A,\nmultiline*%\n\nM02*";
+        let spans = spans(src, [0..6, 8..26, 28..32]);
+        assert_eq!(
+            spans
+                .iter()
+                .map(|s| (s.line, s.end_line))
+                .collect::<Vec<_>>(),
+            [(1, 1), (2, 3), (5, 5)]
+        );
+    }
+
+    #[test]
+    fn test_line_index() {
+        let src = "ab\r\nΩc\n\nd";
+        let index = LineIndex::new(src);
+        let position = |offset| {
+            let LineColumn { line, column } = index.line_column(offset);
+            (line, column)
+        };
+        assert_eq!(position(0), (1, 1));
+        assert_eq!(position(1), (1, 2));
+        assert_eq!(position(4), (2, 1));
+        // Ω is two bytes but one column
+        assert_eq!(position(6), (2, 2));
+        assert_eq!(position(8), (3, 1));
+        assert_eq!(position(9), (4, 1));
+        assert_eq!(position(src.len()), (4, 2));
+
+        let span = Span {
+            bytes: 4..7,
+            line: 2,
+            end_line: 2,
+        };
+        assert_eq!(
+            index.span(&span),
+            LineColumn { line: 2, column: 1 }..LineColumn { line: 2, column: 3 }
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Editing the commands of a parsed file
+//!
+//! A [Splice] inserts, replaces or removes commands by index, like
+//! [Vec::splice]. Applying splices to the source only rewrites the text of
+//! the commands they touch, found from the spans recorded by the parser.
+//! Like [decorate](crate::decorate) everything else is copied verbatim, so
+//! comments, line endings and the formatting of the other commands are
+//! kept. New commands are written as they [display](crate::writer), one per
+//! line.
+
+use std::ops::Range;
+
+use crate::command::Command;
+use crate::{GerberError, GerberLayer};
+
+/// A change to the commands of a layer
+#[derive(Clone, PartialEq, Debug)]
+pub struct Splice<'a> {
+    /// Indices of the replaced commands, empty to insert before the
+    /// command at `range.start`, or at the end of the file when it is the
+    /// number of commands
+    pub range: Range<usize>,
+
+    /// The commands replacing them, empty to remove them
+    pub commands: Vec<Command<'a>>,
+}
+
+impl<'a> Splice<'a> {
+    /// Insert `commands` before the command at `index`
+    pub fn insert(index: usize, commands: Vec<Command<'a>>) -> Self {
+        Self {
+            range: index..index,
+            commands,
+        }
+    }
+
+    /// Replace the commands in `range` with `commands`
+    pub fn replace(range: Range<usize>, commands: Vec<Command<'a>>) -> Self {
+        Self { range, commands }
+    }
+
+    /// Remove the commands in `range`
+    pub fn remove(range: Range<usize>) -> Self {
+        Self {
+            range,
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl GerberLayer<'_> {
+    /// Apply `splices` to `src`, the source the layer was parsed from
+    ///
+    /// Indices refer to the commands of the layer before any splice, so
+    /// the order of the splices doesn't matter, except for insertions at
+    /// the same index which are made in order. Returns
+    /// [GerberError::ParseError] when a range is out of bounds, two ranges
+    /// overlap or the spans don't fit in `src`.
+    ///
+    /// Removed commands take the line ending after them when they start a
+    /// line, so removing whole lines leaves no blank line behind.
+    ///
+    /// ```
+    /// use gerber::command::Command;
+    /// use gerber::data::EscapedString;
+    /// use gerber::attribute::FileAttributeName;
+    /// use gerber::splice::Splice;
+    /// use gerber::GerberLayer;
+    ///
+    /// let src = "%TF.CreationDate,2024-05-01T12:30:00Z*%\n%FSLAX26Y26*%\n\
+    ///            %MOMM*%\nG04 keep   this*\nM02*\n";
+    /// let layer = GerberLayer::parse(src).unwrap();
+    /// let date = layer
+    ///     .commands()
+    ///     .iter()
+    ///     .position(|command| {
+    ///         matches!(command, Command::AttributeOnFile(FileAttributeName::CreationDate, _))
+    ///     })
+    ///     .unwrap();
+    /// let new_date = Command::AttributeOnFile(
+    ///     FileAttributeName::CreationDate,
+    ///     vec![EscapedString::new_unescaped("2025-01-31T08:00:00Z")],
+    /// );
+    /// let patched = layer.splice(src, &[Splice::replace(date..date + 1, vec![new_date])]);
+    /// assert_eq!(
+    ///     patched.unwrap(),
+    ///     "%TF.CreationDate,2025-01-31T08:00:00Z*%\n%FSLAX26Y26*%\n\
+    ///      %MOMM*%\nG04 keep   this*\nM02*\n"
+    /// );
+    /// ```
+    pub fn splice(&self, src: &str, splices: &[Splice<'_>]) -> Result<String, GerberError> {
+        let error = |message: &str| GerberError::ParseError(message.to_string());
+        let newline = if src.contains("\r\n") { "\r\n" } else { "\n" };
+        let count = self.commands().len();
+
+        let mut splices: Vec<_> = splices.iter().collect();
+        splices.sort_by_key(|splice| (splice.range.start, splice.range.end));
+
+        let mut edits: Vec<(Range<usize>, String)> = Vec::with_capacity(splices.len());
+        let mut previous_end = 0;
+        for splice in splices {
+            let Range { start, end } = splice.range;
+            if start > end || end > count {
+                return Err(error("splice is out of range"));
+            }
+            if start < previous_end {
+                return Err(error("splices overlap"));
+            }
+            previous_end = end;
+
+            let lines = splice.commands.iter().map(ToString::to_string);
+            let edit = if start == end {
+                let mut text = String::new();
+                // a file without a line ending after its last command
+                if start == count && !src.is_empty() && !src.ends_with('\n') {
+                    text.push_str(newline);
+                }
+                for line in lines {
+                    text.push_str(&line);
+                    text.push_str(newline);
+                }
+                let at = match self.span(start) {
+                    Some(span) => span.bytes.start,
+                    None => src.len(),
+                };
+                if !src.is_char_boundary(at) {
+                    return Err(error("spans don't match the source"));
+                }
+                (at..at, text)
+            } else {
+                let first = self.span(start).map(|span| span.bytes.start);
+                let last = self.span(end - 1).map(|span| span.bytes.end);
+                let (Some(first), Some(mut last)) = (first, last) else {
+                    return Err(error("splice is out of range"));
+                };
+                let (Some(before), Some(rest)) = (src.get(..first), src.get(last..)) else {
+                    return Err(error("spans don't match the source"));
+                };
+                if splice.commands.is_empty() && (before.is_empty() || before.ends_with('\n')) {
+                    if rest.starts_with("\r\n") {
+                        last += 2;
+                    } else if rest.starts_with('\n') {
+                        last += 1;
+                    }
+                }
+                (first..last, lines.collect::<Vec<_>>().join(newline))
+            };
+            edits.push(edit);
+        }
+
+        let added: usize = edits.iter().map(|(_, text)| text.len()).sum();
+        let mut spliced = String::with_capacity(src.len() + added);
+        let mut copied = 0;
+        for (range, text) in edits {
+            spliced.push_str(&src[copied..range.start]);
+            spliced.push_str(&text);
+            copied = range.end;
+        }
+        spliced.push_str(&src[copied..]);
+        Ok(spliced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Coordinates, EscapedString, Polarity};
+    use indoc::indoc;
+
+    const SRC: &str = indoc! {"
+        G04 header  kept as is*
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10C,0.1*%
+        D10*
+        X0Y0D03*X1000000Y0D03*
+        X2000000Y0D03*
+
+        M02*
+    "};
+
+    fn flash(x: i64) -> Command<'static> {
+        Command::Flash(Coordinates {
+            x: Some(x),
+            y: Some(0),
+        })
+    }
+
+    fn splice(src: &str, splices: &[Splice]) -> String {
+        let layer = GerberLayer::parse(src).unwrap();
+        let spliced = layer.splice(src, splices).unwrap();
+        // the result is what splicing the commands gives
+        let mut commands = layer.commands().to_vec();
+        let mut sorted: Vec<_> = splices.iter().collect();
+        sorted.sort_by_key(|splice| (splice.range.start, splice.range.end));
+        for splice in sorted.into_iter().rev() {
+            commands.splice(splice.range.clone(), splice.commands.clone());
+        }
+        assert_eq!(GerberLayer::parse(&spliced).unwrap().commands(), commands);
+        spliced
+    }
+
+    #[test]
+    fn test_splice() {
+        let polarity = Command::LoadPolarity(Polarity::Clear);
+        assert_eq!(
+            splice(
+                SRC,
+                &[
+                    Splice::remove(6..7),
+                    Splice::insert(4, vec![polarity.clone()]),
+                    Splice::replace(5..6, vec![flash(5), flash(6)]),
+                    Splice::insert(
+                        8,
+                        vec![Command::Comment(EscapedString::new_unescaped(" trailer"))]
+                    ),
+                ]
+            ),
+            indoc! {"
+                G04 header  kept as is*
+                %FSLAX26Y26*%
+                %MOMM*%
+                %ADD10C,0.1*%
+                %LPC*%
+                D10*
+                X5Y0D03*
+                X6Y0D03*
+                X2000000Y0D03*
+
+                G04 trailer*
+                M02*
+            "}
+        );
+        // whole lines are removed with their line ending
+        assert_eq!(
+            splice(SRC, &[Splice::remove(7..8), Splice::remove(0..1)]),
+            indoc! {"
+                %FSLAX26Y26*%
+                %MOMM*%
+                %ADD10C,0.1*%
+                D10*
+                X0Y0D03*X1000000Y0D03*
+
+                M02*
+            "}
+        );
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let src = "%FSLAX26Y26*%\r\n%MOMM*%\r\nX0Y0D03*\r\nM02*";
+        assert_eq!(
+            splice(
+                src,
+                &[
+                    Splice::remove(1..2),
+                    Splice::insert(2, vec![flash(1), flash(2)]),
+                ]
+            ),
+            "%FSLAX26Y26*%\r\nX1Y0D03*\r\nX2Y0D03*\r\nX0Y0D03*\r\nM02*"
+        );
+        // after the last command, which can't be parsed back
+        let layer = GerberLayer::parse(src).unwrap();
+        assert_eq!(
+            layer
+                .splice(src, &[Splice::insert(4, vec![flash(3)])])
+                .unwrap(),
+            "%FSLAX26Y26*%\r\n%MOMM*%\r\nX0Y0D03*\r\nM02*\r\nX3Y0D03*\r\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let layer = GerberLayer::parse(SRC).unwrap();
+        for splices in [
+            vec![Splice::remove(8..10)],
+            vec![Splice::insert(10, vec![])],
+            vec![Splice::remove(2..4), Splice::replace(3..5, vec![flash(0)])],
+        ] {
+            assert!(matches!(
+                layer.splice(SRC, &splices),
+                Err(GerberError::ParseError(_))
+            ));
+        }
+        assert!(layer.splice("M02*\n", &[Splice::remove(2..3)]).is_err());
+    }
+}
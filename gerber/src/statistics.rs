@@ -0,0 +1,205 @@
+//! Summary statistics of a layer
+//!
+//! [Statistics] accumulates one command at a time, so it can be filled
+//! while parsing with [Statistics::from_source] without keeping the
+//! commands in memory.
+
+use std::collections::BTreeMap;
+use std::ops::ControlFlow;
+
+use crate::command::Command::{self, *};
+use crate::data::ApertureId;
+use crate::{each_command, GerberError};
+
+/// Counts of the commands in a layer
+///
+/// ```
+/// use gerber::statistics::Statistics;
+///
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10C,0.1*%\nD10*\nX0Y0D03*\nX5Y-2D03*\nM02*\n";
+/// let statistics = Statistics::from_source(src).unwrap();
+/// assert_eq!(statistics.commands["D03"], 2);
+/// assert_eq!(statistics.bounds.unwrap().max_x, 5);
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Statistics {
+    /// Number of commands of each kind, by [code](Command::code)
+    pub commands: BTreeMap<&'static str, usize>,
+
+    /// Operations using each aperture
+    pub apertures: BTreeMap<ApertureId, ApertureUsage>,
+
+    /// Extent of the current point after each operation
+    pub bounds: Option<Bounds>,
+
+    /// Number of times each attribute is set, by command and name, e.g.
+    /// `TF.FileFunction`
+    pub attributes: BTreeMap<String, usize>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    aperture: Option<ApertureId>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    point: (i64, i64),
+}
+
+/// Operations using an aperture
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ApertureUsage {
+    /// D03 operations
+    pub flashes: usize,
+
+    /// D01 operations, both linear and circular
+    pub draws: usize,
+}
+
+/// Minimum and maximum coordinates, in the integer units of the format
+/// specification
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Bounds {
+    pub min_x: i64,
+    pub min_y: i64,
+    pub max_x: i64,
+    pub max_y: i64,
+}
+
+impl Bounds {
+    fn include(&mut self, (x, y): (i64, i64)) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+}
+
+impl Statistics {
+    /// Parse `src`, accumulating statistics without keeping the commands
+    pub fn from_source(src: &str) -> Result<Self, GerberError> {
+        let mut statistics = Self::default();
+        each_command(src, |command, _| {
+            statistics.add(&command);
+            ControlFlow::Continue(())
+        })?;
+        Ok(statistics)
+    }
+
+    /// Accumulate one command
+    pub fn add(&mut self, command: &Command) {
+        *self.commands.entry(command.code()).or_default() += 1;
+        match command {
+            SetCurrentAperture(id) => self.aperture = Some(*id),
+            Plot(coordinates, _) | Move(coordinates) | Flash(coordinates) => {
+                let x = coordinates.x.unwrap_or(self.point.0);
+                let y = coordinates.y.unwrap_or(self.point.1);
+                self.point = (x, y);
+                match &mut self.bounds {
+                    Some(bounds) => bounds.include(self.point),
+                    None => {
+                        self.bounds = Some(Bounds {
+                            min_x: x,
+                            min_y: y,
+                            max_x: x,
+                            max_y: y,
+                        })
+                    }
+                }
+                if let Some(id) = self.aperture {
+                    let usage = self.apertures.entry(id).or_default();
+                    match command {
+                        Plot(..) => usage.draws += 1,
+                        Flash(_) => usage.flashes += 1,
+                        _ => (),
+                    }
+                }
+            }
+            AttributeOnFile(name, _) => self.add_attribute(command.code(), name.name()),
+            AttributeOnAperture(name, _) => self.add_attribute(command.code(), name.name()),
+            AttributeOnObject(name, _) => self.add_attribute(command.code(), name.name()),
+            _ => (),
+        }
+    }
+
+    fn add_attribute(&mut self, code: &str, name: &str) {
+        *self.attributes.entry(format!("{code}{name}")).or_default() += 1;
+    }
+}
+
+impl<'c, 'a: 'c> Extend<&'c Command<'a>> for Statistics {
+    fn extend<T: IntoIterator<Item = &'c Command<'a>>>(&mut self, commands: T) {
+        for command in commands {
+            self.add(command);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    const SRC: &str = indoc! {"
+        %TF.Part,Single*%
+        %FSLAX26Y26*%
+        %MOMM*%
+        %TA.AperFunction,ComponentPad*%
+        %ADD10C,0.1*%
+        %ADD11R,0.5X0.5*%
+        D10*
+        X-100Y200D02*
+        X300D01*
+        Y-50D01*
+        D11*
+        %TO.N,GND*%
+        X0Y0D03*
+        M02*
+    "};
+
+    #[test]
+    fn test_statistics() {
+        let statistics = Statistics::from_source(SRC).unwrap();
+        assert_eq!(statistics.commands["AD"], 2);
+        assert_eq!(statistics.commands["D01"], 2);
+        assert_eq!(statistics.commands["M02"], 1);
+
+        let usage = |id| statistics.apertures[&ApertureId::new(id).unwrap()];
+        assert_eq!(
+            usage(10),
+            ApertureUsage {
+                flashes: 0,
+                draws: 2
+            }
+        );
+        assert_eq!(
+            usage(11),
+            ApertureUsage {
+                flashes: 1,
+                draws: 0
+            }
+        );
+
+        assert_eq!(
+            statistics.bounds,
+            Some(Bounds {
+                min_x: -100,
+                min_y: -50,
+                max_x: 300,
+                max_y: 200,
+            })
+        );
+
+        let attributes: Vec<_> = statistics.attributes.keys().map(String::as_str).collect();
+        assert_eq!(attributes, ["TA.AperFunction", "TF.Part", "TO.N"]);
+    }
+
+    #[test]
+    fn test_extend_matches_from_source() {
+        let layer = GerberLayer::parse(SRC).unwrap();
+        let mut statistics = Statistics::default();
+        statistics.extend(layer.commands());
+        assert_eq!(statistics, Statistics::from_source(SRC).unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+//! Custom aperture shapes
+//!
+//! Shapes beyond the standard circle, rectangle, obround and polygon are
+//! written as aperture macros. A [MacroTemplate] describes such a shape
+//! once, as a macro whose `$n` variables are its parameters, and each
+//! aperture of that shape as the values of the parameters. The
+//! [writer](crate::writer::GerberWriter::define_macro_aperture) writes the
+//! macro before the first aperture using it, so a library of shapes only
+//! needs to implement the trait.
+
+use crate::aperture::ApertureTemplate;
+use crate::command::Command;
+use crate::macros::ApertureMacro;
+use crate::GerberError;
+
+/// A parametric aperture shape which lowers to an aperture macro
+pub trait MacroTemplate {
+    /// The name of the macro, the same for every aperture of the shape
+    fn name(&self) -> &str;
+
+    /// The words of the macro body without their `*`, as read by
+    /// [ApertureMacro::parse], the same for every aperture of the shape
+    fn content(&self) -> Vec<String>;
+
+    /// The values of the macro variables for this aperture, `$1` first
+    fn parameters(&self) -> Vec<f64>;
+
+    /// The `%AM` command defining the macro, or an error if a word of
+    /// the content is invalid
+    fn definition(&self) -> Result<Command<'_>, GerberError> {
+        let body = ApertureMacro::parse(self.name(), &self.content())?;
+        Ok(Command::ApertureMacro(self.name().into(), body.statements))
+    }
+
+    /// The template of an `%AD` command using the macro
+    fn aperture(&self) -> ApertureTemplate<'_> {
+        ApertureTemplate::Macro {
+            name: self.name().into(),
+            parameters: self.parameters(),
+        }
+    }
+}
+
+/// A rectangle with rounded corners, centered on the origin, as commonly
+/// used for SMD pads
+///
+/// Sizes are in the unit of the file. The radius is at most half the
+/// shorter side, which makes an obround.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RoundedRectangle {
+    pub width: f64,
+    pub height: f64,
+    pub radius: f64,
+}
+
+impl MacroTemplate for RoundedRectangle {
+    fn name(&self) -> &str {
+        "RoundedRectangle"
+    }
+
+    fn content(&self) -> Vec<String> {
+        // two overlapping rectangles and a circle in each corner
+        [
+            "0 width $1 height $2 corner radius $3",
+            "21,1,$1,$2-2x$3,0,0,0",
+            "21,1,$1-2x$3,$2,0,0,0",
+            "1,1,2x$3,$1/2-$3,$2/2-$3",
+            "1,1,2x$3,$3-$1/2,$2/2-$3",
+            "1,1,2x$3,$3-$1/2,$3-$2/2",
+            "1,1,2x$3,$1/2-$3,$3-$2/2",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    fn parameters(&self) -> Vec<f64> {
+        vec![self.width, self.height, self.radius]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::ApertureId;
+    use crate::writer::GerberWriter;
+    use indoc::indoc;
+    use std::io;
+
+    /// A circle under the name of the rounded rectangle
+    struct Impostor;
+
+    impl MacroTemplate for Impostor {
+        fn name(&self) -> &str {
+            "RoundedRectangle"
+        }
+
+        fn content(&self) -> Vec<String> {
+            vec!["1,1,$1,0,0".into()]
+        }
+
+        fn parameters(&self) -> Vec<f64> {
+            vec![1.0]
+        }
+    }
+
+    #[test]
+    fn test_macro_aperture() {
+        let id = |id| ApertureId::new(id).unwrap();
+        let pad = RoundedRectangle {
+            width: 1.5,
+            height: 0.8,
+            radius: 0.2,
+        };
+        let square = RoundedRectangle { radius: 0.0, ..pad };
+
+        let mut writer = GerberWriter::new(Vec::new());
+        writer.define_macro_aperture(id(10), &pad).unwrap();
+        writer.define_macro_aperture(id(11), &square).unwrap();
+        let error = writer.define_macro_aperture(id(12), &Impostor).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            String::from_utf8(writer.finish().unwrap()).unwrap(),
+            indoc! {"
+                %AMRoundedRectangle*
+                0 width $1 height $2 corner radius $3*
+                21,1,$1,$2-2x$3,0,0,0*
+                21,1,$1-2x$3,$2,0,0,0*
+                1,1,2x$3,$1/2-$3,$2/2-$3*
+                1,1,2x$3,$3-$1/2,$2/2-$3*
+                1,1,2x$3,$3-$1/2,$3-$2/2*
+                1,1,2x$3,$1/2-$3,$3-$2/2*%
+                %ADD10RoundedRectangle,1.5X0.8X0.2*%
+                %ADD11RoundedRectangle,1.5X0.8X0*%
+            "}
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Test point extraction
+//!
+//! Flying-probe testers need the location, net and side of every test
+//! point. Gerber X2 marks test pads with `.AperFunction,TestPad`, and many
+//! CAD tools instead place footprints with `TP` reference designators whose
+//! pads are ordinary `SMDPad` or `ComponentPad`s.
+//! [test_points](GerberLayer::test_points) collects both.
+
+use crate::image::{Attributes, Image, Point, Segment, Shape};
+use crate::GerberLayer;
+
+/// A test point on a copper layer
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TestPoint {
+    /// Center of the pad in millimeters
+    pub at: Point,
+
+    /// The `.AperFunction` of the pad
+    pub function: String,
+
+    /// The net name, `.N`
+    pub net: Option<String>,
+
+    /// The reference designator, `.C`
+    pub component: Option<String>,
+
+    /// The side of the board, from `.FileFunction`
+    pub side: Option<Side>,
+
+    /// Index of the object in [Image::objects]
+    pub object: usize,
+}
+
+/// A side of the board
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Side {
+    Top,
+    Bottom,
+}
+
+impl Side {
+    /// The side of a layer from its `.FileFunction` attribute, e.g.
+    /// `Copper,L1,Top` or `Soldermask,Bot`
+    pub fn of(image: &Image) -> Option<Side> {
+        let function = image.file_attributes.get(".FileFunction")?;
+        function
+            .iter()
+            .skip(1)
+            .find_map(|value| match value.as_ref() {
+                "Top" => Some(Side::Top),
+                "Bot" => Some(Side::Bottom),
+                _ => None,
+            })
+    }
+}
+
+impl GerberLayer<'_> {
+    /// Find the test points of a copper layer
+    ///
+    /// A test point is a flash or region whose aperture function is
+    /// `TestPad`, or a pad of a component whose reference designator starts
+    /// with `TP`. Draws and arcs are never test points. The center of a
+    /// region is the center of its bounding box.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    /// use gerber::testpoint::Side;
+    ///
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%TF.FileFunction,Copper,L2,Bot*%\n\
+    ///            %TA.AperFunction,TestPad*%\n%ADD10C,1*%\n%TO.N,VCC*%\n\
+    ///            D10*\nX1000000Y2000000D03*\nM02*\n";
+    /// let points = GerberLayer::parse(src).unwrap().test_points();
+    /// assert_eq!(points[0].net.as_deref(), Some("VCC"));
+    /// assert_eq!(points[0].side, Some(Side::Bottom));
+    /// assert_eq!((points[0].at.x, points[0].at.y), (1.0, 2.0));
+    /// ```
+    pub fn test_points(&self) -> Vec<TestPoint> {
+        let image = self.image();
+        let side = Side::of(&image);
+        image
+            .objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| is_test_point(&object.attributes))
+            .filter_map(|(index, object)| {
+                let at = match &object.shape {
+                    Shape::Flash { at, .. } => *at,
+                    Shape::Region { contours } => {
+                        let points = contours.iter().flat_map(|contour| {
+                            std::iter::once(contour.start).chain(contour.segments.iter().map(
+                                |segment| match *segment {
+                                    Segment::Line { end } | Segment::Arc { end, .. } => end,
+                                },
+                            ))
+                        });
+                        center(points)?
+                    }
+                    Shape::Draw { .. } | Shape::Arc { .. } => return None,
+                };
+                let attributes = &object.attributes;
+                Some(TestPoint {
+                    at,
+                    function: attributes.aperture_function()?.to_string(),
+                    net: attributes.net().map(str::to_string),
+                    component: attributes.component().map(str::to_string),
+                    side,
+                    object: index,
+                })
+            })
+            .collect()
+    }
+}
+
+fn is_test_point(attributes: &Attributes) -> bool {
+    match attributes.aperture_function() {
+        Some("TestPad") => true,
+        Some("SMDPad" | "ComponentPad") => attributes
+            .component()
+            .is_some_and(|component| component.starts_with("TP")),
+        _ => false,
+    }
+}
+
+/// The center of the bounding box of some points
+fn center(points: impl Iterator<Item = Point>) -> Option<Point> {
+    let (min, max) = points.fold(None, |bounds: Option<(Point, Point)>, point| {
+        Some(match bounds {
+            None => (point, point),
+            Some((min, max)) => (
+                Point {
+                    x: min.x.min(point.x),
+                    y: min.y.min(point.y),
+                },
+                Point {
+                    x: max.x.max(point.x),
+                    y: max.y.max(point.y),
+                },
+            ),
+        })
+    })?;
+    Some(Point {
+        x: (min.x + max.x) / 2.0,
+        y: (min.y + max.y) / 2.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    fn test_points(body: &str) -> Vec<TestPoint> {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().test_points()
+    }
+
+    #[test]
+    fn test_test_pads() {
+        let points = test_points(indoc! {"
+            %TF.FileFunction,Copper,L1,Top*%
+            %TA.AperFunction,TestPad*%
+            %ADD10C,1*%
+            %TA.AperFunction,SMDPad,CuDef*%
+            %ADD11R,1X1*%
+            %TA.AperFunction,Conductor*%
+            %ADD12C,0.2*%
+            %TD*%
+            %TO.N,GND*%
+            D10*
+            X0Y0D03*
+            %TO.N,SIG*%
+            %TO.C,TP3*%
+            D11*
+            X1000000Y0D03*
+            %TO.C,R1*%
+            X2000000Y0D03*
+            %TD.C*%
+            D12*
+            X3000000Y0D01*
+            %TA.AperFunction,TestPad*%
+            G36*
+            X0Y1000000D02*
+            X2000000Y1000000D01*
+            Y2000000D01*
+            X0D01*
+            Y1000000D01*
+            G37*
+        "});
+        let summary: Vec<_> = points
+            .iter()
+            .map(|point| {
+                (
+                    point.at.x,
+                    point.at.y,
+                    point.function.as_str(),
+                    point.net.as_deref(),
+                    point.component.as_deref(),
+                    point.object,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0.0, 0.0, "TestPad", Some("GND"), None, 0),
+                (1.0, 0.0, "SMDPad", Some("SIG"), Some("TP3"), 1),
+                (1.0, 1.5, "TestPad", Some("SIG"), None, 4),
+            ]
+        );
+        assert!(points.iter().all(|point| point.side == Some(Side::Top)));
+    }
+
+    #[test]
+    fn test_no_side() {
+        let points = test_points(indoc! {"
+            %TA.AperFunction,TestPad*%
+            %ADD10C,1*%
+            D10*
+            X0Y0D03*
+        "});
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].side, None);
+    }
+}
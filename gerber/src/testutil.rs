@@ -0,0 +1,275 @@
+//! [proptest](https://crates.io/crates/proptest) strategies for Gerber files
+//!
+//! The strategies produce syntactically valid files: a single format
+//! specification and unit up front, apertures defined before they are
+//! selected, regions bounded by simple contours, balanced block aperture
+//! statements, and a terminating `M02*`. Downstream tools can use them to exercise their own
+//! pipelines with inputs that are valid but otherwise unpredictable.
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::fmt::Write;
+
+/// Controls the shape of generated files
+#[derive(Clone, Debug)]
+pub struct FileConfig {
+    /// Maximum number of standard apertures defined by the file
+    pub max_apertures: usize,
+
+    /// Maximum number of top-level items (flashes, draws, regions, ...)
+    pub max_items: usize,
+
+    /// Generate region statements (G36/G37)
+    pub regions: bool,
+
+    /// Generate block aperture statements (AB), off by default since the
+    /// parser doesn't read `%AB` yet
+    pub blocks: bool,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        Self {
+            max_apertures: 8,
+            max_items: 16,
+            regions: true,
+            blocks: false,
+        }
+    }
+}
+
+/// Coordinate format shared by X and Y, as declared by `%FS`
+#[derive(Copy, Clone, Debug)]
+pub struct Format {
+    pub integer: u8,
+    pub decimal: u8,
+}
+
+impl Format {
+    /// Largest magnitude representable by this format
+    pub fn max_coordinate(&self) -> i64 {
+        10i64.pow((self.integer + self.decimal) as u32) - 1
+    }
+}
+
+/// Generate a coordinate format with 1-6 integer digits and 6 decimals
+pub fn format() -> impl Strategy<Value = Format> {
+    (1u8..=6).prop_map(|integer| Format {
+        integer,
+        decimal: 6,
+    })
+}
+
+/// Generate a coordinate pair representable in `format`
+pub fn point(format: Format) -> impl Strategy<Value = (i64, i64)> {
+    let max = format.max_coordinate();
+    (-max..=max, -max..=max)
+}
+
+/// Generate a decimal size in the range 0.001..10.000
+fn size() -> impl Strategy<Value = String> {
+    (1u32..10_000)
+        .prop_map(|thousandths| format!("{}.{:03}", thousandths / 1000, thousandths % 1000))
+}
+
+/// Generate the modifiers of a standard aperture template, e.g. `C,0.100`
+pub fn aperture_template() -> impl Strategy<Value = String> {
+    prop_oneof![
+        size().prop_map(|d| format!("C,{d}")),
+        (size(), size()).prop_map(|(x, y)| format!("R,{x}X{y}")),
+        (size(), size()).prop_map(|(x, y)| format!("O,{x}X{y}")),
+        (size(), 3u32..=12, 0u32..360).prop_map(|(d, n, r)| format!("P,{d}X{n}X{r}")),
+    ]
+}
+
+/// Generate the vertices of a star-shaped polygon representable in
+/// `format`, counter-clockwise around a center
+///
+/// Each of the 3 to 7 vertices lies in the first half of its own equal
+/// sector around the center, so consecutive vertices are less than 180°
+/// apart and the contour through them never crosses itself.
+pub fn star_polygon(format: Format) -> impl Strategy<Value = Vec<(i64, i64)>> {
+    let max = format.max_coordinate();
+    (3usize..8).prop_flat_map(move |n| {
+        (
+            (-max / 2..=max / 2, -max / 2..=max / 2),
+            vec((0.0..0.5f64, max / 4..=max / 2), n),
+        )
+            .prop_map(move |((x, y), vertices)| {
+                let sector = std::f64::consts::TAU / n as f64;
+                vertices
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &(offset, radius))| {
+                        let (sin, cos) = ((i as f64 + offset) * sector).sin_cos();
+                        let radius = radius as f64;
+                        (x + (radius * cos) as i64, y + (radius * sin) as i64)
+                    })
+                    .collect()
+            })
+    })
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Flashes(usize, Vec<(i64, i64)>),
+    Draws(usize, (i64, i64), Vec<(i64, i64)>),
+    Polarity(bool),
+    Region(Vec<(i64, i64)>),
+    Block(usize, Vec<(i64, i64)>, (i64, i64)),
+}
+
+fn item(format: Format, apertures: usize, config: &FileConfig) -> BoxedStrategy<Item> {
+    let points = move || vec(point(format), 1..8);
+    let mut items = vec![
+        (0..apertures, points())
+            .prop_map(|(a, p)| Item::Flashes(a, p))
+            .boxed(),
+        (0..apertures, point(format), points())
+            .prop_map(|(a, s, p)| Item::Draws(a, s, p))
+            .boxed(),
+        any::<bool>().prop_map(Item::Polarity).boxed(),
+    ];
+    if config.regions {
+        items.push(star_polygon(format).prop_map(Item::Region).boxed());
+    }
+    if config.blocks {
+        items.push(
+            (0..apertures, points(), point(format))
+                .prop_map(|(a, p, at)| Item::Block(a, p, at))
+                .boxed(),
+        );
+    }
+    proptest::strategy::Union::new(items).boxed()
+}
+
+fn write_point(out: &mut String, (x, y): (i64, i64), code: &str) {
+    writeln!(out, "X{x}Y{y}{code}*").unwrap();
+}
+
+fn write_item(out: &mut String, item: &Item, block_id: &mut usize) {
+    match item {
+        Item::Flashes(a, points) => {
+            writeln!(out, "D{}*", 10 + a).unwrap();
+            for p in points {
+                write_point(out, *p, "D03");
+            }
+        }
+        Item::Draws(a, start, points) => {
+            writeln!(out, "D{}*", 10 + a).unwrap();
+            writeln!(out, "G01*").unwrap();
+            write_point(out, *start, "D02");
+            for p in points {
+                write_point(out, *p, "D01");
+            }
+        }
+        Item::Polarity(dark) => {
+            writeln!(out, "%LP{}*%", if *dark { "D" } else { "C" }).unwrap();
+        }
+        Item::Region(points) => {
+            writeln!(out, "G36*").unwrap();
+            writeln!(out, "G01*").unwrap();
+            write_point(out, points[0], "D02");
+            for p in &points[1..] {
+                write_point(out, *p, "D01");
+            }
+            // contours must be closed
+            write_point(out, points[0], "D01");
+            writeln!(out, "G37*").unwrap();
+        }
+        Item::Block(a, points, at) => {
+            // block apertures are numbered after the standard ones
+            *block_id += 1;
+            writeln!(out, "%ABD{block_id}*%").unwrap();
+            writeln!(out, "D{}*", 10 + a).unwrap();
+            for p in points {
+                write_point(out, *p, "D03");
+            }
+            writeln!(out, "%AB*%").unwrap();
+            writeln!(out, "D{block_id}*").unwrap();
+            write_point(out, *at, "D03");
+        }
+    }
+}
+
+/// Generate a complete Gerber file with the default [FileConfig]
+pub fn gerber_file() -> impl Strategy<Value = String> {
+    gerber_file_with(FileConfig::default())
+}
+
+/// Generate a complete Gerber file shaped by `config`
+pub fn gerber_file_with(config: FileConfig) -> impl Strategy<Value = String> {
+    let max_apertures = config.max_apertures.max(1);
+    let max_items = config.max_items;
+    (
+        format(),
+        any::<bool>(),
+        vec(aperture_template(), 1..=max_apertures),
+    )
+        .prop_flat_map(move |(format, metric, apertures)| {
+            let items = vec(item(format, apertures.len(), &config), 0..=max_items);
+            (Just(format), Just(metric), Just(apertures), items)
+        })
+        .prop_map(|(format, metric, apertures, items)| {
+            let mut out = String::new();
+            writeln!(out, "G04 Generated by gerber::testutil*").unwrap();
+            writeln!(out, "%FSLAX{0}{1}Y{0}{1}*%", format.integer, format.decimal).unwrap();
+            writeln!(out, "%MO{}*%", if metric { "MM" } else { "IN" }).unwrap();
+            for (i, template) in apertures.iter().enumerate() {
+                writeln!(out, "%ADD{}{template}*%", 10 + i).unwrap();
+            }
+            let mut block_id = 9 + apertures.len();
+            for item in &items {
+                write_item(&mut out, item, &mut block_id);
+            }
+            writeln!(out, "M02*").unwrap();
+            out
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GerberLayer;
+
+    proptest! {
+        #[test]
+        fn test_gerber_file_structure(src in gerber_file_with(FileConfig {
+            blocks: true,
+            ..FileConfig::default()
+        })) {
+            prop_assert!(src.starts_with("G04"));
+            prop_assert!(src.ends_with("M02*\n"));
+            prop_assert_eq!(src.matches("G36*").count(), src.matches("G37*").count());
+            prop_assert_eq!(src.matches("%ABD").count(), src.matches("%AB*%").count());
+        }
+
+        #[test]
+        fn test_gerber_file_parses(src in gerber_file()) {
+            prop_assert!(GerberLayer::parse(&src).is_ok());
+        }
+
+        #[test]
+        fn test_star_polygon(points in format().prop_flat_map(star_polygon)) {
+            // no two edges which don't share a vertex cross
+            let cross = |o: (i64, i64), a: (i64, i64), b: (i64, i64)| {
+                let (ax, ay) = ((a.0 - o.0) as i128, (a.1 - o.1) as i128);
+                let (bx, by) = ((b.0 - o.0) as i128, (b.1 - o.1) as i128);
+                (ax * by - ay * bx).signum()
+            };
+            let n = points.len();
+            let edge = |i: usize| (points[i], points[(i + 1) % n]);
+            for i in 0..n {
+                for j in i + 2..n {
+                    if (j + 1) % n == i {
+                        continue;
+                    }
+                    let ((a, b), (c, d)) = (edge(i), edge(j));
+                    let crossing = cross(a, b, c) * cross(a, b, d) < 0
+                        && cross(c, d, a) * cross(c, d, b) < 0;
+                    prop_assert!(!crossing, "edges {} and {} cross", i, j);
+                }
+            }
+        }
+    }
+}
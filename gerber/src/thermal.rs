@@ -0,0 +1,502 @@
+//! Thermal relief detection
+//!
+//! A pad in a plane is often connected to it by a few narrow spokes across
+//! a clear gap, so the plane doesn't sink the heat of the soldering iron.
+//! Thermal reliefs are found in the united [copper](crate::copper) rather
+//! than from how they were drawn, so spokes drawn as tracks, thermal
+//! apertures and gaps cut out of a pour are all recognised.
+//!
+//! Around each pad, rings at growing distances from its outline are
+//! sampled. Inside the gap a ring only crosses the spokes, and once past
+//! the gap it lies in the plane. A flash of a macro aperture with a thermal
+//! primitive is a relief by construction, and is reported as its primitive
+//! describes it instead.
+
+use std::f64::consts::TAU;
+
+use std::collections::HashSet;
+
+use crate::aperture::ApertureTemplate;
+use crate::command::Command::*;
+use crate::copper::{flash_outline, Polygon};
+use crate::data::{Mirroring, Polarity, Unit};
+use crate::image::{Object, Point, Shape};
+use crate::macros::{ApertureMacro, MacroPrimitive};
+use crate::GerberLayer;
+
+/// Points sampled on each ring
+const SAMPLES: usize = 360;
+
+/// Distance between rings in millimeters
+const STEP: f64 = 0.05;
+
+/// The widest gap found in millimeters
+const MAX_GAP: f64 = 1.5;
+
+/// The fraction of a ring in copper for it to be in the plane
+const PLANE: f64 = 0.9;
+
+/// A pad connected to a plane by spokes
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Thermal {
+    /// The flash point of the pad in millimeters
+    pub at: Point,
+
+    pub spokes: usize,
+
+    /// Mean width of the spokes in millimeters
+    pub spoke_width: f64,
+
+    /// Width of the gap between the pad and the plane in millimeters,
+    /// to within half the ring spacing of 0.05 mm
+    pub gap: f64,
+
+    /// Index of the pad, or of the flash of a thermal primitive, in
+    /// [Image::objects](crate::image::Image::objects)
+    pub object: usize,
+}
+
+impl GerberLayer<'_> {
+    /// Find the pads connected to a plane by thermal relief spokes
+    ///
+    /// A dark flash is a thermal relief pad if, going outwards from its
+    /// outline, there is a gap up to 1.5 mm wide crossed by two or more
+    /// spokes, and beyond it copper around at least 90% of the pad.
+    /// `tolerance` is passed to [copper](GerberLayer::copper). The outline
+    /// of a macro aperture is taken as its convex hull.
+    ///
+    /// A flash of a macro aperture with a thermal primitive (code 7), of
+    /// either polarity, is a relief with four spokes as wide as the gaps of
+    /// the primitive, across a gap of half the difference of its diameters.
+    /// Pads inside its inner diameter are not reported again.
+    ///
+    /// ```
+    /// use gerber::GerberLayer;
+    ///
+    /// // a plane with a round gap around a pad, crossed by two tracks
+    /// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,10X10*%\n%ADD11C,4*%\n\
+    ///            %ADD12C,2*%\n%ADD13C,0.3*%\nD10*\nX0Y0D03*\n%LPC*%\nD11*\nX0Y0D03*\n\
+    ///            %LPD*%\nD12*\nX0Y0D03*\nD13*\nG01*\nX-2500000Y0D02*\nX2500000Y0D01*\n\
+    ///            X0Y-2500000D02*\nX0Y2500000D01*\nM02*\n";
+    /// let thermals = GerberLayer::parse(src).unwrap().thermal_reliefs(0.001);
+    /// assert_eq!(thermals[0].spokes, 4);
+    /// assert!((thermals[0].gap - 1.0).abs() < 0.03);
+    /// ```
+    pub fn thermal_reliefs(&self, tolerance: f64) -> Vec<Thermal> {
+        let image = self.image();
+        let unit = image.unit.unwrap_or(Unit::Millimeters);
+        let copper = self.copper(tolerance);
+        let macros = self.aperture_macros();
+        let bounds: Vec<_> = copper.polygons.iter().map(bounds).collect();
+
+        let flashes: Vec<_> = image
+            .objects
+            .iter()
+            .enumerate()
+            .filter_map(|(index, object)| {
+                let Shape::Flash { at, .. } = object.shape else {
+                    return None;
+                };
+                match object.source.aperture.map(|index| &self.commands[index]) {
+                    Some(ApertureDefine(_, template)) => Some((index, object, at, template)),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        // reliefs by construction, and the inner circles left for their pads
+        let mut thermals = Vec::new();
+        let mut inner = Vec::new();
+        for &(index, object, _, template) in &flashes {
+            for (thermal, radius) in thermal_primitives(index, object, template, &macros, unit) {
+                inner.push((thermal.at, radius));
+                thermals.push(thermal);
+            }
+        }
+        let constructed: HashSet<_> = thermals.iter().map(|thermal| thermal.object).collect();
+
+        for &(index, object, at, template) in &flashes {
+            let in_relief = |&(center, radius): &(Point, f64)| {
+                (at.x - center.x).hypot(at.y - center.y) < radius
+            };
+            if object.polarity != Polarity::Dark
+                || constructed.contains(&index)
+                || inner.iter().any(in_relief)
+            {
+                continue;
+            }
+            let Some(outline) = flash_outline(object, template, &macros, unit, tolerance) else {
+                continue;
+            };
+            if outline.is_empty() {
+                continue;
+            }
+            let pad = Pad::new(at, &outline);
+            let reach = pad.extent + MAX_GAP + STEP;
+            let near: Vec<&Polygon> = copper
+                .polygons
+                .iter()
+                .zip(&bounds)
+                .filter(|(_, (min, max))| {
+                    min.x <= at.x + reach
+                        && min.y <= at.y + reach
+                        && max.x >= at.x - reach
+                        && max.y >= at.y - reach
+                })
+                .map(|(polygon, _)| polygon)
+                .collect();
+            thermals.extend(pad.thermal(index, &near));
+        }
+        thermals.sort_by_key(|thermal| thermal.object);
+        thermals
+    }
+}
+
+/// The reliefs described by the thermal primitives of a flash of a macro
+/// aperture, each with the radius of its inner circle, in millimeters
+fn thermal_primitives(
+    index: usize,
+    object: &Object,
+    template: &ApertureTemplate,
+    macros: &[ApertureMacro],
+    unit: Unit,
+) -> Vec<(Thermal, f64)> {
+    let ApertureTemplate::Macro { name, parameters } = template else {
+        return Vec::new();
+    };
+    let Ok(primitives) =
+        ApertureMacro::find(macros, name).and_then(|body| body.evaluate(parameters))
+    else {
+        return Vec::new();
+    };
+    let Shape::Flash { at, .. } = object.shape else {
+        return Vec::new();
+    };
+    let scale = unit.to_mm(1.0) * object.scaling.0;
+    primitives
+        .into_iter()
+        .filter_map(|primitive| {
+            let MacroPrimitive::Thermal {
+                center,
+                outer,
+                inner,
+                gap,
+                rotation,
+            } = primitive
+            else {
+                return None;
+            };
+            // about the origin of the macro, then as the object
+            let center = rotate(center, rotation);
+            let center = match object.mirroring {
+                Mirroring::None => center,
+                Mirroring::X => Point {
+                    x: -center.x,
+                    ..center
+                },
+                Mirroring::Y => Point {
+                    y: -center.y,
+                    ..center
+                },
+                Mirroring::XY => Point {
+                    x: -center.x,
+                    y: -center.y,
+                },
+            };
+            let center = rotate(center, object.rotation.0);
+            let thermal = Thermal {
+                at: Point {
+                    x: at.x + center.x * scale,
+                    y: at.y + center.y * scale,
+                },
+                spokes: 4,
+                spoke_width: gap * scale,
+                gap: (outer - inner) / 2.0 * scale,
+                object: index,
+            };
+            Some((thermal, inner / 2.0 * scale))
+        })
+        .collect()
+}
+
+/// A point rotated `degrees` counter-clockwise about the origin
+fn rotate(point: Point, degrees: f64) -> Point {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    Point {
+        x: point.x * cos - point.y * sin,
+        y: point.x * sin + point.y * cos,
+    }
+}
+
+/// The directions rings are sampled in and how far the pad reaches in each
+struct Pad {
+    at: Point,
+    directions: Vec<(Point, f64)>,
+
+    /// The distance from the flash point to the farthest point of the pad
+    extent: f64,
+}
+
+impl Pad {
+    fn new(at: Point, outline: &[Point]) -> Self {
+        let directions: Vec<_> = (0..SAMPLES)
+            .map(|i| {
+                let angle = TAU * i as f64 / SAMPLES as f64;
+                let direction = Point {
+                    x: angle.cos(),
+                    y: angle.sin(),
+                };
+                (direction, ray_exit(outline, direction))
+            })
+            .collect();
+        let extent = directions
+            .iter()
+            .map(|(_, reach)| *reach)
+            .fold(0.0, f64::max);
+        Pad {
+            at,
+            directions,
+            extent,
+        }
+    }
+
+    /// The points of the ring `offset` millimeters outside the pad
+    fn ring(&self, offset: f64) -> impl Iterator<Item = Point> + '_ {
+        self.directions.iter().map(move |(direction, reach)| Point {
+            x: self.at.x + direction.x * (reach + offset),
+            y: self.at.y + direction.y * (reach + offset),
+        })
+    }
+
+    fn thermal(&self, object: usize, copper: &[&Polygon]) -> Option<Thermal> {
+        let in_copper = |point: Point| copper.iter().any(|polygon| polygon.contains(point));
+
+        // the ring with the least copper inside the gap
+        let mut narrowest: Option<(Vec<bool>, f64)> = None;
+        for step in 1..=(MAX_GAP / STEP).round() as usize + 1 {
+            let offset = step as f64 * STEP;
+            let ring: Vec<bool> = self.ring(offset).map(in_copper).collect();
+            let fraction = ring.iter().filter(|&&dark| dark).count() as f64 / SAMPLES as f64;
+            if fraction >= PLANE {
+                // without a narrower ring the pad is in the plane, no gap
+                let (ring, ring_offset) = narrowest?;
+                let runs = runs(&ring);
+                if runs.len() < 2 {
+                    return None;
+                }
+                let points: Vec<_> = self.ring(ring_offset).collect();
+                let length = |run: &(usize, usize)| {
+                    (0..run.1)
+                        .map(|i| {
+                            let (a, b) = (
+                                points[(run.0 + i) % SAMPLES],
+                                points[(run.0 + i + 1) % SAMPLES],
+                            );
+                            (b.x - a.x).hypot(b.y - a.y)
+                        })
+                        .sum::<f64>()
+                };
+                return Some(Thermal {
+                    at: self.at,
+                    spokes: runs.len(),
+                    spoke_width: runs.iter().map(length).sum::<f64>() / runs.len() as f64,
+                    gap: offset - STEP / 2.0,
+                    object,
+                });
+            }
+            let dark = ring.iter().filter(|&&dark| dark).count();
+            let narrower = narrowest
+                .as_ref()
+                .is_none_or(|(narrowest, _)| dark < narrowest.iter().filter(|&&dark| dark).count());
+            if narrower {
+                narrowest = Some((ring, offset));
+            }
+        }
+        None
+    }
+}
+
+/// The distance from the origin to where a ray in `direction` leaves a
+/// star-shaped outline around the origin
+fn ray_exit(outline: &[Point], direction: Point) -> f64 {
+    let cross = |a: Point, b: Point| a.x * b.y - a.y * b.x;
+    outline
+        .iter()
+        .zip(outline.iter().cycle().skip(1))
+        .filter_map(|(&p, &q)| {
+            let edge = Point {
+                x: q.x - p.x,
+                y: q.y - p.y,
+            };
+            let denominator = cross(direction, edge);
+            if denominator == 0.0 {
+                return None;
+            }
+            // the ray point t * direction equals p + u * edge
+            let t = cross(p, edge) / denominator;
+            let u = cross(p, direction) / denominator;
+            (t >= 0.0 && (0.0..=1.0).contains(&u)).then_some(t)
+        })
+        .fold(0.0, f64::max)
+}
+
+/// The start and length of each run of samples in copper, wrapping around
+fn runs(ring: &[bool]) -> Vec<(usize, usize)> {
+    let Some(first_clear) = ring.iter().position(|&dark| !dark) else {
+        return Vec::new();
+    };
+    let mut runs = Vec::new();
+    let mut start = None;
+    for i in first_clear..first_clear + ring.len() {
+        match (ring[i % ring.len()], start) {
+            (true, None) => start = Some(i),
+            (false, Some(begin)) => {
+                runs.push((begin % ring.len(), i - begin));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(begin) = start {
+        runs.push((begin % ring.len(), first_clear + ring.len() - begin));
+    }
+    runs
+}
+
+fn bounds(polygon: &Polygon) -> (Point, Point) {
+    polygon.exterior.iter().fold(
+        (
+            Point {
+                x: f64::INFINITY,
+                y: f64::INFINITY,
+            },
+            Point {
+                x: f64::NEG_INFINITY,
+                y: f64::NEG_INFINITY,
+            },
+        ),
+        |(min, max), point| {
+            (
+                Point {
+                    x: min.x.min(point.x),
+                    y: min.y.min(point.y),
+                },
+                Point {
+                    x: max.x.max(point.x),
+                    y: max.y.max(point.y),
+                },
+            )
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    const HEADER: &str = indoc! {"
+        %FSLAX26Y26*%
+        %MOMM*%
+        %ADD10R,10X10*%
+        %ADD11C,4*%
+        %ADD12C,2*%
+        %ADD13C,0.3*%
+        %ADD14R,2X1*%
+        G01*
+        D10*
+        X0Y0D03*
+        %LPC*%
+        D11*
+        X0Y0D03*
+        %LPD*%
+    "};
+
+    fn thermals(body: &str) -> Vec<Thermal> {
+        let src = format!("{HEADER}{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().thermal_reliefs(0.001)
+    }
+
+    #[test]
+    fn test_spokes() {
+        let thermals = thermals(indoc! {"
+            D12*
+            X0Y0D03*
+            D13*
+            X0Y0D02*
+            X2500000Y0D01*
+            X0Y0D02*
+            X-1250000Y2165064D01*
+            X0Y0D02*
+            X-1250000Y-2165064D01*
+        "});
+        assert_eq!(thermals.len(), 1, "{thermals:?}");
+        let thermal = &thermals[0];
+        assert_eq!(thermal.spokes, 3);
+        assert_eq!(thermal.object, 2);
+        assert!((thermal.spoke_width - 0.3).abs() < 0.05, "{thermal:?}");
+        assert!((thermal.gap - 1.0).abs() < 0.03, "{thermal:?}");
+    }
+
+    #[test]
+    fn test_rectangular_pad() {
+        let thermals = thermals(indoc! {"
+            D14*
+            X0Y0D03*
+            D13*
+            X0Y0D02*
+            X2500000Y0D01*
+            X0Y0D02*
+            X-2500000Y0D01*
+        "});
+        assert_eq!(thermals.len(), 1, "{thermals:?}");
+        assert_eq!(thermals[0].spokes, 2);
+    }
+
+    #[test]
+    fn test_not_thermal() {
+        // a single spoke, a pad in the plane, and a pad isolated in the gap
+        let found = thermals(indoc! {"
+            D12*
+            X0Y0D03*
+            D13*
+            X0Y0D02*
+            X2500000Y0D01*
+            D12*
+            X4000000Y4000000D03*
+        "});
+        assert!(found.is_empty());
+        assert!(thermals("D12*\nX0Y0D03*\n").is_empty());
+    }
+
+    #[test]
+    fn test_thermal_primitive() {
+        // a relief cut by a thermal macro around a pad, off the flash point
+        let thermals = thermals(indoc! {"
+            %AMTHERMAL*7,0.5,0,2,1.4,0.3,0*%
+            %ADD15THERMAL*%
+            %LPC*%
+            D15*
+            X3000000Y0D03*
+            %LPD*%
+            D13*
+            X3500000Y0D03*
+        "});
+        assert_eq!(thermals.len(), 1, "{thermals:?}");
+        let thermal = &thermals[0];
+        assert_eq!(thermal.at, Point { x: 3.5, y: 0.0 });
+        assert_eq!(thermal.spokes, 4);
+        assert_eq!(thermal.object, 2);
+        assert!((thermal.spoke_width - 0.3).abs() < 1e-9);
+        assert!((thermal.gap - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_runs() {
+        assert_eq!(runs(&[true, true, false, true, false]), [(3, 1), (0, 2)]);
+        assert_eq!(runs(&[true, false, false, true]), [(3, 2)]);
+        assert!(runs(&[true, true]).is_empty());
+        assert!(runs(&[false, false]).is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+//! Thumbnails of layers
+//!
+//! One call renders one or more layers, stacked in the order given, into a
+//! small PNG or SVG image for file browsers and upload previews. The image
+//! is fitted to the objects of all layers, with the longer side `max_px`
+//! pixels long.
+
+use crate::image::Point;
+use crate::raster::{PixelFormat, Raster, RenderOptions};
+use crate::GerberLayer;
+
+/// Colors of stacked layers from the bottom, repeating
+const PALETTE: [[u8; 4]; 6] = [
+    [184, 115, 51, 255],
+    [0, 96, 192, 192],
+    [0, 160, 64, 192],
+    [192, 32, 32, 192],
+    [128, 64, 192, 192],
+    [96, 96, 96, 192],
+];
+
+const BACKGROUND: [u8; 4] = [255, 255, 255, 255];
+
+/// How closely curves are approximated, in millimeters, which is far below
+/// a pixel of any thumbnail of a real board
+const TOLERANCE: f64 = 0.01;
+
+/// Render a PNG thumbnail of layers stacked from the first to the last
+///
+/// Layers without any objects give a single background pixel.
+///
+/// ```
+/// use gerber::GerberLayer;
+/// use gerber::thumbnail::thumbnail;
+///
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,100X50*%\nD10*\nX0Y0D03*\nM02*\n";
+/// let layer = GerberLayer::parse(src).unwrap();
+/// let png = thumbnail([&layer], 64);
+/// assert_eq!(&png[1..4], b"PNG");
+/// // the width and height in the header
+/// assert_eq!(&png[16..24], [0, 0, 0, 64, 0, 0, 0, 32]);
+/// ```
+pub fn thumbnail<'a, 'b: 'a>(
+    layers: impl IntoIterator<Item = &'a GerberLayer<'b>>,
+    max_px: usize,
+) -> Vec<u8> {
+    let renderers: Vec<_> = layers
+        .into_iter()
+        .map(|layer| layer.renderer(TOLERANCE))
+        .collect();
+    let bounds = union(renderers.iter().filter_map(|renderer| renderer.bounds()));
+    let Some(fit) = Fit::new(bounds, max_px) else {
+        let raster = Raster {
+            width: 1,
+            height: 1,
+            format: PixelFormat::Rgba,
+            pixels: BACKGROUND.to_vec(),
+            origin: Point::default(),
+            pixels_per_mm: 1.0,
+        };
+        return raster.to_png();
+    };
+
+    let mut stack: Option<Raster> = None;
+    for (index, renderer) in renderers.iter().enumerate() {
+        let options = RenderOptions {
+            pixels_per_mm: fit.pixels_per_mm,
+            foreground: PALETTE[index % PALETTE.len()],
+            background: if index == 0 { BACKGROUND } else { [0; 4] },
+            ..Default::default()
+        };
+        let raster = renderer.render_tile(fit.min, fit.width, fit.height, &options);
+        match &mut stack {
+            Some(stack) => stack.composite(&raster),
+            None => stack = Some(raster),
+        }
+    }
+    stack.expect("layers with bounds").to_png()
+}
+
+/// Draw an SVG thumbnail of layers stacked from the first to the last
+///
+/// The SVG is `max_px` pixels along its longer side, with the layers'
+/// united [copper](crate::copper) as paths, so it stays sharp when scaled.
+///
+/// ```
+/// use gerber::GerberLayer;
+/// use gerber::thumbnail::thumbnail_svg;
+///
+/// let src = "%FSLAX26Y26*%\n%MOMM*%\n%ADD10R,100X50*%\nD10*\nX0Y0D03*\nM02*\n";
+/// let layer = GerberLayer::parse(src).unwrap();
+/// let svg = thumbnail_svg([&layer], 64);
+/// assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="64" height="32""#));
+/// ```
+pub fn thumbnail_svg<'a, 'b: 'a>(
+    layers: impl IntoIterator<Item = &'a GerberLayer<'b>>,
+    max_px: usize,
+) -> String {
+    let coppers: Vec<_> = layers
+        .into_iter()
+        .map(|layer| layer.copper(TOLERANCE))
+        .collect();
+    let bounds = union(coppers.iter().filter_map(|copper| copper.bounds()));
+    let Fit {
+        min,
+        max,
+        width,
+        height,
+        ..
+    } = Fit::new(bounds, max_px).unwrap_or(Fit {
+        min: Point::default(),
+        max: Point::default(),
+        width: 1,
+        height: 1,
+        pixels_per_mm: 1.0,
+    });
+    let [red, green, blue, _] = BACKGROUND;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"{} {} {} {}\">\n\
+         <rect x=\"{}\" y=\"{}\" width=\"100%\" height=\"100%\" \
+         fill=\"#{red:02x}{green:02x}{blue:02x}\"/>\n",
+        min.x,
+        -max.y,
+        max.x - min.x,
+        max.y - min.y,
+        min.x,
+        -max.y,
+    );
+    for (index, copper) in coppers.iter().enumerate() {
+        svg.push_str(&copper.svg_paths(PALETTE[index % PALETTE.len()]));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn union(bounds: impl Iterator<Item = (Point, Point)>) -> Option<(Point, Point)> {
+    bounds.reduce(|(min, max), (other_min, other_max)| {
+        (
+            Point {
+                x: min.x.min(other_min.x),
+                y: min.y.min(other_min.y),
+            },
+            Point {
+                x: max.x.max(other_max.x),
+                y: max.y.max(other_max.y),
+            },
+        )
+    })
+}
+
+/// An image of some bounds whose longer side is `max_px` long
+struct Fit {
+    min: Point,
+    max: Point,
+    width: usize,
+    height: usize,
+    pixels_per_mm: f64,
+}
+
+impl Fit {
+    fn new(bounds: Option<(Point, Point)>, max_px: usize) -> Option<Self> {
+        let (min, max) = bounds?;
+        let longer = (max.x - min.x).max(max.y - min.y);
+        if longer <= 0.0 || max_px == 0 {
+            return None;
+        }
+        let pixels_per_mm = max_px as f64 / longer;
+        let pixels = |length: f64| ((length * pixels_per_mm).round() as usize).clamp(1, max_px);
+        Some(Fit {
+            min,
+            max,
+            width: pixels(max.x - min.x),
+            height: pixels(max.y - min.y),
+            pixels_per_mm,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(body: &str) -> GerberLayer<'static> {
+        let src = format!("%FSLAX26Y26*%\n%MOMM*%\n{body}M02*\n");
+        GerberLayer::parse(&src).unwrap().into_owned()
+    }
+
+    #[test]
+    fn test_stacked() {
+        let bottom = layer("%ADD10R,10X10*%\nD10*\nX0Y0D03*\n");
+        let top = layer("%ADD10R,2X2*%\nD10*\nX20000000Y0D03*\n");
+        let png = thumbnail([&bottom, &top], 100);
+        // the layers together are 26 × 10 mm
+        assert_eq!(&png[16..24], [0, 0, 0, 100, 0, 0, 0, 38]);
+
+        let svg = thumbnail_svg([&bottom, &top], 100);
+        assert_eq!(svg.matches("<path").count(), 2);
+        assert!(svg.contains(r#"viewBox="-5 -5 26 10""#));
+    }
+
+    #[test]
+    fn test_empty() {
+        let png = thumbnail([&layer("")], 100);
+        assert_eq!(&png[16..24], [0, 0, 0, 1, 0, 0, 0, 1]);
+        assert!(thumbnail_svg(std::iter::empty(), 100).contains(r#"width="1" height="1""#));
+    }
+}
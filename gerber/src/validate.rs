@@ -0,0 +1,971 @@
+//! Semantic validation
+//!
+//! The parser only checks syntax. The checks in this module apply the rules
+//! of the specification which span several commands, reporting violations
+//! as [Diagnostic]s instead of rejecting the file.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::aperture::ApertureTemplate;
+use crate::command::Command::{self, *};
+use crate::data::ApertureId;
+use crate::image::{self, ArcGeometry, Image, Segment, Shape};
+use crate::lint::{Lint, LINTS};
+use crate::macros;
+
+/// How much the start and end radius of an arc may differ, in millimeters
+///
+/// Coarse coordinate formats round the end points by a few micrometers, so
+/// this is looser than the resolution of the file.
+pub const ARC_TOLERANCE: f64 = 0.01;
+
+/// How serious a [Diagnostic] is
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Severity {
+    /// The file is valid but likely not what the author intended
+    Warning,
+
+    /// The file violates the specification
+    Error,
+}
+
+/// The rule violated by a [Diagnostic]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DiagnosticKind {
+    /// `%FS` is never set
+    MissingFormatSpecification,
+
+    /// `%FS` is set more than once
+    DuplicateFormatSpecification,
+
+    /// `%FS` is set after the first operation
+    LateFormatSpecification,
+
+    /// `%MO` is never set
+    MissingMode,
+
+    /// `%MO` is set more than once
+    DuplicateMode,
+
+    /// `%MO` is set after the first operation
+    LateMode,
+
+    /// An arc is plotted before `G75*`
+    MissingArcInit,
+
+    /// `D01*` is issued before `G01*`, `G02*` or `G03*`
+    MissingInterpolationMode,
+
+    /// The start and end points of an arc are at different distances from
+    /// its center, by more than [ARC_TOLERANCE]
+    InconsistentArc,
+
+    /// More than one center fits a single quadrant (`G74*`) arc, whose
+    /// offsets are unsigned
+    AmbiguousArcCenter,
+
+    /// Single quadrant mode (`G74*`) is set, which is deprecated
+    SingleQuadrantMode,
+
+    /// `Dnn*` selects an aperture which has not been defined by `%AD`
+    UndefinedAperture(ApertureId),
+
+    /// An object is created before any aperture is selected
+    MissingCurrentAperture,
+
+    /// An aperture is defined but never selected
+    UnusedAperture(ApertureId),
+
+    /// An aperture is defined again, shadowing the earlier definition
+    RedefinedAperture(ApertureId),
+
+    /// A zero-size circle is used to draw, which creates no image
+    ZeroSizeDraw(ApertureId),
+
+    /// A linear `D01*` ends at its start point, which some tools mean as a
+    /// flash, see [ZeroLengthDraws](crate::image::ZeroLengthDraws)
+    ZeroLengthDraw,
+
+    /// A rectangle, obround, polygon or macro aperture without area is
+    /// defined
+    DegenerateAperture(ApertureId),
+
+    /// A polygon with other than 3 to 12 vertices, or a macro aperture
+    /// which is not defined or can't be evaluated, is defined
+    InvalidAperture(ApertureId),
+
+    /// A coordinate has more digits than `%FS` allows
+    CoordinateOutOfRange(i64),
+
+    /// Every coordinate is within 0.01 units of the origin
+    ///
+    /// Such coordinates rely entirely on the omitted leading digits, which
+    /// usually means the file was written for a different format or unit.
+    SmallCoordinates,
+
+    /// `%FS` uses deprecated options or fewer than six decimals, and the
+    /// coordinates were converted when parsing, see
+    /// [ParseOptions::legacy_format](crate::ParseOptions::legacy_format)
+    LegacyFormatSpecification,
+}
+
+impl DiagnosticKind {
+    /// The id of the lint checking this rule, see [lint](crate::lint)
+    fn lint_id(&self) -> &'static str {
+        match self {
+            Self::MissingFormatSpecification => "missing_format_specification",
+            Self::DuplicateFormatSpecification => "duplicate_format_specification",
+            Self::LateFormatSpecification => "late_format_specification",
+            Self::MissingMode => "missing_mode",
+            Self::DuplicateMode => "duplicate_mode",
+            Self::LateMode => "late_mode",
+            Self::MissingArcInit => "missing_arc_init",
+            Self::MissingInterpolationMode => "missing_interpolation_mode",
+            Self::InconsistentArc => "inconsistent_arc",
+            Self::AmbiguousArcCenter => "ambiguous_arc_center",
+            Self::SingleQuadrantMode => "single_quadrant_mode",
+            Self::UndefinedAperture(_) => "undefined_aperture",
+            Self::MissingCurrentAperture => "missing_current_aperture",
+            Self::UnusedAperture(_) => "unused_aperture",
+            Self::RedefinedAperture(_) => "redefined_aperture",
+            Self::ZeroSizeDraw(_) => "zero_size_draw",
+            Self::ZeroLengthDraw => "zero_length_draw",
+            Self::DegenerateAperture(_) => "degenerate_aperture",
+            Self::InvalidAperture(_) => "invalid_aperture",
+            Self::CoordinateOutOfRange(_) => "coordinate_out_of_range",
+            Self::SmallCoordinates => "small_coordinates",
+            Self::LegacyFormatSpecification => "legacy_format_specification",
+        }
+    }
+
+    /// The lint checking this rule, see [lint](crate::lint)
+    pub fn lint(&self) -> &'static Lint {
+        let id = self.lint_id();
+        LINTS
+            .iter()
+            .find(|lint| lint.id == id)
+            .expect("every rule has a lint")
+    }
+
+    /// The default severity of violating this rule
+    pub fn severity(&self) -> Severity {
+        self.lint().severity
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingFormatSpecification => write!(f, "format specification (FS) is missing"),
+            Self::DuplicateFormatSpecification => {
+                write!(f, "format specification (FS) must only be set once")
+            }
+            Self::LateFormatSpecification => write!(
+                f,
+                "format specification (FS) must be set before the first operation"
+            ),
+            Self::MissingMode => write!(f, "unit (MO) is missing"),
+            Self::DuplicateMode => write!(f, "unit (MO) must only be set once"),
+            Self::LateMode => write!(f, "unit (MO) must be set before the first operation"),
+            Self::MissingArcInit => write!(f, "arc plotted before G75"),
+            Self::MissingInterpolationMode => {
+                write!(f, "D01 issued before the interpolation mode was set")
+            }
+            Self::InconsistentArc => write!(
+                f,
+                "arc start and end points are at different distances from its center"
+            ),
+            Self::AmbiguousArcCenter => {
+                write!(f, "several centers fit the single quadrant arc")
+            }
+            Self::SingleQuadrantMode => write!(f, "single quadrant mode (G74) is deprecated"),
+            Self::UndefinedAperture(id) => write!(f, "aperture {id} is not defined"),
+            Self::MissingCurrentAperture => write!(f, "object created without a current aperture"),
+            Self::UnusedAperture(id) => write!(f, "aperture {id} is never used"),
+            Self::RedefinedAperture(id) => {
+                write!(
+                    f,
+                    "aperture {id} is redefined, shadowing its earlier definition"
+                )
+            }
+            Self::ZeroSizeDraw(id) => write!(f, "zero-size aperture {id} used to draw"),
+            Self::ZeroLengthDraw => write!(f, "draw ends at its start point"),
+            Self::DegenerateAperture(id) => write!(f, "aperture {id} has no area"),
+            Self::InvalidAperture(id) => write!(f, "aperture {id} is invalid"),
+            Self::CoordinateOutOfRange(value) => {
+                write!(
+                    f,
+                    "coordinate {value} exceeds the format specification (FS)"
+                )
+            }
+            Self::SmallCoordinates => write!(
+                f,
+                "all coordinates are within 0.01 units of the origin, check FS and MO"
+            ),
+            Self::LegacyFormatSpecification => write!(
+                f,
+                "legacy format specification (FS) converted, re-export the file with current software"
+            ),
+        }
+    }
+}
+
+/// A violation of the specification found by [validate]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+
+    /// Index of the offending command, if the problem is with a specific one
+    pub command: Option<usize>,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(kind: DiagnosticKind, command: Option<usize>) -> Self {
+        Self {
+            severity: kind.severity(),
+            kind,
+            command,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.command {
+            Some(index) => write!(f, "{severity}: {} (command {index})", self.kind),
+            None => write!(f, "{severity}: {}", self.kind),
+        }
+    }
+}
+
+/// Check a list of commands against the semantic rules of the specification
+pub fn validate(commands: &[Command]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    check_header(commands, &mut diagnostics);
+    check_graphics_state(commands, &mut diagnostics);
+    check_apertures(commands, &mut diagnostics);
+    check_aperture_sizes(commands, &mut diagnostics);
+    check_coordinates(commands, &mut diagnostics);
+    let (image, ambiguous_arcs) = image::evaluate_checked(commands);
+    check_arcs(&image, ambiguous_arcs, &mut diagnostics);
+    check_draws(&image, &mut diagnostics);
+    diagnostics.sort_by_key(|d| d.command);
+    diagnostics
+}
+
+/// FS and MO must each appear exactly once, before the first operation
+fn check_header(commands: &[Command], diagnostics: &mut Vec<Diagnostic>) {
+    let first_operation = commands
+        .iter()
+        .position(|c| matches!(c, Plot(..) | Move(..) | Flash(..)));
+
+    let rules = [
+        (
+            (|c: &Command| matches!(c, FormatSpecification(..))) as fn(&Command) -> bool,
+            DiagnosticKind::MissingFormatSpecification,
+            DiagnosticKind::DuplicateFormatSpecification,
+            DiagnosticKind::LateFormatSpecification,
+        ),
+        (
+            |c: &Command| matches!(c, Mode(_)),
+            DiagnosticKind::MissingMode,
+            DiagnosticKind::DuplicateMode,
+            DiagnosticKind::LateMode,
+        ),
+    ];
+
+    for (matches, missing, duplicate, late) in rules {
+        let mut seen = false;
+        for (index, _) in commands.iter().enumerate().filter(|(_, c)| matches(c)) {
+            if seen {
+                diagnostics.push(Diagnostic::new(duplicate.clone(), Some(index)));
+            } else if first_operation.is_some_and(|first| index > first) {
+                diagnostics.push(Diagnostic::new(late.clone(), Some(index)));
+            }
+            seen = true;
+        }
+        if !seen {
+            diagnostics.push(Diagnostic::new(missing, None));
+        }
+    }
+}
+
+/// Plotting requires an interpolation mode, and arcs also require `G75*`
+///
+/// Only the first violation of each rule is reported, as a missing mode
+/// usually affects every following plot. `G74*` does not initialize arcs,
+/// and is reported once as deprecated.
+fn check_graphics_state(commands: &[Command], diagnostics: &mut Vec<Diagnostic>) {
+    let mut circular = None;
+    let mut arc_init = false;
+    let mut reported_mode = false;
+    let mut reported_arc_init = false;
+    let mut reported_single_quadrant = false;
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            SetLinear => circular = Some(false),
+            SetCWCircular | SetCCWCircular => circular = Some(true),
+            ArcInit => arc_init = true,
+            SingleQuadrant if !reported_single_quadrant => {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::SingleQuadrantMode,
+                    Some(index),
+                ));
+                reported_single_quadrant = true;
+            }
+            Plot(..) => match circular {
+                None if !reported_mode => {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticKind::MissingInterpolationMode,
+                        Some(index),
+                    ));
+                    reported_mode = true;
+                }
+                Some(true) if !arc_init && !reported_arc_init => {
+                    diagnostics.push(Diagnostic::new(DiagnosticKind::MissingArcInit, Some(index)));
+                    reported_arc_init = true;
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Apertures must be defined before they are selected, and selected before
+/// they are used to create an object
+///
+/// Undefined apertures are reported at their first selection, and unused
+/// or redefined apertures at their definition.
+fn check_apertures(commands: &[Command], diagnostics: &mut Vec<Diagnostic>) {
+    let mut defined = HashSet::new();
+    let mut used = HashSet::new();
+    let mut definitions = Vec::new();
+    let mut reported = HashSet::new();
+    let mut selected = false;
+    let mut region = false;
+    let mut reported_missing = false;
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            ApertureDefine(id, _) => {
+                if !defined.insert(*id) {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticKind::RedefinedAperture(*id),
+                        Some(index),
+                    ));
+                }
+                definitions.push((*id, index));
+            }
+            SetCurrentAperture(id) => {
+                selected = true;
+                used.insert(*id);
+                if !defined.contains(id) && reported.insert(*id) {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticKind::UndefinedAperture(*id),
+                        Some(index),
+                    ));
+                }
+            }
+            StartRegion => region = true,
+            EndRegion => region = false,
+            // contours in a region are not drawn with the current aperture
+            Flash(..) | Plot(..) if !selected && !region && !reported_missing => {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::MissingCurrentAperture,
+                    Some(index),
+                ));
+                reported_missing = true;
+            }
+            _ => (),
+        }
+    }
+    for (id, index) in definitions {
+        // inserting reports each unused aperture once
+        if used.insert(id) {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::UnusedAperture(id),
+                Some(index),
+            ));
+        }
+    }
+}
+
+/// Apertures without area usually indicate a generator bug
+///
+/// Zero-size circles are allowed, but draws with one create no image so are
+/// reported at the first draw with each such aperture. Macro apertures are
+/// evaluated with the macro defined before them.
+fn check_aperture_sizes(commands: &[Command], diagnostics: &mut Vec<Diagnostic>) {
+    let mut templates = HashMap::new();
+    let mut macros = HashMap::new();
+    let mut reported = HashSet::new();
+    let mut current = None;
+    let mut region = false;
+    for (index, command) in commands.iter().enumerate() {
+        match command {
+            ApertureMacro(name, statements) => {
+                let body = macros::ApertureMacro {
+                    name: name.to_string(),
+                    statements: statements.clone(),
+                };
+                macros.insert(name.to_string(), body);
+            }
+            ApertureDefine(id, template) => {
+                let degenerate = match template {
+                    ApertureTemplate::Circle { .. } => Ok(false),
+                    ApertureTemplate::Polygon { .. } if template.standard().is_err() => Err(()),
+                    ApertureTemplate::Macro { name, parameters } => macros
+                        .get(&**name)
+                        .ok_or(())
+                        .and_then(|body| body.is_degenerate(parameters).map_err(|_| ())),
+                    _ => Ok(template.is_degenerate()),
+                };
+                let kind = match degenerate {
+                    Ok(false) => None,
+                    Ok(true) => Some(DiagnosticKind::DegenerateAperture(*id)),
+                    Err(()) => Some(DiagnosticKind::InvalidAperture(*id)),
+                };
+                if let Some(kind) = kind {
+                    diagnostics.push(Diagnostic::new(kind, Some(index)));
+                }
+                templates.insert(*id, template);
+            }
+            SetCurrentAperture(id) => current = Some(*id),
+            StartRegion => region = true,
+            EndRegion => region = false,
+            Plot(..) if !region => {
+                let Some(id) = current else { continue };
+                let zero_circle = templates.get(&id).is_some_and(|t| {
+                    matches!(t, ApertureTemplate::Circle { .. }) && t.is_degenerate()
+                });
+                if zero_circle && reported.insert(id) {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticKind::ZeroSizeDraw(id),
+                        Some(index),
+                    ));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Coordinates must fit in the digits declared by `%FS`
+fn check_coordinates(commands: &[Command], diagnostics: &mut Vec<Diagnostic>) {
+    let mut format = None;
+    let mut first_coordinate = None;
+    let mut small = true;
+    for (index, command) in commands.iter().enumerate() {
+        let (coordinates, offset) = match command {
+            FormatSpecification(x, y) => {
+                format = Some((*x, *y));
+                continue;
+            }
+            Plot(coordinates, offset) => (coordinates, *offset),
+            Move(coordinates) | Flash(coordinates) => (coordinates, None),
+            _ => continue,
+        };
+        // a missing FS is reported by check_header
+        let Some((x_format, y_format)) = format else {
+            continue;
+        };
+
+        let values = [
+            (coordinates.x, x_format),
+            (coordinates.y, y_format),
+            (offset.map(|o| o.i), x_format),
+            (offset.map(|o| o.j), y_format),
+        ];
+        let out_of_range = values
+            .iter()
+            .filter_map(|(value, format)| value.map(|v| (v, format)))
+            .find(|(v, format)| v.abs() > format.max_coordinate());
+        if let Some((value, _)) = out_of_range {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::CoordinateOutOfRange(value),
+                Some(index),
+            ));
+        }
+
+        for (value, format) in &values[..2] {
+            let Some(value) = value.filter(|v| *v != 0) else {
+                continue;
+            };
+            first_coordinate.get_or_insert(index);
+            if value.abs() >= 10i64.pow(format.decimal.saturating_sub(2) as u32) {
+                small = false;
+            }
+        }
+    }
+    if let (true, Some(index)) = (small, first_coordinate) {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::SmallCoordinates,
+            Some(index),
+        ));
+    }
+}
+
+/// The end points of every arc must lie on the same circle, and single
+/// quadrant arcs must have one center which fits
+///
+/// Arcs are reported at their D01, and inconsistent arcs in a region
+/// contour at the G36 starting the region.
+fn check_arcs(image: &Image, ambiguous: Vec<usize>, diagnostics: &mut Vec<Diagnostic>) {
+    for index in ambiguous {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::AmbiguousArcCenter,
+            Some(index),
+        ));
+    }
+    for object in &image.objects {
+        let consistent = match &object.shape {
+            Shape::Arc { .. } => object
+                .shape
+                .arc_geometry()
+                .is_none_or(|arc| arc.is_consistent(ARC_TOLERANCE)),
+            Shape::Region { contours } => contours.iter().all(|contour| {
+                let mut from = contour.start;
+                contour.segments.iter().all(|segment| match *segment {
+                    Segment::Line { end } => {
+                        from = end;
+                        true
+                    }
+                    Segment::Arc {
+                        end,
+                        center,
+                        direction,
+                    } => {
+                        let arc = ArcGeometry::new(from, end, center, direction);
+                        from = end;
+                        arc.is_consistent(ARC_TOLERANCE)
+                    }
+                })
+            }),
+            _ => true,
+        };
+        if !consistent {
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::InconsistentArc,
+                Some(object.source.commands.start),
+            ));
+        }
+    }
+}
+
+/// Draws should have a length, as their meaning otherwise varies between
+/// tools
+fn check_draws(image: &Image, diagnostics: &mut Vec<Diagnostic>) {
+    for object in &image.objects {
+        if let Shape::Draw { start, end, .. } = object.shape {
+            if start == end {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::ZeroLengthDraw,
+                    Some(object.source.commands.start),
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::into_aperture_id;
+    use crate::GerberLayer;
+    use indoc::indoc;
+
+    fn kinds(src: &str) -> Vec<DiagnosticKind> {
+        GerberLayer::parse(src)
+            .unwrap()
+            .validate()
+            .into_iter()
+            .map(|d| d.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_valid() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X0Y0D03*
+            G01*
+            X100000Y100000D01*
+            G75*
+            G03*
+            X200000Y200000I100000J0D01*
+            M02*
+        "};
+        assert!(kinds(src).is_empty());
+    }
+
+    #[test]
+    fn test_missing_header() {
+        assert_eq!(
+            kinds("M02*"),
+            vec![
+                DiagnosticKind::MissingFormatSpecification,
+                DiagnosticKind::MissingMode
+            ]
+        );
+    }
+
+    #[test]
+    fn test_graphics_state() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X0Y0D02*
+            X100000Y100000D01*
+            X200000Y200000D01*
+            G02*
+            X300000Y300000I100000J0D01*
+            G75*
+            X400000Y400000I100000J0D01*
+            M02*
+        "};
+        let diagnostics = GerberLayer::parse(src).unwrap().validate();
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(DiagnosticKind::MissingInterpolationMode, Some(5)),
+                Diagnostic::new(DiagnosticKind::MissingArcInit, Some(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arcs() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            G75*
+            G03*
+            X1000000Y0D02*
+            X0Y1000000I-1000000J0D01*
+            X-1000000Y0I0J-995000D01*
+            X0Y-1000000I1000000J50000D01*
+            G36*
+            X1000000Y0D02*
+            X0Y1200000I-1000000J0D01*
+            G01*
+            X1000000Y0D01*
+            G37*
+            M02*
+        "};
+        let diagnostics = GerberLayer::parse(src).unwrap().validate();
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(DiagnosticKind::InconsistentArc, Some(9)),
+                Diagnostic::new(DiagnosticKind::InconsistentArc, Some(10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zero_length_draws() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            G01*
+            X0Y0D02*
+            X0Y0D01*
+            X1000000Y0D01*
+            M02*
+        "};
+        assert_eq!(kinds(src), vec![DiagnosticKind::ZeroLengthDraw]);
+    }
+
+    #[test]
+    fn test_single_quadrant_arcs() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            G74*
+            G03*
+            X1000000Y0D02*
+            X0Y1000000I1000000J0D01*
+            X0Y0D02*
+            X1000Y0I500J5000D01*
+            M02*
+        "};
+        let diagnostics = GerberLayer::parse(src).unwrap().validate();
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(DiagnosticKind::SingleQuadrantMode, Some(4)),
+                Diagnostic::new(DiagnosticKind::MissingArcInit, Some(7)),
+                Diagnostic::new(DiagnosticKind::AmbiguousArcCenter, Some(9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_quadrant_mode() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            G74*
+            G75*
+            G03*
+            X1000000Y0D02*
+            X0Y1000000I-1000000J0D01*
+            G74*
+            M02*
+        "};
+        let diagnostics = GerberLayer::parse(src).unwrap().validate();
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::new(DiagnosticKind::SingleQuadrantMode, Some(4))]
+        );
+        assert_eq!(
+            DiagnosticKind::SingleQuadrantMode.severity(),
+            Severity::Warning
+        );
+    }
+
+    #[test]
+    fn test_apertures() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            X0Y0D03*
+            D11*
+            X0Y0D03*
+            D11*
+            D10*
+            X0Y0D03*
+            %ADD11C,0.1*%
+            M02*
+        "};
+        let diagnostics = GerberLayer::parse(src).unwrap().validate();
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(DiagnosticKind::MissingCurrentAperture, Some(3)),
+                Diagnostic::new(
+                    DiagnosticKind::UndefinedAperture(into_aperture_id(11)),
+                    Some(4)
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "error: aperture D11 is not defined (command 4)"
+        );
+    }
+
+    #[test]
+    fn test_unused_and_redefined_apertures() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            %ADD11C,0.2*%
+            %ADD12C,0.3*%
+            %ADD10C,0.4*%
+            D10*
+            X0Y0D03*
+            D12*
+            X0Y0D03*
+            M02*
+        "};
+        let diagnostics = GerberLayer::parse(src).unwrap().validate();
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    DiagnosticKind::UnusedAperture(into_aperture_id(11)),
+                    Some(3)
+                ),
+                Diagnostic::new(
+                    DiagnosticKind::RedefinedAperture(into_aperture_id(10)),
+                    Some(5)
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "warning: aperture D10 is redefined, shadowing its earlier definition (command 5)"
+        );
+    }
+
+    #[test]
+    fn test_aperture_sizes() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0*%
+            %ADD11R,1X0*%
+            %ADD12O,0.5X0.2*%
+            D10*
+            X0Y0D03*
+            G01*
+            X100000Y100000D01*
+            X200000Y200000D01*
+            D11*
+            X0Y0D03*
+            D12*
+            X0Y0D03*
+            M02*
+        "};
+        let diagnostics = GerberLayer::parse(src).unwrap().validate();
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(
+                    DiagnosticKind::DegenerateAperture(into_aperture_id(11)),
+                    Some(3)
+                ),
+                Diagnostic::new(DiagnosticKind::ZeroSizeDraw(into_aperture_id(10)), Some(8)),
+            ]
+        );
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_invalid_apertures() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %AMEmpty*0 nothing*%
+            %AMLine*20,1,$1,0,0,1,0,0*%
+            %AMBad*5,1,$1,0,0,1,0*%
+            %ADD10Empty*%
+            %ADD11Line,0*%
+            %ADD12Line,0.1*%
+            %ADD13Bad,2.5*%
+            %ADD14Undefined*%
+            D10*
+            X0Y0D03*
+            D11*
+            D12*
+            D13*
+            D14*
+            X0Y0D03*
+            M02*
+        "};
+        let layer = GerberLayer::parse(src).unwrap();
+        let kinds = |commands: &[Command]| {
+            let diagnostics = validate(commands).into_iter();
+            diagnostics.map(|d| (d.kind, d.command)).collect::<Vec<_>>()
+        };
+        let id = into_aperture_id;
+        assert_eq!(
+            kinds(layer.commands()),
+            [
+                (DiagnosticKind::DegenerateAperture(id(10)), Some(5)),
+                (DiagnosticKind::DegenerateAperture(id(11)), Some(6)),
+                (DiagnosticKind::InvalidAperture(id(13)), Some(8)),
+                (DiagnosticKind::InvalidAperture(id(14)), Some(9)),
+            ]
+        );
+        assert_eq!(
+            DiagnosticKind::InvalidAperture(id(13)).lint().severity,
+            Severity::Error
+        );
+
+        // polygons with too few vertices can only be built by hand
+        let mut commands = layer.commands().to_vec();
+        for vertices in [0.0, 2.5, -3.0] {
+            commands[7] = ApertureDefine(
+                id(12),
+                ApertureTemplate::Polygon {
+                    diameter: 1.0,
+                    vertices,
+                    rotation: None,
+                    hole: None,
+                },
+            );
+            assert!(kinds(&commands).contains(&(DiagnosticKind::InvalidAperture(id(12)), Some(7))));
+        }
+    }
+
+    #[test]
+    fn test_coordinates() {
+        let src = indoc! {"
+            %FSLAX16Y16*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X9999999Y-9999999D03*
+            X10000000Y0D03*
+            G01*
+            X0Y0D02*
+            X1000Y0I0J-10000000D01*
+            M02*
+        "};
+        assert_eq!(
+            GerberLayer::parse(src).unwrap().validate(),
+            vec![
+                Diagnostic::new(DiagnosticKind::CoordinateOutOfRange(10000000), Some(5)),
+                Diagnostic::new(DiagnosticKind::CoordinateOutOfRange(-10000000), Some(8)),
+            ]
+        );
+
+        let small = indoc! {"
+            %FSLAX26Y26*%
+            %MOMM*%
+            %ADD10C,0.1*%
+            D10*
+            X0Y0D03*
+            X2500Y5D03*
+            X-9999Y0D03*
+            M02*
+        "};
+        assert_eq!(
+            GerberLayer::parse(small).unwrap().validate(),
+            vec![Diagnostic::new(DiagnosticKind::SmallCoordinates, Some(5))]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_and_late_header() {
+        let src = indoc! {"
+            %FSLAX26Y26*%
+            %ADD10C,0.1*%
+            D10*
+            X0Y0D03*
+            %MOMM*%
+            %FSLAX26Y26*%
+            %MOIN*%
+            M02*
+        "};
+        let diagnostics = GerberLayer::parse(src).unwrap().validate();
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic::new(DiagnosticKind::LateMode, Some(4)),
+                Diagnostic::new(DiagnosticKind::DuplicateFormatSpecification, Some(5)),
+                Diagnostic::new(DiagnosticKind::DuplicateMode, Some(6)),
+            ]
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: unit (MO) must be set before the first operation (command 4)"
+        );
+    }
+}
@@ -0,0 +1,512 @@
+//! Streaming Gerber output
+//!
+//! [GerberWriter] writes commands as they are produced, so a generator of
+//! millions of flashes never holds the whole file in memory. Commands are
+//! buffered and written one per line as [Command] displays them.
+//!
+//! Regions are written through a [RegionWriter], which only accepts
+//! closed contours made of lines and arcs, so a region statement can't be
+//! left open, contain anything but its contours or start a contour with
+//! anything but a move.
+
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+
+use crate::command::Command;
+use crate::data::{ApertureId, Coordinates, InterpolationMode, Offset};
+use crate::template::MacroTemplate;
+use crate::GerberLayer;
+
+/// Writes commands to any [Write], with internal buffering
+///
+/// The writer doesn't check the commands make a valid file: the caller
+/// writes the format and unit first and [EndOfFile](Command::EndOfFile)
+/// last. Dropping the writer flushes it but discards any error, so call
+/// [finish](GerberWriter::finish) to know the file was written.
+///
+/// ```
+/// use gerber::command::Command;
+/// use gerber::data::{Coordinates, CoordinateFormat, Unit};
+/// use gerber::writer::GerberWriter;
+///
+/// let format = CoordinateFormat { integer: 2, decimal: 6 };
+/// let mut writer = GerberWriter::new(Vec::new());
+/// writer.write_command(&Command::FormatSpecification(format, format))?;
+/// writer.write_command(&Command::Mode(Unit::Millimeters))?;
+/// for x in 0..3 {
+///     let at = Coordinates { x: Some(x * 1_000_000), y: Some(0) };
+///     writer.write_command(&Command::Flash(at))?;
+/// }
+/// writer.write_command(&Command::EndOfFile)?;
+/// let gerber = writer.finish()?;
+/// assert_eq!(
+///     String::from_utf8(gerber).unwrap(),
+///     "%FSLAX26Y26*%\n%MOMM*%\nX0Y0D03*\nX1000000Y0D03*\nX2000000Y0D03*\nM02*\n"
+/// );
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct GerberWriter<W: Write> {
+    out: BufWriter<W>,
+
+    /// The content of the macros written by
+    /// [define_macro_aperture](GerberWriter::define_macro_aperture), by
+    /// name
+    macros: HashMap<String, Vec<String>>,
+}
+
+impl<W: Write> GerberWriter<W> {
+    /// Write to `out` through a buffer of the default capacity
+    pub fn new(out: W) -> Self {
+        Self {
+            out: BufWriter::new(out),
+            macros: HashMap::new(),
+        }
+    }
+
+    /// Write to `out` through a buffer of `capacity` bytes
+    pub fn with_capacity(capacity: usize, out: W) -> Self {
+        Self {
+            out: BufWriter::with_capacity(capacity, out),
+            macros: HashMap::new(),
+        }
+    }
+
+    /// Write a command on its own line
+    pub fn write_command(&mut self, command: &Command<'_>) -> io::Result<()> {
+        writeln!(self.out, "{command}")
+    }
+
+    /// Define aperture `id` with a custom shape
+    ///
+    /// The macro of the shape is written before its first aperture.
+    /// Returns an [InvalidInput](io::ErrorKind::InvalidInput) error if a
+    /// macro of the same name was written with different content.
+    ///
+    /// ```
+    /// use gerber::data::ApertureId;
+    /// use gerber::template::RoundedRectangle;
+    /// use gerber::writer::GerberWriter;
+    ///
+    /// let mut writer = GerberWriter::new(Vec::new());
+    /// for (id, width) in [(10, 1.0), (11, 2.0)] {
+    ///     let pad = RoundedRectangle { width, height: 0.5, radius: 0.1 };
+    ///     writer.define_macro_aperture(ApertureId::new(id).unwrap(), &pad)?;
+    /// }
+    /// let gerber = String::from_utf8(writer.finish()?).unwrap();
+    /// assert_eq!(gerber.matches("%AMRoundedRectangle*").count(), 1);
+    /// assert!(gerber.ends_with(
+    ///     "%ADD10RoundedRectangle,1X0.5X0.1*%\n%ADD11RoundedRectangle,2X0.5X0.1*%\n"
+    /// ));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn define_macro_aperture(
+        &mut self,
+        id: ApertureId,
+        template: &(impl MacroTemplate + ?Sized),
+    ) -> io::Result<()> {
+        let content = template.content();
+        match self.macros.get(template.name()) {
+            Some(written) if *written != content => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("macro {} is already defined differently", template.name()),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                let definition = template.definition().map_err(|error| {
+                    io::Error::new(io::ErrorKind::InvalidInput, error.to_string())
+                })?;
+                self.write_command(&definition)?;
+                self.macros.insert(template.name().to_string(), content);
+            }
+        }
+        self.write_command(&Command::ApertureDefine(id, template.aperture()))
+    }
+
+    /// Write the buffered commands to the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Flush the buffer and return the underlying writer
+    pub fn finish(self) -> io::Result<W> {
+        self.out
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)
+    }
+
+    /// Start a region statement, writing `G36`
+    ///
+    /// The region borrows the writer until it ends, so nothing but its
+    /// contours, such as an aperture change, can be written inside it. The
+    /// polarity and other transformations in effect apply to the region.
+    ///
+    /// ```
+    /// use gerber::data::{InterpolationMode, Offset};
+    /// use gerber::writer::GerberWriter;
+    ///
+    /// let mut writer = GerberWriter::new(Vec::new());
+    /// let mut region = writer.region()?;
+    /// let mut contour = region.contour((0, 0))?;
+    /// contour.line_to((2000, 0))?;
+    /// contour.arc_to((2000, 2000), Offset { i: 0, j: 1000 }, InterpolationMode::CounterClockwise)?;
+    /// contour.close()?;
+    /// region.end()?;
+    /// assert_eq!(
+    ///     String::from_utf8(writer.finish()?).unwrap(),
+    ///     "G36*\nX0Y0D02*\nG01*\nX2000Y0D01*\nG03*\nX2000Y2000I0J1000D01*\n\
+    ///      G01*\nX0Y0D01*\nG37*\n"
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn region(&mut self) -> io::Result<RegionWriter<'_, W>> {
+        self.write_command(&Command::StartRegion)?;
+        Ok(RegionWriter {
+            writer: self,
+            mode: None,
+            ended: false,
+        })
+    }
+}
+
+/// A region statement being written, see [GerberWriter::region]
+///
+/// Dropping the region ends it but discards any error, so call
+/// [end](RegionWriter::end) to know it was written.
+#[derive(Debug)]
+pub struct RegionWriter<'w, W: Write> {
+    writer: &'w mut GerberWriter<W>,
+
+    /// The interpolation mode last written in the region
+    mode: Option<InterpolationMode>,
+
+    ended: bool,
+}
+
+impl<W: Write> RegionWriter<'_, W> {
+    /// Start a contour at `start`, in the coordinate format of the file
+    ///
+    /// The contour borrows the region until it is closed, so contours
+    /// can't be interleaved.
+    pub fn contour(&mut self, start: (i64, i64)) -> io::Result<ContourWriter<'_, W>> {
+        self.writer
+            .write_command(&Command::Move(coordinates(start)))?;
+        Ok(ContourWriter {
+            writer: self.writer,
+            mode: &mut self.mode,
+            start,
+            current: start,
+            closed: false,
+        })
+    }
+
+    /// End the region statement, writing `G37`
+    pub fn end(mut self) -> io::Result<()> {
+        self.ended = true;
+        self.writer.write_command(&Command::EndRegion)
+    }
+}
+
+impl<W: Write> Drop for RegionWriter<'_, W> {
+    fn drop(&mut self) {
+        if !self.ended {
+            let _ = self.writer.write_command(&Command::EndRegion);
+        }
+    }
+}
+
+/// A contour being written, see [RegionWriter::contour]
+///
+/// Dropping the contour closes it but discards any error, so call
+/// [close](ContourWriter::close) to know it was written.
+#[derive(Debug)]
+pub struct ContourWriter<'r, W: Write> {
+    writer: &'r mut GerberWriter<W>,
+
+    /// The interpolation mode last written in the region
+    mode: &'r mut Option<InterpolationMode>,
+
+    start: (i64, i64),
+    current: (i64, i64),
+    closed: bool,
+}
+
+impl<W: Write> ContourWriter<'_, W> {
+    /// Add a straight segment to `to`
+    pub fn line_to(&mut self, to: (i64, i64)) -> io::Result<()> {
+        self.segment(to, None, InterpolationMode::Linear)
+    }
+
+    /// Add an arc to `to` around the `center` offset from the current
+    /// point
+    ///
+    /// `G75` must have been written before, as for any arc. A linear
+    /// `direction` adds a straight segment.
+    pub fn arc_to(
+        &mut self,
+        to: (i64, i64),
+        center: Offset,
+        direction: InterpolationMode,
+    ) -> io::Result<()> {
+        self.segment(to, direction.is_circular().then_some(center), direction)
+    }
+
+    /// Close the contour with a straight segment back to its start, unless
+    /// it is already there
+    pub fn close(mut self) -> io::Result<()> {
+        self.closed = true;
+        if self.current == self.start {
+            return Ok(());
+        }
+        self.segment(self.start, None, InterpolationMode::Linear)
+    }
+
+    fn segment(
+        &mut self,
+        to: (i64, i64),
+        center: Option<Offset>,
+        mode: InterpolationMode,
+    ) -> io::Result<()> {
+        if *self.mode != Some(mode) {
+            self.writer.write_command(&match mode {
+                InterpolationMode::Linear => Command::SetLinear,
+                InterpolationMode::Clockwise => Command::SetCWCircular,
+                InterpolationMode::CounterClockwise => Command::SetCCWCircular,
+            })?;
+            *self.mode = Some(mode);
+        }
+        self.writer
+            .write_command(&Command::Plot(coordinates(to), center))?;
+        self.current = to;
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for ContourWriter<'_, W> {
+    fn drop(&mut self) {
+        if !self.closed && self.current != self.start {
+            let _ = self.segment(self.start, None, InterpolationMode::Linear);
+        }
+    }
+}
+
+fn coordinates((x, y): (i64, i64)) -> Coordinates {
+    Coordinates {
+        x: Some(x),
+        y: Some(y),
+    }
+}
+
+impl GerberLayer<'_> {
+    /// Write the commands of the layer to `out`, see [GerberWriter]
+    ///
+    /// Parsing the output gives the same commands, though the source
+    /// formatting, such as line breaks within a command, is not kept.
+    pub fn write(&self, out: impl Write) -> io::Result<()> {
+        let mut writer = GerberWriter::new(out);
+        for command in self.commands() {
+            writer.write_command(command)?;
+        }
+        writer.finish().map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aperture::ApertureTemplate;
+    use crate::data::{ApertureId, CoordinateFormat, StepRepeat, Unit};
+    use crate::image::Shape;
+    use crate::macros::{Expression, MacroStatement};
+    use indoc::indoc;
+
+    #[test]
+    fn test_round_trip() {
+        let src = indoc! {r"
+            G04 Round trip*
+            %FSLAX36Y36*%
+            %MOIN*%
+            %TF.FileFunction,Copper,L1,Top*%
+            %TF.Part,Single*%
+            %TA.AperFunction,SMDPad,CuDef*%
+            %ADD10C,0.01*%
+            %ADD11R,0.02X0.03X0.005*%
+            %ADD12O,0.02X0.04*%
+            %ADD13P,0.05X6X15.5X0.01*%
+            %ADD14P,0.05X3*%
+            %ADD15Thermal,0.1X0.08X-0.5*%
+            %ADD16Empty*%
+            %TD.AperFunction*%
+            D10*
+            %TO.N,GND*%
+            X-1000Y2000D03*
+            X3000D03*
+            %TD*%
+            %LPC*%
+            %LMXY*%
+            %LR-45.25*%
+            %LS0.5*%
+            D11*
+            G01*
+            X0Y0D02*
+            Y5000D01*
+            G75*
+            G03*
+            X5000Y0I-500J0D01*
+            G02*
+            G74*
+            X0Y5000I500J500D01*
+            %LPD*%
+            %LMN*%
+            G36*
+            G01*
+            X0Y0D02*
+            X100D01*
+            Y100D01*
+            X0Y0D01*
+            G37*
+            G04 Escaped °*
+            M02*
+        "};
+        let layer = GerberLayer::parse(src).unwrap();
+        let mut out = Vec::new();
+        layer.write(&mut out).unwrap();
+        let written = String::from_utf8(out).unwrap();
+        assert_eq!(written, src);
+        assert_eq!(
+            GerberLayer::parse(&written).unwrap().commands(),
+            layer.commands()
+        );
+    }
+
+    #[test]
+    fn test_unparsed_commands() {
+        // blocks and step and repeat aren't parsed yet
+        let id = ApertureId::new(20).unwrap();
+        for (command, expected) in [
+            (
+                Command::ApertureMacro(
+                    "Donut".into(),
+                    vec![
+                        MacroStatement::Comment("outer ring".into()),
+                        MacroStatement::Primitive(
+                            1,
+                            vec![
+                                Expression::Number(1.0),
+                                Expression::Variable(1),
+                                Expression::Number(0.0),
+                                Expression::Number(0.0),
+                            ],
+                        ),
+                    ],
+                ),
+                "%AMDonut*\n0 outer ring*\n1,1,$1,0,0*%",
+            ),
+            (Command::ApertureBlock(Some(id)), "%ABD20*%"),
+            (Command::ApertureBlock(None), "%AB*%"),
+            (
+                Command::StepAndRepeat(Some(StepRepeat {
+                    x: 3,
+                    y: 2,
+                    i: 5.5,
+                    j: 4.0,
+                })),
+                "%SRX3Y2I5.5J4*%",
+            ),
+            (Command::StepAndRepeat(None), "%SR*%"),
+            (
+                Command::ApertureDefine(
+                    id,
+                    ApertureTemplate::Polygon {
+                        diameter: 1.0,
+                        vertices: 4.0,
+                        rotation: None,
+                        hole: Some(0.25),
+                    },
+                ),
+                "%ADD20P,1X4X0X0.25*%",
+            ),
+        ] {
+            assert_eq!(command.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_region() {
+        let format = CoordinateFormat {
+            integer: 2,
+            decimal: 6,
+        };
+        let mut writer = GerberWriter::new(Vec::new());
+        for command in [
+            Command::FormatSpecification(format, format),
+            Command::Mode(Unit::Millimeters),
+            Command::ArcInit,
+        ] {
+            writer.write_command(&command).unwrap();
+        }
+        {
+            let mut region = writer.region().unwrap();
+            // dropped contours are closed
+            let mut square = region.contour((0, 0)).unwrap();
+            square.line_to((1000000, 0)).unwrap();
+            square.line_to((1000000, 1000000)).unwrap();
+            square.line_to((0, 1000000)).unwrap();
+            drop(square);
+
+            let mut circle = region.contour((5000000, 0)).unwrap();
+            let center = Offset { i: 1000000, j: 0 };
+            circle
+                .arc_to((5000000, 0), center, InterpolationMode::Clockwise)
+                .unwrap();
+            circle.close().unwrap();
+            // and dropped regions ended
+        }
+        writer.write_command(&Command::EndOfFile).unwrap();
+        let src = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            src,
+            indoc! {"
+                %FSLAX26Y26*%
+                %MOMM*%
+                G75*
+                G36*
+                X0Y0D02*
+                G01*
+                X1000000Y0D01*
+                X1000000Y1000000D01*
+                X0Y1000000D01*
+                X0Y0D01*
+                X5000000Y0D02*
+                G02*
+                X5000000Y0I1000000J0D01*
+                G37*
+                M02*
+            "}
+        );
+
+        let layer = GerberLayer::parse(&src).unwrap();
+        assert_eq!(layer.validate(), vec![]);
+        let image = layer.image();
+        let [object] = &image.objects[..] else {
+            panic!("{:?}", image.objects);
+        };
+        assert!(matches!(&object.shape, Shape::Region { contours } if contours.len() == 2));
+    }
+
+    #[test]
+    fn test_streaming() {
+        // output reaches the underlying writer when the buffer fills, not
+        // only at the end
+        let flash = Command::Flash(Default::default());
+        let mut writer = GerberWriter::with_capacity(16, Vec::new());
+        for _ in 0..100 {
+            writer.write_command(&flash).unwrap();
+        }
+        assert!(writer.out.get_ref().len() > 400);
+        let out = writer.finish().unwrap();
+        assert_eq!(out, b"D03*\n".repeat(100));
+    }
+}